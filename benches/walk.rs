@@ -0,0 +1,90 @@
+//! Walks synthetic trees with different options and reports the timings.
+//!
+//! Run with `cargo bench --features testkit`. Cases can be filtered by a
+//! substring of their name, e.g. `cargo bench --features testkit -- sorted`.
+
+use std::time::{Duration, Instant};
+
+use walkdir::synth::{make_tree, FileSizes, SynthSpec};
+use walkdir::{DefaultDirEntry, FsDirEntry, WalkDirBuilder};
+
+type WalkDir = WalkDirBuilder<DefaultDirEntry>;
+type Case = (&'static str, fn(WalkDir) -> WalkDir);
+
+const RUNS: usize = 10;
+
+fn trees() -> Vec<(&'static str, SynthSpec)> {
+    vec![
+        (
+            "wide",
+            SynthSpec { width: 2000, dirs: 20, depth: 1, ..SynthSpec::default() },
+        ),
+        (
+            "deep",
+            SynthSpec { width: 4, dirs: 2, depth: 12, ..SynthSpec::default() },
+        ),
+        (
+            "mixed",
+            SynthSpec {
+                width: 12,
+                dirs: 4,
+                depth: 5,
+                file_sizes: FileSizes::Uniform { min: 0, max: 4096 },
+                symlink_percent: 20,
+                seed: 1,
+            },
+        ),
+    ]
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        ("default", |wd| wd),
+        ("sorted", |wd| wd.sort_by(|a, b, _| a.0.file_name().cmp(b.0.file_name()))),
+        ("follow_links", |wd| wd.follow_links(true)),
+        ("max_open=1", |wd| wd.max_open(1)),
+        ("all", |wd| {
+            wd.sort_by(|a, b, _| a.0.file_name().cmp(b.0.file_name()))
+                .follow_links(true)
+                .max_open(1)
+        }),
+    ]
+}
+
+fn run(root: &std::path::Path, case: fn(WalkDir) -> WalkDir) -> (Duration, usize) {
+    let start = Instant::now();
+    let count = case(WalkDir::new(root)).into_classic().filter(|r| r.is_ok()).count();
+    (start.elapsed(), count)
+}
+
+fn main() {
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+
+    for (tree_name, spec) in trees() {
+        let tree = make_tree(&spec).expect("cannot create synthetic tree");
+
+        for (case_name, case) in cases() {
+            let name = format!("{}/{}", tree_name, case_name);
+            if let Some(ref filter) = filter {
+                if !name.contains(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            // Warm up the OS caches
+            let (_, count) = run(tree.root(), case);
+
+            let mut times: Vec<_> = (0..RUNS).map(|_| run(tree.root(), case).0).collect();
+            times.sort();
+            println!(
+                "{:<24} {:>6} entries   min {:>10.3?}   median {:>10.3?}",
+                name,
+                count,
+                times[0],
+                times[RUNS / 2],
+            );
+        }
+
+        assert_eq!(tree.counts().entries() + 1, run(tree.root(), cases()[0].1).1);
+    }
+}
@@ -0,0 +1,260 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use crate::fs;
+use crate::wd::{Depth, FnNormalize};
+use crate::cp::{ContentProcessor, SlimContentProcessor, SlimEntry, SlimFileType};
+
+use core::iter::FromIterator;
+use core::time::Duration;
+
+/////////////////////////////////////////////////////////////////////////////////
+
+/// Everything of a [`SlimEntry`] except the path
+#[derive(Debug, Clone, Copy)]
+struct ArenaRecord {
+    /// End of the path in the arena (it starts at the end of the previous one)
+    end: usize,
+    len: u64,
+    modified: Option<Duration>,
+    depth: Depth,
+    file_type: SlimFileType,
+    path_is_symlink: bool,
+    is_dir: bool,
+}
+
+/// A collection of compact entries with all paths stored in one buffer.
+///
+/// Every path is appended to a single growing buffer (a bump arena owned by
+/// the collection), so collecting a whole walk makes a few large allocations
+/// instead of one per entry, and freeing it is just as cheap. Entries are read
+/// back as [`ArenaEntry`] views borrowing from the collection.
+///
+/// It is the collection of [`ArenaContentProcessor`] and can also be collected
+/// from any iterator over [`SlimEntry`]:
+///
+/// ```no_run
+/// use walkdir::{ArenaContentProcessor, ArenaEntries, DefaultDirEntry, WalkDirBuilder};
+///
+/// let index: ArenaEntries = WalkDirBuilder::<DefaultDirEntry, ArenaContentProcessor>::new("foo")
+///     .into_classic()
+///     .filter_map(|e| e.ok())
+///     .collect();
+/// for entry in index.iter() {
+///     println!("{} {}", entry.path().display(), entry.len());
+/// }
+/// ```
+///
+/// Available with the `arena` feature.
+///
+/// [`SlimEntry`]: struct.SlimEntry.html
+/// [`ArenaEntry`]: struct.ArenaEntry.html
+/// [`ArenaContentProcessor`]: struct.ArenaContentProcessor.html
+#[derive(Debug, Clone, Default)]
+pub struct ArenaEntries {
+    /// Encoded bytes of all paths, back to back
+    arena: Vec<u8>,
+    records: Vec<ArenaRecord>,
+}
+
+impl ArenaEntries {
+    /// Make new empty collection
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make new empty collection with room for `entries` entries with paths of
+    /// `bytes` bytes in total
+    pub fn with_capacity(entries: usize, bytes: usize) -> Self {
+        Self { arena: Vec::with_capacity(bytes), records: Vec::with_capacity(entries) }
+    }
+
+    /// Append an entry (its path is copied into the arena)
+    pub fn push<E>(&mut self, entry: &SlimEntry<E>)
+    where
+        E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+    {
+        self.arena.extend_from_slice(entry.path().as_os_str().as_encoded_bytes());
+        self.records.push(ArenaRecord {
+            end: self.arena.len(),
+            len: entry.len(),
+            modified: entry.modified(),
+            depth: entry.depth(),
+            file_type: entry.file_type(),
+            path_is_symlink: entry.path_is_symlink(),
+            is_dir: entry.is_dir(),
+        });
+    }
+
+    /// Count of entries
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns `true` if there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Total size of all paths in bytes
+    pub fn arena_len(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// Get the entry at `index`
+    pub fn get(&self, index: usize) -> Option<ArenaEntry<'_>> {
+        let record = self.records.get(index)?;
+        let start = match index {
+            0 => 0,
+            _ => self.records[index - 1].end,
+        };
+        // SAFETY: the bytes were taken from a whole `OsStr` by `as_encoded_bytes`
+        // and are cut at the same boundaries
+        let path = unsafe { OsStr::from_encoded_bytes_unchecked(&self.arena[start..record.end]) };
+        Some(ArenaEntry { path: Path::new(path), record })
+    }
+
+    /// Iterate over entries
+    pub fn iter(&self) -> impl Iterator<Item = ArenaEntry<'_>> + '_ {
+        (0..self.len()).filter_map(move |index| self.get(index))
+    }
+
+    /// Drop all entries, keeping the allocated memory
+    pub fn clear(&mut self) {
+        self.arena.clear();
+        self.records.clear();
+    }
+}
+
+impl<E> Extend<SlimEntry<E>> for ArenaEntries
+where
+    E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+{
+    fn extend<I: IntoIterator<Item = SlimEntry<E>>>(&mut self, iter: I) {
+        for entry in iter {
+            self.push(&entry);
+        }
+    }
+}
+
+impl<E> FromIterator<SlimEntry<E>> for ArenaEntries
+where
+    E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+{
+    fn from_iter<I: IntoIterator<Item = SlimEntry<E>>>(iter: I) -> Self {
+        let mut entries = Self::new();
+        entries.extend(iter);
+        entries
+    }
+}
+
+/// An entry of [`ArenaEntries`], borrowing its path from the arena.
+///
+/// [`ArenaEntries`]: struct.ArenaEntries.html
+#[derive(Debug, Clone, Copy)]
+pub struct ArenaEntry<'a> {
+    path: &'a Path,
+    record: &'a ArenaRecord,
+}
+
+impl<'a> ArenaEntry<'a> {
+    /// The full path that this entry represents.
+    pub fn path(&self) -> &'a Path {
+        self.path
+    }
+
+    /// Return the file type for the file that this entry points to.
+    pub fn file_type(&self) -> SlimFileType {
+        self.record.file_type
+    }
+
+    /// Returns `true` if and only if this entry points to a directory.
+    pub fn is_dir(&self) -> bool {
+        self.record.is_dir
+    }
+
+    /// Returns `true` if and only if this entry was created from a symbolic
+    /// link.
+    pub fn path_is_symlink(&self) -> bool {
+        self.record.path_is_symlink
+    }
+
+    /// Size of the file in bytes.
+    pub fn len(&self) -> u64 {
+        self.record.len
+    }
+
+    /// Returns `true` if the size is zero.
+    pub fn is_empty(&self) -> bool {
+        self.record.len == 0
+    }
+
+    /// Last modification time (since the Unix epoch), if the backend reports it.
+    pub fn modified(&self) -> Option<Duration> {
+        self.record.modified
+    }
+
+    /// Returns the depth at which this entry was created relative to the root.
+    pub fn depth(&self) -> Depth {
+        self.record.depth
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////////
+
+/// Convertor from RawDirEntry into SlimEntry, collecting into ArenaEntries
+#[derive(Debug, Default, Clone)]
+pub struct ArenaContentProcessor {
+    inner: SlimContentProcessor,
+}
+
+impl<E> ContentProcessor<E> for ArenaContentProcessor
+where
+    E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+{
+    type Item = SlimEntry<E>;
+    type Collection = ArenaEntries;
+
+    /// Convert RawDirEntry into final entry type (e.g. DirEntry)
+    fn process_root_direntry(
+        &self,
+        fsdent: &mut E::RootDirEntry,
+        follow_link: bool,
+        is_dir: bool,
+        depth: Depth,
+        ctx: &mut E::Context,
+    ) -> Option<Self::Item> {
+        self.inner.process_root_direntry(fsdent, follow_link, is_dir, depth, ctx)
+    }
+
+    /// Convert RawDirEntry into final entry type (e.g. DirEntry)
+    fn process_direntry(
+        &self,
+        fsdent: &mut E,
+        follow_link: bool,
+        is_dir: bool,
+        depth: Depth,
+        ctx: &mut E::Context,
+    ) -> Option<Self::Item> {
+        self.inner.process_direntry(fsdent, follow_link, is_dir, depth, ctx)
+    }
+
+    /// Check if final entry is dir
+    fn is_dir(item: &Self::Item) -> bool {
+        item.is_dir()
+    }
+
+    /// Replace path of final entry with its normalized form
+    fn normalize_path(item: &mut Self::Item, normalize: FnNormalize<E>) {
+        <SlimContentProcessor as ContentProcessor<E>>::normalize_path(item, normalize)
+    }
+
+    /// Collects iterator over items into collection
+    fn collect(&self, iter: impl Iterator<Item = Self::Item>) -> Self::Collection {
+        iter.collect()
+    }
+    /// Empty items collection
+    fn empty_collection() -> Self::Collection {
+        ArenaEntries::new()
+    }
+}
@@ -1,9 +1,18 @@
 //use crate::error::{into_io_err, Error};
-use crate::fs::{self, FsFileType, FsRootDirEntry, FsMetadata};
-use crate::wd::{Depth, IntoSome};
+use crate::fs::{self, FsFileType, FsRootDirEntry, FsMetadata, FsPath, FsPathBuf};
+use crate::wd::{Depth, FnNormalize, IntoSome, Origin};
 use crate::cp::ContentProcessor;
 
-use std::vec::Vec;
+use core::fmt;
+use alloc::borrow::Cow;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// `OnceLock` keeps `DirEntry` `Sync`, but it isn't available without `std`
+#[cfg(feature = "std")]
+type CacheCell<T> = std::sync::OnceLock<T>;
+#[cfg(not(feature = "std"))]
+type CacheCell<T> = core::cell::OnceCell<T>;
 
 /////////////////////////////////////////////////////////////////////////////////
 
@@ -33,7 +42,8 @@ use std::vec::Vec;
 /// [`file_name`]: #method.file_name
 /// [`follow_links`]: struct.WalkDir.html#method.follow_links
 /// [`DirEntryExt`]: trait.DirEntryExt.html
-#[derive(Debug, Clone)]
+#[cfg(feature = "std")]
+#[derive(Debug)]
 pub struct DirEntry<E: fs::FsDirEntry = fs::DefaultDirEntry> {
     /// Raw dent
     path: E::PathBuf,
@@ -43,10 +53,77 @@ pub struct DirEntry<E: fs::FsDirEntry = fs::DefaultDirEntry> {
     is_dir: bool,
     /// Cached metadata
     metadata: E::Metadata,
-    /// Cached file name
-    file_name: E::FileName,
     /// The depth at which this entry was generated relative to the root.
     depth: Depth,
+    /// Cached canonical path
+    canonical: CacheCell<E::PathBuf>,
+    /// Cached metadata of the link target
+    target_metadata: CacheCell<E::Metadata>,
+    /// Content of this dir is skipped by sampling
+    sampled_out: bool,
+    /// This dir is yielded before its content (see `announce_dirs`)
+    announcement: bool,
+    /// A sibling has the same name under Unicode normalization
+    unicode_collision: bool,
+    /// This dir is on another device than its parent
+    mount_point: bool,
+    /// Root and followed link this entry was found through
+    origin: Origin<E::PathBuf>,
+    /// Device of the root (only with `same_file_system`)
+    device: Option<E::DeviceNum>,
+}
+
+/// A directory entry (there is no default backend without `std`).
+#[cfg(not(feature = "std"))]
+#[derive(Debug)]
+pub struct DirEntry<E: fs::FsDirEntry> {
+    /// Raw dent
+    path: E::PathBuf,
+    /// Follow link
+    follow_link: bool,
+    /// Is normal dir
+    is_dir: bool,
+    /// Cached metadata
+    metadata: E::Metadata,
+    /// The depth at which this entry was generated relative to the root.
+    depth: Depth,
+    /// Cached canonical path
+    canonical: CacheCell<E::PathBuf>,
+    /// Cached metadata of the link target
+    target_metadata: CacheCell<E::Metadata>,
+    /// Content of this dir is skipped by sampling
+    sampled_out: bool,
+    /// This dir is yielded before its content (see `announce_dirs`)
+    announcement: bool,
+    /// A sibling has the same name under Unicode normalization
+    unicode_collision: bool,
+    /// This dir is on another device than its parent
+    mount_point: bool,
+    /// Root and followed link this entry was found through
+    origin: Origin<E::PathBuf>,
+    /// Device of the root (only with `same_file_system`)
+    device: Option<E::DeviceNum>,
+}
+
+// Not derived: that would require the backend entry type to be `Clone` too
+impl<E: fs::FsDirEntry> Clone for DirEntry<E> {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            follow_link: self.follow_link,
+            is_dir: self.is_dir,
+            metadata: self.metadata.clone(),
+            depth: self.depth,
+            canonical: self.canonical.clone(),
+            target_metadata: self.target_metadata.clone(),
+            sampled_out: self.sampled_out,
+            announcement: self.announcement,
+            unicode_collision: self.unicode_collision,
+            mount_point: self.mount_point,
+            origin: self.origin.clone(),
+            device: self.device,
+        }
+    }
 }
 
 impl<E: fs::FsDirEntry> DirEntry<E> {
@@ -118,6 +195,81 @@ impl<E: fs::FsDirEntry> DirEntry<E> {
         &self.metadata
     }
 
+    /// Return the metadata of the target of this entry, following symbolic
+    /// links even if [`follow_links`] is disabled.
+    ///
+    /// If this entry is not a symbolic link (or [`follow_links`] is enabled),
+    /// this is the same as [`metadata`] and never makes any system calls.
+    /// Otherwise, the target is queried with the backend's `metadata_path` on
+    /// the first call and cached, so later calls (and clones made after the
+    /// first call) never make any system calls. Errors aren't cached. This
+    /// allows to report both a link and its target without walking again.
+    ///
+    /// # Errors
+    ///
+    /// Similar to [`std::fs::metadata`], returns errors for broken symbolic
+    /// links and targets that the program does not have permissions to
+    /// access.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    /// [`metadata`]: struct.DirEntry.html#method.metadata
+    /// [`std::fs::metadata`]: https://doc.rust-lang.org/std/fs/fn.metadata.html
+    pub fn target_metadata(&self) -> Result<&E::Metadata, E::Error> {
+        if !self.metadata.file_type().is_symlink() {
+            return Ok(&self.metadata);
+        }
+        if let Some(metadata) = self.target_metadata.get() {
+            return Ok(metadata);
+        }
+        let metadata = E::metadata_path(&self.path, true)?;
+        Ok(self.target_metadata.get_or_init(|| metadata))
+    }
+
+    /// Open the contents of this entry for reading with the backend's reader
+    /// (a [`std::fs::File`] for the standard backends), so processors which
+    /// hash or search contents work with every backend the same way.
+    ///
+    /// Symbolic links are always followed. The file is opened anew on each
+    /// call and nothing is cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns the backend's error if the entry can't be opened, e.g. if it
+    /// was removed after being yielded.
+    ///
+    /// [`std::fs::File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+    pub fn open(&self) -> Result<E::Reader, E::Error> {
+        E::open_path(&self.path)
+    }
+
+    /// Return the count of bytes this entry occupies on disk, which is less
+    /// than its logical size for sparse or compressed files and may be more
+    /// for small ones. This allows `du`-style tools to report real disk usage.
+    ///
+    /// On Unix this is taken from `st_blocks` of [`metadata`] and never makes
+    /// any system calls. On Windows `GetCompressedFileSizeW` is called each
+    /// time (symbolic links are always followed there). Returns `None` if the
+    /// backend can't tell or the query fails.
+    ///
+    /// [`metadata`]: struct.DirEntry.html#method.metadata
+    pub fn allocated_size(&self) -> Option<u64> {
+        self.metadata.allocated_size().or_else(|| E::allocated_size_path(&self.path))
+    }
+
+    /// Returns true if this entry is a cloud placeholder whose data (or
+    /// directory content) is fetched from the cloud on access, e.g. a OneDrive
+    /// file which is not available offline. Reading such an entry may be slow
+    /// and needs network access; see [`avoid_hydration`].
+    ///
+    /// This is taken from [`metadata`] and never makes any system calls.
+    /// Currently, placeholders are only detected on Windows.
+    ///
+    /// [`avoid_hydration`]: struct.WalkDir.html#method.avoid_hydration
+    /// [`metadata`]: struct.DirEntry.html#method.metadata
+    pub fn is_placeholder(&self) -> bool {
+        self.metadata.is_placeholder()
+    }
+
     /// Return the file type for the file that this entry points to.
     ///
     /// If this is a symbolic link and [`follow_links`] is `true`, then this
@@ -134,8 +286,12 @@ impl<E: fs::FsDirEntry> DirEntry<E> {
     ///
     /// If this entry has no file name (e.g., `/`), then the full path is
     /// returned.
+    ///
+    /// The name is borrowed from [`path`], so this never allocates.
+    ///
+    /// [`path`]: struct.DirEntry.html#method.path
     pub fn file_name(&self) -> &E::FileName {
-        &self.file_name
+        self.path.file_name().unwrap_or_else(|| self.path.as_file_name())
     }
 
     /// Returns the depth at which this entry was created relative to the root.
@@ -147,12 +303,464 @@ impl<E: fs::FsDirEntry> DirEntry<E> {
         self.depth
     }
 
+    /// Returns `true` if this entry is one of the roots given to the
+    /// builder (i.e. its depth is `0`).
+    ///
+    /// This is handy with multiple roots (see [`add_roots`]) to tell the given
+    /// paths from the found ones.
+    ///
+    /// [`add_roots`]: struct.WalkDir.html#method.add_roots
+    pub fn is_root(&self) -> bool {
+        self.depth == 0
+    }
+
+    /// Returns `true` if this entry is a directory which was not descended
+    /// into because it wasn't chosen by [`sample`].
+    ///
+    /// [`sample`]: struct.WalkDir.html#method.sample
+    pub fn sampled_out(&self) -> bool {
+        self.sampled_out
+    }
+
+    /// Returns true if this is a directory yielded before its contents with
+    /// [`announce_dirs`] enabled. The same directory is yielded once more after
+    /// its contents, and then this returns false.
+    ///
+    /// [`announce_dirs`]: struct.WalkDir.html#method.announce_dirs
+    pub fn is_announcement(&self) -> bool {
+        self.announcement
+    }
+
+    /// Returns true if the name of this entry differs from the name of a
+    /// sibling only in Unicode normalization (e.g. one is composed as NFC and
+    /// the other is decomposed as NFD), so both would be the same file on
+    /// file systems which normalize names. Such entries are only detected with
+    /// [`detect_unicode_collisions`] enabled, and all of them are marked.
+    ///
+    /// [`detect_unicode_collisions`]: struct.WalkDir.html#method.detect_unicode_collisions
+    pub fn has_unicode_collision(&self) -> bool {
+        self.unicode_collision
+    }
+
+    /// Returns true if this entry is a directory on another device than its
+    /// parent directory, e.g. a mount point on Unix or a volume mounted into a
+    /// folder on Windows. Mount points are only detected with
+    /// [`detect_mount_points`] or [`skip_mount_points`] enabled.
+    ///
+    /// [`detect_mount_points`]: struct.WalkDir.html#method.detect_mount_points
+    /// [`skip_mount_points`]: struct.WalkDir.html#method.skip_mount_points
+    pub fn is_mount_point(&self) -> bool {
+        self.mount_point
+    }
+
+    /// Returns where this entry was found: the index of the root it belongs
+    /// to (when several roots are walked, see [`add_roots`]) and, if
+    /// it was reached through a followed symbolic link to a directory, the
+    /// path of the innermost such link.
+    ///
+    /// An entry which is itself a followed link doesn't count as reached
+    /// through it; only its contents do.
+    ///
+    /// [`add_roots`]: struct.WalkDir.html#method.add_roots
+    pub fn origin(&self) -> &Origin<E::PathBuf> {
+        &self.origin
+    }
+
+    /// Returns the device this entry is on if [`same_file_system`] is
+    /// enabled. Such a walk never leaves the device of its root, so the device
+    /// is queried once per root and shared by every entry, saving a lookup per
+    /// entry in per-device reports. Returns `None` without the option, or if
+    /// the device of the root couldn't be queried (see
+    /// [`same_file_system_soft`]).
+    ///
+    /// [`same_file_system`]: struct.WalkDir.html#method.same_file_system
+    /// [`same_file_system_soft`]: struct.WalkDir.html#method.same_file_system_soft
+    pub fn device(&self) -> Option<E::DeviceNum> {
+        self.device
+    }
+
+    /// Returns the path of this entry's ancestor at the given depth.
+    ///
+    /// `ancestor_path(0)` is the root given to [`WalkDir::new`] and
+    /// `ancestor_path(self.depth())` is [`path`] itself. Returns `None` if
+    /// `depth` is greater than the depth of this entry.
+    ///
+    /// Since the iterator builds the path of every entry by joining its file
+    /// name to the path of its parent, the ancestor is found by dropping
+    /// trailing components of [`path`]. This never makes any system calls or
+    /// allocations.
+    ///
+    /// [`WalkDir::new`]: struct.WalkDir.html#method.new
+    /// [`path`]: struct.DirEntry.html#method.path
+    pub fn ancestor_path(&self, depth: Depth) -> Option<&E::Path> {
+        if depth > self.depth {
+            return None;
+        }
+        let mut path: &E::Path = &self.path;
+        for _ in depth..self.depth {
+            path = path.parent()?;
+        }
+        Some(path)
+    }
+
+    /// Returns the file names of this entry and its ancestors below the root,
+    /// starting from the one at depth `1`.
+    ///
+    /// The count of yielded names is equal to [`depth`]. This never makes any
+    /// system calls.
+    ///
+    /// [`depth`]: struct.DirEntry.html#method.depth
+    pub fn components_from_root(&self) -> impl Iterator<Item = &E::FileName> + '_ {
+        let mut paths = Vec::with_capacity(self.depth);
+        let mut path: Option<&E::Path> = Some(&self.path);
+        for _ in 0..self.depth {
+            match path {
+                Some(p) => {
+                    paths.push(p);
+                    path = p.parent();
+                }
+                None => break,
+            }
+        }
+        paths.into_iter().rev().filter_map(|p| p.file_name())
+    }
+
+    /// Returns the canonical path of this entry, with all symbolic links
+    /// resolved.
+    ///
+    /// The path is computed with the backend's `canonicalize_path` on the
+    /// first call and cached, so later calls (and clones made after the first
+    /// call) never make any system calls. Errors aren't cached.
+    ///
+    /// # Errors
+    ///
+    /// Similar to [`std::fs::canonicalize`], returns errors for path values
+    /// that do not exist (e.g. broken symbolic links).
+    ///
+    /// [`std::fs::canonicalize`]: https://doc.rust-lang.org/stable/std/fs/fn.canonicalize.html
+    pub fn canonical_path(&self) -> Result<&E::Path, E::Error> {
+        if let Some(canonical) = self.canonical.get() {
+            return Ok(canonical);
+        }
+        let canonical = E::canonicalize_path(&self.path)?;
+        Ok(self.canonical.get_or_init(|| canonical))
+    }
+
     /////////////////////////////////////////////////////////////////////////////////
     
     /// Returns true if and only if this entry points to a directory.
     pub(crate) fn is_dir(&self) -> bool {
         self.is_dir
     }
+
+    /// Make the depth relative to an ancestor `depth` levels above the root
+    #[cfg(feature = "parallel")]
+    pub(crate) fn add_depth(&mut self, depth: Depth) {
+        self.depth += depth;
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E> DirEntry<E>
+where
+    E: fs::FsDirEntry<Path = std::path::Path, PathBuf = std::path::PathBuf>,
+{
+    /// Replace the root part of the path with `prefix` (see `map_root`).
+    /// The path buffer is reused when its capacity allows.
+    pub(crate) fn map_root(&mut self, prefix: &std::path::Path) {
+        let root_len = match self.ancestor_path(0) {
+            Some(root) => root.as_os_str().len(),
+            None => return,
+        };
+        self.canonical = CacheCell::new();
+        self.target_metadata = CacheCell::new();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+            let prefix = prefix.as_os_str().as_bytes();
+            let mut bytes = core::mem::take(&mut self.path).into_os_string().into_vec();
+            // Keep exactly one separator between the prefix and the rest
+            let mut end = root_len;
+            if (prefix.is_empty() || prefix.ends_with(b"/")) && bytes.get(end) == Some(&b'/') {
+                end += 1;
+            }
+            bytes.splice(..end, prefix.iter().copied());
+            self.path = std::ffi::OsString::from_vec(bytes).into();
+        }
+
+        #[cfg(not(unix))]
+        {
+            let path = core::mem::take(&mut self.path);
+            match (path.into_os_string().into_string(), prefix.to_str()) {
+                (Ok(mut path), Some(prefix)) => {
+                    // Keep exactly one separator between the prefix and the rest
+                    let mut end = root_len;
+                    if (prefix.is_empty() || prefix.ends_with(std::path::is_separator))
+                        && path[end..].starts_with(std::path::is_separator)
+                    {
+                        end += 1;
+                    }
+                    path.replace_range(..end, prefix);
+                    self.path = path.into();
+                }
+                (path, _) => {
+                    // Not Unicode: rebuild from components
+                    self.path = match path {
+                        Ok(path) => path.into(),
+                        Err(path) => path.into(),
+                    };
+                    let mut mapped = prefix.to_path_buf();
+                    for name in self.components_from_root() {
+                        mapped.push(name);
+                    }
+                    self.path = mapped;
+                }
+            }
+        }
+    }
+}
+
+impl<E: fs::FsDirEntry> fmt::Display for DirEntry<E> {
+    /// Displays the path of this entry (lossily, as [`Path::display`] does).
+    ///
+    /// [`Path::display`]: https://doc.rust-lang.org/stable/std/path/struct.Path.html#method.display
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.path.display().fmt(f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<E> serde::Serialize for DirEntry<E>
+where
+    E: fs::FsDirEntry,
+    E::PathBuf: serde::Serialize,
+{
+    /// Serializes path, depth, type (`dir`, `file`, `symlink` or `other`) and whether
+    /// the path is a symlink. Metadata isn't serialized.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let ty = self.file_type();
+        let ty = if ty.is_dir() {
+            "dir"
+        } else if ty.is_file() {
+            "file"
+        } else if ty.is_symlink() {
+            "symlink"
+        } else {
+            "other"
+        };
+
+        let mut state = serializer.serialize_struct("DirEntry", 4)?;
+        state.serialize_field("path", &self.path)?;
+        state.serialize_field("depth", &self.depth)?;
+        state.serialize_field("type", ty)?;
+        state.serialize_field("symlink", &self.path_is_symlink())?;
+        state.end()
+    }
+}
+
+#[cfg(all(unix, feature = "xattr"))]
+impl<E> DirEntry<E>
+where
+    E: fs::FsDirEntry<Path = std::path::Path, PathBuf = std::path::PathBuf>,
+{
+    /// Returns the names of all extended attributes of this entry.
+    ///
+    /// If this entry is a symbolic link and [`follow_links`] is enabled, then
+    /// the attributes of the target are returned. Names are as the system
+    /// reports them, including the namespace (e.g. `user.comment` or
+    /// `security.selinux`).
+    ///
+    /// This requires the `xattr` feature and is only available on Unix.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    pub fn xattrs(&self) -> std::io::Result<Vec<std::ffi::OsString>> {
+        let names = if self.follow_link {
+            xattr::list_deref(&self.path)?
+        } else {
+            xattr::list(&self.path)?
+        };
+        Ok(names.collect())
+    }
+
+    /// Returns the value of the extended attribute `name` of this entry, or
+    /// `None` if it isn't set.
+    ///
+    /// Symbolic links are handled as in [`xattrs`].
+    ///
+    /// [`xattrs`]: struct.DirEntry.html#method.xattrs
+    pub fn xattr<N: AsRef<std::ffi::OsStr>>(&self, name: N) -> std::io::Result<Option<Vec<u8>>> {
+        if self.follow_link {
+            xattr::get_deref(&self.path, name)
+        } else {
+            xattr::get(&self.path, name)
+        }
+    }
+}
+
+#[cfg(all(feature = "linux-flags", target_os = "linux"))]
+impl<E> DirEntry<E>
+where
+    E: fs::FsDirEntry<Path = std::path::Path, PathBuf = std::path::PathBuf>,
+{
+    /// Returns the inode flags of this entry (as shown by `lsattr`), e.g. to
+    /// check if it is immutable, append-only or excluded from backups.
+    ///
+    /// This makes one `ioctl` call each time it is called. Only regular files
+    /// and directories are queried (the target, if this is a followed link);
+    /// other entries and entries on file systems without inode flags report
+    /// none.
+    ///
+    /// This requires the `linux-flags` feature and is only available on
+    /// Linux.
+    pub fn file_flags(&self) -> std::io::Result<fs::FileFlags> {
+        let ty = self.file_type();
+        if !ty.is_dir() && !ty.is_file() {
+            return Ok(fs::FileFlags::default());
+        }
+        fs::file_flags_from_path(&self.path)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E> DirEntry<E>
+where
+    E: fs::FsDirEntry<Path = std::path::Path, PathBuf = std::path::PathBuf>,
+{
+    /// Returns the [`std::fs::DirEntry`] for this entry, to pass it to APIs
+    /// which expect one.
+    ///
+    /// A `std::fs::DirEntry` keeps its directory open while it lives, so
+    /// entries yielded by the walk don't hold one: the parent directory is read
+    /// again to find the entry by name. This costs a read of the whole parent,
+    /// so prefer [`path`] and [`metadata`] where they suffice. Returns `None`
+    /// if the entry has no parent (e.g. `/`) or is no longer there.
+    ///
+    /// [`std::fs::DirEntry`]: https://doc.rust-lang.org/std/fs/struct.DirEntry.html
+    /// [`path`]: struct.DirEntry.html#method.path
+    /// [`metadata`]: struct.DirEntry.html#method.metadata
+    pub fn into_std(self) -> Option<std::fs::DirEntry> {
+        let name = self.path.file_name()?;
+        let parent = match self.path.parent()? {
+            parent if parent.as_os_str().is_empty() => std::path::Path::new("."),
+            parent => parent,
+        };
+        std::fs::read_dir(parent).ok()?.filter_map(Result::ok).find(|ent| ent.file_name() == name)
+    }
+
+    /// Returns options to open this entry with, like [`std::fs::OpenOptions`]
+    /// but already bound to the path of this entry.
+    ///
+    /// No access mode is set, as with `std::fs::OpenOptions::new`. Symbolic
+    /// links are followed when the file is opened. For example, to append to
+    /// a file found by the walk:
+    ///
+    /// ```no_run
+    /// use std::io::Write;
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder};
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// for entry in WalkDirBuilder::<DefaultDirEntry>::new("logs").into_classic() {
+    ///     let entry = entry?;
+    ///     if entry.file_type().is_file() {
+    ///         let mut file = entry.open_options().append(true).open()?;
+    ///         writeln!(file, "rotated")?;
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`std::fs::OpenOptions`]: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html
+    pub fn open_options(&self) -> DirEntryOpenOptions<'_> {
+        DirEntryOpenOptions { path: &self.path, inner: std::fs::OpenOptions::new() }
+    }
+}
+
+/// Options to open a [`DirEntry`] with, made by [`DirEntry::open_options`].
+///
+/// Methods mirror those of [`std::fs::OpenOptions`]; platform-specific ones
+/// (e.g. `OpenOptionsExt::mode`) can be set through [`as_std_mut`].
+///
+/// [`DirEntry`]: struct.DirEntry.html
+/// [`DirEntry::open_options`]: struct.DirEntry.html#method.open_options
+/// [`std::fs::OpenOptions`]: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html
+/// [`as_std_mut`]: #method.as_std_mut
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct DirEntryOpenOptions<'a> {
+    path: &'a std::path::Path,
+    inner: std::fs::OpenOptions,
+}
+
+#[cfg(feature = "std")]
+impl DirEntryOpenOptions<'_> {
+    /// Set the option for read access
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.inner.read(read);
+        self
+    }
+
+    /// Set the option for write access
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.inner.write(write);
+        self
+    }
+
+    /// Set the option for the append mode
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.inner.append(append);
+        self
+    }
+
+    /// Set the option for truncating the file
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.inner.truncate(truncate);
+        self
+    }
+
+    /// Set the option to create the file if it was removed since the walk
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.inner.create(create);
+        self
+    }
+
+    /// The underlying options, e.g. to set platform-specific ones
+    pub fn as_std_mut(&mut self) -> &mut std::fs::OpenOptions {
+        &mut self.inner
+    }
+
+    /// Open the entry with these options
+    pub fn open(&self) -> std::io::Result<std::fs::File> {
+        self.inner.open(self.path)
+    }
+}
+
+/// Windows-specific extension methods for `walkdir::DirEntry`
+#[cfg(all(windows, feature = "windows-ext"))]
+pub trait WindowsDirEntryExt {
+    /// Returns the kind of this entry if it is a link (a symbolic link, a
+    /// junction, a volume mount point or an app execution alias), or `None`
+    /// otherwise.
+    ///
+    /// The link itself is queried even if [`follow_links`] is enabled. This
+    /// opens the entry on each call.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    fn link_kind(&self) -> std::io::Result<Option<crate::wd::LinkKind>>;
+}
+
+#[cfg(all(windows, feature = "windows-ext"))]
+impl<E> WindowsDirEntryExt for DirEntry<E>
+where
+    E: fs::FsDirEntry<Path = std::path::Path, PathBuf = std::path::PathBuf>,
+{
+    fn link_kind(&self) -> std::io::Result<Option<crate::wd::LinkKind>> {
+        fs::WindowsDirEntry::link_kind_from_path(&self.path)
+    }
 }
 
 // /////////////////////////////////////////////////////////////////////////////////
@@ -178,7 +786,7 @@ impl<E: fs::FsDirEntry> DirEntry<E> {
 
 
 /// Convertor from RawDirEntry into DirEntry
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct DirEntryContentProcessor {}
 
 impl<E: fs::FsDirEntry> ContentProcessor<E> for DirEntryContentProcessor {
@@ -194,15 +802,22 @@ impl<E: fs::FsDirEntry> ContentProcessor<E> for DirEntryContentProcessor {
         depth: Depth,
         ctx: &mut E::Context,
     ) -> Option<Self::Item> {
-        let (path, metadata, file_name) = fsdent.to_parts( follow_link, true, true, ctx ); 
+        let (path, metadata) = fsdent.to_parts( follow_link, true, ctx ); 
 
         Self::Item {
             path,
             follow_link,
             is_dir,
             metadata: metadata.unwrap(),
-            file_name: file_name.unwrap(),
             depth,
+            canonical: CacheCell::new(),
+            target_metadata: CacheCell::new(),
+            sampled_out: false,
+            announcement: false,
+            unicode_collision: false,
+            mount_point: false,
+            origin: Origin::default(),
+            device: None,
         }.into_some()
     }
 
@@ -215,15 +830,22 @@ impl<E: fs::FsDirEntry> ContentProcessor<E> for DirEntryContentProcessor {
         depth: Depth,
         ctx: &mut E::Context,
     ) -> Option<Self::Item> {
-        let (path, metadata, file_name) = fsdent.to_parts( follow_link, true, true, ctx ); 
+        let (path, metadata) = fsdent.to_parts( follow_link, true, ctx ); 
 
         Self::Item {
             path,
             follow_link,
             is_dir,
             metadata: metadata.unwrap(),
-            file_name: file_name.unwrap(),
             depth,
+            canonical: CacheCell::new(),
+            target_metadata: CacheCell::new(),
+            sampled_out: false,
+            announcement: false,
+            unicode_collision: false,
+            mount_point: false,
+            origin: Origin::default(),
+            device: None,
         }.into_some()
     }
 
@@ -232,6 +854,43 @@ impl<E: fs::FsDirEntry> ContentProcessor<E> for DirEntryContentProcessor {
         item.is_dir()
     }
 
+    /// Mark final entry as a dir whose content is skipped by sampling
+    fn mark_sampled_out(item: &mut Self::Item) {
+        item.sampled_out = true;
+    }
+
+    /// Mark final entry as a dir announced before its content
+    fn mark_announcement(item: &mut Self::Item) {
+        item.announcement = true;
+    }
+
+    /// Mark final entry as having a sibling with the same name under Unicode normalization
+    fn mark_unicode_collision(item: &mut Self::Item) {
+        item.unicode_collision = true;
+    }
+
+    /// Mark final entry as a mount point
+    fn mark_mount_point(item: &mut Self::Item) {
+        item.mount_point = true;
+    }
+
+    /// Attach the origin to final entry
+    fn set_origin(item: &mut Self::Item, origin: &Origin<E::PathBuf>) {
+        item.origin = origin.clone();
+    }
+
+    /// Attach the device of the root to final entry
+    fn set_device(item: &mut Self::Item, device: E::DeviceNum) {
+        item.device = Some(device);
+    }
+
+    /// Replace path of final entry with its normalized form
+    fn normalize_path(item: &mut Self::Item, normalize: FnNormalize<E>) {
+        if let Cow::Owned(path) = normalize(&item.path) {
+            item.path = path;
+        }
+    }
+
     /// Collects iterator over items into collection
     fn collect(&self, iter: impl Iterator<Item = Self::Item>) -> Self::Collection {
         iter.collect()
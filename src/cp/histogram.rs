@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use crate::fs::{self, FsFileType};
+use crate::wd::{Depth, FnNormalize};
+use crate::cp::{ContentProcessor, SlimContentProcessor, SlimEntry, SlimFileType};
+
+use core::iter::FromIterator;
+
+/////////////////////////////////////////////////////////////////////////////////
+
+/// Count and total size of a group of entries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeTally {
+    /// Count of entries
+    pub count: usize,
+    /// Sum of sizes of entries in bytes
+    pub bytes: u64,
+}
+
+impl TypeTally {
+    fn add(&mut self, other: TypeTally) {
+        self.count += other.count;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Counts and sizes of entries per file type and per extension.
+///
+/// It is the collection of [`TypeHistogramProcessor`] and can also be
+/// collected from any iterator over [`SlimEntry`]:
+///
+/// ```no_run
+/// use walkdir::{DefaultDirEntry, TypeHistogram, TypeHistogramProcessor, WalkDirBuilder};
+///
+/// let report: TypeHistogram = WalkDirBuilder::<DefaultDirEntry, TypeHistogramProcessor>::new("foo")
+///     .into_classic()
+///     .filter_map(|e| e.ok())
+///     .collect();
+/// println!("{} files, {} bytes", report.files().count, report.files().bytes);
+/// for (ext, tally) in report.by_extension() {
+///     println!("{:?}: {} files, {} bytes", ext, tally.count, tally.bytes);
+/// }
+/// ```
+///
+/// Entries followed through links are tallied by the type of their target.
+/// Only entries other than dirs are tallied per extension, which is taken
+/// as is (so `a.TXT` and `b.txt` are tallied apart).
+///
+/// [`TypeHistogramProcessor`]: struct.TypeHistogramProcessor.html
+/// [`SlimEntry`]: struct.SlimEntry.html
+#[derive(Debug, Clone, Default)]
+pub struct TypeHistogram {
+    /// Tallies per file type, in order of first occurrence
+    by_file_type: Vec<(SlimFileType, TypeTally)>,
+    /// Tallies per extension (`None` for no extension)
+    by_extension: BTreeMap<Option<OsString>, TypeTally>,
+}
+
+impl TypeHistogram {
+    /// Make new empty report
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tally an entry
+    pub fn push<E>(&mut self, entry: &SlimEntry<E>)
+    where
+        E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+    {
+        let tally = TypeTally { count: 1, bytes: entry.len() };
+        self.add_file_type(entry.file_type(), tally);
+        if !entry.is_dir() {
+            let ext = entry.path().extension().map(OsStr::to_os_string);
+            self.by_extension.entry(ext).or_default().add(tally);
+        }
+    }
+
+    /// Add tallies of another report (e.g. made by another thread)
+    pub fn merge(&mut self, other: &TypeHistogram) {
+        for (ty, tally) in &other.by_file_type {
+            self.add_file_type(*ty, *tally);
+        }
+        for (ext, tally) in &other.by_extension {
+            self.by_extension.entry(ext.clone()).or_default().add(*tally);
+        }
+    }
+
+    fn add_file_type(&mut self, ty: SlimFileType, tally: TypeTally) {
+        match self.by_file_type.iter_mut().find(|(t, _)| *t == ty) {
+            Some((_, total)) => total.add(tally),
+            None => self.by_file_type.push((ty, tally)),
+        }
+    }
+
+    /// Tally of all entries
+    pub fn total(&self) -> TypeTally {
+        let mut total = TypeTally::default();
+        for (_, tally) in &self.by_file_type {
+            total.add(*tally);
+        }
+        total
+    }
+
+    /// Tally of entries of given file type
+    pub fn file_type(&self, ty: SlimFileType) -> TypeTally {
+        self.by_file_type.iter().find(|(t, _)| *t == ty).map(|(_, tally)| *tally).unwrap_or_default()
+    }
+
+    /// Tallies per file type, in order of first occurrence
+    pub fn by_file_type(&self) -> impl Iterator<Item = (SlimFileType, TypeTally)> + '_ {
+        self.by_file_type.iter().copied()
+    }
+
+    /// Tally of regular files
+    pub fn files(&self) -> TypeTally {
+        self.tally_where(|ty| ty.is_file())
+    }
+
+    /// Tally of dirs
+    pub fn dirs(&self) -> TypeTally {
+        self.tally_where(|ty| ty.is_dir())
+    }
+
+    /// Tally of symlinks (not followed)
+    pub fn symlinks(&self) -> TypeTally {
+        self.tally_where(|ty| ty.is_symlink())
+    }
+
+    fn tally_where(&self, pred: impl Fn(&SlimFileType) -> bool) -> TypeTally {
+        self.by_file_type.iter().find(|(ty, _)| pred(ty)).map(|(_, tally)| *tally).unwrap_or_default()
+    }
+
+    /// Tally of entries with given extension (`None` for entries without one)
+    pub fn extension(&self, ext: Option<&OsStr>) -> TypeTally {
+        self.by_extension.get(&ext.map(OsStr::to_os_string)).copied().unwrap_or_default()
+    }
+
+    /// Tallies per extension, sorted by extension (entries without one first)
+    pub fn by_extension(&self) -> impl Iterator<Item = (Option<&OsStr>, TypeTally)> + '_ {
+        self.by_extension.iter().map(|(ext, tally)| (ext.as_deref(), *tally))
+    }
+
+    /// Returns `true` if no entries were tallied
+    pub fn is_empty(&self) -> bool {
+        self.by_file_type.is_empty()
+    }
+}
+
+impl<E> Extend<SlimEntry<E>> for TypeHistogram
+where
+    E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+{
+    fn extend<I: IntoIterator<Item = SlimEntry<E>>>(&mut self, iter: I) {
+        for entry in iter {
+            self.push(&entry);
+        }
+    }
+}
+
+impl<E> FromIterator<SlimEntry<E>> for TypeHistogram
+where
+    E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+{
+    fn from_iter<I: IntoIterator<Item = SlimEntry<E>>>(iter: I) -> Self {
+        let mut report = Self::new();
+        report.extend(iter);
+        report
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////////
+
+/// Convertor from RawDirEntry into SlimEntry, collecting into TypeHistogram
+#[derive(Debug, Default, Clone)]
+pub struct TypeHistogramProcessor {
+    inner: SlimContentProcessor,
+}
+
+impl<E> ContentProcessor<E> for TypeHistogramProcessor
+where
+    E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+{
+    type Item = SlimEntry<E>;
+    type Collection = TypeHistogram;
+
+    /// Convert RawDirEntry into final entry type (e.g. DirEntry)
+    fn process_root_direntry(
+        &self,
+        fsdent: &mut E::RootDirEntry,
+        follow_link: bool,
+        is_dir: bool,
+        depth: Depth,
+        ctx: &mut E::Context,
+    ) -> Option<Self::Item> {
+        self.inner.process_root_direntry(fsdent, follow_link, is_dir, depth, ctx)
+    }
+
+    /// Convert RawDirEntry into final entry type (e.g. DirEntry)
+    fn process_direntry(
+        &self,
+        fsdent: &mut E,
+        follow_link: bool,
+        is_dir: bool,
+        depth: Depth,
+        ctx: &mut E::Context,
+    ) -> Option<Self::Item> {
+        self.inner.process_direntry(fsdent, follow_link, is_dir, depth, ctx)
+    }
+
+    /// Check if final entry is dir
+    fn is_dir(item: &Self::Item) -> bool {
+        item.is_dir()
+    }
+
+    /// Replace path of final entry with its normalized form
+    fn normalize_path(item: &mut Self::Item, normalize: FnNormalize<E>) {
+        <SlimContentProcessor as ContentProcessor<E>>::normalize_path(item, normalize)
+    }
+
+    /// Collects iterator over items into collection
+    fn collect(&self, iter: impl Iterator<Item = Self::Item>) -> Self::Collection {
+        iter.collect()
+    }
+    /// Empty items collection
+    fn empty_collection() -> Self::Collection {
+        TypeHistogram::new()
+    }
+}
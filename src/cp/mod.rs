@@ -1,14 +1,28 @@
 mod dent;
+mod slim;
+#[cfg(feature = "arena")]
+mod arena;
+#[cfg(feature = "std")]
+mod histogram;
 
 use crate::fs;
-use crate::wd::Depth;
+use crate::wd::{Depth, FnNormalize, Origin};
 
 pub use dent::{DirEntry, DirEntryContentProcessor};
+#[cfg(feature = "std")]
+pub use dent::DirEntryOpenOptions;
+pub use slim::{SlimContentProcessor, SlimEntry, SlimFileType};
+#[cfg(feature = "arena")]
+pub use arena::{ArenaContentProcessor, ArenaEntries, ArenaEntry};
+#[cfg(feature = "std")]
+pub use histogram::{TypeHistogram, TypeHistogramProcessor, TypeTally};
+#[cfg(all(windows, feature = "windows-ext"))]
+pub use dent::WindowsDirEntryExt;
 
-use std::iter::FromIterator;
+use core::iter::FromIterator;
 
 /// Convertor from RawDirEntry into final entry type (e.g. DirEntry)
-pub trait ContentProcessor<E: fs::FsDirEntry>: std::fmt::Debug {
+pub trait ContentProcessor<E: fs::FsDirEntry>: core::fmt::Debug {
     /// Final entry type
     type Item;
     /// Collection of items
@@ -37,6 +51,27 @@ pub trait ContentProcessor<E: fs::FsDirEntry>: std::fmt::Debug {
     /// Check if final entry is dir
     fn is_dir(item: &Self::Item) -> bool;
 
+    /// Mark final entry as a dir whose content is skipped by sampling (does nothing by default)
+    fn mark_sampled_out(_item: &mut Self::Item) {}
+
+    /// Mark final entry as a dir announced before its content (does nothing by default)
+    fn mark_announcement(_item: &mut Self::Item) {}
+
+    /// Mark final entry as having a sibling with the same name under Unicode normalization (does nothing by default)
+    fn mark_unicode_collision(_item: &mut Self::Item) {}
+
+    /// Mark final entry as a mount point (does nothing by default)
+    fn mark_mount_point(_item: &mut Self::Item) {}
+
+    /// Attach the origin (root and followed link) to final entry (does nothing by default)
+    fn set_origin(_item: &mut Self::Item, _origin: &Origin<E::PathBuf>) {}
+
+    /// Attach the device of the root to final entry (does nothing by default)
+    fn set_device(_item: &mut Self::Item, _device: E::DeviceNum) {}
+
+    /// Replace path of final entry with its normalized form (does nothing by default)
+    fn normalize_path(_item: &mut Self::Item, _normalize: FnNormalize<E>) {}
+
     /// Collects iterator over items into collection
     fn collect(&self, iter: impl Iterator<Item = Self::Item>) -> Self::Collection;
     /// Empty items collection
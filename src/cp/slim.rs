@@ -0,0 +1,299 @@
+use crate::fs::{self, FsFileType, FsRootDirEntry, FsMetadata, FsPath, FsPathBuf};
+use crate::wd::{Depth, FnNormalize, IntoSome};
+use crate::cp::ContentProcessor;
+
+use core::fmt;
+use core::time::Duration;
+use alloc::borrow::Cow;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/////////////////////////////////////////////////////////////////////////////////
+
+/// Type of a [`SlimEntry`] packed into one byte.
+///
+/// [`SlimEntry`]: struct.SlimEntry.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SlimFileType(u8);
+
+impl SlimFileType {
+    const OTHER: u8 = 0;
+    const FILE: u8 = 1;
+    const DIR: u8 = 2;
+    const SYMLINK: u8 = 3;
+    const FIFO: u8 = 4;
+    const SOCKET: u8 = 5;
+    const BLOCK_DEVICE: u8 = 6;
+    const CHAR_DEVICE: u8 = 7;
+
+    /// Pack a file type of any backend
+    pub fn new<T: FsFileType>(ty: &T) -> Self {
+        Self(if ty.is_dir() {
+            Self::DIR
+        } else if ty.is_file() {
+            Self::FILE
+        } else if ty.is_symlink() {
+            Self::SYMLINK
+        } else if ty.is_fifo() {
+            Self::FIFO
+        } else if ty.is_socket() {
+            Self::SOCKET
+        } else if ty.is_block_device() {
+            Self::BLOCK_DEVICE
+        } else if ty.is_char_device() {
+            Self::CHAR_DEVICE
+        } else {
+            Self::OTHER
+        })
+    }
+}
+
+impl FsFileType for SlimFileType {
+    fn is_dir(&self) -> bool {
+        self.0 == Self::DIR
+    }
+    fn is_file(&self) -> bool {
+        self.0 == Self::FILE
+    }
+    fn is_symlink(&self) -> bool {
+        self.0 == Self::SYMLINK
+    }
+    fn is_fifo(&self) -> bool {
+        self.0 == Self::FIFO
+    }
+    fn is_socket(&self) -> bool {
+        self.0 == Self::SOCKET
+    }
+    fn is_block_device(&self) -> bool {
+        self.0 == Self::BLOCK_DEVICE
+    }
+    fn is_char_device(&self) -> bool {
+        self.0 == Self::CHAR_DEVICE
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////////
+
+/// A compact directory entry.
+///
+/// Keeps only the path, the type, the size and the modification time of an
+/// entry (64 bytes plus the path on 64-bit targets), so collecting large walks
+/// into memory takes several times less than with [`DirEntry`]. It is yielded
+/// by walkers made with [`SlimContentProcessor`]:
+///
+/// ```no_run
+/// use walkdir::{DefaultDirEntry, SlimContentProcessor, WalkDirBuilder};
+///
+/// let index: Vec<_> = WalkDirBuilder::<DefaultDirEntry, SlimContentProcessor>::new("foo")
+///     .into_classic()
+///     .filter_map(|e| e.ok())
+///     .collect();
+/// ```
+///
+/// As with [`DirEntry`], if [`follow_links`] was enabled, the type, the size
+/// and the modification time are of the link target.
+///
+/// [`DirEntry`]: struct.DirEntry.html
+/// [`SlimContentProcessor`]: struct.SlimContentProcessor.html
+/// [`follow_links`]: struct.WalkDir.html#method.follow_links
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct SlimEntry<E: fs::FsDirEntry = fs::DefaultDirEntry> {
+    /// Path of the entry
+    path: E::PathBuf,
+    /// Size in bytes
+    len: u64,
+    /// Modification time (since the Unix epoch)
+    modified: Option<Duration>,
+    /// The depth at which this entry was generated relative to the root.
+    depth: Depth,
+    /// Packed file type
+    file_type: SlimFileType,
+    /// Follow link
+    follow_link: bool,
+    /// Is normal dir
+    is_dir: bool,
+}
+
+/// A compact directory entry (there is no default backend without `std`).
+#[cfg(not(feature = "std"))]
+#[derive(Debug)]
+pub struct SlimEntry<E: fs::FsDirEntry> {
+    /// Path of the entry
+    path: E::PathBuf,
+    /// Size in bytes
+    len: u64,
+    /// Modification time (since the Unix epoch)
+    modified: Option<Duration>,
+    /// The depth at which this entry was generated relative to the root.
+    depth: Depth,
+    /// Packed file type
+    file_type: SlimFileType,
+    /// Follow link
+    follow_link: bool,
+    /// Is normal dir
+    is_dir: bool,
+}
+
+impl<E: fs::FsDirEntry> SlimEntry<E> {
+    fn new(
+        path: E::PathBuf,
+        metadata: Option<E::Metadata>,
+        follow_link: bool,
+        is_dir: bool,
+        depth: Depth,
+    ) -> Self {
+        let metadata = metadata.unwrap();
+        Self {
+            path,
+            len: metadata.len(),
+            modified: metadata.modified(),
+            depth,
+            file_type: SlimFileType::new(&metadata.file_type()),
+            follow_link,
+            is_dir,
+        }
+    }
+
+    /// The full path that this entry represents.
+    pub fn path(&self) -> &E::Path {
+        &self.path
+    }
+
+    /// The full path that this entry represents.
+    ///
+    /// Analogous to [`path`], but moves ownership of the path.
+    ///
+    /// [`path`]: #method.path
+    pub fn into_path(self) -> E::PathBuf {
+        self.path
+    }
+
+    /// Return the file name of this entry.
+    ///
+    /// If this entry has no file name (e.g., `/`), then the full path is
+    /// returned.
+    pub fn file_name(&self) -> &E::FileName {
+        self.path.file_name().unwrap_or_else(|| self.path.as_file_name())
+    }
+
+    /// Return the file type for the file that this entry points to.
+    pub fn file_type(&self) -> SlimFileType {
+        self.file_type
+    }
+
+    /// Returns `true` if and only if this entry was created from a symbolic
+    /// link. This is unaffected by the [`follow_links`] setting.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    pub fn path_is_symlink(&self) -> bool {
+        self.file_type.is_symlink() || self.follow_link
+    }
+
+    /// Size of the file in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the size is zero.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Last modification time (since the Unix epoch), if the backend reports it.
+    pub fn modified(&self) -> Option<Duration> {
+        self.modified
+    }
+
+    /// Returns the depth at which this entry was created relative to the root.
+    pub fn depth(&self) -> Depth {
+        self.depth
+    }
+
+    /// Returns true if and only if this entry points to a directory.
+    pub(crate) fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+}
+
+// Not derived, since the backend type itself isn't stored (and may be not `Clone`)
+impl<E: fs::FsDirEntry> Clone for SlimEntry<E> {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            len: self.len,
+            modified: self.modified,
+            depth: self.depth,
+            file_type: self.file_type,
+            follow_link: self.follow_link,
+            is_dir: self.is_dir,
+        }
+    }
+}
+
+impl<E: fs::FsDirEntry> fmt::Display for SlimEntry<E> {
+    /// Displays the path of this entry (lossily, as [`Path::display`] does).
+    ///
+    /// [`Path::display`]: https://doc.rust-lang.org/stable/std/path/struct.Path.html#method.display
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.path.display().fmt(f)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////////
+
+/// Convertor from RawDirEntry into SlimEntry
+#[derive(Debug, Default, Clone)]
+pub struct SlimContentProcessor {}
+
+impl<E: fs::FsDirEntry> ContentProcessor<E> for SlimContentProcessor {
+    type Item = SlimEntry<E>;
+    type Collection = Vec<SlimEntry<E>>;
+
+    /// Convert RawDirEntry into final entry type (e.g. DirEntry)
+    fn process_root_direntry(
+        &self,
+        fsdent: &mut E::RootDirEntry,
+        follow_link: bool,
+        is_dir: bool,
+        depth: Depth,
+        ctx: &mut E::Context,
+    ) -> Option<Self::Item> {
+        let (path, metadata) = fsdent.to_parts(follow_link, true, ctx);
+        SlimEntry::new(path, metadata, follow_link, is_dir, depth).into_some()
+    }
+
+    /// Convert RawDirEntry into final entry type (e.g. DirEntry)
+    fn process_direntry(
+        &self,
+        fsdent: &mut E,
+        follow_link: bool,
+        is_dir: bool,
+        depth: Depth,
+        ctx: &mut E::Context,
+    ) -> Option<Self::Item> {
+        let (path, metadata) = fsdent.to_parts(follow_link, true, ctx);
+        SlimEntry::new(path, metadata, follow_link, is_dir, depth).into_some()
+    }
+
+    /// Check if final entry is dir
+    fn is_dir(item: &Self::Item) -> bool {
+        item.is_dir()
+    }
+
+    /// Replace path of final entry with its normalized form
+    fn normalize_path(item: &mut Self::Item, normalize: FnNormalize<E>) {
+        if let Cow::Owned(path) = normalize(&item.path) {
+            item.path = path;
+        }
+    }
+
+    /// Collects iterator over items into collection
+    fn collect(&self, iter: impl Iterator<Item = Self::Item>) -> Self::Collection {
+        iter.collect()
+    }
+    /// Empty items collection
+    fn empty_collection() -> Self::Collection {
+        vec![]
+    }
+}
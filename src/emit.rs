@@ -0,0 +1,154 @@
+/*!
+Helpers writing the result of a walk in common interchange formats.
+
+* [`print0`] writes paths delimited with NUL bytes, like `find -print0` does, so
+  the output can be fed to `xargs -0` and similar tools.
+* [`json_lines`] writes one JSON object per line for each entry or error, which is
+  convenient for log pipelines.
+
+Both helpers consume a classic iterator (see [`into_classic`]) and return
+[`EmitStats`] with counts of written entries and met walk errors.
+
+[`print0`]: fn.print0.html
+[`json_lines`]: fn.json_lines.html
+[`into_classic`]: ../struct.WalkDirBuilder.html#method.into_classic
+[`EmitStats`]: struct.EmitStats.html
+*/
+
+use std::io;
+use std::path::Path;
+
+use crate::cp::DirEntry;
+use crate::fs::{self, FsFileType};
+use crate::wd;
+
+/// Counts of items written by emitting helpers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmitStats {
+    /// Count of written entries
+    pub entries: u64,
+    /// Count of walk errors
+    pub errors: u64,
+}
+
+/// Write path of every entry followed by a NUL byte.
+///
+/// Walk errors are not written (there is no way to tell them from paths in this
+/// format), they are only counted in the returned stats.
+///
+/// On Unix, paths are written as is. Elsewhere, paths which are not valid Unicode
+/// are converted lossily.
+pub fn print0<W, I, E>(mut wtr: W, walk: I) -> io::Result<EmitStats>
+where
+    W: io::Write,
+    I: IntoIterator<Item = wd::Result<DirEntry<E>, E>>,
+    E: fs::FsDirEntry<Path = Path>,
+{
+    let mut stats = EmitStats::default();
+    for result in walk {
+        match result {
+            Ok(dent) => {
+                write_path(&mut wtr, dent.path())?;
+                wtr.write_all(b"\0")?;
+                stats.entries += 1;
+            },
+            Err(_) => stats.errors += 1,
+        }
+    }
+    wtr.flush()?;
+    Ok(stats)
+}
+
+/// Write one JSON object per line for every entry and every walk error.
+///
+/// An entry is written as
+/// `{"path":"foo/bar","depth":1,"type":"dir","symlink":false}` where `type` is one
+/// of `dir`, `file`, `symlink` or `other`. An error is written as
+/// `{"error":"...","path":"foo/baz","depth":1}` where `path` is `null` if the error
+/// isn't associated with a path.
+///
+/// Paths which are not valid Unicode are converted lossily.
+pub fn json_lines<W, I, E>(mut wtr: W, walk: I) -> io::Result<EmitStats>
+where
+    W: io::Write,
+    I: IntoIterator<Item = wd::Result<DirEntry<E>, E>>,
+    E: fs::FsDirEntry<Path = Path>,
+{
+    let mut stats = EmitStats::default();
+    for result in walk {
+        match result {
+            Ok(dent) => {
+                let ty = dent.file_type();
+                let ty = if ty.is_dir() {
+                    "dir"
+                } else if ty.is_file() {
+                    "file"
+                } else if ty.is_symlink() {
+                    "symlink"
+                } else {
+                    "other"
+                };
+                wtr.write_all(b"{\"path\":")?;
+                write_json_str(&mut wtr, &dent.path().to_string_lossy())?;
+                writeln!(
+                    wtr,
+                    ",\"depth\":{},\"type\":\"{}\",\"symlink\":{}}}",
+                    dent.depth(),
+                    ty,
+                    dent.path_is_symlink()
+                )?;
+                stats.entries += 1;
+            },
+            Err(err) => {
+                wtr.write_all(b"{\"error\":")?;
+                write_json_str(&mut wtr, &err.to_string())?;
+                wtr.write_all(b",\"path\":")?;
+                match err.path() {
+                    Some(path) => write_json_str(&mut wtr, &path.to_string_lossy())?,
+                    None => wtr.write_all(b"null")?,
+                }
+                writeln!(wtr, ",\"depth\":{}}}", err.depth())?;
+                stats.errors += 1;
+            },
+        }
+    }
+    wtr.flush()?;
+    Ok(stats)
+}
+
+#[cfg(unix)]
+fn write_path<W: io::Write>(wtr: &mut W, path: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    wtr.write_all(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_path<W: io::Write>(wtr: &mut W, path: &Path) -> io::Result<()> {
+    wtr.write_all(path.to_string_lossy().as_bytes())
+}
+
+fn write_json_str<W: io::Write>(wtr: &mut W, s: &str) -> io::Result<()> {
+    wtr.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        let escaped = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            c if (c as u32) < 0x20 => "",
+            _ => continue,
+        };
+        wtr.write_all(&s.as_bytes()[start..i])?;
+        if escaped.is_empty() {
+            write!(wtr, "\\u{:04x}", c as u32)?;
+        } else {
+            wtr.write_all(escaped.as_bytes())?;
+        }
+        start = i + c.len_utf8();
+    }
+    wtr.write_all(&s.as_bytes()[start..])?;
+    wtr.write_all(b"\"")
+}
@@ -1,4 +1,4 @@
-use std::fmt;
+use core::fmt;
 
 use crate::fs;
 use crate::fs::{FsPath, FsPathBuf};
@@ -6,14 +6,23 @@ use crate::wd::Depth;
 
 /// An error produced by recursively walking a directory.
 ///
-/// This error type is a light wrapper around [`std::io::Error`]. In
-/// particular, it adds the following information:
+/// This error type is a light wrapper around the error type of the backend
+/// (`E::Error`, which is [`std::io::Error`] for the standard backends). The
+/// original error is available with [`backend_error`], so custom backends
+/// (e.g. over HTTP or SFTP) can pass status codes and protocol details to
+/// callers. In particular, it adds the following information:
 ///
 /// * The depth at which the error occurred in the file tree, relative to the
 /// root.
 /// * The path, if any, associated with the IO error.
 /// * An indication that a loop occurred when following symbolic links. In this
 /// case, there is no underlying IO error.
+/// * An indication that a path exceeded the limit set by `max_path_len`. In
+///   this case, there is no underlying IO error either.
+/// * A summary of the errors met while reading a directory, when
+///   `summarize_entry_errors` is enabled.
+/// * An indication that a special file was met while `special_files` is set
+///   to `SpecialFilePolicy::Error`.
 ///
 /// To maintain good ergonomics, this type has a
 /// [`impl From<Error> for std::io::Error`][impl] defined which preserves the original context.
@@ -23,16 +32,48 @@ use crate::wd::Depth;
 /// [`std::io::Error`]: https://doc.rust-lang.org/stable/std/io/struct.Error.html
 /// [`io::Result`]: https://doc.rust-lang.org/stable/std/io/type.Result.html
 /// [impl]: struct.Error.html#impl-From%3CError%3E
+/// [`backend_error`]: struct.Error.html#method.backend_error
 #[derive(Debug)]
 pub struct Error<E: fs::FsDirEntry> {
     inner: ErrorInner<E>,
     depth: Depth,
 }
 
+/// Kind of an [`Error`]
+///
+/// [`Error`]: struct.Error.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An error of the backend, see [`Error::backend_error`]
+    ///
+    /// [`Error::backend_error`]: struct.Error.html#method.backend_error
+    Io,
+    /// A file system loop, see [`Error::loop_ancestor`]
+    ///
+    /// [`Error::loop_ancestor`]: struct.Error.html#method.loop_ancestor
+    Loop,
+    /// A path longer than allowed by `max_path_len`, see [`Error::path_len`]
+    ///
+    /// [`Error::path_len`]: struct.Error.html#method.path_len
+    PathTooLong,
+    /// Some entries of a directory failed, see [`Error::entry_errors`]
+    ///
+    /// [`Error::entry_errors`]: struct.Error.html#method.entry_errors
+    PartialDir,
+    /// A FIFO, socket or device node met with `SpecialFilePolicy::Error`, see
+    /// [`Error::path`]
+    ///
+    /// [`Error::path`]: struct.Error.html#method.path
+    SpecialFile,
+}
+
 #[derive(Debug)]
 pub enum ErrorInner<E: fs::FsDirEntry> {
     Io { path: Option<E::PathBuf>, err: Option<E::Error> },
-    Loop { ancestor: E::PathBuf, child: E::PathBuf },
+    Loop { ancestor: E::PathBuf, ancestor_depth: Depth, child: E::PathBuf, child_depth: Depth },
+    PathTooLong { path: E::PathBuf, len: usize, max: usize },
+    PartialDir { path: E::PathBuf, errors: usize },
+    SpecialFile { path: E::PathBuf },
 }
 
 impl<E: fs::FsDirEntry> ErrorInner<E> {
@@ -48,8 +89,30 @@ impl<E: fs::FsDirEntry> ErrorInner<E> {
         Self::Io { path: None, err: Some(err) }
     }
 
-    pub(crate) fn from_loop(ancestor: &E::Path, child: &E::Path) -> Self {
-        Self::Loop { ancestor: ancestor.to_path_buf(), child: child.to_path_buf() }
+    pub(crate) fn from_loop(
+        ancestor: &E::Path,
+        ancestor_depth: Depth,
+        child: &E::Path,
+        child_depth: Depth,
+    ) -> Self {
+        Self::Loop {
+            ancestor: ancestor.to_path_buf(),
+            ancestor_depth,
+            child: child.to_path_buf(),
+            child_depth,
+        }
+    }
+
+    pub(crate) fn from_too_long(path: &E::Path, len: usize, max: usize) -> Self {
+        Self::PathTooLong { path: path.to_path_buf(), len, max }
+    }
+
+    pub(crate) fn from_partial_dir(path: &E::Path, errors: usize) -> Self {
+        Self::PartialDir { path: path.to_path_buf(), errors }
+    }
+
+    pub(crate) fn from_special_file(path: &E::Path) -> Self {
+        Self::SpecialFile { path: path.to_path_buf() }
     }
 
     pub fn take(&mut self) -> Self {
@@ -58,33 +121,53 @@ impl<E: fs::FsDirEntry> ErrorInner<E> {
                 path: path.clone(), 
                 err: err.take() 
             },
-            Self::Loop { ancestor, child } => Self::Loop { 
+            Self::Loop { ancestor, ancestor_depth, child, child_depth } => Self::Loop { 
                 ancestor: ancestor.clone(), 
-                child: child.clone() 
+                ancestor_depth: *ancestor_depth, 
+                child: child.clone(), 
+                child_depth: *child_depth 
+            },
+            Self::PathTooLong { path, len, max } => Self::PathTooLong { 
+                path: path.clone(), 
+                len: *len, 
+                max: *max 
+            },
+            Self::PartialDir { path, errors } => Self::PartialDir { 
+                path: path.clone(), 
+                errors: *errors 
+            },
+            Self::SpecialFile { path } => Self::SpecialFile { 
+                path: path.clone() 
             },
         }
     }
 }
 
-impl<E: fs::FsDirEntry> std::error::Error for Error<E> {
+impl<E: fs::FsDirEntry> core::error::Error for Error<E> {
     #[allow(deprecated)]
     fn description(&self) -> &str {
         match self.inner {
             ErrorInner::Io { err: Some(ref err), .. } => err.description(),
             ErrorInner::Io { err: None, .. } => "error was consumed before",
             ErrorInner::Loop { .. } => "file system loop found",
+            ErrorInner::PathTooLong { .. } => "path too long",
+            ErrorInner::PartialDir { .. } => "some directory entries failed",
+            ErrorInner::SpecialFile { .. } => "special file found",
         }
     }
 
-    fn cause(&self) -> Option<&dyn std::error::Error> {
+    fn cause(&self) -> Option<&dyn core::error::Error> {
         self.source()
     }
 
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
         match self.inner {
             ErrorInner::Io { err: Some(ref err), .. } => Some(err),
             ErrorInner::Io { err: None, .. } => None,
             ErrorInner::Loop { .. } => None,
+            ErrorInner::PathTooLong { .. } => None,
+            ErrorInner::PartialDir { .. } => None,
+            ErrorInner::SpecialFile { .. } => None,
         }
     }
 }
@@ -100,12 +183,32 @@ impl<E: fs::FsDirEntry> fmt::Display for Error<E> {
             ErrorInner::Io { path: Some(ref path), err: None } => {
                 write!(f, "IO error for operation on {}", path.display())
             }
-            ErrorInner::Loop { ref ancestor, ref child } => write!(
+            ErrorInner::Loop { ref ancestor, ancestor_depth, ref child, child_depth } => write!(
                 f,
                 "File system loop found: \
-                 {} points to an ancestor {}",
+                 {} at depth {} points to an ancestor {} at depth {}",
                 child.display(),
-                ancestor.display()
+                child_depth,
+                ancestor.display(),
+                ancestor_depth
+            ),
+            ErrorInner::PathTooLong { ref path, len, max } => write!(
+                f,
+                "Path too long ({} bytes, at most {} allowed): {}",
+                len,
+                max,
+                path.display()
+            ),
+            ErrorInner::PartialDir { ref path, errors } => write!(
+                f,
+                "Failed to read {} entries of {}",
+                errors,
+                path.display()
+            ),
+            ErrorInner::SpecialFile { ref path } => write!(
+                f,
+                "Special file found: {}",
+                path.display()
             ),
         }
     }
@@ -153,6 +256,20 @@ impl<E: fs::FsDirEntry> Error<E> {
             ErrorInner::Io { path: None, .. } => None,
             ErrorInner::Io { path: Some(ref path), .. } => Some(path),
             ErrorInner::Loop { ref child, .. } => Some(child),
+            ErrorInner::PathTooLong { ref path, .. } => Some(path),
+            ErrorInner::PartialDir { ref path, .. } => Some(path),
+            ErrorInner::SpecialFile { ref path } => Some(path),
+        }
+    }
+
+    /// Returns the kind of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self.inner {
+            ErrorInner::Io { .. } => ErrorKind::Io,
+            ErrorInner::Loop { .. } => ErrorKind::Loop,
+            ErrorInner::PathTooLong { .. } => ErrorKind::PathTooLong,
+            ErrorInner::PartialDir { .. } => ErrorKind::PartialDir,
+            ErrorInner::SpecialFile { .. } => ErrorKind::SpecialFile,
         }
     }
 
@@ -175,6 +292,65 @@ impl<E: fs::FsDirEntry> Error<E> {
         }
     }
 
+    /// Returns the depth of the ancestor returned by [`loop_ancestor`].
+    ///
+    /// If no cycle was detected, [`None`] is returned.
+    ///
+    /// [`loop_ancestor`]: struct.Error.html#method.loop_ancestor
+    /// [`None`]: https://doc.rust-lang.org/stable/std/option/enum.Option.html#variant.None
+    pub fn loop_ancestor_depth(&self) -> Option<Depth> {
+        match self.inner {
+            ErrorInner::Loop { ancestor_depth, .. } => Some(ancestor_depth),
+            _ => None,
+        }
+    }
+
+    /// Returns the path and the depth of the child directory entry which
+    /// links back to [`loop_ancestor`].
+    ///
+    /// If no cycle was detected, [`None`] is returned. Together with
+    /// [`loop_ancestor`] and [`loop_ancestor_depth`], this allows to report
+    /// e.g. "X at depth 2 links back to Y at depth 0" without parsing the
+    /// message.
+    ///
+    /// [`loop_ancestor`]: struct.Error.html#method.loop_ancestor
+    /// [`loop_ancestor_depth`]: struct.Error.html#method.loop_ancestor_depth
+    /// [`None`]: https://doc.rust-lang.org/stable/std/option/enum.Option.html#variant.None
+    pub fn loop_child(&self) -> Option<(&E::Path, Depth)> {
+        match self.inner {
+            ErrorInner::Loop { ref child, child_depth, .. } => Some((child, child_depth)),
+            _ => None,
+        }
+    }
+
+    /// Returns the length of the path and the limit it exceeded if this
+    /// error was produced because of `max_path_len`.
+    ///
+    /// Otherwise, [`None`] is returned.
+    ///
+    /// [`None`]: https://doc.rust-lang.org/stable/std/option/enum.Option.html#variant.None
+    pub fn path_len(&self) -> Option<(usize, usize)> {
+        match self.inner {
+            ErrorInner::PathTooLong { len, max, .. } => Some((len, max)),
+            _ => None,
+        }
+    }
+
+    /// Returns the count of failed entries of the directory (see [`path`])
+    /// if this error is a summary produced because of
+    /// `summarize_entry_errors`.
+    ///
+    /// Otherwise, [`None`] is returned.
+    ///
+    /// [`path`]: struct.Error.html#method.path
+    /// [`None`]: https://doc.rust-lang.org/stable/std/option/enum.Option.html#variant.None
+    pub fn entry_errors(&self) -> Option<usize> {
+        match self.inner {
+            ErrorInner::PartialDir { errors, .. } => Some(errors),
+            _ => None,
+        }
+    }
+
     /// Returns the depth at which this error occurred relative to the root.
     ///
     /// The smallest depth is `0` and always corresponds to the path given to
@@ -246,10 +422,7 @@ impl<E: fs::FsDirEntry> Error<E> {
     /// [`into_io_error`]: struct.Error.html#method.into_io_error
     /// [impl]: struct.Error.html#impl-From%3CError%3E
     pub fn io_error(&self) -> Option<&E::Error> {
-        match self.inner {
-            ErrorInner::Io { ref err, .. } => err.as_ref(),
-            ErrorInner::Loop { .. } => None,
-        }
+        self.backend_error()
     }
 
     /// Similar to [`io_error`] except consumes self to convert to the original
@@ -258,15 +431,53 @@ impl<E: fs::FsDirEntry> Error<E> {
     /// [`io_error`]: struct.Error.html#method.io_error
     /// [`io::Error`]: https://doc.rust-lang.org/stable/std/io/struct.Error.html
     pub fn into_io_error(self) -> Option<E::Error> {
+        self.into_backend_error()
+    }
+
+    /// Inspect the original error of the backend if there is one.
+    ///
+    /// This is the native error type of the backend (`E::Error`): an
+    /// [`io::Error`] for the standard backends, or e.g. an error carrying an
+    /// HTTP status code for a custom one. [`None`] is returned for errors
+    /// which were not produced by the backend, such as loops, too long
+    /// paths and summaries of failed entries (see [`kind`]).
+    ///
+    /// [`io::Error`]: https://doc.rust-lang.org/stable/std/io/struct.Error.html
+    /// [`None`]: https://doc.rust-lang.org/stable/std/option/enum.Option.html#variant.None
+    /// [`kind`]: struct.Error.html#method.kind
+    pub fn backend_error(&self) -> Option<&E::Error> {
+        match self.inner {
+            ErrorInner::Io { ref err, .. } => err.as_ref(),
+            ErrorInner::Loop { .. } => None,
+            ErrorInner::PathTooLong { .. } => None,
+            ErrorInner::PartialDir { .. } => None,
+            ErrorInner::SpecialFile { .. } => None,
+        }
+    }
+
+    /// Similar to [`backend_error`] except consumes self to return the
+    /// original error of the backend if one exists.
+    ///
+    /// [`backend_error`]: struct.Error.html#method.backend_error
+    pub fn into_backend_error(self) -> Option<E::Error> {
         match self.inner {
             ErrorInner::Io { err, .. } => err,
             ErrorInner::Loop { .. } => None,
+            ErrorInner::PathTooLong { .. } => None,
+            ErrorInner::PartialDir { .. } => None,
+            ErrorInner::SpecialFile { .. } => None,
         }
     }
 
     pub(crate) fn from_inner(inner: ErrorInner<E>, depth: Depth) -> Self {
         Self { inner, depth }
     }
+
+    /// Make the depth relative to an ancestor `depth` levels above the root
+    #[cfg(feature = "parallel")]
+    pub(crate) fn add_depth(&mut self, depth: Depth) {
+        self.depth += depth;
+    }
 }
 
 pub fn into_io_err<E: fs::FsDirEntry>(err: E::Error) -> ErrorInner<E> {
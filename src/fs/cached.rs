@@ -0,0 +1,766 @@
+use super::{FsDirEntry, FsFileType, FsMetadata, FsReadDirIterator, FsRootDirEntry};
+use super::standard::{StandardDirEntry, StandardDirFingerprint};
+use crate::wd::{DirId, IntoOk, IntoSome};
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+const CACHE_MAGIC: &[u8; 5] = b"WDRC\x02";
+
+/// Modification time of a dir as stored in cache: (seconds, nanoseconds) since UNIX epoch
+type CacheMtime = (u64, u32);
+
+#[derive(Debug, Clone)]
+struct CachedDir {
+    mtime: CacheMtime,
+    children: Vec<(OsString, CachedFileType)>,
+}
+
+/// A persistent cache of dir listings.
+///
+/// Every dir is keyed by its fingerprint (device and inode number on Unix, the path
+/// elsewhere) together with its modification time. A dir whose key and mtime are both
+/// found in the cache is not read again: its children names and types are taken from
+/// the cache instead.
+///
+/// This is correct only on file systems where dir mtime changes whenever an entry is
+/// added, removed or renamed (which is true for all common local file systems). Changes
+/// made within the mtime resolution of the file system may be missed.
+#[derive(Debug, Default)]
+pub struct ReadDirCache {
+    dirs: HashMap<Vec<u8>, CachedDir>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ReadDirCache {
+    /// Create new empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load cache previously stored with [`save`].
+    ///
+    /// [`save`]: #method.save
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut rdr = io::BufReader::new(std::fs::File::open(path)?);
+
+        let mut magic = [0u8; 5];
+        rdr.read_exact(&mut magic)?;
+        if &magic != CACHE_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a walkdir cache file"));
+        }
+
+        let mut dirs = HashMap::new();
+        let count = read_u64(&mut rdr)?;
+        for _ in 0..count {
+            let key = read_bytes(&mut rdr)?;
+            let mtime = (read_u64(&mut rdr)?, read_u32(&mut rdr)?);
+            let n = read_u32(&mut rdr)?;
+            // The count is untrusted: the vector grows only as children are read
+            let mut children = Vec::new();
+            for _ in 0..n {
+                let mut ty = [0u8; 1];
+                rdr.read_exact(&mut ty)?;
+                let name = bytes_to_os(read_bytes(&mut rdr)?)?;
+                children.push((name, CachedFileType::from_u8(ty[0])?));
+            }
+            dirs.insert(key, CachedDir { mtime, children });
+        }
+
+        Self { dirs, hits: 0, misses: 0 }.into_ok()
+    }
+
+    /// Store cache into file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut wtr = io::BufWriter::new(std::fs::File::create(path)?);
+
+        wtr.write_all(CACHE_MAGIC)?;
+        wtr.write_all(&(self.dirs.len() as u64).to_le_bytes())?;
+        for (key, dir) in &self.dirs {
+            write_bytes(&mut wtr, key)?;
+            wtr.write_all(&dir.mtime.0.to_le_bytes())?;
+            wtr.write_all(&dir.mtime.1.to_le_bytes())?;
+            wtr.write_all(&(dir.children.len() as u32).to_le_bytes())?;
+            for (name, ty) in &dir.children {
+                wtr.write_all(&[ty.to_u8()])?;
+                write_bytes(&mut wtr, &os_to_bytes(name))?;
+            }
+        }
+
+        wtr.flush()
+    }
+
+    /// Count of cached dirs
+    pub fn len(&self) -> usize {
+        self.dirs.len()
+    }
+
+    /// Is cache empty?
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// Count of dirs taken from cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Count of dirs really read
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Forget all cached dirs
+    pub fn clear(&mut self) {
+        self.dirs.clear();
+    }
+
+    fn lookup(&mut self, key: &[u8], mtime: CacheMtime) -> Option<Vec<(OsString, CachedFileType)>> {
+        match self.dirs.get(key) {
+            Some(dir) if dir.mtime == mtime => {
+                self.hits += 1;
+                dir.children.clone().into_some()
+            },
+            _ => {
+                self.misses += 1;
+                None
+            },
+        }
+    }
+
+    fn insert(&mut self, key: Vec<u8>, mtime: CacheMtime, children: Vec<(OsString, CachedFileType)>) {
+        self.dirs.insert(key, CachedDir { mtime, children });
+    }
+}
+
+fn read_u32<R: Read>(rdr: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    rdr.read_exact(&mut buf)?;
+    u32::from_le_bytes(buf).into_ok()
+}
+
+fn read_u64<R: Read>(rdr: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    rdr.read_exact(&mut buf)?;
+    u64::from_le_bytes(buf).into_ok()
+}
+
+// The length is untrusted, so the buffer is bounded by the remaining input
+// instead of being allocated in advance
+fn read_bytes<R: Read>(rdr: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u32(rdr)?;
+    let mut buf = Vec::new();
+    rdr.take(u64::from(len)).read_to_end(&mut buf)?;
+    if buf.len() != len as usize {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated walkdir cache file"));
+    }
+    buf.into_ok()
+}
+
+fn write_bytes<W: Write>(wtr: &mut W, bytes: &[u8]) -> io::Result<()> {
+    wtr.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    wtr.write_all(bytes)
+}
+
+#[cfg(unix)]
+fn os_to_bytes(s: &std::ffi::OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    s.as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn bytes_to_os(bytes: Vec<u8>) -> io::Result<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes).into_ok()
+}
+
+#[cfg(windows)]
+fn os_to_bytes(s: &std::ffi::OsStr) -> Vec<u8> {
+    use std::os::windows::ffi::OsStrExt;
+    s.encode_wide().flat_map(|w| w.to_le_bytes().to_vec()).collect()
+}
+
+#[cfg(windows)]
+fn bytes_to_os(bytes: Vec<u8>) -> io::Result<OsString> {
+    use std::os::windows::ffi::OsStringExt;
+    if bytes.len() % 2 != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "odd length of UTF-16 name"));
+    }
+    let wide: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    OsString::from_wide(&wide).into_ok()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn os_to_bytes(s: &std::ffi::OsStr) -> Vec<u8> {
+    s.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn bytes_to_os(bytes: Vec<u8>) -> io::Result<OsString> {
+    String::from_utf8(bytes)
+        .map(OsString::from)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Cache key for dir: its device and inode number
+#[cfg(unix)]
+fn dir_key(_path: &Path, md: &std::fs::Metadata) -> Vec<u8> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut key = md.dev().to_le_bytes().to_vec();
+    key.extend_from_slice(&md.ino().to_le_bytes());
+    key
+}
+
+/// Cache key for dir: its path
+#[cfg(not(unix))]
+fn dir_key(path: &Path, _md: &std::fs::Metadata) -> Vec<u8> {
+    os_to_bytes(path.as_os_str())
+}
+
+fn dir_mtime(md: &std::fs::Metadata) -> Option<CacheMtime> {
+    let since = md.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    (since.as_secs(), since.subsec_nanos()).into_some()
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Type of entry which can be stored in cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachedFileType {
+    /// A dir
+    Dir,
+    /// A regular file
+    File,
+    /// A symlink
+    Symlink,
+    /// A named pipe (FIFO)
+    Fifo,
+    /// A socket
+    Socket,
+    /// A block device
+    BlockDevice,
+    /// A character device
+    CharDevice,
+    /// Anything else
+    Other,
+}
+
+impl CachedFileType {
+    fn from_u8(v: u8) -> io::Result<Self> {
+        match v {
+            0 => Self::Dir,
+            1 => Self::File,
+            2 => Self::Symlink,
+            3 => Self::Other,
+            4 => Self::Fifo,
+            5 => Self::Socket,
+            6 => Self::BlockDevice,
+            7 => Self::CharDevice,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown file type in cache")),
+        }.into_ok()
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Dir => 0,
+            Self::File => 1,
+            Self::Symlink => 2,
+            Self::Other => 3,
+            Self::Fifo => 4,
+            Self::Socket => 5,
+            Self::BlockDevice => 6,
+            Self::CharDevice => 7,
+        }
+    }
+}
+
+impl From<std::fs::FileType> for CachedFileType {
+    fn from(ty: std::fs::FileType) -> Self {
+        if ty.is_symlink() {
+            Self::Symlink
+        } else if ty.is_dir() {
+            Self::Dir
+        } else if ty.is_file() {
+            Self::File
+        } else if FsFileType::is_fifo(&ty) {
+            Self::Fifo
+        } else if FsFileType::is_socket(&ty) {
+            Self::Socket
+        } else if FsFileType::is_block_device(&ty) {
+            Self::BlockDevice
+        } else if FsFileType::is_char_device(&ty) {
+            Self::CharDevice
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// Functions for FsFileType
+impl FsFileType for CachedFileType {
+    /// Is it dir?
+    fn is_dir(&self) -> bool {
+        *self == Self::Dir
+    }
+    /// Is it file
+    fn is_file(&self) -> bool {
+        *self == Self::File
+    }
+    /// Is it symlink
+    fn is_symlink(&self) -> bool {
+        *self == Self::Symlink
+    }
+    /// Is it named pipe (FIFO)
+    fn is_fifo(&self) -> bool {
+        *self == Self::Fifo
+    }
+    /// Is it socket
+    fn is_socket(&self) -> bool {
+        *self == Self::Socket
+    }
+    /// Is it block device
+    fn is_block_device(&self) -> bool {
+        *self == Self::BlockDevice
+    }
+    /// Is it character device
+    fn is_char_device(&self) -> bool {
+        *self == Self::CharDevice
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Metadata of CachedDirEntry (always read from fs)
+#[derive(Debug, Clone)]
+pub struct CachedMetadata {
+    inner: std::fs::Metadata,
+}
+
+impl CachedMetadata {
+    /// Get inner fs object
+    pub fn inner(&self) -> &std::fs::Metadata {
+        &self.inner
+    }
+}
+
+/// Functions for FsMetadata
+impl FsMetadata for CachedMetadata {
+    type FileType = CachedFileType;
+
+    /// Get type of this entry
+    fn file_type(&self) -> CachedFileType {
+        self.inner.file_type().into()
+    }
+
+    /// Get size of this entry in bytes
+    fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    /// Get last modification time
+    fn modified(&self) -> Option<core::time::Duration> {
+        FsMetadata::modified(&self.inner)
+    }
+
+    /// Get count of bytes allocated on disk
+    fn allocated_size(&self) -> Option<u64> {
+        self.inner.allocated_size()
+    }
+
+    /// Is this a cloud placeholder?
+    fn is_placeholder(&self) -> bool {
+        self.inner.is_placeholder()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+enum CachedReadDirInner {
+    /// Dir is really read. Its content is recorded to be stored in cache on completion.
+    Live {
+        rd: std::fs::ReadDir,
+        key: Vec<u8>,
+        mtime: Option<CacheMtime>,
+        children: Vec<(OsString, CachedFileType)>,
+        complete: bool,
+    },
+    /// Dir content is taken from cache.
+    Cached {
+        parent: PathBuf,
+        children: std::vec::IntoIter<(OsString, CachedFileType)>,
+    },
+}
+
+/// A FsReadDir implementation serving dir content from ReadDirCache when possible
+#[derive(Debug)]
+pub struct CachedReadDir {
+    inner: CachedReadDirInner,
+}
+
+impl CachedReadDir {
+    fn from_path(path: &Path, cache: &mut ReadDirCache) -> io::Result<Self> {
+        let md = std::fs::metadata(path)?;
+        let key = dir_key(path, &md);
+        let mtime = dir_mtime(&md);
+
+        if let Some(mtime) = mtime {
+            if let Some(children) = cache.lookup(&key, mtime) {
+                return Self {
+                    inner: CachedReadDirInner::Cached {
+                        parent: path.to_path_buf(),
+                        children: children.into_iter(),
+                    },
+                }.into_ok();
+            }
+        }
+
+        Self {
+            inner: CachedReadDirInner::Live {
+                rd: std::fs::read_dir(path)?,
+                key,
+                mtime,
+                children: vec![],
+                complete: true,
+            },
+        }.into_ok()
+    }
+
+    /// Is dir content taken from cache?
+    pub fn is_cached(&self) -> bool {
+        match self.inner {
+            CachedReadDirInner::Live { .. } => false,
+            CachedReadDirInner::Cached { .. } => true,
+        }
+    }
+}
+
+impl FsReadDirIterator for CachedReadDir {
+    type Context    = ReadDirCache;
+    type Error      = std::io::Error;
+    type DirEntry   = CachedDirEntry;
+
+    fn next_entry(
+        &mut self,
+        ctx: &mut Self::Context,
+    ) -> Option<Result<Self::DirEntry, Self::Error>> {
+        match self.inner {
+            CachedReadDirInner::Live { ref mut rd, ref key, mtime, ref mut children, ref mut complete } => {
+                let dent = match rd.next() {
+                    Some(Ok(dent)) => dent,
+                    Some(Err(err)) => {
+                        *complete = false;
+                        return Err(err).into_some();
+                    },
+                    None => {
+                        if let (true, Some(mtime)) = (*complete, mtime) {
+                            ctx.insert(key.clone(), mtime, std::mem::take(children));
+                        }
+                        *complete = false;
+                        return None;
+                    },
+                };
+
+                let ty: CachedFileType = match dent.file_type() {
+                    Ok(ty) => ty.into(),
+                    Err(err) => {
+                        *complete = false;
+                        return Err(err).into_some();
+                    },
+                };
+                let file_name = dent.file_name();
+                children.push((file_name.clone(), ty));
+
+                CachedDirEntry { pathbuf: dent.path(), file_name, ty }.into_ok().into_some()
+            },
+            CachedReadDirInner::Cached { ref parent, ref mut children } => {
+                let (file_name, ty) = children.next()?;
+                CachedDirEntry { pathbuf: parent.join(&file_name), file_name, ty }.into_ok().into_some()
+            },
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(unix)]
+type CachedDeviceNum = u64;
+#[cfg(not(unix))]
+type CachedDeviceNum = ();
+
+/// A FsDirEntry implementation which doesn't read unmodified dirs twice (see [`ReadDirCache`])
+///
+/// [`ReadDirCache`]: struct.ReadDirCache.html
+#[derive(Debug)]
+pub struct CachedDirEntry {
+    pathbuf:    PathBuf,
+    file_name:  OsString,
+    ty:         CachedFileType,
+}
+
+impl CachedDirEntry {
+    #[cfg(unix)]
+    fn device_num_from_path(path: &Path) -> io::Result<CachedDeviceNum> {
+        use std::os::unix::fs::MetadataExt;
+
+        path.metadata().map(|md| md.dev())
+    }
+
+    #[cfg(not(unix))]
+    fn device_num_from_path(path: &Path) -> io::Result<CachedDeviceNum> {
+        StandardDirEntry::device_num_from_path(path)
+    }
+
+    fn metadata_from_path(path: &Path, follow_link: bool) -> io::Result<CachedMetadata> {
+        CachedMetadata { inner: StandardDirEntry::metadata_from_path(path, follow_link)? }.into_ok()
+    }
+}
+
+/// Functions for FsDirEntry
+impl FsDirEntry for CachedDirEntry {
+    type Context        = ReadDirCache;
+
+    type Path           = Path;
+    type PathBuf        = PathBuf;
+    type FileName       = OsStr;
+
+    type Error          = std::io::Error;
+    type FileType       = CachedFileType;
+    type Metadata       = CachedMetadata;
+    type ReadDir        = CachedReadDir;
+    type DirFingerprint = StandardDirFingerprint;
+    type DeviceNum      = CachedDeviceNum;
+    type RootDirEntry   = CachedRootDirEntry;
+    type Reader         = std::fs::File;
+
+    /// Get path of this entry
+    fn path(&self) -> &Self::Path {
+        &self.pathbuf
+    }
+    /// Get path of this entry
+    fn pathbuf(&self) -> Self::PathBuf {
+        self.pathbuf.clone()
+    }
+    /// Get path of this entry
+    fn canonicalize(&self) -> Result<Self::PathBuf, Self::Error> {
+        StandardDirEntry::canonicalize_from_path(&self.pathbuf)
+    }
+    fn canonicalize_path(path: &Self::Path) -> Result<Self::PathBuf, Self::Error> {
+        StandardDirEntry::canonicalize_from_path(path)
+    }
+    fn metadata_path(path: &Self::Path, follow_link: bool) -> Result<Self::Metadata, Self::Error> {
+        Self::metadata_from_path(path, follow_link)
+    }
+    fn open_path(path: &Self::Path) -> Result<Self::Reader, Self::Error> {
+        StandardDirEntry::open_path(path)
+    }
+    #[cfg(unix)]
+    fn dir_id_path(path: &Self::Path) -> Result<Option<DirId>, Self::Error> {
+        StandardDirEntry::dir_id_from_path(path)
+    }
+    fn file_name(&self) -> &Self::FileName {
+        &self.file_name
+    }
+
+    /// Get file type
+    fn file_type(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> Result<Self::FileType, Self::Error> {
+        if !follow_link || !self.ty.is_symlink() {
+            return self.ty.into_ok();
+        };
+
+        let metadata = self.metadata(follow_link, ctx)?;
+        metadata.file_type().into_ok()
+    }
+
+    /// Get metadata
+    fn metadata(
+        &self,
+        follow_link: bool,
+        _ctx: &mut Self::Context,
+    ) -> Result<Self::Metadata, Self::Error> {
+        Self::metadata_from_path(&self.pathbuf, follow_link)
+    }
+
+    /// Read dir
+    fn read_dir(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<Self::ReadDir, Self::Error> {
+        CachedReadDir::from_path(&self.pathbuf, ctx)
+    }
+
+    /// Return the unique handle
+    fn fingerprint(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<Self::DirFingerprint, Self::Error> {
+        StandardDirEntry::fingerprint_from_path(&self.pathbuf)
+    }
+
+    fn is_same(
+        lhs: (&Self::Path, &Self::DirFingerprint),
+        rhs: (&Self::Path, &Self::DirFingerprint),
+    ) -> bool {
+        lhs.1 == rhs.1
+    }
+
+    /// device_num
+    fn device_num(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<Self::DeviceNum, Self::Error> {
+        Self::device_num_from_path(&self.pathbuf)
+    }
+
+    #[cfg(all(unix, feature = "xattr"))]
+    fn has_xattr(
+        &self,
+        name: &str,
+        follow_link: bool,
+        _ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        StandardDirEntry::has_xattr_from_path(&self.pathbuf, name, follow_link)
+    }
+
+    #[cfg(windows)]
+    fn is_placeholder(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        Ok(self.metadata(false, ctx)?.is_placeholder())
+    }
+
+    fn is_binary(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        super::standard::sniff_binary(self, ctx)
+    }
+
+    #[cfg(all(feature = "linux-flags", target_os = "linux"))]
+    fn is_nodump(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        super::linux::is_nodump(self, follow_link, ctx)
+    }
+
+    fn is_case_insensitive() -> bool {
+        StandardDirEntry::is_case_insensitive()
+    }
+
+    fn to_parts(
+        &mut self,
+        follow_link: bool,
+        force_metadata: bool,
+        ctx: &mut Self::Context,
+    ) -> (Self::PathBuf, Option<Self::Metadata>) {
+        let md = if force_metadata {self.metadata(follow_link, ctx).ok()} else {None};
+        (self.pathbuf.clone(), md)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A FsRootDirEntry implementation for CachedDirEntry
+#[derive(Debug)]
+pub struct CachedRootDirEntry {
+    pathbuf:    PathBuf,
+}
+
+/// Functions for FsDirEntry
+impl FsRootDirEntry for CachedRootDirEntry {
+    type Context    = ReadDirCache;
+    type DirEntry   = CachedDirEntry;
+
+    fn from_path(
+        path: &Path,
+        _ctx: &mut Self::Context,
+    ) -> Result<Self, io::Error> {
+        Self {
+            pathbuf: path.to_path_buf(),
+        }.into_ok()
+    }
+
+    /// Get path of this entry
+    fn path(&self) -> &Path {
+        &self.pathbuf
+    }
+    /// Get path of this entry
+    fn pathbuf(&self) -> PathBuf {
+        self.pathbuf.clone()
+    }
+    /// Get path of this entry
+    fn canonicalize(&self) -> Result<PathBuf, io::Error> {
+        StandardDirEntry::canonicalize_from_path(&self.pathbuf)
+    }
+
+    fn file_name(&self) -> &OsStr {
+        StandardDirEntry::file_name_from_path(&self.pathbuf)
+    }
+
+    /// Get file type
+    fn file_type(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> Result<CachedFileType, io::Error> {
+        let metadata = self.metadata(follow_link, ctx)?;
+        metadata.file_type().into_ok()
+    }
+
+    /// Get metadata
+    fn metadata(
+        &self,
+        follow_link: bool,
+        _ctx: &mut Self::Context,
+    ) -> Result<CachedMetadata, io::Error> {
+        CachedDirEntry::metadata_from_path(&self.pathbuf, follow_link)
+    }
+
+    /// Read dir
+    fn read_dir(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<CachedReadDir, io::Error> {
+        CachedReadDir::from_path(&self.pathbuf, ctx)
+    }
+
+    /// Return the unique handle
+    fn fingerprint(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<StandardDirFingerprint, io::Error> {
+        StandardDirEntry::fingerprint_from_path(&self.pathbuf)
+    }
+
+    /// device_num
+    fn device_num(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<CachedDeviceNum, io::Error> {
+        CachedDirEntry::device_num_from_path(&self.pathbuf)
+    }
+
+    fn to_parts(
+        &mut self,
+        follow_link: bool,
+        force_metadata: bool,
+        ctx: &mut Self::Context,
+    ) -> (PathBuf, Option<CachedMetadata>) {
+        let md = if force_metadata {self.metadata(follow_link, ctx).ok()} else {None};
+        (self.pathbuf.clone(), md)
+    }
+}
@@ -0,0 +1,490 @@
+use super::{FsDirEntry, FsReadDirIterator, FsRootDirEntry};
+use crate::rng::Rng;
+use crate::wd::{CachePolicy, DirId, IntoErr, IntoOk, IntoSome, LinkKind};
+
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Kind of operation which may fail in [`FlakyFs`]
+///
+/// [`FlakyFs`]: struct.FlakyFs.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlakyOp {
+    /// Opening a dir
+    ReadDir,
+    /// Reading next entry of an opened dir
+    NextEntry,
+    /// Querying metadata (including file type of followed links)
+    Metadata,
+    /// Getting fingerprint of a dir (used for loop detection)
+    Fingerprint,
+    /// Getting device number (used by `same_file_system`)
+    DeviceNum,
+    /// Opening a file for reading
+    Open,
+}
+
+/// Context of [`FlakyFs`]: the inner backend context and the failure rules.
+///
+/// By default nothing fails. Operations fail either randomly (see [`fail_percent`],
+/// the sequence of failures is determined by [`seed`]) or always for the given
+/// paths (see [`fail_path`]).
+///
+/// [`FlakyFs`]: struct.FlakyFs.html
+/// [`fail_percent`]: #method.fail_percent
+/// [`seed`]: #method.seed
+/// [`fail_path`]: #method.fail_path
+#[derive(Debug)]
+pub struct FlakyContext<C> {
+    inner:      C,
+    rng:        Rng,
+    percent:    u32,
+    kind:       io::ErrorKind,
+    paths:      Vec<(PathBuf, io::ErrorKind)>,
+    ops:        Vec<FlakyOp>,
+    failures:   usize,
+}
+
+impl<C: Default> Default for FlakyContext<C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
+
+impl<C> FlakyContext<C> {
+    /// Make context over the inner backend context
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            rng: Rng::new(0),
+            percent: 0,
+            kind: io::ErrorKind::Other,
+            paths: vec![],
+            ops: vec![],
+            failures: 0,
+        }
+    }
+
+    /// Fail `percent`% of operations with errors of given kind
+    pub fn fail_percent(mut self, percent: u32, kind: io::ErrorKind) -> Self {
+        self.percent = percent.min(100);
+        self.kind = kind;
+        self
+    }
+
+    /// Always fail operations on `path` with errors of given kind
+    pub fn fail_path<P: Into<PathBuf>>(mut self, path: P, kind: io::ErrorKind) -> Self {
+        self.paths.push((path.into(), kind));
+        self
+    }
+
+    /// Fail only given operations (all operations by default)
+    pub fn only_ops(mut self, ops: &[FlakyOp]) -> Self {
+        self.ops = ops.to_vec();
+        self
+    }
+
+    /// Seed of random failures
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Get inner backend context
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Get inner backend context
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Count of injected failures so far
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+
+    fn next_percent(&mut self) -> u32 {
+        self.rng.below(100) as u32
+    }
+
+    /// Err if operation `op` on `path` must fail
+    fn check(&mut self, op: FlakyOp, path: &Path) -> io::Result<()> {
+        if !self.ops.is_empty() && !self.ops.contains(&op) {
+            return Ok(());
+        }
+
+        let kind = match self.paths.iter().find(|(p, _)| p == path).map(|(_, kind)| *kind) {
+            Some(kind) => kind,
+            None if self.percent > 0 && self.next_percent() < self.percent => self.kind,
+            None => return Ok(()),
+        };
+
+        self.failures += 1;
+        io::Error::new(kind, format!("injected {:?} failure at {}", op, path.display())).into_err()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A FsReadDir implementation for FlakyFs
+#[derive(Debug)]
+pub struct FlakyReadDir<E: FsDirEntry> {
+    inner:  E::ReadDir,
+}
+
+impl<E> FsReadDirIterator for FlakyReadDir<E>
+where
+    E: FsDirEntry<Path = Path, PathBuf = PathBuf, FileName = OsStr, Error = io::Error>,
+{
+    type Context    = FlakyContext<E::Context>;
+    type Error      = io::Error;
+    type DirEntry   = FlakyFs<E>;
+
+    fn next_entry(
+        &mut self,
+        ctx: &mut Self::Context,
+    ) -> Option<Result<Self::DirEntry, Self::Error>> {
+        let inner = match self.inner.next_entry(&mut ctx.inner)? {
+            Ok(inner) => inner,
+            Err(err) => return Err(err).into_some(),
+        };
+        if let Err(err) = ctx.check(FlakyOp::NextEntry, inner.path()) {
+            return Err(err).into_some();
+        }
+        FlakyFs { inner }.into_ok().into_some()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A FsDirEntry implementation wrapping any other backend and failing its operations on
+/// demand (see [`FlakyContext`]).
+///
+/// [`FlakyContext`]: struct.FlakyContext.html
+#[derive(Debug)]
+pub struct FlakyFs<E> {
+    inner: E,
+}
+
+impl<E> FlakyFs<E> {
+    /// Get wrapped entry
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+}
+
+/// Functions for FsDirEntry
+impl<E> FsDirEntry for FlakyFs<E>
+where
+    E: FsDirEntry<Path = Path, PathBuf = PathBuf, FileName = OsStr, Error = io::Error>,
+{
+    type Context        = FlakyContext<E::Context>;
+
+    type Path           = Path;
+    type PathBuf        = PathBuf;
+    type FileName       = OsStr;
+
+    type Error          = io::Error;
+    type FileType       = E::FileType;
+    type Metadata       = E::Metadata;
+    type ReadDir        = FlakyReadDir<E>;
+    type DirFingerprint = E::DirFingerprint;
+    type DeviceNum      = E::DeviceNum;
+    type RootDirEntry   = FlakyRootDirEntry<E>;
+    type Reader         = E::Reader;
+
+    /// Get path of this entry
+    fn path(&self) -> &Path {
+        self.inner.path()
+    }
+    /// Get path of this entry
+    fn pathbuf(&self) -> PathBuf {
+        self.inner.pathbuf()
+    }
+    /// Get path of this entry
+    fn canonicalize(&self) -> io::Result<PathBuf> {
+        self.inner.canonicalize()
+    }
+    fn canonicalize_path(path: &Path) -> io::Result<PathBuf> {
+        E::canonicalize_path(path)
+    }
+    fn metadata_path(path: &Path, follow_link: bool) -> io::Result<Self::Metadata> {
+        // Without a context nothing can be injected
+        E::metadata_path(path, follow_link)
+    }
+    fn allocated_size_path(path: &Path) -> Option<u64> {
+        E::allocated_size_path(path)
+    }
+    fn dir_id_path(path: &Path) -> io::Result<Option<DirId>> {
+        // Without a context nothing can be injected
+        E::dir_id_path(path)
+    }
+    fn open_path(path: &Path) -> io::Result<Self::Reader> {
+        // Without a context nothing can be injected
+        E::open_path(path)
+    }
+    fn file_name(&self) -> &Self::FileName {
+        self.inner.file_name()
+    }
+
+    /// Get file type
+    fn file_type(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::FileType> {
+        if follow_link {
+            ctx.check(FlakyOp::Metadata, self.path())?;
+        }
+        self.inner.file_type(follow_link, &mut ctx.inner)
+    }
+
+    /// Get metadata
+    fn metadata(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::Metadata> {
+        ctx.check(FlakyOp::Metadata, self.path())?;
+        self.inner.metadata(follow_link, &mut ctx.inner)
+    }
+
+    /// Read dir
+    fn read_dir(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::ReadDir> {
+        ctx.check(FlakyOp::ReadDir, self.path())?;
+        FlakyReadDir {
+            inner: self.inner.read_dir(&mut ctx.inner)?,
+        }.into_ok()
+    }
+
+    /// Open file
+    fn open(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::Reader> {
+        ctx.check(FlakyOp::Open, self.path())?;
+        self.inner.open(&mut ctx.inner)
+    }
+
+    /// Return the unique handle
+    fn fingerprint(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::DirFingerprint> {
+        ctx.check(FlakyOp::Fingerprint, self.path())?;
+        self.inner.fingerprint(&mut ctx.inner)
+    }
+
+    fn is_same(
+        lhs: (&Path, &Self::DirFingerprint),
+        rhs: (&Path, &Self::DirFingerprint),
+    ) -> bool {
+        E::is_same(lhs, rhs)
+    }
+
+    /// device_num
+    fn device_num(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::DeviceNum> {
+        ctx.check(FlakyOp::DeviceNum, self.path())?;
+        self.inner.device_num(&mut ctx.inner)
+    }
+
+    fn is_mount_point(
+        &self,
+        parent_device: &Self::DeviceNum,
+        ctx: &mut Self::Context,
+    ) -> io::Result<bool> {
+        ctx.check(FlakyOp::DeviceNum, self.path())?;
+        self.inner.is_mount_point(parent_device, &mut ctx.inner)
+    }
+
+    fn has_xattr(
+        &self,
+        name: &str,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<bool> {
+        ctx.check(FlakyOp::Metadata, self.path())?;
+        self.inner.has_xattr(name, follow_link, &mut ctx.inner)
+    }
+
+    fn link_kind(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Option<LinkKind>> {
+        self.inner.link_kind(&mut ctx.inner)
+    }
+
+    fn alternate_streams(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Vec<PathBuf>> {
+        ctx.check(FlakyOp::ReadDir, self.path())?;
+        self.inner.alternate_streams(&mut ctx.inner)
+    }
+
+    fn is_placeholder(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<bool> {
+        ctx.check(FlakyOp::Metadata, self.path())?;
+        self.inner.is_placeholder(&mut ctx.inner)
+    }
+
+    fn is_binary(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<bool> {
+        ctx.check(FlakyOp::Open, self.path())?;
+        self.inner.is_binary(&mut ctx.inner)
+    }
+
+    fn is_nodump(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<bool> {
+        ctx.check(FlakyOp::Metadata, self.path())?;
+        self.inner.is_nodump(follow_link, &mut ctx.inner)
+    }
+
+    fn is_case_insensitive() -> bool {
+        E::is_case_insensitive()
+    }
+
+    fn set_readdir_batch(
+        ctx: &mut Self::Context,
+        batch: usize,
+    ) {
+        E::set_readdir_batch(&mut ctx.inner, batch);
+    }
+
+    fn set_metadata_cache(
+        ctx: &mut Self::Context,
+        policy: CachePolicy,
+    ) {
+        E::set_metadata_cache(&mut ctx.inner, policy);
+    }
+
+    fn to_parts(
+        &mut self,
+        follow_link: bool,
+        force_metadata: bool,
+        ctx: &mut Self::Context,
+    ) -> (PathBuf, Option<Self::Metadata>) {
+        // Never fails: entries are converted only after all checks were passed
+        self.inner.to_parts(follow_link, force_metadata, &mut ctx.inner)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A FsRootDirEntry implementation for FlakyFs
+#[derive(Debug)]
+pub struct FlakyRootDirEntry<E: FsDirEntry> {
+    inner: E::RootDirEntry,
+}
+
+/// Functions for FsRootDirEntry
+impl<E> FsRootDirEntry for FlakyRootDirEntry<E>
+where
+    E: FsDirEntry<Path = Path, PathBuf = PathBuf, FileName = OsStr, Error = io::Error>,
+{
+    type Context    = FlakyContext<E::Context>;
+    type DirEntry   = FlakyFs<E>;
+
+    fn from_path(
+        path: &Path,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self> {
+        Self {
+            inner: E::RootDirEntry::from_path(path, &mut ctx.inner)?,
+        }.into_ok()
+    }
+
+    /// Get path of this entry
+    fn path(&self) -> &Path {
+        self.inner.path()
+    }
+    /// Get path of this entry
+    fn pathbuf(&self) -> PathBuf {
+        self.inner.pathbuf()
+    }
+    /// Get path of this entry
+    fn canonicalize(&self) -> io::Result<PathBuf> {
+        self.inner.canonicalize()
+    }
+
+    fn file_name(&self) -> &OsStr {
+        self.inner.file_name()
+    }
+
+    /// Get file type
+    fn file_type(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<E::FileType> {
+        ctx.check(FlakyOp::Metadata, self.path())?;
+        self.inner.file_type(follow_link, &mut ctx.inner)
+    }
+
+    /// Get metadata
+    fn metadata(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<E::Metadata> {
+        ctx.check(FlakyOp::Metadata, self.path())?;
+        self.inner.metadata(follow_link, &mut ctx.inner)
+    }
+
+    /// Read dir
+    fn read_dir(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<FlakyReadDir<E>> {
+        ctx.check(FlakyOp::ReadDir, self.path())?;
+        FlakyReadDir {
+            inner: self.inner.read_dir(&mut ctx.inner)?,
+        }.into_ok()
+    }
+
+    /// Return the unique handle
+    fn fingerprint(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<E::DirFingerprint> {
+        ctx.check(FlakyOp::Fingerprint, self.path())?;
+        self.inner.fingerprint(&mut ctx.inner)
+    }
+
+    /// device_num
+    fn device_num(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<E::DeviceNum> {
+        ctx.check(FlakyOp::DeviceNum, self.path())?;
+        self.inner.device_num(&mut ctx.inner)
+    }
+
+    fn to_parts(
+        &mut self,
+        follow_link: bool,
+        force_metadata: bool,
+        ctx: &mut Self::Context,
+    ) -> (PathBuf, Option<E::Metadata>) {
+        // Never fails: entries are converted only after all checks were passed
+        self.inner.to_parts(follow_link, force_metadata, &mut ctx.inner)
+    }
+}
@@ -0,0 +1,85 @@
+use std::io;
+use std::path::Path;
+
+/// Inode flags of a file on Linux (as shown by `lsattr`)
+///
+/// Only flags which matter to backup tools have accessors, others are available
+/// with [`bits`].
+///
+/// [`bits`]: #method.bits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FileFlags(u32);
+
+impl FileFlags {
+    /// The file can't be modified, removed or linked to (`chattr +i`)
+    pub const IMMUTABLE: u32 = 0x0000_0010;
+    /// The file can only be opened for appending (`chattr +a`)
+    pub const APPEND_ONLY: u32 = 0x0000_0020;
+    /// The file must not be backed up by `dump` (`chattr +d`)
+    pub const NODUMP: u32 = 0x0000_0040;
+
+    /// Make flags from raw `FS_IOC_GETFLAGS` bits
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Raw `FS_IOC_GETFLAGS` bits
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// The file is immutable
+    pub fn is_immutable(&self) -> bool {
+        self.0 & Self::IMMUTABLE != 0
+    }
+
+    /// The file is append-only
+    pub fn is_append_only(&self) -> bool {
+        self.0 & Self::APPEND_ONLY != 0
+    }
+
+    /// The file is excluded from backups
+    pub fn is_nodump(&self) -> bool {
+        self.0 & Self::NODUMP != 0
+    }
+}
+
+/// Query flags of a regular file or a dir (symlinks are followed).
+///
+/// The file is opened for this, so it must not be used on devices or FIFOs. File
+/// systems without inode flags report none.
+pub(crate) fn file_flags_from_path(path: &Path) -> io::Result<FileFlags> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+
+    let mut bits: libc::c_int = 0;
+    // The kernel reads and writes an int, despite the declared type of the ioctl
+    let res = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut bits) };
+    if res < 0 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ENOTTY) | Some(libc::EOPNOTSUPP) | Some(libc::EINVAL) => Ok(FileFlags::default()),
+            _ => Err(err),
+        };
+    }
+    Ok(FileFlags(bits as u32))
+}
+
+/// Check if an entry is excluded from backups (only regular files and dirs are queried)
+pub(crate) fn is_nodump<E>(ent: &E, follow_link: bool, ctx: &mut E::Context) -> io::Result<bool>
+where
+    E: super::FsDirEntry<Path = Path, Error = io::Error>,
+{
+    use super::FsFileType;
+
+    let ty = ent.file_type(follow_link, ctx)?;
+    if !ty.is_dir() && !ty.is_file() {
+        return Ok(false);
+    }
+    file_flags_from_path(ent.path()).map(|flags| flags.is_nodump())
+}
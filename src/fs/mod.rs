@@ -1,29 +1,55 @@
-use std::ops::Deref;
-use std::fmt::Debug;
+use core::ops::Deref;
+use core::fmt::Debug;
+use alloc::borrow::ToOwned;
 
 mod path;
+mod url;
+#[cfg(feature = "std")]
 mod standard;
-#[cfg(unix)]
+#[cfg(feature = "std")]
+mod readonly;
+#[cfg(feature = "cache")]
+mod cached;
+#[cfg(feature = "testkit")]
+mod flaky;
+#[cfg(all(feature = "unix-ext", unix))]
 mod unix;
-#[cfg(windows)]
+#[cfg(all(feature = "windows-ext", windows))]
 mod windows;
+#[cfg(all(feature = "linux-flags", target_os = "linux"))]
+mod linux;
 
-use crate::wd::{IntoSome, IntoErr};
+use crate::wd::{CachePolicy, DirId, IntoSome, IntoErr, LinkKind};
 pub use self::path::{FsPath, FsPathBuf};
-pub use self::standard::{StandardDirEntry, StandardDirFingerprint, StandardReadDir, StandardRootDirEntry};
-
-#[cfg(unix)]
+pub use self::url::{UrlPath, UrlPathBuf};
+#[cfg(feature = "std")]
+pub use self::standard::{FsCallCounts, StandardContext, StandardDirEntry, StandardDirFingerprint, StandardReadDir, StandardRootDirEntry};
+#[cfg(feature = "std")]
+pub use self::readonly::{NoReader, ReadOnlyContext, ReadOnlyFs, ReadOnlyReadDir, ReadOnlyRootDirEntry};
+#[cfg(feature = "cache")]
+pub use self::cached::{CachedDirEntry, CachedFileType, CachedMetadata, CachedReadDir, CachedRootDirEntry, ReadDirCache};
+#[cfg(feature = "testkit")]
+pub use self::flaky::{FlakyContext, FlakyFs, FlakyOp, FlakyReadDir, FlakyRootDirEntry};
+
+#[cfg(all(feature = "unix-ext", unix))]
 pub use self::unix::{UnixDirEntry, UnixReadDir, UnixRootDirEntry};
-#[cfg(windows)]
+#[cfg(all(feature = "windows-ext", windows))]
 pub use self::windows::{WindowsDirEntry, WindowsReadDir, WindowsRootDirEntry};
-
-#[cfg(not(any(unix, windows)))]
+#[cfg(all(feature = "linux-flags", target_os = "linux"))]
+pub use self::linux::FileFlags;
+#[cfg(all(feature = "linux-flags", target_os = "linux"))]
+pub(crate) use self::linux::file_flags_from_path;
+
+#[cfg(all(
+    feature = "std",
+    not(any(all(feature = "unix-ext", unix), all(feature = "windows-ext", windows)))
+))]
 /// Default storage-specific type.
 pub type DefaultDirEntry = StandardDirEntry;
-#[cfg(unix)]
+#[cfg(all(feature = "unix-ext", unix))]
 /// Default source-specific type.
 pub type DefaultDirEntry = UnixDirEntry;
-#[cfg(windows)]
+#[cfg(all(feature = "windows-ext", windows))]
 /// Default source-specific type.
 pub type DefaultDirEntry = WindowsDirEntry;
 
@@ -31,9 +57,9 @@ pub type DefaultDirEntry = WindowsDirEntry;
 ///////////////////////////////////////////////////////////////////////////////////////////////
 
 /// Functions for FsMetadata
-pub trait FsError: 'static + std::error::Error + Debug {
+pub trait FsError: 'static + core::error::Error + Debug {
     /// Inner error type
-    type Inner: std::error::Error;
+    type Inner: core::error::Error;
 
     /// Creates a new I/O error from a known kind of error as well as an arbitrary error payload.
     fn from_inner(error: Self::Inner) -> Self;
@@ -42,6 +68,10 @@ pub trait FsError: 'static + std::error::Error + Debug {
 ///////////////////////////////////////////////////////////////////////////////////////////////
 
 /// Functions for FsFileType
+///
+/// Predicates of special types (`is_fifo`, `is_socket`, `is_block_device`,
+/// `is_char_device`) are optional and return `false` unless the backend knows
+/// such types; the standard backends implement them on Unix.
 pub trait FsFileType: Clone + Copy + Debug {
     /// Is it dir?
     fn is_dir(&self) -> bool;
@@ -49,17 +79,55 @@ pub trait FsFileType: Clone + Copy + Debug {
     fn is_file(&self) -> bool;
     /// Is it symlink
     fn is_symlink(&self) -> bool;
+    /// Is it named pipe (FIFO)
+    fn is_fifo(&self) -> bool {
+        false
+    }
+    /// Is it socket
+    fn is_socket(&self) -> bool {
+        false
+    }
+    /// Is it block device
+    fn is_block_device(&self) -> bool {
+        false
+    }
+    /// Is it character device
+    fn is_char_device(&self) -> bool {
+        false
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////
 
 /// Functions for FsMetadata
+#[allow(clippy::len_without_is_empty)]
 pub trait FsMetadata: Debug + Clone {
     /// Associated FileType type
     type FileType: FsFileType;
 
     /// Get type of this entry
     fn file_type(&self) -> Self::FileType;
+
+    /// Get size of this entry in bytes
+    fn len(&self) -> u64;
+
+    /// Get count of bytes allocated on disk for this entry (less than `len` for
+    /// sparse or compressed files), if the metadata holds it
+    fn allocated_size(&self) -> Option<u64> {
+        None
+    }
+
+    /// Get last modification time of this entry (since the Unix epoch), if the
+    /// metadata holds it
+    fn modified(&self) -> Option<core::time::Duration> {
+        None
+    }
+
+    /// Is this entry a cloud placeholder whose data (or dir content) is fetched on
+    /// access, e.g. a OneDrive file which isn't available offline?
+    fn is_placeholder(&self) -> bool {
+        false
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////
@@ -70,7 +138,7 @@ pub trait FsReadDirIterator: Debug + Sized {
     type Context: Debug;
 
     /// Associated error type
-    type Error: std::error::Error;
+    type Error: core::error::Error;
     /// Associated FsDirEntry implementation type
     type DirEntry;
 
@@ -139,17 +207,63 @@ impl<RD> FsReadDirIterator for RD where RD: FsReadDir {
 
 ///////////////////////////////////////////////////////////////////////////////////////////////
 
+/// Hook called by the standard backend around its syscalls.
+///
+/// All methods do nothing by default. An observer is stored in [`StandardContext`]
+/// and sees every dir read and every metadata query made by the walker, so it can be
+/// used to count syscalls in tests or to collect metrics.
+///
+/// [`StandardContext`]: struct.StandardContext.html
+#[cfg(feature = "std")]
+pub trait FsObserver: Debug {
+    /// A dir at `path` is about to be read
+    fn on_read_dir(&mut self, _path: &std::path::Path) {}
+    /// Metadata of `path` is about to be queried
+    fn on_metadata(&mut self, _path: &std::path::Path, _follow_link: bool) {}
+    /// An operation on `path` failed with `err`
+    fn on_error(&mut self, _path: &std::path::Path, _err: &std::io::Error) {}
+    /// Metadata of `path` was looked up in the cache (see `WalkDir::metadata_cache`);
+    /// on a miss, it is queried next
+    fn on_metadata_cache(&mut self, _path: &std::path::Path, _hit: bool) {}
+}
+
+#[cfg(feature = "std")]
+impl<O: FsObserver + ?Sized> FsObserver for std::sync::Arc<std::sync::Mutex<O>> {
+    fn on_read_dir(&mut self, path: &std::path::Path) {
+        if let Ok(mut o) = self.lock() {
+            o.on_read_dir(path)
+        }
+    }
+    fn on_metadata(&mut self, path: &std::path::Path, follow_link: bool) {
+        if let Ok(mut o) = self.lock() {
+            o.on_metadata(path, follow_link)
+        }
+    }
+    fn on_error(&mut self, path: &std::path::Path, err: &std::io::Error) {
+        if let Ok(mut o) = self.lock() {
+            o.on_error(path, err)
+        }
+    }
+    fn on_metadata_cache(&mut self, path: &std::path::Path, hit: bool) {
+        if let Ok(mut o) = self.lock() {
+            o.on_metadata_cache(path, hit)
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
 /// Functions for FsDirEntry
 pub trait FsDirEntry: Debug + Sized {
     /// Associated fs context
     type Context:   Debug;
 
     /// Path type (unsized)
-    type Path:      FsPath<PathBuf = Self::PathBuf, FileName = Self::FileName> + AsRef<Self::Path> + ?Sized;
+    type Path:      FsPath<PathBuf = Self::PathBuf, FileName = Self::FileName> + AsRef<Self::Path> + ToOwned<Owned = Self::PathBuf> + ?Sized;
     /// Owned path type
     type PathBuf:   for<'p> FsPathBuf<'p> + AsRef<Self::Path> + Deref<Target = Self::Path> + Sized;
-    /// Owned file name type
-    type FileName:  Sized;
+    /// File name type (unsized, borrowed from a path)
+    type FileName:  ?Sized;
 
     /// Error type
     type Error:             FsError;
@@ -165,6 +279,8 @@ pub trait FsDirEntry: Debug + Sized {
     type DeviceNum:         Debug + Eq + Clone + Copy;
     /// FsRootReadDir implementation object type
     type RootDirEntry:      FsRootDirEntry<Context=Self::Context, DirEntry=Self>;
+    /// Reader of file contents (e.g. `std::fs::File`)
+    type Reader;
 
     /// Get path of this entry
     fn path(&self) -> &Self::Path;
@@ -172,8 +288,24 @@ pub trait FsDirEntry: Debug + Sized {
     fn pathbuf(&self) -> Self::PathBuf;
     /// Get canonical path of this entry (don't follow symlink!)
     fn canonicalize(&self) -> Result<Self::PathBuf, Self::Error>;
+    /// Get canonical path of given path (with all symlinks resolved)
+    fn canonicalize_path(path: &Self::Path) -> Result<Self::PathBuf, Self::Error>;
+    /// Get metadata of given path
+    fn metadata_path(path: &Self::Path, follow_link: bool) -> Result<Self::Metadata, Self::Error>;
+    /// Get count of bytes allocated on disk for given path, if the metadata doesn't hold
+    /// it (see `FsMetadata::allocated_size`) but the backend can query it
+    fn allocated_size_path(_path: &Self::Path) -> Option<u64> {
+        None
+    }
+    /// Get persistent fingerprint of the dir at given path (follow symlink), if the backend
+    /// can make one
+    fn dir_id_path(_path: &Self::Path) -> Result<Option<DirId>, Self::Error> {
+        Ok(None)
+    }
+    /// Open contents of given path for reading (follow symlink)
+    fn open_path(path: &Self::Path) -> Result<Self::Reader, Self::Error>;
     /// Get bare name of this entry withot any leading path components (don't follow symlink!)
-    fn file_name(&self) -> Self::FileName;
+    fn file_name(&self) -> &Self::FileName;
 
     /// Get file type
     fn file_type(
@@ -213,14 +345,116 @@ pub trait FsDirEntry: Debug + Sized {
         ctx: &mut Self::Context,
     ) -> Result<Self::DeviceNum, Self::Error>;
 
+    /// Check if this entry is a mount point, i.e. a dir on another device than
+    /// its parent dir (whose device is `parent_device`).
+    fn is_mount_point(
+        &self,
+        parent_device: &Self::DeviceNum,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        Ok(self.device_num(ctx)? != *parent_device)
+    }
+
+    /// Check if the extended attribute `name` is set on this entry (or on its
+    /// target with `follow_link`). Backends without extended attributes
+    /// support report none.
+    fn has_xattr(
+        &self,
+        _name: &str,
+        _follow_link: bool,
+        _ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    /// Get kind of this entry if it is a link (don't follow symlink!)
+    fn link_kind(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<Option<LinkKind>, Self::Error> {
+        let is_symlink = self.file_type(false, ctx)?.is_symlink();
+        Ok(if is_symlink { Some(LinkKind::Symlink) } else { None })
+    }
+
+    /// Open contents of this entry for reading (follow symlink)
+    fn open(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<Self::Reader, Self::Error> {
+        Self::open_path(self.path())
+    }
+
+    /// Get paths of alternate data streams of this file (like `file.txt:name`).
+    /// Backends without such streams report none.
+    fn alternate_streams(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<alloc::vec::Vec<Self::PathBuf>, Self::Error> {
+        Ok(alloc::vec::Vec::new())
+    }
+
+    /// Check if this entry is a cloud placeholder (see `FsMetadata::is_placeholder`)
+    /// without opening it. Backends without placeholders report none.
+    fn is_placeholder(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    /// Check if contents of this file look binary, i.e. there is a NUL byte among
+    /// the first 8 KiB (symlinks are followed). Backends which can't read
+    /// contents report none.
+    fn is_binary(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    /// Check if this entry (or its target with `follow_link`) is excluded from
+    /// backups by the `nodump` flag. Backends without such flags report none.
+    fn is_nodump(
+        &self,
+        _follow_link: bool,
+        _ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    /// Are names compared case-insensitively on the storage of this backend
+    /// (used by `CaseMatch::Auto`)? Backends report case-sensitive names by default.
+    fn is_case_insensitive() -> bool {
+        false
+    }
+
+    /// Hint how many entries to fetch from the system at once while reading
+    /// a dir (see `WalkDir::readdir_batch`), e.g. the size of a `getdents`
+    /// buffer, the large fetch flag of `FindFirstFileEx` or the page size of a
+    /// `PROPFIND` request. Called once before the walk starts. Backends which
+    /// can't tune it ignore the hint.
+    fn set_readdir_batch(
+        _ctx: &mut Self::Context,
+        _batch: usize,
+    ) {
+    }
+
+    /// Keep metadata queries for reuse as `policy` says (see
+    /// `WalkDir::metadata_cache`). Called once before the walk starts.
+    /// Backends which don't cache metadata ignore it.
+    fn set_metadata_cache(
+        _ctx: &mut Self::Context,
+        _policy: CachePolicy,
+    ) {
+    }
+
     /// Get cached metadata (if exists)
     fn to_parts(
         &mut self,
         follow_link: bool,
         force_metadata: bool,
-        force_file_name: bool,
         ctx: &mut Self::Context,
-    ) -> (Self::PathBuf, Option<Self::Metadata>, Option<Self::FileName>);
+    ) -> (Self::PathBuf, Option<Self::Metadata>);
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////
@@ -239,7 +473,7 @@ pub trait FsRootDirEntry: Debug + Sized {
     /// Get canonical path of this entry
     fn canonicalize(&self) -> Result<<Self::DirEntry as FsDirEntry>::PathBuf, <Self::DirEntry as FsDirEntry>::Error>;
     /// Get bare name of this entry withot any leading path components
-    fn file_name(&self) -> <Self::DirEntry as FsDirEntry>::FileName;
+    fn file_name(&self) -> &<Self::DirEntry as FsDirEntry>::FileName;
 
     /// Create new root dir entry object from path
     fn from_path(
@@ -284,7 +518,6 @@ pub trait FsRootDirEntry: Debug + Sized {
         &mut self,
         follow_link: bool,
         force_metadata: bool,
-        force_file_name: bool,
         ctx: &mut Self::Context,
-    ) -> (<Self::DirEntry as FsDirEntry>::PathBuf, Option<<Self::DirEntry as FsDirEntry>::Metadata>, Option<<Self::DirEntry as FsDirEntry>::FileName>);
+    ) -> (<Self::DirEntry as FsDirEntry>::PathBuf, Option<<Self::DirEntry as FsDirEntry>::Metadata>);
 }
@@ -1,9 +1,13 @@
-use std::cmp::Ord;
+use core::cmp::Ord;
 //use std::convert::AsRef;
-use std::fmt;
-use std::marker::Send;
-//use std::ops::Deref;
+use core::fmt;
+use core::marker::Send;
 
+use alloc::borrow::Cow;
+use alloc::string::ToString;
+//use core::ops::Deref;
+
+#[cfg(feature = "std")]
 use crate::wd::IntoSome;
 
 /// Functions for StorageExt::Path
@@ -11,20 +15,46 @@ pub trait FsPath: Ord
 {
     /// Associated owned path type
     type PathBuf: Sized;
-    /// Associated owned file name type
-    type FileName: Sized;
+    /// Associated file name type (unsized, borrowed from the path)
+    type FileName: ?Sized;
 
     /// Copy to owned
     fn to_path_buf(&self) -> Self::PathBuf;
 
     /// Try to get file name from path
-    fn file_name(&self) -> Option<Self::FileName>;
+    fn file_name(&self) -> Option<&Self::FileName>;
+
+    /// View the whole path as a file name (for paths without one, e.g. `/`)
+    fn as_file_name(&self) -> &Self::FileName;
+
+    /// Try to get path without its final component
+    fn parent(&self) -> Option<&Self>;
+
+    /// Make path of the child `name` of this path (as the backend joins them,
+    /// e.g. with a separator)
+    fn join(&self, name: &Self::FileName) -> Self::PathBuf;
+
+    /// Try to get file name without its extension
+    fn file_stem(&self) -> Option<&Self::FileName>;
+
+    /// Try to get file name from path as a string (invalid characters are replaced)
+    fn file_name_lossy(&self) -> Option<Cow<'_, str>>;
+
+    /// Try to get file name from path as bytes, to order names byte-wise
+    /// (`None` if the backend has no such form)
+    fn file_name_bytes(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Length of the full path in bytes
+    fn path_len(&self) -> usize;
 }
 
 /// Functions for StorageExt::PathBuf
 pub trait FsPathBuf<'s>: Sized 
 + fmt::Debug
 + Clone
++ Ord
 + Send
 + Sync
 {
@@ -44,17 +74,53 @@ pub trait FsPathBuf<'s>: Sized
 
 //////////////////////////////////////////////////////////////////////////////////////
 
+#[cfg(feature = "std")]
 impl FsPath for std::path::Path {
     type PathBuf = std::path::PathBuf;
-    type FileName = std::ffi::OsString;
+    type FileName = std::ffi::OsStr;
 
     #[inline(always)]
     fn to_path_buf(&self) -> std::path::PathBuf {
         self.to_path_buf()
     }
 
-    fn file_name(&self) -> Option<Self::FileName> {
-        self.file_name()?.to_os_string().into_some()
+    #[inline(always)]
+    fn file_name(&self) -> Option<&Self::FileName> {
+        self.file_name()
+    }
+
+    #[inline(always)]
+    fn as_file_name(&self) -> &Self::FileName {
+        self.as_os_str()
+    }
+
+    #[inline(always)]
+    fn parent(&self) -> Option<&Self> {
+        self.parent()
+    }
+
+    #[inline(always)]
+    fn join(&self, name: &Self::FileName) -> std::path::PathBuf {
+        self.join(name)
+    }
+
+    #[inline(always)]
+    fn file_stem(&self) -> Option<&Self::FileName> {
+        self.file_stem()
+    }
+
+    fn file_name_lossy(&self) -> Option<Cow<'_, str>> {
+        self.file_name()?.to_string_lossy().into_some()
+    }
+
+    #[inline(always)]
+    fn file_name_bytes(&self) -> Option<&[u8]> {
+        self.file_name().map(std::ffi::OsStr::as_encoded_bytes)
+    }
+
+    #[inline(always)]
+    fn path_len(&self) -> usize {
+        self.as_os_str().len()
     }
 }
 
@@ -66,6 +132,7 @@ impl FsPath for std::path::Path {
 //     }
 // }
 
+#[cfg(feature = "std")]
 impl<'s> FsPathBuf<'s> for std::path::PathBuf {
     type Display = std::path::Display<'s>;
 
@@ -79,31 +146,62 @@ impl<'s> FsPathBuf<'s> for std::path::PathBuf {
 //////////////////////////////////////////////////////////////////////////////////////
 
 impl FsPath for str {
-    type PathBuf = std::string::String;
-    type FileName = std::string::String;
+    type PathBuf = alloc::string::String;
+    type FileName = str;
 
     #[inline(always)]
-    fn to_path_buf(&self) -> std::string::String {
+    fn to_path_buf(&self) -> alloc::string::String {
         self.to_string()
     }
 
-    fn file_name(&self) -> Option<Self::FileName> {
+    fn file_name(&self) -> Option<&Self::FileName> {
+        None
+    }
+
+    #[inline(always)]
+    fn as_file_name(&self) -> &Self::FileName {
+        self
+    }
+
+    fn parent(&self) -> Option<&Self> {
+        None
+    }
+
+    fn join(&self, name: &Self::FileName) -> alloc::string::String {
+        let mut path = self.to_string();
+        if !path.is_empty() && !path.ends_with('/') {
+            path.push('/');
+        }
+        path.push_str(name);
+        path
+    }
+
+    fn file_stem(&self) -> Option<&Self::FileName> {
         None
     }
+
+    fn file_name_lossy(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    #[inline(always)]
+    fn path_len(&self) -> usize {
+        self.len()
+    }
 }
 
 pub struct StringDisplay<'s> {
-    inner: &'s std::string::String,
+    inner: &'s alloc::string::String,
 }
 
-impl<'s> std::fmt::Display for StringDisplay<'s> {
+impl<'s> core::fmt::Display for StringDisplay<'s> {
     #[inline(always)]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Display::fmt(self.inner, f)
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Display::fmt(self.inner, f)
     }
 }
 
-impl<'s> FsPathBuf<'s> for std::string::String {
+impl<'s> FsPathBuf<'s> for alloc::string::String {
     type Display = StringDisplay<'s>;
 
     #[inline(always)]
@@ -0,0 +1,438 @@
+use super::{FsDirEntry, FsReadDirIterator, FsRootDirEntry};
+use crate::wd::{CachePolicy, DirId, IntoOk, IntoSome, LinkKind};
+
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Context of [`ReadOnlyFs`]: the inner backend context and the audit mode.
+///
+/// By default, operations which open contents of files are refused with
+/// `PermissionDenied` errors. With [`audit`], they panic instead, so a test
+/// suite proves that a walk never gets to them.
+///
+/// [`ReadOnlyFs`]: struct.ReadOnlyFs.html
+/// [`audit`]: #method.audit
+#[derive(Debug)]
+pub struct ReadOnlyContext<C> {
+    inner:      C,
+    audit:      bool,
+    refused:    usize,
+}
+
+impl<C: Default> Default for ReadOnlyContext<C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
+
+impl<C> ReadOnlyContext<C> {
+    /// Make context over the inner backend context
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            audit: false,
+            refused: 0,
+        }
+    }
+
+    /// Panic on any operation which opens contents of a file, instead of
+    /// failing it
+    pub fn audit(mut self, yes: bool) -> Self {
+        self.audit = yes;
+        self
+    }
+
+    /// Get inner backend context
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Get inner backend context
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Count of refused operations so far
+    pub fn refused(&self) -> usize {
+        self.refused
+    }
+
+    fn refuse(&mut self, op: &str, path: &Path) -> io::Error {
+        assert!(!self.audit, "read-only walk attempted {} of {}", op, path.display());
+        self.refused += 1;
+        refused(op, path)
+    }
+}
+
+fn refused(op: &str, path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{} of {} refused by a read-only walk", op, path.display()),
+    )
+}
+
+/// Reader of [`ReadOnlyFs`], which has no values: contents of files can't be
+/// read through a read-only walk
+///
+/// [`ReadOnlyFs`]: struct.ReadOnlyFs.html
+#[derive(Debug)]
+pub enum NoReader {}
+
+impl io::Read for NoReader {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        match *self {}
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A FsReadDir implementation for ReadOnlyFs
+#[derive(Debug)]
+pub struct ReadOnlyReadDir<E: FsDirEntry> {
+    inner:  E::ReadDir,
+}
+
+impl<E> FsReadDirIterator for ReadOnlyReadDir<E>
+where
+    E: FsDirEntry<Path = Path, PathBuf = PathBuf, FileName = OsStr, Error = io::Error>,
+{
+    type Context    = ReadOnlyContext<E::Context>;
+    type Error      = io::Error;
+    type DirEntry   = ReadOnlyFs<E>;
+
+    fn next_entry(
+        &mut self,
+        ctx: &mut Self::Context,
+    ) -> Option<Result<Self::DirEntry, Self::Error>> {
+        self.inner.next_entry(&mut ctx.inner)?.map(|inner| ReadOnlyFs { inner }).into_some()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A FsDirEntry implementation wrapping any other backend and refusing the
+/// operations which open contents of files (see [`ReadOnlyContext`]).
+///
+/// Backends never write, but opening a file is not free of side effects
+/// either: it updates the access time, blocks or consumes data on a FIFO,
+/// and may act on a device (e.g. rewind a tape). A walk over this backend
+/// only lists dirs and queries metadata. Its type is the marker: code taking
+/// `WalkDirBuilder<ReadOnlyFs>` can't read contents through the walk, as the
+/// reader type [`NoReader`] has no values. Options which need contents, like
+/// `skip_binary_files`, yield errors instead (or panic in the audit mode),
+/// and so does `DirEntry::open` of yielded entries.
+///
+/// ```
+/// use walkdir::{DirEntryContentProcessor, ReadOnlyContext, ReadOnlyFs, WalkDirBuilder};
+///
+/// let ctx = ReadOnlyContext::default().audit(true);
+/// let wd = WalkDirBuilder::<ReadOnlyFs>::with_context("src", ctx, DirEntryContentProcessor {});
+/// for entry in wd.into_classic() {
+///     println!("{}", entry.unwrap().path().display());
+/// }
+/// ```
+///
+/// [`ReadOnlyContext`]: struct.ReadOnlyContext.html
+/// [`NoReader`]: enum.NoReader.html
+#[derive(Debug)]
+pub struct ReadOnlyFs<E = super::DefaultDirEntry> {
+    inner: E,
+}
+
+impl<E> ReadOnlyFs<E> {
+    /// Get wrapped entry
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+}
+
+/// Functions for FsDirEntry
+impl<E> FsDirEntry for ReadOnlyFs<E>
+where
+    E: FsDirEntry<Path = Path, PathBuf = PathBuf, FileName = OsStr, Error = io::Error>,
+{
+    type Context        = ReadOnlyContext<E::Context>;
+
+    type Path           = Path;
+    type PathBuf        = PathBuf;
+    type FileName       = OsStr;
+
+    type Error          = io::Error;
+    type FileType       = E::FileType;
+    type Metadata       = E::Metadata;
+    type ReadDir        = ReadOnlyReadDir<E>;
+    type DirFingerprint = E::DirFingerprint;
+    type DeviceNum      = E::DeviceNum;
+    type RootDirEntry   = ReadOnlyRootDirEntry<E>;
+    type Reader         = NoReader;
+
+    /// Get path of this entry
+    fn path(&self) -> &Path {
+        self.inner.path()
+    }
+    /// Get path of this entry
+    fn pathbuf(&self) -> PathBuf {
+        self.inner.pathbuf()
+    }
+    /// Get path of this entry
+    fn canonicalize(&self) -> io::Result<PathBuf> {
+        self.inner.canonicalize()
+    }
+    fn canonicalize_path(path: &Path) -> io::Result<PathBuf> {
+        E::canonicalize_path(path)
+    }
+    fn metadata_path(path: &Path, follow_link: bool) -> io::Result<Self::Metadata> {
+        E::metadata_path(path, follow_link)
+    }
+    fn allocated_size_path(path: &Path) -> Option<u64> {
+        E::allocated_size_path(path)
+    }
+    fn dir_id_path(path: &Path) -> io::Result<Option<DirId>> {
+        E::dir_id_path(path)
+    }
+    fn open_path(path: &Path) -> io::Result<Self::Reader> {
+        // Without a context there is no audit mode, but nothing can be opened anyway
+        Err(refused("open", path))
+    }
+    fn file_name(&self) -> &Self::FileName {
+        self.inner.file_name()
+    }
+
+    /// Get file type
+    fn file_type(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::FileType> {
+        self.inner.file_type(follow_link, &mut ctx.inner)
+    }
+
+    /// Get metadata
+    fn metadata(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::Metadata> {
+        self.inner.metadata(follow_link, &mut ctx.inner)
+    }
+
+    /// Read dir
+    fn read_dir(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::ReadDir> {
+        ReadOnlyReadDir {
+            inner: self.inner.read_dir(&mut ctx.inner)?,
+        }.into_ok()
+    }
+
+    /// Open file
+    fn open(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::Reader> {
+        Err(ctx.refuse("open", self.path()))
+    }
+
+    /// Return the unique handle
+    fn fingerprint(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::DirFingerprint> {
+        self.inner.fingerprint(&mut ctx.inner)
+    }
+
+    fn is_same(
+        lhs: (&Path, &Self::DirFingerprint),
+        rhs: (&Path, &Self::DirFingerprint),
+    ) -> bool {
+        E::is_same(lhs, rhs)
+    }
+
+    /// device_num
+    fn device_num(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self::DeviceNum> {
+        self.inner.device_num(&mut ctx.inner)
+    }
+
+    fn is_mount_point(
+        &self,
+        parent_device: &Self::DeviceNum,
+        ctx: &mut Self::Context,
+    ) -> io::Result<bool> {
+        self.inner.is_mount_point(parent_device, &mut ctx.inner)
+    }
+
+    fn has_xattr(
+        &self,
+        name: &str,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<bool> {
+        self.inner.has_xattr(name, follow_link, &mut ctx.inner)
+    }
+
+    fn link_kind(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Option<LinkKind>> {
+        self.inner.link_kind(&mut ctx.inner)
+    }
+
+    fn alternate_streams(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Vec<PathBuf>> {
+        self.inner.alternate_streams(&mut ctx.inner)
+    }
+
+    fn is_placeholder(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<bool> {
+        self.inner.is_placeholder(&mut ctx.inner)
+    }
+
+    fn is_binary(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<bool> {
+        Err(ctx.refuse("reading contents", self.path()))
+    }
+
+    fn is_nodump(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<bool> {
+        self.inner.is_nodump(follow_link, &mut ctx.inner)
+    }
+
+    fn is_case_insensitive() -> bool {
+        E::is_case_insensitive()
+    }
+
+    fn set_readdir_batch(
+        ctx: &mut Self::Context,
+        batch: usize,
+    ) {
+        E::set_readdir_batch(&mut ctx.inner, batch);
+    }
+
+    fn set_metadata_cache(
+        ctx: &mut Self::Context,
+        policy: CachePolicy,
+    ) {
+        E::set_metadata_cache(&mut ctx.inner, policy);
+    }
+
+    fn to_parts(
+        &mut self,
+        follow_link: bool,
+        force_metadata: bool,
+        ctx: &mut Self::Context,
+    ) -> (PathBuf, Option<Self::Metadata>) {
+        self.inner.to_parts(follow_link, force_metadata, &mut ctx.inner)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A FsRootDirEntry implementation for ReadOnlyFs
+#[derive(Debug)]
+pub struct ReadOnlyRootDirEntry<E: FsDirEntry> {
+    inner: E::RootDirEntry,
+}
+
+/// Functions for FsRootDirEntry
+impl<E> FsRootDirEntry for ReadOnlyRootDirEntry<E>
+where
+    E: FsDirEntry<Path = Path, PathBuf = PathBuf, FileName = OsStr, Error = io::Error>,
+{
+    type Context    = ReadOnlyContext<E::Context>;
+    type DirEntry   = ReadOnlyFs<E>;
+
+    fn from_path(
+        path: &Path,
+        ctx: &mut Self::Context,
+    ) -> io::Result<Self> {
+        Self {
+            inner: E::RootDirEntry::from_path(path, &mut ctx.inner)?,
+        }.into_ok()
+    }
+
+    /// Get path of this entry
+    fn path(&self) -> &Path {
+        self.inner.path()
+    }
+    /// Get path of this entry
+    fn pathbuf(&self) -> PathBuf {
+        self.inner.pathbuf()
+    }
+    /// Get path of this entry
+    fn canonicalize(&self) -> io::Result<PathBuf> {
+        self.inner.canonicalize()
+    }
+
+    fn file_name(&self) -> &OsStr {
+        self.inner.file_name()
+    }
+
+    /// Get file type
+    fn file_type(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<E::FileType> {
+        self.inner.file_type(follow_link, &mut ctx.inner)
+    }
+
+    /// Get metadata
+    fn metadata(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> io::Result<E::Metadata> {
+        self.inner.metadata(follow_link, &mut ctx.inner)
+    }
+
+    /// Read dir
+    fn read_dir(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<ReadOnlyReadDir<E>> {
+        ReadOnlyReadDir {
+            inner: self.inner.read_dir(&mut ctx.inner)?,
+        }.into_ok()
+    }
+
+    /// Return the unique handle
+    fn fingerprint(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<E::DirFingerprint> {
+        self.inner.fingerprint(&mut ctx.inner)
+    }
+
+    /// device_num
+    fn device_num(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> io::Result<E::DeviceNum> {
+        self.inner.device_num(&mut ctx.inner)
+    }
+
+    fn to_parts(
+        &mut self,
+        follow_link: bool,
+        force_metadata: bool,
+        ctx: &mut Self::Context,
+    ) -> (PathBuf, Option<E::Metadata>) {
+        self.inner.to_parts(follow_link, force_metadata, &mut ctx.inner)
+    }
+}
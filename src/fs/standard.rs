@@ -1,10 +1,222 @@
-use super::{FsError, FsFileType, FsMetadata, FsReadDir, FsDirEntry, FsRootDirEntry, FsReadDirIterator};
-use crate::wd::{IntoOk, IntoSome};
+use super::{FsError, FsFileType, FsMetadata, FsObserver, FsReadDir, FsDirEntry, FsRootDirEntry, FsReadDirIterator};
+use crate::wd::{CachePolicy, DirId, IntoErr, IntoOk, IntoSome};
 
+use std::collections::{BTreeMap, HashMap};
+
+#[cfg(all(feature = "same-file", not(target_os = "wasi")))]
 use same_file;
 
 ///////////////////////////////////////////////////////////////////////////////////////////////
 
+/// Context of the standard backend (and of backends built upon it)
+#[derive(Debug, Default)]
+pub struct StandardContext {
+    observer: Option<Box<dyn FsObserver + Send + Sync>>,
+    readdir_batch: Option<usize>,
+    metadata_cache: MetadataCache,
+}
+
+/// Metadata kept with an entry under `CachePolicy::PerEntry`: follow mode and metadata
+/// of its first query (boxed to keep entries small when the cache is off)
+type KeptMetadata = core::cell::OnceCell<Box<(bool, std::fs::Metadata)>>;
+
+/// Last queried metadata under `CachePolicy::Lru`: kept queries by path and follow
+/// mode with their ticks, and the same keys by tick (the least recent first)
+#[derive(Debug, Default)]
+struct MetadataCache {
+    policy: CachePolicy,
+    entries: HashMap<(std::path::PathBuf, bool), (u64, std::fs::Metadata)>,
+    order: BTreeMap<u64, (std::path::PathBuf, bool)>,
+    tick: u64,
+}
+
+impl MetadataCache {
+    fn new(policy: CachePolicy) -> Self {
+        Self { policy, ..Self::default() }
+    }
+
+    /// Take the kept query out of the cache
+    fn take(&mut self, path: &std::path::Path, follow_link: bool) -> Option<std::fs::Metadata> {
+        let (tick, md) = self.entries.remove(&(path.to_path_buf(), follow_link))?;
+        self.order.remove(&tick);
+        Some(md)
+    }
+
+    /// Keep the query as the most recent one, dropping the least recent ones over `capacity`
+    fn put(&mut self, path: &std::path::Path, follow_link: bool, md: std::fs::Metadata, capacity: usize) {
+        while self.entries.len() >= capacity {
+            match self.order.pop_first() {
+                Some((_, key)) => self.entries.remove(&key),
+                None => break,
+            };
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, (path.to_path_buf(), follow_link));
+        self.entries.insert((path.to_path_buf(), follow_link), (self.tick, md));
+    }
+}
+
+impl StandardContext {
+    /// Context without observer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Context calling given observer around every syscall.
+    ///
+    /// To inspect the observer after the walk, share it via `Arc<Mutex<_>>`.
+    pub fn with_observer<O: FsObserver + Send + Sync + 'static>(observer: O) -> Self {
+        Self {
+            observer: Some(Box::new(observer)),
+            readdir_batch: None,
+            metadata_cache: MetadataCache::default(),
+        }
+    }
+
+    /// Count of entries to fetch at once while reading a dir, if set with
+    /// `WalkDir::readdir_batch`.
+    ///
+    /// `std::fs::read_dir` uses a buffer of a fixed size, so the standard
+    /// backends only keep the hint for backends built upon them.
+    pub fn readdir_batch(&self) -> Option<usize> {
+        self.readdir_batch
+    }
+
+    /// Set count of entries to fetch at once while reading a dir
+    pub fn set_readdir_batch(&mut self, batch: usize) {
+        self.readdir_batch = Some(batch);
+    }
+
+    /// Metadata queries kept for reuse, if set with `WalkDir::metadata_cache`
+    pub fn metadata_cache(&self) -> CachePolicy {
+        self.metadata_cache.policy
+    }
+
+    /// Set metadata queries kept for reuse (dropping the kept ones)
+    pub fn set_metadata_cache(&mut self, policy: CachePolicy) {
+        self.metadata_cache = MetadataCache::new(policy);
+    }
+
+    /// Query metadata of `path`, reusing a kept query if the cache is enabled.
+    ///
+    /// `kept` is the per entry cell of the queried entry.
+    fn metadata(
+        &mut self,
+        path: &std::path::Path,
+        follow_link: bool,
+        kept: &KeptMetadata,
+    ) -> std::io::Result<std::fs::Metadata> {
+        match self.metadata_cache.policy {
+            CachePolicy::None | CachePolicy::Lru(0) => self.query_metadata(path, follow_link),
+            CachePolicy::PerEntry => {
+                let found = kept.get().filter(|k| k.0 == follow_link).map(|k| k.1.clone());
+                self.on_metadata_cache(path, found.is_some());
+                if let Some(md) = found {
+                    return Ok(md);
+                }
+                let md = self.query_metadata(path, follow_link)?;
+                // Only the first query is kept
+                let _ = kept.set(Box::new((follow_link, md.clone())));
+                Ok(md)
+            },
+            CachePolicy::Lru(capacity) => {
+                let found = self.metadata_cache.take(path, follow_link);
+                self.on_metadata_cache(path, found.is_some());
+                let md = match found {
+                    Some(md) => md,
+                    None => self.query_metadata(path, follow_link)?,
+                };
+                self.metadata_cache.put(path, follow_link, md.clone(), capacity);
+                Ok(md)
+            },
+        }
+    }
+
+    fn query_metadata(&mut self, path: &std::path::Path, follow_link: bool) -> std::io::Result<std::fs::Metadata> {
+        self.on_metadata(path, follow_link);
+        self.on_result(path, StandardDirEntry::metadata_from_path(path, follow_link))
+    }
+
+    fn on_metadata_cache(&mut self, path: &std::path::Path, hit: bool) {
+        if let Some(ref mut o) = self.observer {
+            o.on_metadata_cache(path, hit);
+        }
+    }
+
+    /// Get installed observer
+    pub fn observer(&self) -> Option<&(dyn FsObserver + Send + Sync)> {
+        self.observer.as_deref()
+    }
+
+    /// Remove installed observer and return it
+    pub fn take_observer(&mut self) -> Option<Box<dyn FsObserver + Send + Sync>> {
+        self.observer.take()
+    }
+
+    /// Report dir read to observer
+    pub fn on_read_dir(&mut self, path: &std::path::Path) {
+        if let Some(ref mut o) = self.observer {
+            o.on_read_dir(path);
+        }
+    }
+
+    /// Report metadata query to observer
+    pub fn on_metadata(&mut self, path: &std::path::Path, follow_link: bool) {
+        if let Some(ref mut o) = self.observer {
+            o.on_metadata(path, follow_link);
+        }
+    }
+
+    /// Report error (if any) to observer and pass result through
+    pub fn on_result<T>(
+        &mut self,
+        path: &std::path::Path,
+        res: Result<T, std::io::Error>,
+    ) -> Result<T, std::io::Error> {
+        if let (Some(ref mut o), Err(ref err)) = (&mut self.observer, &res) {
+            o.on_error(path, err);
+        }
+        res
+    }
+}
+
+/// A simple observer counting calls
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FsCallCounts {
+    /// Count of dir reads
+    pub read_dir: usize,
+    /// Count of metadata queries
+    pub metadata: usize,
+    /// Count of errors
+    pub errors: usize,
+    /// Count of metadata queries reused from the cache
+    pub cache_hits: usize,
+    /// Count of metadata queries missing in the cache (and made)
+    pub cache_misses: usize,
+}
+
+impl FsObserver for FsCallCounts {
+    fn on_read_dir(&mut self, _path: &std::path::Path) {
+        self.read_dir += 1;
+    }
+    fn on_metadata(&mut self, _path: &std::path::Path, _follow_link: bool) {
+        self.metadata += 1;
+    }
+    fn on_error(&mut self, _path: &std::path::Path, _err: &std::io::Error) {
+        self.errors += 1;
+    }
+    fn on_metadata_cache(&mut self, _path: &std::path::Path, hit: bool) {
+        if hit {
+            self.cache_hits += 1;
+        } else {
+            self.cache_misses += 1;
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////
+
 impl FsError for std::io::Error {
     type Inner = Self;
 
@@ -30,6 +242,26 @@ impl FsFileType for std::fs::FileType {
     fn is_symlink(&self) -> bool {
         std::fs::FileType::is_symlink(self)
     }
+    /// Is it named pipe (FIFO)
+    #[cfg(unix)]
+    fn is_fifo(&self) -> bool {
+        std::os::unix::fs::FileTypeExt::is_fifo(self)
+    }
+    /// Is it socket
+    #[cfg(unix)]
+    fn is_socket(&self) -> bool {
+        std::os::unix::fs::FileTypeExt::is_socket(self)
+    }
+    /// Is it block device
+    #[cfg(unix)]
+    fn is_block_device(&self) -> bool {
+        std::os::unix::fs::FileTypeExt::is_block_device(self)
+    }
+    /// Is it character device
+    #[cfg(unix)]
+    fn is_char_device(&self) -> bool {
+        std::os::unix::fs::FileTypeExt::is_char_device(self)
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////
@@ -42,12 +274,40 @@ impl FsMetadata for std::fs::Metadata {
     fn file_type(&self) -> std::fs::FileType {
         std::fs::Metadata::file_type(self)    
     }
+
+    /// Get size of this entry in bytes
+    fn len(&self) -> u64 {
+        std::fs::Metadata::len(self)
+    }
+
+    /// Get last modification time (times before the Unix epoch aren't reported)
+    fn modified(&self) -> Option<core::time::Duration> {
+        std::fs::Metadata::modified(self).ok()?.duration_since(std::time::UNIX_EPOCH).ok()
+    }
+
+    /// Get count of bytes allocated on disk (`st_blocks` are always 512 bytes)
+    #[cfg(unix)]
+    fn allocated_size(&self) -> Option<u64> {
+        use std::os::unix::fs::MetadataExt;
+
+        Some(self.blocks() * 512)
+    }
+
+    /// Is this a cloud placeholder (recalled on open or on data access)?
+    #[cfg(windows)]
+    fn is_placeholder(&self) -> bool {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x0004_0000;
+        const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x0040_0000;
+
+        self.file_attributes() & (FILE_ATTRIBUTE_RECALL_ON_OPEN | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS) != 0
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////
 
 impl FsReadDirIterator for std::fs::ReadDir {
-    type Context    = ();
+    type Context    = StandardContext;
     type Error      = std::io::Error;
     type DirEntry   = std::fs::DirEntry;
 
@@ -63,6 +323,7 @@ impl FsReadDirIterator for std::fs::ReadDir {
 #[derive(Debug)]
 pub struct StandardReadDir {
     inner:      std::fs::ReadDir,
+    path:       std::path::PathBuf,
 }
 
 impl StandardReadDir {
@@ -74,7 +335,7 @@ impl StandardReadDir {
 
 /// Functions for FsReadDir
 impl FsReadDir for StandardReadDir {
-    type Context    = ();
+    type Context    = StandardContext;
     type Inner      = std::fs::ReadDir;
     type Error      = std::io::Error;
     type DirEntry   = StandardDirEntry;
@@ -86,6 +347,16 @@ impl FsReadDir for StandardReadDir {
     fn process_inner_entry(&mut self, inner_entry: std::fs::DirEntry) -> Result<Self::DirEntry, Self::Error> {
         Self::DirEntry::from_inner(inner_entry)    
     }
+
+    fn next_fsentry(
+        &mut self,
+        ctx: &mut Self::Context,
+    ) -> Option<Result<Self::DirEntry, Self::Error>> {
+        match self.inner.next_entry(ctx)? {
+            Ok(inner_entry) => self.process_inner_entry(inner_entry),
+            Err(err)        => ctx.on_result(&self.path, err.into_err()),
+        }.into_some()
+    }
 }
 
 
@@ -104,6 +375,7 @@ impl FsReadDir for StandardReadDir {
 pub struct StandardDirEntry {
     pathbuf:    std::path::PathBuf,
     inner:      std::fs::DirEntry,
+    metadata:   KeptMetadata,
 }
 
 impl StandardDirEntry {
@@ -118,6 +390,7 @@ impl StandardDirEntry {
         Self {
             pathbuf,
             inner,
+            metadata: KeptMetadata::new(),
         }.into_ok()
     }
 }
@@ -131,14 +404,11 @@ impl StandardDirEntry {
         std::fs::canonicalize(path)
     }
 
-    /// Get file name from given path
+    /// Get file name from given path (the whole path if it has no file name, e.g. `/`)
     pub fn file_name_from_path(
         path: &<Self as FsDirEntry>::Path,
-    ) -> <Self as FsDirEntry>::FileName {
-        match path.file_name() {
-            Some(n) => n.to_os_string(),
-            None => panic!("Wrong path!"),
-        } 
+    ) -> &<Self as FsDirEntry>::FileName {
+        path.file_name().unwrap_or(path.as_os_str())
     }
 
     /// Get metadata
@@ -153,16 +423,29 @@ impl StandardDirEntry {
         }
     }
 
+    /// Persistent fingerprint (device and inode numbers)
+    #[cfg(unix)]
+    pub fn dir_id_from_path(
+        path: &<Self as FsDirEntry>::Path,
+    ) -> Result<Option<DirId>, <Self as FsDirEntry>::Error> {
+        use std::os::unix::fs::MetadataExt;
+
+        let md = std::fs::metadata(path)?;
+        DirId { device: md.dev(), index: md.ino() }.into_some().into_ok()
+    }
+
     /// Read dir
     pub fn read_dir_from_path(
         path: &<Self as FsDirEntry>::Path,
     ) -> Result<<Self as FsDirEntry>::ReadDir, <Self as FsDirEntry>::Error> {
         StandardReadDir {
             inner: std::fs::read_dir(path)?,
+            path: path.to_path_buf(),
         }.into_ok()
     }
 
     /// Return the unique handle
+    #[cfg(all(feature = "same-file", not(target_os = "wasi")))]
     pub fn fingerprint_from_path(
         path: &<Self as FsDirEntry>::Path,
     ) -> Result<<Self as FsDirEntry>::DirFingerprint, <Self as FsDirEntry>::Error> {
@@ -171,6 +454,20 @@ impl StandardDirEntry {
         }.into_ok()
     }
 
+    /// Return the unique handle (without `same-file`, e.g. on WASI where there are no
+    /// file handles, this is the canonical path, or the normalized absolute path if
+    /// canonicalization isn't supported by the runtime)
+    #[cfg(not(all(feature = "same-file", not(target_os = "wasi"))))]
+    pub fn fingerprint_from_path(
+        path: &<Self as FsDirEntry>::Path,
+    ) -> Result<<Self as FsDirEntry>::DirFingerprint, <Self as FsDirEntry>::Error> {
+        let path = match std::fs::canonicalize(path) {
+            Ok(path) => path,
+            Err(_) => normalize_path(&std::env::current_dir()?.join(path)),
+        };
+        StandardDirFingerprint { path }.into_ok()
+    }
+
     /// device_num
     pub fn device_num_from_path(
         _path: &<Self as FsDirEntry>::Path,
@@ -178,15 +475,35 @@ impl StandardDirEntry {
         ().into_ok()
     }
 
+    /// Check if the extended attribute `name` is set
+    #[cfg(all(unix, feature = "xattr"))]
+    pub fn has_xattr_from_path(
+        path: &<Self as FsDirEntry>::Path,
+        name: &str,
+        follow_link: bool,
+    ) -> Result<bool, <Self as FsDirEntry>::Error> {
+        let value = if follow_link {
+            xattr::get_deref(path, name)
+        } else {
+            xattr::get(path, name)
+        };
+        match value {
+            Ok(value) => Ok(value.is_some()),
+            // The file system doesn't support extended attributes at all
+            Err(err) if err.kind() == std::io::ErrorKind::Unsupported => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
 }
 
 /// Functions for FsDirEntry
 impl FsDirEntry for StandardDirEntry {
-    type Context        = ();
+    type Context        = StandardContext;
 
     type Path           = std::path::Path;
     type PathBuf        = std::path::PathBuf;
-    type FileName       = std::ffi::OsString;
+    type FileName       = std::ffi::OsStr;
 
     type Error          = std::io::Error;
     type FileType       = std::fs::FileType;
@@ -195,6 +512,7 @@ impl FsDirEntry for StandardDirEntry {
     type DirFingerprint = StandardDirFingerprint;
     type DeviceNum      = ();
     type RootDirEntry   = StandardRootDirEntry;
+    type Reader         = std::fs::File;
 
     /// Get path of this entry
     fn path(&self) -> &Self::Path {
@@ -208,8 +526,21 @@ impl FsDirEntry for StandardDirEntry {
     fn canonicalize(&self) -> Result<Self::PathBuf, Self::Error> {
         Self::canonicalize_from_path(self.path())
     }
-    fn file_name(&self) -> Self::FileName {
-        self.inner.file_name()
+    fn canonicalize_path(path: &Self::Path) -> Result<Self::PathBuf, Self::Error> {
+        Self::canonicalize_from_path(path)
+    }
+    fn metadata_path(path: &Self::Path, follow_link: bool) -> Result<Self::Metadata, Self::Error> {
+        Self::metadata_from_path(path, follow_link)
+    }
+    fn open_path(path: &Self::Path) -> Result<Self::Reader, Self::Error> {
+        std::fs::File::open(path)
+    }
+    #[cfg(unix)]
+    fn dir_id_path(path: &Self::Path) -> Result<Option<DirId>, Self::Error> {
+        Self::dir_id_from_path(path)
+    }
+    fn file_name(&self) -> &Self::FileName {
+        Self::file_name_from_path(&self.pathbuf)
     }
 
     /// Get file type
@@ -230,17 +561,26 @@ impl FsDirEntry for StandardDirEntry {
     fn metadata(
         &self,
         follow_link: bool,
-        _ctx: &mut Self::Context,
+        ctx: &mut Self::Context,
     ) -> Result<Self::Metadata, Self::Error> {
-        Self::metadata_from_path( &self.pathbuf, follow_link )
+        ctx.metadata(&self.pathbuf, follow_link, &self.metadata)
     }
 
     /// Read dir
     fn read_dir(
         &self,
-        _ctx: &mut Self::Context,
+        ctx: &mut Self::Context,
     ) -> Result<Self::ReadDir, Self::Error> {
-        Self::read_dir_from_path( self.path() )
+        ctx.on_read_dir(self.path());
+        ctx.on_result(self.path(), Self::read_dir_from_path( self.path() ))
+    }
+
+    /// Open file
+    fn open(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<Self::Reader, Self::Error> {
+        ctx.on_result(self.path(), Self::open_path(self.path()))
     }
 
     /// Return the unique handle
@@ -266,16 +606,68 @@ impl FsDirEntry for StandardDirEntry {
         Self::device_num_from_path( self.path() )
     }
 
+    #[cfg(all(unix, feature = "xattr"))]
+    fn has_xattr(
+        &self,
+        name: &str,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        ctx.on_result(self.path(), Self::has_xattr_from_path( self.path(), name, follow_link ))
+    }
+
+    /// Symlink metadata never recalls the placeholder
+    #[cfg(windows)]
+    fn is_placeholder(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        Ok(self.metadata(false, ctx)?.is_placeholder())
+    }
+
+    fn is_binary(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        sniff_binary(self, ctx)
+    }
+
+    #[cfg(all(feature = "linux-flags", target_os = "linux"))]
+    fn is_nodump(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        super::linux::is_nodump(self, follow_link, ctx)
+    }
+
+    /// NTFS and APFS (as they are set up by default) ignore case of names
+    fn is_case_insensitive() -> bool {
+        cfg!(any(windows, target_os = "macos", target_os = "ios"))
+    }
+
+    fn set_readdir_batch(
+        ctx: &mut Self::Context,
+        batch: usize,
+    ) {
+        ctx.set_readdir_batch(batch);
+    }
+
+    fn set_metadata_cache(
+        ctx: &mut Self::Context,
+        policy: CachePolicy,
+    ) {
+        ctx.set_metadata_cache(policy);
+    }
+
     fn to_parts(
         &mut self,
         follow_link: bool,
         force_metadata: bool,
-        force_file_name: bool,
         ctx: &mut Self::Context,
-    ) -> (Self::PathBuf, Option<Self::Metadata>, Option<Self::FileName>) {
+    ) -> (Self::PathBuf, Option<Self::Metadata>) {
         let md = if force_metadata {self.metadata(follow_link, ctx).ok()} else {None};
-        let n = if force_file_name {self.file_name().into_some()} else {None};
-        (self.pathbuf.clone(), md, n)
+        (self.pathbuf.clone(), md)
     }
 }
 
@@ -284,7 +676,44 @@ impl FsDirEntry for StandardDirEntry {
 /// A fingerprint for dir
 #[derive(Debug, PartialEq, Eq)]
 pub struct StandardDirFingerprint {
+    #[cfg(all(feature = "same-file", not(target_os = "wasi")))]
     handle: same_file::Handle,
+    #[cfg(not(all(feature = "same-file", not(target_os = "wasi"))))]
+    path: std::path::PathBuf,
+}
+
+/// Count of bytes sniffed by `sniff_binary`
+const BINARY_SNIFF_LEN: u64 = 8192;
+
+/// Check if a file looks binary: there is a NUL byte among its first 8 KiB
+pub(crate) fn sniff_binary<E>(ent: &E, ctx: &mut E::Context) -> std::io::Result<bool>
+where
+    E: FsDirEntry<Error = std::io::Error>,
+    E::Reader: std::io::Read,
+{
+    use std::io::Read;
+
+    let mut prefix = Vec::with_capacity(BINARY_SNIFF_LEN as usize);
+    ent.open(ctx)?.take(BINARY_SNIFF_LEN).read_to_end(&mut prefix)?;
+    Ok(prefix.contains(&0))
+}
+
+/// Resolve `.` and `..` components lexically
+#[cfg(not(all(feature = "same-file", not(target_os = "wasi"))))]
+fn normalize_path(path: &std::path::Path) -> std::path::PathBuf {
+    use std::path::Component;
+
+    let mut normalized = std::path::PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            component => normalized.push(component.as_os_str()),
+        }
+    }
+    normalized
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////
@@ -293,6 +722,7 @@ pub struct StandardDirFingerprint {
 #[derive(Debug)]
 pub struct StandardRootDirEntry {
     pathbuf:    std::path::PathBuf,
+    metadata:   KeptMetadata,
 }
 
 /// Functions for FsDirEntry
@@ -306,6 +736,7 @@ impl FsRootDirEntry for StandardRootDirEntry {
     ) -> Result<Self, <Self::DirEntry as FsDirEntry>::Error> {
         Self {
             pathbuf: path.to_path_buf(),
+            metadata: KeptMetadata::new(),
         }.into_ok()
     }
 
@@ -324,7 +755,7 @@ impl FsRootDirEntry for StandardRootDirEntry {
 
     fn file_name(
         &self
-    ) -> <Self::DirEntry as FsDirEntry>::FileName {
+    ) -> &<Self::DirEntry as FsDirEntry>::FileName {
         StandardDirEntry::file_name_from_path( self.path() )
     }
 
@@ -342,17 +773,18 @@ impl FsRootDirEntry for StandardRootDirEntry {
     fn metadata(
         &self,
         follow_link: bool,
-        _ctx: &mut <Self::DirEntry as FsDirEntry>::Context,
+        ctx: &mut <Self::DirEntry as FsDirEntry>::Context,
     ) -> Result<<Self::DirEntry as FsDirEntry>::Metadata, <Self::DirEntry as FsDirEntry>::Error> {
-        StandardDirEntry::metadata_from_path( self.path(), follow_link )
+        ctx.metadata(self.path(), follow_link, &self.metadata)
     }
 
     /// Read dir
     fn read_dir(
         &self,
-        _ctx: &mut <Self::DirEntry as FsDirEntry>::Context,
+        ctx: &mut <Self::DirEntry as FsDirEntry>::Context,
     ) -> Result<<Self::DirEntry as FsDirEntry>::ReadDir, <Self::DirEntry as FsDirEntry>::Error> {
-        StandardDirEntry::read_dir_from_path( self.path() )
+        ctx.on_read_dir(self.path());
+        ctx.on_result(self.path(), StandardDirEntry::read_dir_from_path( self.path() ))
     }
 
     /// Return the unique handle
@@ -375,11 +807,9 @@ impl FsRootDirEntry for StandardRootDirEntry {
         &mut self,
         follow_link: bool,
         force_metadata: bool,
-        force_file_name: bool,
         ctx: &mut Self::Context,
-    ) -> (<Self::DirEntry as FsDirEntry>::PathBuf, Option<<Self::DirEntry as FsDirEntry>::Metadata>, Option<<Self::DirEntry as FsDirEntry>::FileName>) {
+    ) -> (<Self::DirEntry as FsDirEntry>::PathBuf, Option<<Self::DirEntry as FsDirEntry>::Metadata>) {
         let md = if force_metadata {self.metadata(follow_link, ctx).ok()} else {None};
-        let n = if force_file_name {self.file_name().into_some()} else {None};
-        (self.pathbuf.clone(), md, n)
+        (self.pathbuf.clone(), md)
     }
 }
@@ -125,7 +125,7 @@
 
 use crate::fs::standard::{StandardDirEntry, StandardReadDir, StandardRootDirEntry};
 use crate::fs::{FsDirEntry, FsReadDir, FsRootDirEntry};
-use crate::wd::IntoOk;
+use crate::wd::{CachePolicy, DirId, IntoOk};
 
 use std::fmt::Debug;
 
@@ -173,7 +173,7 @@ impl Iterator for UnixReadDir {
     type Item = Result<UnixDirEntry, std::io::Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.next_fsentry(&mut ())
+        self.next_fsentry(&mut Default::default())
     }
 }
 
@@ -260,6 +260,7 @@ impl FsDirEntry for UnixDirEntry {
     type DirFingerprint = <StandardDirEntry as FsDirEntry>::DirFingerprint;
     type DeviceNum      = u64;
     type RootDirEntry   = UnixRootDirEntry;
+    type Reader         = <StandardDirEntry as FsDirEntry>::Reader;
 
     /// Get path of this entry
     fn path(&self) -> &Self::Path {
@@ -273,7 +274,19 @@ impl FsDirEntry for UnixDirEntry {
     fn canonicalize(&self) -> Result<Self::PathBuf, Self::Error> {
         self.standard.canonicalize()
     }
-    fn file_name(&self) -> Self::FileName {
+    fn canonicalize_path(path: &Self::Path) -> Result<Self::PathBuf, Self::Error> {
+        StandardDirEntry::canonicalize_from_path(path)
+    }
+    fn metadata_path(path: &Self::Path, follow_link: bool) -> Result<Self::Metadata, Self::Error> {
+        StandardDirEntry::metadata_from_path(path, follow_link)
+    }
+    fn open_path(path: &Self::Path) -> Result<Self::Reader, Self::Error> {
+        StandardDirEntry::open_path(path)
+    }
+    fn dir_id_path(path: &Self::Path) -> Result<Option<DirId>, Self::Error> {
+        StandardDirEntry::dir_id_from_path(path)
+    }
+    fn file_name(&self) -> &Self::FileName {
         self.standard.file_name()
     }
 
@@ -283,8 +296,7 @@ impl FsDirEntry for UnixDirEntry {
         follow_link: bool,
         ctx: &mut Self::Context,
     ) -> Result<Self::FileType, Self::Error> {
-        let metadata = self.metadata(follow_link, ctx)?;
-        metadata.file_type().into_ok()
+        self.standard.file_type(follow_link, ctx)
     }
 
     /// Get metadata
@@ -306,6 +318,14 @@ impl FsDirEntry for UnixDirEntry {
         }.into_ok()
     }
 
+    /// Open file
+    fn open(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<Self::Reader, Self::Error> {
+        self.standard.open(ctx)
+    }
+
     /// Return the unique handle
     fn fingerprint(
         &self,
@@ -324,19 +344,63 @@ impl FsDirEntry for UnixDirEntry {
     /// device_num
     fn device_num(
         &self,
-        _ctx: &mut Self::Context,
+        ctx: &mut Self::Context,
     ) -> Result<Self::DeviceNum, Self::Error> {
-        Self::device_num_from_path( self.path() )
+        ctx.on_metadata(self.path(), true);
+        ctx.on_result(self.path(), Self::device_num_from_path( self.path() ))
+    }
+
+    #[cfg(feature = "xattr")]
+    fn has_xattr(
+        &self,
+        name: &str,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        ctx.on_result(self.path(), StandardDirEntry::has_xattr_from_path( self.path(), name, follow_link ))
+    }
+
+    fn is_binary(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        self.standard.is_binary(ctx)
+    }
+
+    #[cfg(all(feature = "linux-flags", target_os = "linux"))]
+    fn is_nodump(
+        &self,
+        follow_link: bool,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        super::linux::is_nodump(self, follow_link, ctx)
+    }
+
+    fn is_case_insensitive() -> bool {
+        StandardDirEntry::is_case_insensitive()
+    }
+
+    fn set_readdir_batch(
+        ctx: &mut Self::Context,
+        batch: usize,
+    ) {
+        StandardDirEntry::set_readdir_batch(ctx, batch);
+    }
+
+    fn set_metadata_cache(
+        ctx: &mut Self::Context,
+        policy: CachePolicy,
+    ) {
+        StandardDirEntry::set_metadata_cache(ctx, policy);
     }
 
     fn to_parts(
         &mut self,
         follow_link: bool,
         force_metadata: bool,
-        force_file_name: bool,
         ctx: &mut Self::Context,
-    ) -> (Self::PathBuf, Option<Self::Metadata>, Option<Self::FileName>) {
-        self.standard.to_parts( follow_link, force_metadata, force_file_name, ctx )
+    ) -> (Self::PathBuf, Option<Self::Metadata>) {
+        self.standard.to_parts( follow_link, force_metadata, ctx )
     }
 }
 
@@ -377,7 +441,7 @@ impl FsRootDirEntry for UnixRootDirEntry {
 
     fn file_name(
         &self
-    ) -> <Self::DirEntry as FsDirEntry>::FileName {
+    ) -> &<Self::DirEntry as FsDirEntry>::FileName {
         self.standard.file_name()    
     }
 
@@ -420,18 +484,18 @@ impl FsRootDirEntry for UnixRootDirEntry {
     /// device_num
     fn device_num(
         &self,
-        _ctx: &mut <Self::DirEntry as FsDirEntry>::Context,
+        ctx: &mut <Self::DirEntry as FsDirEntry>::Context,
     ) -> Result<<Self::DirEntry as FsDirEntry>::DeviceNum, <Self::DirEntry as FsDirEntry>::Error> {
-        UnixDirEntry::device_num_from_path( self.path() )
+        ctx.on_metadata(self.path(), true);
+        ctx.on_result(self.path(), UnixDirEntry::device_num_from_path( self.path() ))
     }
 
     fn to_parts(
         &mut self,
         follow_link: bool,
         force_metadata: bool,
-        force_file_name: bool,
         ctx: &mut Self::Context,
-    ) -> (<Self::DirEntry as FsDirEntry>::PathBuf, Option<<Self::DirEntry as FsDirEntry>::Metadata>, Option<<Self::DirEntry as FsDirEntry>::FileName>) {
-        self.standard.to_parts( follow_link, force_metadata, force_file_name, ctx )
+    ) -> (<Self::DirEntry as FsDirEntry>::PathBuf, Option<<Self::DirEntry as FsDirEntry>::Metadata>) {
+        self.standard.to_parts( follow_link, force_metadata, ctx )
     }
 }
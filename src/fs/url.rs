@@ -0,0 +1,303 @@
+use core::borrow::Borrow;
+use core::fmt;
+use core::ops::Deref;
+
+use alloc::borrow::{Cow, ToOwned};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::path::{FsPath, FsPathBuf};
+
+/////////////////////////////////////////////////////////////////////////////////
+
+/// A path of a network backend: a URL (e.g. `s3://bucket/dir/key`) or its
+/// path part (e.g. `/dir/key`).
+///
+/// Components are always separated by `/` and are kept percent-encoded, so a
+/// name may contain any character, including `/`. The root of a path is the
+/// scheme with the authority (`s3://bucket`), or `/` for absolute paths
+/// without a scheme; it is never split by [`parent`] or [`file_name`].
+/// Decoded names are returned by [`file_name_lossy`], and [`join`] encodes the
+/// name being added:
+///
+/// ```
+/// use walkdir::{FsPath, UrlPath};
+///
+/// let dir = UrlPath::new("sftp://host/home/my%20docs");
+/// assert_eq!(Some("my%20docs"), dir.file_name());
+/// assert_eq!("my docs", dir.file_name_lossy().unwrap());
+/// assert_eq!("sftp://host/home/my%20docs/a%2Fb.txt", dir.join("a/b.txt").as_str());
+/// assert_eq!(Some(UrlPath::new("sftp://host")), dir.parent().and_then(|p| p.parent()));
+/// ```
+///
+/// [`parent`]: trait.FsPath.html#tymethod.parent
+/// [`file_name`]: trait.FsPath.html#tymethod.file_name
+/// [`file_name_lossy`]: trait.FsPath.html#tymethod.file_name_lossy
+/// [`join`]: trait.FsPath.html#tymethod.join
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct UrlPath(str);
+
+impl UrlPath {
+    /// Wrap a string (which must be already percent-encoded)
+    pub fn new<S: AsRef<str> + ?Sized>(s: &S) -> &Self {
+        let s: &str = s.as_ref();
+        // SAFETY: `UrlPath` is a `repr(transparent)` wrapper of `str`
+        unsafe { &*(s as *const str as *const UrlPath) }
+    }
+
+    /// The path as a string (percent-encoded)
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Percent-encode `name` to be used as one component
+    pub fn encode(name: &str) -> Cow<'_, str> {
+        if name.bytes().all(is_unencoded) {
+            return Cow::Borrowed(name);
+        }
+        let mut encoded = String::with_capacity(name.len() + 8);
+        for b in name.bytes() {
+            if is_unencoded(b) {
+                encoded.push(b as char);
+            } else {
+                encoded.push('%');
+                encoded.push(HEX[(b >> 4) as usize] as char);
+                encoded.push(HEX[(b & 0xf) as usize] as char);
+            }
+        }
+        Cow::Owned(encoded)
+    }
+
+    /// Decode percent-encoded `s` (invalid sequences are kept as is, invalid
+    /// UTF-8 is replaced with `U+FFFD`)
+    pub fn decode(s: &str) -> Cow<'_, str> {
+        if !s.contains('%') {
+            return Cow::Borrowed(s);
+        }
+        let bytes = s.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let hex = match bytes.get(i + 1..i + 3) {
+                Some(&[hi, lo]) if bytes[i] == b'%' => hex_value(hi).zip(hex_value(lo)),
+                _ => None,
+            };
+            match hex {
+                Some((hi, lo)) => {
+                    decoded.push(hi << 4 | lo);
+                    i += 3;
+                },
+                None => {
+                    decoded.push(bytes[i]);
+                    i += 1;
+                },
+            }
+        }
+        Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+    }
+
+    // Length of the root (`scheme://authority` or `/`), 0 for relative paths
+    fn root_len(&self) -> usize {
+        let s = &self.0;
+        match s.find("://") {
+            Some(i) if s[..i].bytes().all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b)) => {
+                let authority = i + 3;
+                s[authority..].find('/').map_or(s.len(), |j| authority + j)
+            },
+            _ if s.starts_with('/') => 1,
+            _ => 0,
+        }
+    }
+
+    // The path without trailing separators (but not shorter than the root)
+    // and the length of the root
+    fn trimmed(&self) -> (&str, usize) {
+        let root_len = self.root_len();
+        let mut end = self.0.len();
+        while end > root_len && self.0.as_bytes()[end - 1] == b'/' {
+            end -= 1;
+        }
+        (&self.0[..end], root_len)
+    }
+}
+
+impl FsPath for UrlPath {
+    type PathBuf = UrlPathBuf;
+    type FileName = str;
+
+    fn to_path_buf(&self) -> UrlPathBuf {
+        UrlPathBuf(self.0.into())
+    }
+
+    fn file_name(&self) -> Option<&str> {
+        let (path, root_len) = self.trimmed();
+        if path.len() <= root_len {
+            return None;
+        }
+        let start = path.rfind('/').map_or(0, |i| i + 1).max(root_len);
+        Some(&path[start..])
+    }
+
+    fn as_file_name(&self) -> &str {
+        &self.0
+    }
+
+    fn parent(&self) -> Option<&Self> {
+        let (path, root_len) = self.trimmed();
+        if path.len() <= root_len {
+            return None;
+        }
+        let end = match path.rfind('/') {
+            Some(i) if i >= root_len => i,
+            _ => root_len,
+        };
+        Some(Self::new(&path[..end]))
+    }
+
+    fn join(&self, name: &str) -> UrlPathBuf {
+        let mut path = self.to_path_buf();
+        path.push(name);
+        path
+    }
+
+    fn file_stem(&self) -> Option<&str> {
+        let name = self.file_name()?;
+        match name.rfind('.') {
+            Some(0) | None => Some(name),
+            Some(i) => Some(&name[..i]),
+        }
+    }
+
+    fn file_name_lossy(&self) -> Option<Cow<'_, str>> {
+        Some(Self::decode(self.file_name()?))
+    }
+
+    fn file_name_bytes(&self) -> Option<&[u8]> {
+        self.file_name().map(str::as_bytes)
+    }
+
+    fn path_len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl AsRef<UrlPath> for UrlPath {
+    fn as_ref(&self) -> &UrlPath {
+        self
+    }
+}
+
+impl AsRef<UrlPath> for str {
+    fn as_ref(&self) -> &UrlPath {
+        UrlPath::new(self)
+    }
+}
+
+impl ToOwned for UrlPath {
+    type Owned = UrlPathBuf;
+
+    fn to_owned(&self) -> UrlPathBuf {
+        self.to_path_buf()
+    }
+}
+
+impl fmt::Display for UrlPath {
+    /// Displays the path as is (percent-encoded)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// An owned [`UrlPath`].
+///
+/// [`UrlPath`]: struct.UrlPath.html
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UrlPathBuf(String);
+
+impl UrlPathBuf {
+    /// Make new empty path
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append the component `name` (it is percent-encoded)
+    pub fn push(&mut self, name: &str) {
+        if !self.0.is_empty() && !self.0.ends_with('/') {
+            self.0.push('/');
+        }
+        self.0.push_str(&UrlPath::encode(name));
+    }
+
+    /// Remove the last component, returns `false` if there is none
+    pub fn pop(&mut self) -> bool {
+        match self.parent().map(|parent| parent.0.len()) {
+            Some(len) => {
+                self.0.truncate(len);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Unwrap into the string (percent-encoded)
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl From<String> for UrlPathBuf {
+    /// Wrap a string (which must be already percent-encoded)
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+impl From<&str> for UrlPathBuf {
+    /// Copy a string (which must be already percent-encoded)
+    fn from(s: &str) -> Self {
+        Self(s.into())
+    }
+}
+
+impl Deref for UrlPathBuf {
+    type Target = UrlPath;
+
+    fn deref(&self) -> &UrlPath {
+        UrlPath::new(&self.0)
+    }
+}
+
+impl AsRef<UrlPath> for UrlPathBuf {
+    fn as_ref(&self) -> &UrlPath {
+        self
+    }
+}
+
+impl Borrow<UrlPath> for UrlPathBuf {
+    fn borrow(&self) -> &UrlPath {
+        self
+    }
+}
+
+impl<'s> FsPathBuf<'s> for UrlPathBuf {
+    type Display = &'s UrlPath;
+
+    #[inline(always)]
+    fn display(&'s self) -> Self::Display {
+        self
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////////
+
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+// Bytes allowed in a path component as is (RFC 3986 `pchar` without `%`)
+fn is_unencoded(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&b)
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
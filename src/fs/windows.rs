@@ -1,6 +1,6 @@
 use crate::fs::standard::{StandardDirEntry, StandardReadDir, StandardRootDirEntry};
-use crate::fs::{FsDirEntry, FsReadDir, FsRootDirEntry};
-use crate::wd::IntoOk;
+use crate::fs::{FsDirEntry, FsMetadata, FsReadDir, FsRootDirEntry};
+use crate::wd::{CachePolicy, DirId, IntoOk, IntoSome, LinkKind};
 
 use std::fmt::Debug;
 use std::fs;
@@ -49,7 +49,7 @@ impl Iterator for WindowsReadDir {
     type Item = Result<WindowsDirEntry, std::io::Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.next_fsentry(&mut ())
+        self.next_fsentry(&mut Default::default())
     }
 }
 
@@ -123,6 +123,155 @@ impl WindowsDirEntry {
         let h = Handle::from_path_any(path)?;
         file::information(h).map(|info| info.volume_serial_number())
     }
+
+    /// Persistent fingerprint (volume serial number and file index)
+    pub fn dir_id_from_path(
+        path: &<Self as FsDirEntry>::Path,
+    ) -> Result<Option<DirId>, <Self as FsDirEntry>::Error> {
+        use winapi_util::{file, Handle};
+
+        let h = Handle::from_path_any(path)?;
+        file::information(h).map(|info| DirId {
+            device: info.volume_serial_number(),
+            index: info.file_index(),
+        }.into_some())
+    }
+
+    /// Kind of the link at given path (`None` if it isn't a link)
+    pub fn link_kind_from_path(
+        path: &<Self as FsDirEntry>::Path,
+    ) -> Result<Option<LinkKind>, <Self as FsDirEntry>::Error> {
+        use std::os::windows::fs::OpenOptionsExt;
+        use std::os::windows::io::AsRawHandle;
+        use winapi::shared::minwindef::DWORD;
+        use winapi::um::ioapiset::DeviceIoControl;
+        use winapi::um::winioctl::FSCTL_GET_REPARSE_POINT;
+        use winapi::um::winnt::HANDLE;
+
+        const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+        const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+        const MAXIMUM_REPARSE_DATA_BUFFER_SIZE: usize = 16 * 1024;
+        const ERROR_NOT_A_REPARSE_POINT: i32 = 4390;
+        const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+        const IO_REPARSE_TAG_SYMLINK: u32 = 0xA000_000C;
+        const IO_REPARSE_TAG_APPEXECLINK: u32 = 0x8000_001B;
+
+        // Open the link itself, only to read its attributes
+        let file = std::fs::OpenOptions::new()
+            .access_mode(0)
+            .custom_flags(FILE_FLAG_OPEN_REPARSE_POINT | FILE_FLAG_BACKUP_SEMANTICS)
+            .open(path)?;
+
+        let mut buf = vec![0u8; MAXIMUM_REPARSE_DATA_BUFFER_SIZE];
+        let mut len: DWORD = 0;
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle() as HANDLE,
+                FSCTL_GET_REPARSE_POINT,
+                std::ptr::null_mut(),
+                0,
+                buf.as_mut_ptr() as _,
+                buf.len() as DWORD,
+                &mut len,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(ERROR_NOT_A_REPARSE_POINT) {
+                return Ok(None);
+            }
+            return Err(err);
+        }
+
+        // REPARSE_DATA_BUFFER: tag, data length and reserved, then (for mount points)
+        // offset and length of the substitute name within the path buffer at 16
+        let u16_at = |i: usize| u16::from_le_bytes([buf[i], buf[i + 1]]) as usize;
+        let tag = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let kind = match tag {
+            IO_REPARSE_TAG_SYMLINK => LinkKind::Symlink,
+            IO_REPARSE_TAG_APPEXECLINK => LinkKind::AppExecLink,
+            IO_REPARSE_TAG_MOUNT_POINT => {
+                // Volume mount points target `\??\Volume{GUID}\`, junctions target dirs
+                let start = 16 + u16_at(8);
+                let name: Vec<u16> = buf.get(start..start + u16_at(10))
+                    .unwrap_or(&[])
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect();
+                let volume: Vec<u16> = "\\??\\Volume{".encode_utf16().collect();
+                if name.starts_with(&volume) { LinkKind::MountPoint } else { LinkKind::Junction }
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(kind))
+    }
+
+    /// Paths of alternate data streams of the file at given path (like `file.txt:name`)
+    pub fn alternate_streams_from_path(
+        path: &<Self as FsDirEntry>::Path,
+    ) -> Result<Vec<<Self as FsDirEntry>::PathBuf>, <Self as FsDirEntry>::Error> {
+        use std::ffi::OsString;
+        use std::os::windows::ffi::{OsStrExt, OsStringExt};
+        use winapi::shared::winerror::ERROR_HANDLE_EOF;
+        use winapi::um::fileapi::{FindClose, FindFirstStreamW, FindNextStreamW};
+        use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+        use winapi::um::minwinbase::{FindStreamInfoStandard, WIN32_FIND_STREAM_DATA};
+
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut data: WIN32_FIND_STREAM_DATA = unsafe { std::mem::zeroed() };
+        let handle = unsafe {
+            FindFirstStreamW(wide.as_ptr(), FindStreamInfoStandard, &mut data as *mut _ as _, 0)
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) {
+                return Ok(vec![]);
+            }
+            return Err(err);
+        }
+
+        // Names look like `:name:$DATA`, the main stream is `::$DATA`
+        let suffix: Vec<u16> = ":$DATA".encode_utf16().collect();
+        let mut streams = vec![];
+        let res = loop {
+            let len = data.cStreamName.iter().position(|&c| c == 0).unwrap_or(data.cStreamName.len());
+            let name = &data.cStreamName[..len];
+            if name.len() > suffix.len() + 1 && name.ends_with(&suffix) {
+                let mut stream = path.as_os_str().to_os_string();
+                stream.push(OsString::from_wide(&name[..name.len() - suffix.len()]));
+                streams.push(stream.into());
+            }
+            if unsafe { FindNextStreamW(handle, &mut data as *mut _ as _) } == 0 {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) {
+                    break Ok(streams);
+                }
+                break Err(err);
+            }
+        };
+        unsafe { FindClose(handle) };
+        res
+    }
+
+    /// Count of bytes allocated on disk (of the target, for symlinks)
+    fn allocated_size_from_path(
+        path: &<Self as FsDirEntry>::Path,
+    ) -> Option<u64> {
+        use std::os::windows::ffi::OsStrExt;
+        use winapi::shared::minwindef::DWORD;
+        use winapi::um::errhandlingapi::GetLastError;
+        use winapi::um::fileapi::{GetCompressedFileSizeW, INVALID_FILE_SIZE};
+
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut high: DWORD = 0;
+        let low = unsafe { GetCompressedFileSizeW(wide.as_ptr(), &mut high) };
+        // INVALID_FILE_SIZE is a valid low part of large sizes, so the error must be checked too
+        if low == INVALID_FILE_SIZE && unsafe { GetLastError() } != 0 {
+            return None;
+        }
+        Some((u64::from(high) << 32) | u64::from(low))
+    }
 }
 
 /// Functions for FsDirEntry
@@ -140,6 +289,7 @@ impl FsDirEntry for WindowsDirEntry {
     type DirFingerprint = <StandardDirEntry as FsDirEntry>::DirFingerprint;
     type DeviceNum      = u64;
     type RootDirEntry   = WindowsRootDirEntry;
+    type Reader         = <StandardDirEntry as FsDirEntry>::Reader;
 
     /// Get path of this entry
     fn path(&self) -> &Self::Path {
@@ -153,7 +303,22 @@ impl FsDirEntry for WindowsDirEntry {
     fn canonicalize(&self) -> Result<Self::PathBuf, Self::Error> {
         self.standard.canonicalize()
     }
-    fn file_name(&self) -> Self::FileName {
+    fn canonicalize_path(path: &Self::Path) -> Result<Self::PathBuf, Self::Error> {
+        StandardDirEntry::canonicalize_from_path(path)
+    }
+    fn metadata_path(path: &Self::Path, follow_link: bool) -> Result<Self::Metadata, Self::Error> {
+        StandardDirEntry::metadata_from_path(path, follow_link)
+    }
+    fn open_path(path: &Self::Path) -> Result<Self::Reader, Self::Error> {
+        StandardDirEntry::open_path(path)
+    }
+    fn allocated_size_path(path: &Self::Path) -> Option<u64> {
+        Self::allocated_size_from_path(path)
+    }
+    fn dir_id_path(path: &Self::Path) -> Result<Option<DirId>, Self::Error> {
+        Self::dir_id_from_path(path)
+    }
+    fn file_name(&self) -> &Self::FileName {
         self.standard.file_name()
     }
 
@@ -194,6 +359,14 @@ impl FsDirEntry for WindowsDirEntry {
         }.into_ok()
     }
 
+    /// Open file
+    fn open(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<Self::Reader, Self::Error> {
+        self.standard.open(ctx)
+    }
+
     /// Return the unique handle
     fn fingerprint(
         &self,
@@ -217,20 +390,89 @@ impl FsDirEntry for WindowsDirEntry {
         Self::device_num_from_path( self.path() )
     }
 
+    /// Only reparse points are queried
+    fn link_kind(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<Option<LinkKind>, Self::Error> {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+        if self.metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT == 0 {
+            return Ok(None);
+        }
+        Self::link_kind_from_path(self.path())
+    }
+
+    fn alternate_streams(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<Vec<Self::PathBuf>, Self::Error> {
+        Self::alternate_streams_from_path(self.path())
+    }
+
+    /// The metadata read with the dir is used, so the placeholder is never recalled
+    fn is_placeholder(
+        &self,
+        _ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        Ok(self.metadata.is_placeholder())
+    }
+
+    fn is_binary(
+        &self,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        self.standard.is_binary(ctx)
+    }
+
+    /// Only reparse points (volume mount points, junctions and symlinks) may
+    /// be mount points, so others are rejected without opening a handle.
+    fn is_mount_point(
+        &self,
+        parent_device: &Self::DeviceNum,
+        ctx: &mut Self::Context,
+    ) -> Result<bool, Self::Error> {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+        if self.metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT == 0 {
+            return Ok(false);
+        }
+        Ok(self.device_num(ctx)? != *parent_device)
+    }
+
+    fn is_case_insensitive() -> bool {
+        StandardDirEntry::is_case_insensitive()
+    }
+
+    fn set_readdir_batch(
+        ctx: &mut Self::Context,
+        batch: usize,
+    ) {
+        StandardDirEntry::set_readdir_batch(ctx, batch);
+    }
+
+    fn set_metadata_cache(
+        ctx: &mut Self::Context,
+        policy: CachePolicy,
+    ) {
+        StandardDirEntry::set_metadata_cache(ctx, policy);
+    }
+
     fn to_parts(
         &mut self,
         follow_link: bool,
         force_metadata: bool,
-        force_file_name: bool,
         ctx: &mut Self::Context,
-    ) -> (Self::PathBuf, Option<Self::Metadata>, Option<Self::FileName>) {
+    ) -> (Self::PathBuf, Option<Self::Metadata>) {
         let (fmd, md) = if !follow_link {
             (false, Some(self.metadata.clone()))
         } else {
             (force_metadata, None)
         };
 
-        let (pathbuf, smd, n) = self.standard.to_parts( follow_link, fmd, force_file_name, ctx );
+        let (pathbuf, smd) = self.standard.to_parts( follow_link, fmd, ctx );
 
         let md = if !follow_link {
             md
@@ -238,7 +480,7 @@ impl FsDirEntry for WindowsDirEntry {
             smd
         };
 
-        (pathbuf, md, n)
+        (pathbuf, md)
     }
 }
 
@@ -279,7 +521,7 @@ impl FsRootDirEntry for WindowsRootDirEntry {
 
     fn file_name(
         &self
-    ) -> <Self::DirEntry as FsDirEntry>::FileName {
+    ) -> &<Self::DirEntry as FsDirEntry>::FileName {
         self.standard.file_name()    
     }
 
@@ -331,9 +573,8 @@ impl FsRootDirEntry for WindowsRootDirEntry {
         &mut self,
         follow_link: bool,
         force_metadata: bool,
-        force_file_name: bool,
         ctx: &mut Self::Context,
-    ) -> (<Self::DirEntry as FsDirEntry>::PathBuf, Option<<Self::DirEntry as FsDirEntry>::Metadata>, Option<<Self::DirEntry as FsDirEntry>::FileName>) {
-        self.standard.to_parts( follow_link, force_metadata, force_file_name, ctx )
+    ) -> (<Self::DirEntry as FsDirEntry>::PathBuf, Option<<Self::DirEntry as FsDirEntry>::Metadata>) {
+        self.standard.to_parts( follow_link, force_metadata, ctx )
     }
 }
@@ -101,10 +101,27 @@ for entry in walker.filter_entry(|e| !is_hidden(e)) {
 ```
 
 [`filter_entry`]: struct.IntoIter.html#method.filter_entry
+
+# `no_std`
+
+The traversal itself only needs `alloc`. With default features disabled, the crate
+is `no_std` and walks any custom [`FsDirEntry`] implementation; the standard
+backends (and everything built upon them) require the `std` feature, which is
+enabled by default.
+
+```toml
+[dependencies]
+walkdir = { version = "3", default-features = false }
+```
+
+[`FsDirEntry`]: trait.FsDirEntry.html
 */
 
 #![deny(missing_docs)]
 #![allow(unknown_lints)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
 #[cfg(doctest)]
 doc_comment::doctest!("../README.md");
@@ -113,15 +130,27 @@ mod error;
 mod fs;
 mod walk;
 mod cp;
+#[cfg(feature = "std")]
+pub mod emit;
+#[cfg(any(all(test, feature = "std"), feature = "testkit"))]
+pub mod testlib;
+#[cfg(feature = "testkit")]
+pub mod synth;
+#[cfg(feature = "notify")]
+mod watch;
+#[cfg(feature = "std")]
+mod util;
 // mod classic_iter;
 // mod dent;
 // mod dir;
 // mod iter;
 // mod opts;
 // pub mod storage;
-// #[cfg(test)]
-// mod tests;
+#[cfg(all(test, feature = "std"))]
+mod tests;
 mod wd;
+mod unorm;
+mod rng;
 
 // pub use crate::dent::DirEntry;
 // #[cfg(unix)]
@@ -140,6 +169,38 @@ mod wd;
 
 pub use wd::*;
 pub use walk::*;
-pub use error::Error;
+pub use error::{Error, ErrorKind};
 pub use fs::*;
 pub use cp::*;
+#[cfg(feature = "notify")]
+pub use watch::{WatchDir, WatchEvent};
+#[cfg(feature = "std")]
+pub use util::{
+    ancestors, count, extremes, find_empty_dirs, grep, largest, list_dir, Ancestors, CountOptions, Counts, Extremes,
+    ExtremesBy, Grep, GrepMatch, GrepOptions, ListOptions, sync_walk, SyncPosition, SyncWalk,
+};
+#[cfg(feature = "parallel")]
+pub use util::{par_walk, par_walk_on, Parallelism, ParWait, ParWalk, ParWalkOptions, Spawn, SpawnThreads};
+
+// Iterators over the standard backends, their entries and errors may be moved
+// between threads (see `into_sendable`); entries and errors may be shared too.
+#[cfg(feature = "std")]
+const _: fn() = || {
+    fn assert_backend<E>()
+    where
+        E: FsDirEntry,
+        WalkDirIterator<E, DirEntryContentProcessor>: Send,
+        ClassicIter<E, DirEntryContentProcessor, WalkDirIterator<E, DirEntryContentProcessor>>: Send,
+        DirEntry<E>: Send + Sync,
+        Error<E>: Send + Sync,
+    {
+    }
+
+    assert_backend::<StandardDirEntry>();
+    assert_backend::<DefaultDirEntry>();
+    assert_backend::<ReadOnlyFs>();
+    #[cfg(feature = "cache")]
+    assert_backend::<CachedDirEntry>();
+    #[cfg(feature = "testkit")]
+    assert_backend::<FlakyFs<DefaultDirEntry>>();
+};
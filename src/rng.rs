@@ -0,0 +1,36 @@
+/*!
+A tiny deterministic pseudo-random generator (xorshift64*), shared by sampling
+of dirs (see `WalkDir::sample`), random trees of `testlib` and random failures
+of `FlakyFs`.
+*/
+
+/// A tiny deterministic pseudo-random generator (xorshift64*)
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    /// Make new generator from seed
+    pub fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// Next random number
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Random number in `0..n` (`n` must be positive)
+    #[cfg(any(all(test, feature = "std"), feature = "testkit"))]
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// True with probability of `percent`%
+    #[cfg(any(all(test, feature = "std"), feature = "testkit"))]
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
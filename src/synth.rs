@@ -0,0 +1,184 @@
+/*!
+Synthetic trees for benchmarks.
+
+[`make_tree`] creates a tree of a regular shape described by a [`SynthSpec`]:
+every dir has the same count of entries and subdirs down to the given depth, so
+the size of the tree is known in advance and stays the same between runs. File
+sizes and symlinks are chosen pseudo-randomly from the spec's seed.
+
+The module is available with the `testkit` feature.
+
+[`make_tree`]: fn.make_tree.html
+[`SynthSpec`]: struct.SynthSpec.html
+*/
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::testlib::Rng;
+use crate::wd::IntoOk;
+
+/////////////////////////////////////////////////////////////////////////
+
+/// Distribution of sizes of created files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSizes {
+    /// All files are empty
+    Empty,
+    /// All files have the given size
+    Fixed(usize),
+    /// Sizes are uniformly distributed in `min..=max`
+    Uniform {
+        /// Smallest size
+        min: usize,
+        /// Largest size
+        max: usize,
+    },
+}
+
+/// Shape of a synthetic tree
+#[derive(Debug, Clone)]
+pub struct SynthSpec {
+    /// Count of entries in every dir (including subdirs)
+    pub width: usize,
+    /// Count of subdirs in every dir above `depth` (at most `width`)
+    pub dirs: usize,
+    /// Depth of the deepest dirs (the root has depth `0`)
+    pub depth: usize,
+    /// Sizes of created files
+    pub file_sizes: FileSizes,
+    /// Percentage of non-dir entries created as symlinks to files made before them
+    pub symlink_percent: usize,
+    /// Seed of file sizes and symlinks
+    pub seed: u64,
+}
+
+impl Default for SynthSpec {
+    fn default() -> Self {
+        Self {
+            width: 10,
+            dirs: 3,
+            depth: 4,
+            file_sizes: FileSizes::Empty,
+            symlink_percent: 0,
+            seed: 0,
+        }
+    }
+}
+
+/// Counts of entries in a synthetic tree (the root isn't counted)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SynthCounts {
+    /// Count of dirs
+    pub dirs: usize,
+    /// Count of regular files
+    pub files: usize,
+    /// Count of symlinks
+    pub symlinks: usize,
+}
+
+impl SynthCounts {
+    /// Count of all entries
+    pub fn entries(&self) -> usize {
+        self.dirs + self.files + self.symlinks
+    }
+}
+
+/// A synthetic tree in a temporary dir, removed on drop
+#[derive(Debug)]
+pub struct SynthTree {
+    root: PathBuf,
+    counts: SynthCounts,
+}
+
+impl SynthTree {
+    /// Root of the tree
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Counts of created entries
+    pub fn counts(&self) -> SynthCounts {
+        self.counts
+    }
+}
+
+impl Drop for SynthTree {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Create a tree described by `spec` in a new temporary dir
+pub fn make_tree(spec: &SynthSpec) -> io::Result<SynthTree> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let root = std::env::temp_dir().join(format!(
+        "walkdir-synth-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    if root.exists() {
+        fs::remove_dir_all(&root)?;
+    }
+    fs::create_dir_all(&root)?;
+
+    // Dropping the tree on error removes what was created so far
+    let mut tree = SynthTree { root, counts: SynthCounts::default() };
+
+    let mut rng = Rng::new(spec.seed);
+    let mut files = vec![];
+    let mut dirs = vec![(tree.root.clone(), 0)];
+    let mut i = 0;
+    while i < dirs.len() {
+        let (dir, depth) = dirs[i].clone();
+        let subdirs = if depth < spec.depth { spec.dirs.min(spec.width) } else { 0 };
+        for n in 0..spec.width {
+            if n < subdirs {
+                let path = dir.join(format!("d{}", n));
+                fs::create_dir(&path)?;
+                dirs.push((path, depth + 1));
+                tree.counts.dirs += 1;
+            } else if !files.is_empty() && rng.chance(spec.symlink_percent) {
+                let path = dir.join(format!("l{}", n));
+                let target: &PathBuf = &files[rng.below(files.len())];
+                symlink(target, &path)?;
+                tree.counts.symlinks += 1;
+            } else {
+                let path = dir.join(format!("f{}", n));
+                fs::write(&path, vec![b'x'; file_size(spec.file_sizes, &mut rng)])?;
+                files.push(path);
+                tree.counts.files += 1;
+            }
+        }
+        i += 1;
+    }
+
+    tree.into_ok()
+}
+
+fn file_size(sizes: FileSizes, rng: &mut Rng) -> usize {
+    match sizes {
+        FileSizes::Empty => 0,
+        FileSizes::Fixed(size) => size,
+        FileSizes::Uniform { min, max } if max > min => min + rng.below(max - min + 1),
+        FileSizes::Uniform { min, .. } => min,
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    fs::copy(target, link).map(|_| ())
+}
@@ -0,0 +1,353 @@
+/*!
+Helpers for property-based testing of the walker.
+
+* [`RandomTree`] generates a random tree of dirs, files and symlinks (including
+  broken ones and links looping back to ancestors) from a seed and a [`TreeSpec`].
+  The tree is removed when the value is dropped.
+* [`oracle`] walks a tree with a naive recursive reference implementation built
+  directly upon `std::fs`.
+* [`check`] runs both the walker and the oracle with the same [`OracleOptions`] and
+  reports the first difference; [`check_all_options`] does this for every option
+  combination.
+
+The module is available in tests of this crate and with the `testkit` feature.
+
+[`RandomTree`]: struct.RandomTree.html
+[`TreeSpec`]: struct.TreeSpec.html
+[`oracle`]: fn.oracle.html
+[`check`]: fn.check.html
+[`check_all_options`]: fn.check_all_options.html
+[`OracleOptions`]: struct.OracleOptions.html
+*/
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::fs::DefaultDirEntry;
+use crate::walk::WalkDirBuilder;
+use crate::wd::{Depth, IntoOk};
+
+pub use crate::rng::Rng;
+
+/////////////////////////////////////////////////////////////////////////
+
+/// What a random tree may contain
+#[derive(Debug, Clone)]
+pub struct TreeSpec {
+    /// Maximum depth of nested dirs
+    pub max_depth: usize,
+    /// Maximum count of entries in one dir
+    pub max_entries: usize,
+    /// Create symlinks to files and dirs (and broken ones)
+    pub symlinks: bool,
+    /// Create symlinks pointing to ancestor dirs
+    pub loops: bool,
+    /// Make some dirs unreadable (unix only, has no effect when run as root)
+    pub unreadable: bool,
+}
+
+impl Default for TreeSpec {
+    fn default() -> Self {
+        Self { max_depth: 4, max_entries: 5, symlinks: true, loops: true, unreadable: true }
+    }
+}
+
+/// A random tree in a temporary dir, removed on drop
+#[derive(Debug)]
+pub struct RandomTree {
+    root: PathBuf,
+    seed: u64,
+}
+
+impl RandomTree {
+    /// Generate a tree from `seed`
+    pub fn generate(seed: u64, spec: &TreeSpec) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let root = std::env::temp_dir().join(format!(
+            "walkdir-testlib-{}-{}-{}",
+            std::process::id(),
+            seed,
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        if root.exists() {
+            remove_tree(&root)?;
+        }
+        fs::create_dir_all(&root)?;
+        let this = Self { root, seed };
+
+        let mut rng = Rng::new(seed);
+        let mut dirs = vec![(this.root.clone(), 0)];
+        let mut files = vec![];
+        let mut i = 0;
+        while i < dirs.len() {
+            let (dir, depth) = dirs[i].clone();
+            for n in 0..rng.below(spec.max_entries + 1) {
+                let path = dir.join(format!("e{}", n));
+                if depth + 1 < spec.max_depth && rng.chance(40) {
+                    fs::create_dir(&path)?;
+                    dirs.push((path, depth + 1));
+                } else {
+                    fs::write(&path, n.to_string())?;
+                    files.push(path);
+                }
+            }
+            i += 1;
+        }
+
+        if spec.symlinks || spec.loops {
+            for (dir, _) in dirs.clone() {
+                if !rng.chance(30) {
+                    continue;
+                }
+                let link = dir.join("link");
+                let target = match rng.below(4) {
+                    0 if spec.loops => {
+                        let ancestors: Vec<_> =
+                            dir.ancestors().take_while(|a| a.starts_with(&this.root)).collect();
+                        ancestors[rng.below(ancestors.len())].to_path_buf()
+                    },
+                    1 if spec.symlinks && !files.is_empty() => {
+                        files[rng.below(files.len())].clone()
+                    },
+                    2 if spec.symlinks => this.root.join("missing"),
+                    _ if spec.symlinks => dirs[rng.below(dirs.len())].0.clone(),
+                    _ => continue,
+                };
+                symlink(&target, &link)?;
+            }
+        }
+
+        if spec.unreadable {
+            for (dir, depth) in &dirs {
+                if *depth > 0 && rng.chance(10) {
+                    set_readable(dir, false)?;
+                }
+            }
+        }
+
+        this.into_ok()
+    }
+
+    /// Root of the tree
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Seed the tree was generated from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Drop for RandomTree {
+    fn drop(&mut self) {
+        let _ = remove_tree(&self.root);
+    }
+}
+
+fn remove_tree(root: &Path) -> io::Result<()> {
+    fn restore(dir: &Path) -> io::Result<()> {
+        set_readable(dir, true)?;
+        for ent in fs::read_dir(dir)? {
+            let ent = ent?;
+            if ent.file_type()?.is_dir() {
+                restore(&ent.path())?;
+            }
+        }
+        Ok(())
+    }
+    let _ = restore(root);
+    fs::remove_dir_all(root)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    if target.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_readable(dir: &Path, yes: bool) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(dir, fs::Permissions::from_mode(if yes { 0o755 } else { 0o000 }))
+}
+
+#[cfg(not(unix))]
+fn set_readable(_dir: &Path, _yes: bool) -> io::Result<()> {
+    Ok(())
+}
+
+/////////////////////////////////////////////////////////////////////////
+
+/// Options shared by the walker and the oracle
+#[derive(Debug, Clone)]
+pub struct OracleOptions {
+    /// See `WalkDir::follow_links`
+    pub follow_links: bool,
+    /// See `WalkDir::min_depth`
+    pub min_depth: Depth,
+    /// See `WalkDir::max_depth`
+    pub max_depth: Depth,
+    /// See `WalkDir::contents_first` (doesn't change the set of entries)
+    pub contents_first: bool,
+}
+
+impl Default for OracleOptions {
+    fn default() -> Self {
+        Self { follow_links: false, min_depth: 0, max_depth: Depth::MAX, contents_first: false }
+    }
+}
+
+/// Entries (with depths) and count of errors met during a walk
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WalkOutcome {
+    /// Yielded entries
+    pub entries: BTreeSet<(PathBuf, Depth)>,
+    /// Count of yielded errors
+    pub errors: usize,
+}
+
+/// Walk the tree with a naive recursive reference implementation.
+///
+/// With `follow_links`, a link looping back to an ancestor is never yielded. It is
+/// reported as an error only if it is above `max_depth` (this matches the walker
+/// with `yield_loop_links` disabled).
+pub fn oracle(root: &Path, opts: &OracleOptions) -> WalkOutcome {
+    fn visit(
+        path: &Path,
+        depth: Depth,
+        ancestors: &mut Vec<PathBuf>,
+        opts: &OracleOptions,
+        out: &mut WalkOutcome,
+    ) {
+        let md = if opts.follow_links || depth == 0 {
+            fs::metadata(path)
+        } else {
+            fs::symlink_metadata(path)
+        };
+        let md = match md {
+            Ok(md) => md,
+            Err(_) => {
+                out.errors += 1;
+                return;
+            },
+        };
+
+        let canonical = if md.is_dir() { fs::canonicalize(path).ok() } else { None };
+        if opts.follow_links && depth > 0 {
+            if let Some(ref canonical) = canonical {
+                if ancestors.contains(canonical) {
+                    // Like the walker, report a loop only if it would be descended
+                    if depth < opts.max_depth {
+                        out.errors += 1;
+                    }
+                    return;
+                }
+            }
+        }
+
+        if depth >= opts.min_depth {
+            out.entries.insert((path.to_path_buf(), depth));
+        }
+        if !md.is_dir() || depth >= opts.max_depth {
+            return;
+        }
+
+        let rd = match fs::read_dir(path) {
+            Ok(rd) => rd,
+            Err(_) => {
+                out.errors += 1;
+                return;
+            },
+        };
+        ancestors.push(canonical.unwrap_or_else(|| path.to_path_buf()));
+        for ent in rd {
+            match ent {
+                Ok(ent) => visit(&ent.path(), depth + 1, ancestors, opts, out),
+                Err(_) => out.errors += 1,
+            }
+        }
+        ancestors.pop();
+    }
+
+    let mut out = WalkOutcome::default();
+    visit(root, 0, &mut vec![], opts, &mut out);
+    out
+}
+
+/// Walk the tree with the walker
+pub fn walk(root: &Path, opts: &OracleOptions) -> WalkOutcome {
+    let wd = WalkDirBuilder::<DefaultDirEntry>::new(root)
+        .follow_links(opts.follow_links)
+        .min_depth(opts.min_depth)
+        .max_depth(opts.max_depth)
+        .contents_first(opts.contents_first);
+
+    let mut out = WalkOutcome::default();
+    for result in wd.into_classic() {
+        match result {
+            Ok(dent) => {
+                out.entries.insert((dent.path().to_path_buf(), dent.depth()));
+            },
+            Err(_) => out.errors += 1,
+        }
+    }
+    out
+}
+
+/// Compare the walker against the oracle, describing the first difference
+pub fn check(root: &Path, opts: &OracleOptions) -> Result<(), String> {
+    let expected = oracle(root, opts);
+    let got = walk(root, opts);
+    if expected == got {
+        return Ok(());
+    }
+
+    let missing: Vec<_> = expected.entries.difference(&got.entries).collect();
+    let extra: Vec<_> = got.entries.difference(&expected.entries).collect();
+    Err(format!(
+        "walk of {} with {:?} differs from oracle: \
+         missing {:?}, extra {:?}, errors {} (expected {})",
+        root.display(),
+        opts,
+        missing,
+        extra,
+        got.errors,
+        expected.errors
+    ))
+}
+
+/// Compare the walker against the oracle for every combination of options
+pub fn check_all_options(root: &Path) -> Result<(), String> {
+    for &follow_links in &[false, true] {
+        for &contents_first in &[false, true] {
+            for &min_depth in &[0, 1, 2] {
+                for &max_depth in &[0, 1, 2, Depth::MAX] {
+                    if min_depth > max_depth {
+                        continue;
+                    }
+                    let opts = OracleOptions { follow_links, min_depth, max_depth, contents_first };
+                    check(root, &opts)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,8 +1,10 @@
 use std::fs;
 use std::path::PathBuf;
 
+use crate::fs::FsDirEntry;
 use crate::tests::util::Dir;
-use crate::{ClassicWalkDirIter, ContentFilter, ContentOrder, Position, WalkDir, WalkDirIter};
+use crate::tests::util::WalkDir;
+use crate::{CaseMatch, ClassicWalkDirIter, ContentFilter, ContentOrder, DirEntry, DirId, ErrorKind, LinkKind, Pass, Position, SpecialFilePolicy, SyncPosition, WalkDirIter};
 
 /// Check for defaulted type parameter bug
 /// See https://github.com/rust-lang/rust/issues/27336
@@ -17,13 +19,18 @@ fn check_defaulted_generic_parameter() {
 
 #[test]
 fn send_sync_traits() {
-    use crate::WalkDirIterator;
+    use crate::{DefaultDirEntry, DirEntryContentProcessor, WalkDirIterator};
 
     fn assert_send<T: Send>() {}
     fn assert_sync<T: Sync>() {}
 
     assert_send::<WalkDir>();
     assert_sync::<WalkDir>();
+    assert_send::<WalkDirIterator<DefaultDirEntry, DirEntryContentProcessor>>();
+    assert_send::<DirEntry>();
+    assert_sync::<DirEntry>();
+    assert_send::<crate::Error<DefaultDirEntry>>();
+    assert_sync::<crate::Error<DefaultDirEntry>>();
     // assert_send::<WalkDirIterator>();
     // assert_sync::<WalkDirIterator>();
     // assert_send::<FilterEntry<storage::DefaultStorageExt, WalkDirIterator, (dyn FnMut(&WalkDirIteratorItem<storage::DefaultStorageExt>) -> bool + Send)>>();
@@ -352,9 +359,9 @@ fn sym_root_file_nofollow() {
     assert!(!link.file_type().is_file());
     assert!(!link.file_type().is_dir());
 
-    assert!(link.metadata().unwrap().file_type().is_symlink());
-    assert!(!link.metadata().unwrap().is_file());
-    assert!(!link.metadata().unwrap().is_dir());
+    assert!(link.metadata().file_type().is_symlink());
+    assert!(!link.metadata().is_file());
+    assert!(!link.metadata().is_dir());
 }
 
 #[test]
@@ -382,9 +389,9 @@ fn sym_root_file_follow() {
     assert!(link.file_type().is_file());
     assert!(!link.file_type().is_dir());
 
-    assert!(!link.metadata().unwrap().file_type().is_symlink());
-    assert!(link.metadata().unwrap().is_file());
-    assert!(!link.metadata().unwrap().is_dir());
+    assert!(!link.metadata().file_type().is_symlink());
+    assert!(link.metadata().is_file());
+    assert!(!link.metadata().is_dir());
 }
 
 #[test]
@@ -414,15 +421,88 @@ fn sym_root_dir_nofollow() {
     assert!(!link.file_type().is_file());
     assert!(!link.file_type().is_dir());
 
-    assert!(link.metadata().unwrap().file_type().is_symlink());
-    assert!(!link.metadata().unwrap().is_file());
-    assert!(!link.metadata().unwrap().is_dir());
+    assert!(link.metadata().file_type().is_symlink());
+    assert!(!link.metadata().is_file());
+    assert!(!link.metadata().is_dir());
 
     let link_zzz = &ents[1];
     assert_eq!(dir.join("a-link").join("zzz"), link_zzz.path());
     assert!(!link_zzz.path_is_symlink());
 }
 
+#[test]
+fn sym_dir_unfollowed_link_kind() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.symlink_dir("a", "a-link");
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .follow_link_kind(LinkKind::Symlink, false);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("a/b"), dir.join("a-link")];
+    assert_eq!(expected, r.sorted_paths());
+    let link = r.ents().iter().find(|ent| ent.path() == dir.join("a-link")).unwrap();
+    assert!(link.path_is_symlink());
+    assert!(!link.file_type().is_dir());
+}
+
+#[test]
+fn sym_root_dir_no_follow_root() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.symlink_dir("a", "a-link");
+    dir.touch("a/zzz");
+
+    let wd = WalkDir::new(dir.join("a-link")).follow_root_symlink(false);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let ents = r.sorted_ents();
+    assert_eq!(1, ents.len());
+    let link = &ents[0];
+    assert_eq!(dir.join("a-link"), link.path());
+    assert_eq!(0, link.depth());
+    assert!(link.path_is_symlink());
+    assert!(link.file_type().is_symlink());
+}
+
+#[test]
+fn sym_root_dir_follow_metadata() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.symlink_dir("a", "a-link");
+    dir.symlink_dir("missing", "broken-link");
+    dir.touch("a/zzz");
+
+    let wd = WalkDir::new(dir.join("a-link")).follow_root_metadata(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let ents = r.sorted_ents();
+    assert_eq!(2, ents.len());
+    let link = &ents[0];
+    assert_eq!(dir.join("a-link"), link.path());
+    assert!(link.path_is_symlink());
+    assert!(link.file_type().is_dir());
+    assert!(link.metadata().is_dir());
+    assert!(!ents[1].path_is_symlink());
+
+    // A broken root link is yielded as a link if it isn't followed
+    let wd = WalkDir::new(dir.join("broken-link")).follow_root_symlink(false);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+    assert!(r.ents()[0].file_type().is_symlink());
+
+    let wd = WalkDir::new(dir.join("broken-link")).follow_root_symlink(false).follow_root_metadata(true);
+    let r = dir.run_recursive(wd.into_classic());
+    assert!(r.ents().is_empty());
+    assert_eq!(1, r.errs().len());
+    assert_eq!(0, r.errs()[0].depth());
+}
+
 #[test]
 fn sym_root_dir_follow() {
     let dir = Dir::tmp();
@@ -450,9 +530,9 @@ fn sym_root_dir_follow() {
     assert!(!link.file_type().is_file());
     assert!(link.file_type().is_dir());
 
-    assert!(!link.metadata().unwrap().file_type().is_symlink());
-    assert!(!link.metadata().unwrap().is_file());
-    assert!(link.metadata().unwrap().is_dir());
+    assert!(!link.metadata().file_type().is_symlink());
+    assert!(!link.metadata().is_file());
+    assert!(link.metadata().is_dir());
 
     let link_zzz = &ents[1];
     assert_eq!(dir.join("a-link").join("zzz"), link_zzz.path());
@@ -489,10 +569,33 @@ fn sym_file_nofollow() {
     assert!(!link.file_type().is_file());
     assert!(!link.file_type().is_dir());
 
-    assert!(src.metadata().unwrap().is_file());
-    assert!(link.metadata().unwrap().file_type().is_symlink());
-    assert!(!link.metadata().unwrap().is_file());
-    assert!(!link.metadata().unwrap().is_dir());
+    assert!(src.metadata().is_file());
+    assert!(link.metadata().file_type().is_symlink());
+    assert!(!link.metadata().is_file());
+    assert!(!link.metadata().is_dir());
+}
+
+#[test]
+fn sym_file_target_metadata() {
+    let dir = Dir::tmp();
+    dir.touch("a");
+    dir.symlink_file("a", "a-link");
+    dir.symlink_file("missing", "broken-link");
+
+    let wd = WalkDir::new(dir.path());
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let ents = r.sorted_ents();
+    assert_eq!(4, ents.len());
+    let (src, link, broken) = (&ents[1], &ents[2], &ents[3]);
+    assert_eq!(dir.join("a-link"), link.path());
+
+    assert!(link.metadata().file_type().is_symlink());
+    assert!(link.target_metadata().unwrap().is_file());
+    assert!(link.clone().target_metadata().unwrap().is_file());
+    assert!(src.target_metadata().unwrap().is_file());
+    assert!(broken.target_metadata().is_err());
 }
 
 #[test]
@@ -525,10 +628,10 @@ fn sym_file_follow() {
     assert!(link.file_type().is_file());
     assert!(!link.file_type().is_dir());
 
-    assert!(src.metadata().unwrap().is_file());
-    assert!(!link.metadata().unwrap().file_type().is_symlink());
-    assert!(link.metadata().unwrap().is_file());
-    assert!(!link.metadata().unwrap().is_dir());
+    assert!(src.metadata().is_file());
+    assert!(!link.metadata().file_type().is_symlink());
+    assert!(link.metadata().is_file());
+    assert!(!link.metadata().is_dir());
 }
 
 #[test]
@@ -562,10 +665,10 @@ fn sym_dir_nofollow() {
     assert!(!link.file_type().is_file());
     assert!(!link.file_type().is_dir());
 
-    assert!(src.metadata().unwrap().is_dir());
-    assert!(link.metadata().unwrap().file_type().is_symlink());
-    assert!(!link.metadata().unwrap().is_file());
-    assert!(!link.metadata().unwrap().is_dir());
+    assert!(src.metadata().is_dir());
+    assert!(link.metadata().file_type().is_symlink());
+    assert!(!link.metadata().is_file());
+    assert!(!link.metadata().is_dir());
 }
 
 #[test]
@@ -599,10 +702,10 @@ fn sym_dir_follow() {
     assert!(!link.file_type().is_file());
     assert!(link.file_type().is_dir());
 
-    assert!(src.metadata().unwrap().is_dir());
-    assert!(!link.metadata().unwrap().file_type().is_symlink());
-    assert!(!link.metadata().unwrap().is_file());
-    assert!(link.metadata().unwrap().is_dir());
+    assert!(src.metadata().is_dir());
+    assert!(!link.metadata().file_type().is_symlink());
+    assert!(!link.metadata().is_file());
+    assert!(link.metadata().is_dir());
 
     let (src_zzz, link_zzz) = (&ents[2], &ents[4]);
     assert_eq!(dir.join("a").join("zzz"), src_zzz.path());
@@ -645,11 +748,50 @@ fn sym_loop_detect() {
 
     let expected = dir.join("a");
     assert_eq!(Some(&*expected), err.loop_ancestor());
+    assert_eq!(Some(1), err.loop_ancestor_depth());
+    assert_eq!(Some((&*dir.join("a/b/c/a-link"), 4)), err.loop_child());
 
     assert_eq!(4, err.depth());
     assert!(err.io_error().is_none());
 }
 
+#[test]
+fn sym_loop_revisits() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+    dir.symlink_dir("a", "a/b/c/a-link");
+
+    let wd = WalkDir::new(dir.path()).follow_links(true).allow_loop_revisits(1);
+    let r = dir.run_recursive(wd.into_classic());
+
+    let (ents, errs) = (r.sorted_ents(), r.errs());
+    assert_eq!(7, ents.len());
+    assert_eq!(dir.join("a/b/c/a-link/b/c"), ents[6].path());
+    assert_eq!(1, errs.len());
+
+    // The loop is entered once, then reported against the followed link
+    let err = &errs[0];
+    assert_eq!(Some(&*dir.join("a/b/c/a-link/b/c/a-link")), err.path());
+    assert_eq!(Some(&*dir.join("a/b/c/a-link")), err.loop_ancestor());
+    assert_eq!(Some(4), err.loop_ancestor_depth());
+    assert_eq!(7, err.depth());
+
+    // The same with the priority walker
+    let wd = WalkDir::new(dir.path()).follow_links(true).allow_loop_revisits(1);
+    let mut paths = vec![];
+    let mut errs = vec![];
+    for res in wd.prioritize(|_| 0) {
+        match res {
+            Ok(ent) => paths.push(ent.path().to_path_buf()),
+            Err(err) => errs.push(err),
+        }
+    }
+    paths.sort();
+    assert_eq!(r.sorted_paths(), paths);
+    assert_eq!(1, errs.len());
+    assert_eq!(Some(&*dir.join("a/b/c/a-link")), errs[0].loop_ancestor());
+}
+
 #[test]
 fn sym_self_loop_no_error() {
     let dir = Dir::tmp();
@@ -670,9 +812,9 @@ fn sym_self_loop_no_error() {
     assert!(!ent.file_type().is_file());
     assert!(!ent.file_type().is_dir());
 
-    assert!(ent.metadata().unwrap().file_type().is_symlink());
-    assert!(!ent.metadata().unwrap().file_type().is_file());
-    assert!(!ent.metadata().unwrap().file_type().is_dir());
+    assert!(ent.metadata().file_type().is_symlink());
+    assert!(!ent.metadata().file_type().is_file());
+    assert!(!ent.metadata().file_type().is_dir());
 }
 
 #[test]
@@ -851,12 +993,12 @@ fn classic_contents_first_ordered() {
     let mut wd = WalkDir::new(dir.path())
         .contents_first(false)
         .content_filter(ContentFilter::SkipAll)
-        .sort_by(|a, b| a.file_name().cmp(&b.file_name()))
+        .sort_by(|a, b, _| a.0.file_name().cmp(b.0.file_name()))
         .into_iter();
     let mut r: Vec<(PathBuf, Vec<String>)> = vec![];
     while let Some(pos) = wd.next() {
         match pos {
-            Position::BeforeContent((dent, content)) => {
+            Position::BeforeContent((dent, _)) => {
                 let path = dent.path().to_path_buf();
                 let content = wd
                     .get_current_dir_content(ContentFilter::FilesOnly)
@@ -866,7 +1008,7 @@ fn classic_contents_first_ordered() {
 
                 r.push((path, content));
             }
-            Position::AfterContent => {}
+            Position::AfterContent { .. } => {}
             _ => panic!(),
         }
     }
@@ -881,6 +1023,76 @@ fn classic_contents_first_ordered() {
     assert_eq!(expected, r);
 }
 
+#[test]
+fn get_current_dir_content_with() {
+    use std::ffi::{OsStr, OsString};
+
+    use crate::{ContentProcessor, Depth, FsDirEntry, FsRootDirEntry};
+
+    #[derive(Debug)]
+    struct Names;
+
+    impl<E: FsDirEntry<FileName = OsStr>> ContentProcessor<E> for Names {
+        type Item = OsString;
+        type Collection = Vec<OsString>;
+
+        fn process_root_direntry(
+            &self,
+            fsdent: &mut E::RootDirEntry,
+            _follow_link: bool,
+            _is_dir: bool,
+            _depth: Depth,
+            _ctx: &mut E::Context,
+        ) -> Option<OsString> {
+            Some(fsdent.file_name().to_os_string())
+        }
+
+        fn process_direntry(
+            &self,
+            fsdent: &mut E,
+            _follow_link: bool,
+            _is_dir: bool,
+            _depth: Depth,
+            _ctx: &mut E::Context,
+        ) -> Option<OsString> {
+            Some(fsdent.file_name().to_os_string())
+        }
+
+        fn is_dir(_item: &OsString) -> bool {
+            false
+        }
+
+        fn collect(&self, iter: impl Iterator<Item = OsString>) -> Vec<OsString> {
+            iter.collect()
+        }
+
+        fn empty_collection() -> Vec<OsString> {
+            vec![]
+        }
+    }
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.touch_all(&["a", "foo/b"]);
+
+    let mut wd = WalkDir::new(dir.path()).deterministic(true).into_iter();
+    let mut r = vec![];
+    while let Some(pos) = wd.next() {
+        if let Position::BeforeContent((dent, _)) = pos {
+            let names = wd.get_current_dir_content_with(&Names, ContentFilter::None);
+            let dents = wd.get_current_dir_content(ContentFilter::None);
+            assert_eq!(names.len(), dents.len());
+            r.push((dent.path().to_path_buf(), names));
+        }
+    }
+
+    let expected = vec![
+        (dir.path().to_path_buf(), vec![OsString::from("a"), OsString::from("foo")]),
+        (dir.join("foo"), vec![OsString::from("b")]),
+    ];
+    assert_eq!(expected, r);
+}
+
 #[test]
 fn contents_first_ordered() {
     let dir = Dir::tmp();
@@ -897,7 +1109,7 @@ fn contents_first_ordered() {
     let wd = WalkDir::new(dir.path())
         .contents_first(false)
         .content_order(ContentOrder::FilesFirst)
-        .sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        .sort_by(|a, b, _| a.0.file_name().cmp(b.0.file_name()));
     let r = dir.run_recursive(wd.into_classic());
     r.assert_no_errors();
 
@@ -926,6 +1138,336 @@ fn contents_first_ordered() {
     assert_eq!(expected, r.paths());
 }
 
+#[test]
+fn content_order_by() {
+    let dir = Dir::tmp();
+    dir.mkdirp("sub");
+    fs::write(dir.join("big"), [b'x'; 100]).unwrap();
+    fs::write(dir.join("small"), "x").unwrap();
+    fs::write(dir.join("big2"), [b'x'; 100]).unwrap();
+    fs::write(dir.join("sub/small"), "x").unwrap();
+
+    let wd = WalkDir::new(dir.path())
+        .deterministic(true)
+        .content_order_by(|flat| match fs::symlink_metadata(flat.raw.path()) {
+            Ok(md) if md.is_file() && md.len() < 10 => Pass::FIRST,
+            _ => Pass::SECOND,
+        });
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("small"),
+        dir.join("big"),
+        dir.join("big2"),
+        dir.join("sub"),
+        dir.join("sub").join("small"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn content_order_by_three_passes() {
+    let dir = Dir::tmp();
+    dir.mkdirp("d1");
+    dir.mkdirp("d2");
+    dir.touch_all(&["d1/f", "f1", "f2"]);
+    dir.symlink_file(dir.join("f1"), "l0");
+
+    let wd = WalkDir::new(dir.path()).deterministic(true).content_order_by(|flat| {
+        if flat.raw.is_symlink() {
+            Pass::THIRD
+        } else if flat.is_dir {
+            Pass::FIRST
+        } else {
+            Pass::SECOND
+        }
+    });
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("d1"),
+        dir.join("d1").join("f"),
+        dir.join("d2"),
+        dir.join("f1"),
+        dir.join("f2"),
+        dir.join("l0"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn process_read_dir() {
+    let dir = Dir::tmp();
+    dir.mkdirp("app/target/debug");
+    dir.mkdirp("app/src");
+    dir.mkdirp("lib");
+    dir.touch_all(&["app/Cargo.toml", "app/target/debug/x", "lib/a", "lib/b"]);
+
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let calls = seen.clone();
+    let wd = WalkDir::new(dir.path()).deterministic(true).process_read_dir(move |depth, path, children| {
+        calls.lock().unwrap().push((depth, path.to_path_buf()));
+        if children.iter().any(|flat| flat.raw.file_name() == "Cargo.toml") {
+            children.retain(|flat| flat.raw.file_name() != "target");
+        }
+        children.reverse();
+    });
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("lib"),
+        dir.join("lib").join("b"),
+        dir.join("lib").join("a"),
+        dir.join("app"),
+        dir.join("app").join("src"),
+        dir.join("app").join("Cargo.toml"),
+    ];
+    assert_eq!(expected, r.paths());
+    let mut seen = seen.lock().unwrap().clone();
+    seen.sort();
+    let expected = vec![
+        (0, dir.path().to_path_buf()),
+        (1, dir.join("app")),
+        (1, dir.join("lib")),
+        (2, dir.join("app").join("src")),
+    ];
+    assert_eq!(expected, seen);
+
+    // Run on the threads of `par_walk`, with the same result
+    #[cfg(feature = "parallel")]
+    {
+        let opts = crate::ParWalkOptions {
+            parallelism: crate::Parallelism::Fixed(2),
+            process_read_dir: Some(std::sync::Arc::new(|_, _, children| children.reverse())),
+            ..Default::default()
+        };
+        let got: Vec<_> = crate::par_walk(dir.path(), opts).map(|e| e.unwrap().into_path()).collect();
+        let wd = WalkDir::new(dir.path()).deterministic(true).process_read_dir(|_, _, children| children.reverse());
+        assert_eq!(dir.run_recursive(wd.into_classic()).paths(), got);
+    }
+}
+
+#[test]
+fn iterate_by_ref() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.touch_all(&["a", "foo/b"]);
+
+    let wd = WalkDir::new(dir.path()).sort_by(|a, b, _| b.0.file_name().cmp(a.0.file_name()));
+    let first = dir.run_recursive((&wd).into_iter().into_classic());
+    let second = dir.run_recursive((&wd).into_iter().into_classic());
+    first.assert_no_errors();
+    second.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("foo"),
+        dir.join("foo").join("b"),
+        dir.join("a"),
+    ];
+    assert_eq!(expected, first.paths());
+    assert_eq!(expected, second.paths());
+}
+
+#[test]
+fn skip_siblings() {
+    let dir = Dir::tmp();
+    dir.mkdirp("x");
+    dir.mkdirp("y/z");
+    dir.touch_all(&["c", "x/b", "x/m", "y/m", "y/z/q"]);
+
+    let mut it = WalkDir::new(dir.path()).deterministic(true).into_classic();
+    let mut paths = vec![];
+    while let Some(result) = it.next() {
+        let dent = result.unwrap();
+        paths.push(dent.path().to_path_buf());
+        if dent.file_name() == "m" {
+            it.skip_siblings();
+        }
+    }
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("c"),
+        dir.join("x"),
+        dir.join("x").join("b"),
+        dir.join("x").join("m"),
+        dir.join("y"),
+        dir.join("y").join("m"),
+    ];
+    assert_eq!(expected, paths);
+}
+
+#[test]
+fn skip_siblings_after_dir() {
+    let dir = Dir::tmp();
+    dir.mkdirp("x");
+    dir.mkdirp("y");
+    dir.touch_all(&["c", "x/a", "y/a"]);
+
+    let mut it = WalkDir::new(dir.path()).deterministic(true).contents_first(true).into_iter();
+    let mut r = vec![];
+    while let Some(pos) = it.next() {
+        match pos {
+            Position::Entry(dent) => {
+                r.push(dent.path().to_path_buf());
+                if dent.file_name() == "x" {
+                    it.skip_siblings();
+                }
+            }
+            Position::AfterContent { .. } => r.push(PathBuf::from("AfterContent")),
+            _ => {}
+        }
+    }
+
+    let expected = vec![
+        dir.join("c"),
+        dir.join("x").join("a"),
+        PathBuf::from("AfterContent"),
+        dir.join("x"),
+        PathBuf::from("AfterContent"),
+        dir.path().to_path_buf(),
+    ];
+    assert_eq!(expected, r);
+}
+
+#[test]
+fn slim_entries() {
+    use crate::fs::FsFileType;
+    use crate::{DefaultDirEntry, SlimContentProcessor, SlimEntry, WalkDirBuilder};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    fs::write(dir.join("foo").join("a"), b"hello").unwrap();
+
+    assert!(std::mem::size_of::<SlimEntry>() <= 64);
+
+    let ents: Vec<SlimEntry> = WalkDirBuilder::<DefaultDirEntry, SlimContentProcessor>::new(dir.path())
+        .deterministic(true)
+        .into_classic()
+        .map(|r| r.unwrap())
+        .collect();
+    let paths: Vec<_> = ents.iter().map(|e| e.path().to_path_buf()).collect();
+    assert_eq!(paths, vec![dir.path().to_path_buf(), dir.join("foo"), dir.join("foo").join("a")]);
+
+    assert!(ents[1].file_type().is_dir());
+    assert_eq!(ents[1].depth(), 1);
+    assert!(ents[2].file_type().is_file());
+    assert_eq!(ents[2].len(), 5);
+    assert_eq!(ents[2].depth(), 2);
+    assert!(ents[2].modified().is_some());
+    assert!(!ents[2].path_is_symlink());
+}
+
+#[cfg(feature = "arena")]
+#[test]
+fn arena_entries() {
+    use crate::{ArenaContentProcessor, ArenaEntries, DefaultDirEntry, WalkDirBuilder};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.touch("foo/a");
+    fs::write(dir.join("b"), b"hello").unwrap();
+
+    let entries: ArenaEntries = WalkDirBuilder::<DefaultDirEntry, ArenaContentProcessor>::new(dir.path())
+        .deterministic(true)
+        .into_classic()
+        .map(|r| r.unwrap())
+        .collect();
+    assert_eq!(entries.len(), 4);
+
+    let got: Vec<_> = entries.iter().map(|e| (e.path().to_path_buf(), e.depth(), e.is_dir(), e.len())).collect();
+    let expected = vec![
+        (dir.path().to_path_buf(), 0, true, got[0].3),
+        (dir.join("b"), 1, false, 5),
+        (dir.join("foo"), 1, true, got[2].3),
+        (dir.join("foo").join("a"), 2, false, 0),
+    ];
+    assert_eq!(expected, got);
+}
+
+#[test]
+fn type_histogram() {
+    use std::ffi::OsStr;
+    use crate::{DefaultDirEntry, TypeHistogram, TypeHistogramProcessor, TypeTally, WalkDirBuilder};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo.d");
+    fs::write(dir.join("a.txt"), b"hello").unwrap();
+    fs::write(dir.join("foo.d").join("b.txt"), b"abc").unwrap();
+    fs::write(dir.join("foo.d").join("c.rs"), b"fn").unwrap();
+    dir.touch("foo.d/README");
+
+    let report: TypeHistogram = WalkDirBuilder::<DefaultDirEntry, TypeHistogramProcessor>::new(dir.path())
+        .into_classic()
+        .map(|r| r.unwrap())
+        .collect();
+
+    assert_eq!(6, report.total().count);
+    assert_eq!(2, report.dirs().count);
+    assert_eq!(TypeTally { count: 4, bytes: 10 }, report.files());
+    assert_eq!(TypeTally::default(), report.symlinks());
+
+    assert_eq!(TypeTally { count: 2, bytes: 8 }, report.extension(Some(OsStr::new("txt"))));
+    assert_eq!(TypeTally { count: 1, bytes: 2 }, report.extension(Some(OsStr::new("rs"))));
+    assert_eq!(TypeTally { count: 1, bytes: 0 }, report.extension(None));
+    let exts: Vec<_> = report.by_extension().map(|(ext, _)| ext).collect();
+    assert_eq!(vec![None, Some(OsStr::new("rs")), Some(OsStr::new("txt"))], exts);
+
+    let mut merged = report.clone();
+    merged.merge(&report);
+    assert_eq!(TypeTally { count: 8, bytes: 20 }, merged.files());
+    assert_eq!(TypeTally { count: 4, bytes: 16 }, merged.extension(Some(OsStr::new("txt"))));
+}
+
+#[test]
+fn report_finished() {
+    use crate::WalkSummary;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.touch("foo/a");
+
+    let mut it = WalkDir::new(dir.path()).report_finished(true).into_iter();
+    let mut last = None;
+    for pos in &mut it {
+        assert!(last.is_none(), "item after Position::Finished");
+        if let Position::Finished { summary } = pos {
+            last = Some(summary);
+        }
+    }
+    let summary = last.expect("no Position::Finished");
+    let expected = WalkSummary {
+        roots: 1,
+        failed_roots: 0,
+        entries: 3,
+        dirs: 2,
+        errors: 0,
+        max_depth_reached: 2,
+        duration: summary.duration,
+    };
+    assert_eq!(expected, summary);
+    assert!(it.is_finished());
+    assert!(it.next().is_none());
+
+    let mut it = WalkDir::new(dir.join("missing")).report_finished(true).into_iter();
+    assert!(matches!(it.next(), Some(Position::Error(_))));
+    assert!(!it.is_finished());
+    let summary = match it.next() {
+        Some(Position::Finished { summary }) => summary,
+        _ => panic!("expected Position::Finished"),
+    };
+    assert!(!summary.started());
+    assert_eq!(1, summary.errors);
+    assert!(it.is_finished());
+}
+
 #[test]
 fn skip_current_dir() {
     let dir = Dir::tmp();
@@ -937,7 +1479,7 @@ fn skip_current_dir() {
     while let Some(result) = it.next() {
         let ent = match result {
             Position::Entry(ent) => ent,
-            Position::BeforeContent(_) | Position::AfterContent => continue,
+            Position::BeforeContent(_) | Position::AfterContent { .. } => continue,
             _ => panic!(),
         };
         paths.push(ent.path().to_path_buf());
@@ -976,79 +1518,2156 @@ fn filter_entry() {
 }
 
 #[test]
-fn sort() {
+fn filter_entry_keeps_siblings() {
     let dir = Dir::tmp();
-    dir.mkdirp("foo/bar/baz/abc");
-    dir.mkdirp("quux");
+    dir.mkdirp("a/x");
+    dir.mkdirp("b/y");
+    dir.mkdirp("c");
+    dir.touch_all(&["b/f1", "b/f2"]);
 
-    let wd = WalkDir::new(dir.path()).sort_by(|a, b| a.file_name().cmp(&b.file_name()).reverse());
-    let r = dir.run_recursive(wd.into_classic());
+    let wd = WalkDir::new(dir.path()).deterministic(true).into_classic();
+    let r = dir.run_recursive(wd.filter_entry(|dent| dent.file_name() != "b"));
     r.assert_no_errors();
 
     let expected = vec![
         dir.path().to_path_buf(),
-        dir.join("quux"),
-        dir.join("foo"),
-        dir.join("foo").join("bar"),
-        dir.join("foo").join("bar").join("baz"),
-        dir.join("foo").join("bar").join("baz").join("abc"),
+        dir.join("a"),
+        dir.join("a").join("x"),
+        dir.join("c"),
     ];
     assert_eq!(expected, r.paths());
 }
 
 #[test]
-fn sort_max_open() {
+fn filter_entry_contents_first() {
     let dir = Dir::tmp();
-    dir.mkdirp("foo/bar/baz/abc");
-    dir.mkdirp("quux");
-
-    let wd = WalkDir::new(dir.path())
-        .max_open(1)
-        .sort_by(|a, b| a.file_name().cmp(&b.file_name()).reverse());
-    let r = dir.run_recursive(wd.into_classic());
+    dir.mkdirp("a/x");
+    dir.mkdirp("b/y");
+    dir.mkdirp("c");
+    dir.touch_all(&["b/f1"]);
+
+    // Dirs are yielded after their content, so only they are filtered out
+    let wd = WalkDir::new(dir.path()).deterministic(true).contents_first(true).into_classic();
+    let r = dir.run_recursive(wd.filter_entry(|dent| dent.file_name() != "b"));
     r.assert_no_errors();
 
     let expected = vec![
+        dir.join("a").join("x"),
+        dir.join("a"),
+        dir.join("b").join("f1"),
+        dir.join("b").join("y"),
+        dir.join("c"),
         dir.path().to_path_buf(),
-        dir.join("quux"),
-        dir.join("foo"),
-        dir.join("foo").join("bar"),
-        dir.join("foo").join("bar").join("baz"),
-        dir.join("foo").join("bar").join("baz").join("abc"),
     ];
     assert_eq!(expected, r.paths());
 }
 
-#[cfg(target_os = "linux")]
 #[test]
-fn same_file_system() {
-    use std::path::Path;
+fn filter_entry_nested() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/x/deep");
+    dir.mkdirp("b/y");
+    dir.mkdirp("c/z");
+
+    for &contents_first in &[false, true] {
+        let wd = WalkDir::new(dir.path())
+            .deterministic(true)
+            .contents_first(contents_first)
+            .into_iter()
+            .filter_entry(|dent: &DirEntry| dent.file_name() != "x")
+            .filter_entry(|dent: &DirEntry| dent.file_name() != "b")
+            .into_classic()
+            .filter_entry(|dent| dent.file_name() != "z");
+        let r = dir.run_recursive(wd);
+        r.assert_no_errors();
+
+        let mut expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("c")];
+        if contents_first {
+            expected.push(dir.join("a").join("x").join("deep"));
+            expected.push(dir.join("b").join("y"));
+        }
+        expected.sort();
+        assert_eq!(expected, r.sorted_paths());
+    }
+}
+
+#[test]
+fn skip_current_dir_positions() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/x");
+    dir.mkdirp("b/y");
+    dir.touch_all(&["b/f1", "b/f2", "c"]);
+
+    for &contents_first in &[false, true] {
+        let mut it = WalkDir::new(dir.path())
+            .deterministic(true)
+            .contents_first(contents_first)
+            .into_iter();
+        let mut paths = vec![];
+        while let Some(pos) = it.next() {
+            match pos {
+                // Skip content of b before reading it
+                Position::BeforeContent((ent, _)) if ent.file_name() == "b" => it.skip_current_dir(),
+                // Nothing to skip here
+                Position::AfterContent { .. } => it.skip_current_dir(),
+                Position::Entry(ent) => {
+                    // A dir yielded after its content: nothing to skip
+                    if ent.file_name() == "a" && contents_first {
+                        it.skip_current_dir();
+                    }
+                    paths.push(ent.path().to_path_buf());
+                }
+                Position::Error(err) => panic!("unexpected error: {}", err),
+                _ => {}
+            }
+        }
+        paths.sort();
+
+        let expected = vec![
+            dir.path().to_path_buf(),
+            dir.join("a"),
+            dir.join("a").join("x"),
+            dir.join("b"),
+            dir.join("c"),
+        ];
+        assert_eq!(expected, paths);
+    }
+}
+
+#[test]
+fn sort() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar/baz/abc");
+    dir.mkdirp("quux");
+
+    let wd = WalkDir::new(dir.path()).sort_by(|a, b, _| a.0.file_name().cmp(b.0.file_name()).reverse());
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("quux"),
+        dir.join("foo"),
+        dir.join("foo").join("bar"),
+        dir.join("foo").join("bar").join("baz"),
+        dir.join("foo").join("bar").join("baz").join("abc"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn sort_max_open() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar/baz/abc");
+    dir.mkdirp("quux");
+
+    let wd = WalkDir::new(dir.path())
+        .max_open(1)
+        .sort_by(|a, b, _| a.0.file_name().cmp(b.0.file_name()).reverse());
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("quux"),
+        dir.join("foo"),
+        dir.join("foo").join("bar"),
+        dir.join("foo").join("bar").join("baz"),
+        dir.join("foo").join("bar").join("baz").join("abc"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn deterministic() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar/baz/abc");
+    dir.mkdirp("quux");
+    dir.touch_all(&["foo/b", "foo/a", "c"]);
+
+    let wd = WalkDir::new(dir.path()).deterministic(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("c"),
+        dir.join("foo"),
+        dir.join("foo").join("a"),
+        dir.join("foo").join("b"),
+        dir.join("foo").join("bar"),
+        dir.join("foo").join("bar").join("baz"),
+        dir.join("foo").join("bar").join("baz").join("abc"),
+        dir.join("quux"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn dedupe_canonical() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch("a/b/zzz");
+    dir.symlink_dir("a", "a-link");
+    dir.symlink_dir("a/b", "b-link");
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .deterministic(true)
+        .dedupe_canonical(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("a").join("b"),
+        dir.join("a").join("b").join("zzz"),
+    ];
+    assert_eq!(expected, r.paths());
+
+    let canonical = r.ents()[1].canonical_path().unwrap().to_path_buf();
+    assert_eq!(fs::canonicalize(dir.join("a")).unwrap(), canonical);
+}
+
+#[test]
+fn ancestor_path() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    dir.touch("foo/bar/baz");
+
+    let wd = WalkDir::new(dir.path());
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let ent = r.ents().iter().find(|e| e.depth() == 3).unwrap().clone();
+    assert_eq!(Some(dir.path()), ent.ancestor_path(0));
+    assert_eq!(Some(dir.join("foo").as_path()), ent.ancestor_path(1));
+    assert_eq!(Some(ent.path()), ent.ancestor_path(3));
+    assert_eq!(None, ent.ancestor_path(4));
+
+    let names: Vec<_> = ent.components_from_root().collect();
+    assert_eq!(vec!["foo", "bar", "baz"], names);
+}
+
+#[test]
+fn largest() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.touch("empty");
+    fs::write(dir.join("a"), "12345").unwrap();
+    fs::write(dir.join("b"), "12345").unwrap();
+    fs::write(dir.join("foo").join("c"), "123").unwrap();
+
+    let files = crate::largest(dir.path(), 2).unwrap();
+    let paths: Vec<_> = files.iter().map(|e| e.path().to_path_buf()).collect();
+    assert_eq!(vec![dir.join("a"), dir.join("b")], paths);
+
+    assert!(crate::largest(dir.join("missing"), 2).is_err());
+}
+
+#[test]
+fn extremes() {
+    use std::time::{Duration, SystemTime};
+    use crate::ExtremesBy;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.touch("empty");
+    fs::write(dir.join("a"), "12345").unwrap();
+    fs::write(dir.join("b"), "12345").unwrap();
+    fs::write(dir.join("foo").join("c"), "123").unwrap();
+    let now = SystemTime::now();
+    for (name, age) in [("empty", 30), ("a", 10), ("b", 20), ("foo/c", 40)] {
+        fs::File::options().write(true).open(dir.join(name)).unwrap()
+            .set_modified(now - Duration::from_secs(age)).unwrap();
+    }
+    let paths = |ents: &[crate::SlimEntry]| -> Vec<_> { ents.iter().map(|e| e.path().to_path_buf()).collect() };
+
+    let r = crate::extremes(dir.path(), ExtremesBy::Size, 2).unwrap();
+    assert_eq!(vec![dir.join("a"), dir.join("b")], paths(&r.top));
+    assert_eq!(vec![dir.join("empty"), dir.join("foo").join("c")], paths(&r.bottom));
+
+    let r = crate::extremes(dir.path(), ExtremesBy::Mtime, 1).unwrap();
+    assert_eq!(vec![dir.join("a")], paths(&r.top));
+    assert_eq!(vec![dir.join("foo").join("c")], paths(&r.bottom));
+
+    let r = crate::extremes(dir.path(), ExtremesBy::Mtime, 10).unwrap();
+    assert_eq!(4, r.top.len());
+    assert_eq!(paths(&r.top), paths(&r.bottom).into_iter().rev().collect::<Vec<_>>());
+
+    assert!(crate::extremes(dir.join("missing"), ExtremesBy::Size, 2).is_err());
+}
+
+#[test]
+fn ancestors() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+
+    let r: Vec<_> = crate::ancestors(dir.join("a/b/missing")).up_to(dir.path()).collect();
+    assert_eq!(4, r.len());
+    assert!(r[0].is_err());
+    let paths: Vec<_> = r[1..].iter().map(|r| r.as_ref().unwrap().path().to_path_buf()).collect();
+    assert_eq!(vec![dir.join("a/b"), dir.join("a"), dir.path().to_path_buf()], paths);
+    assert!(r[1..].iter().all(|r| r.as_ref().unwrap().file_type().is_dir()));
+}
+
+#[test]
+fn list_dir() {
+    use crate::{ContentFilter, ListOptions};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("sub/deep");
+    dir.touch_all(&["z", "b", ".h", "sub/x"]);
+
+    let names = |dents: Vec<DirEntry>| -> Vec<String> {
+        dents.iter().map(|dent| dent.file_name().to_str().unwrap().to_string()).collect()
+    };
+
+    let r = crate::list_dir(dir.path(), ListOptions::default()).unwrap();
+    assert!(r.iter().all(|dent| dent.depth() == 1));
+    assert_eq!(vec![".h", "b", "sub", "z"], names(r));
+
+    let opts = ListOptions { filter: ContentFilter::FilesOnly, skip_hidden: true, ..Default::default() };
+    assert_eq!(vec!["b", "z"], names(crate::list_dir(dir.path(), opts).unwrap()));
+
+    let opts = ListOptions { filter: ContentFilter::DirsOnly, ..Default::default() };
+    assert_eq!(vec!["sub"], names(crate::list_dir(dir.path(), opts).unwrap()));
+
+    assert!(crate::list_dir(dir.join("z"), ListOptions::default()).is_err());
+    assert!(crate::list_dir(dir.join("missing"), ListOptions::default()).is_err());
+}
+
+#[test]
+fn count() {
+    use crate::{CountOptions, Counts};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    dir.touch_all(&["foo/a", "foo/bar/b", "c"]);
+    dir.symlink_file("c", "c-link");
+
+    let counts = crate::count(dir.path(), CountOptions::default());
+    let expected = Counts { dirs: 3, files: 3, symlinks: 1, other: 0, errors: 0 };
+    assert_eq!(expected, counts);
+
+    let opts = CountOptions { follow_links: true, max_depth: 1, ..CountOptions::default() };
+    let expected = Counts { dirs: 2, files: 2, symlinks: 0, other: 0, errors: 0 };
+    assert_eq!(expected, crate::count(dir.path(), opts));
+
+    let counts = crate::count(dir.join("missing"), CountOptions::default());
+    assert_eq!(1, counts.errors);
+}
+
+#[test]
+fn find_empty_dirs() {
+    use crate::DirSummary;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.mkdirp("b/c");
+    dir.mkdirp("d");
+    dir.mkdirp("e");
+    dir.touch_all(&["d/f", "e/.DS_Store"]);
+
+    let empty = crate::find_empty_dirs(dir.path()).unwrap();
+    let mut paths: Vec<_> = empty.iter().map(|e| e.path().to_path_buf()).collect();
+    paths.sort();
+    assert_eq!(vec![dir.join("a"), dir.join("b/c")], paths);
+    let empty = crate::find_empty_dirs(dir.join("a")).unwrap();
+    assert_eq!(vec![dir.join("a")], empty.iter().map(|e| e.path().to_path_buf()).collect::<Vec<_>>());
+    assert!(crate::find_empty_dirs(dir.join("missing")).is_err());
+
+    // Entries filtered out don't count
+    let wd = WalkDir::new(dir.path())
+        .deterministic(true)
+        .process_read_dir(|_, _, children| children.retain(|e| e.raw.file_name() != ".DS_Store"));
+    let mut open = vec![];
+    let mut summaries = vec![];
+    for pos in wd.into_iter() {
+        match pos {
+            Position::BeforeContent((parent, _)) => open.push(parent.path().to_path_buf()),
+            Position::AfterContent { summary } => summaries.push((open.pop().unwrap(), summary)),
+            _ => {}
+        }
+    }
+    let expected = vec![
+        (dir.join("a"), DirSummary::default()),
+        (dir.join("b/c"), DirSummary::default()),
+        (dir.join("b"), DirSummary { entries: 1, dirs: 1, errors: 0 }),
+        (dir.join("d"), DirSummary { entries: 1, dirs: 0, errors: 0 }),
+        (dir.join("e"), DirSummary::default()),
+        (dir.path().to_path_buf(), DirSummary { entries: 4, dirs: 4, errors: 0 }),
+    ];
+    assert_eq!(expected, summaries);
+    assert!(summaries[4].1.is_empty() && !summaries[2].1.is_empty());
+}
+
+#[test]
+fn grep() {
+    use crate::{GrepMatch, GrepOptions};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.mkdirp(".git");
+    fs::write(dir.join("a"), "one\ntwo needle\r\nthree\nneedle").unwrap();
+    fs::write(dir.join("foo/b"), "needle").unwrap();
+    fs::write(dir.join(".git/c"), "needle").unwrap();
+
+    let opts = GrepOptions { skip_hidden: true, threads: 2, ..GrepOptions::default() };
+    let mut matches: Vec<_> = crate::grep(dir.path(), "needle", opts).collect();
+    matches.sort_by(|a, b| (&a.path, a.line_no).cmp(&(&b.path, b.line_no)));
+    let expected = vec![
+        GrepMatch { path: dir.join("a"), line_no: 2, line: "two needle".into() },
+        GrepMatch { path: dir.join("a"), line_no: 4, line: "needle".into() },
+        GrepMatch { path: dir.join("foo/b"), line_no: 1, line: "needle".into() },
+    ];
+    assert_eq!(expected, matches);
+
+    assert_eq!(4, crate::grep(dir.path(), "needle", GrepOptions::default()).count());
+
+    let mut it = crate::grep(dir.join("missing"), "needle", GrepOptions::default());
+    assert_eq!(None, it.next());
+    assert_eq!(1, it.errors());
+}
+
+#[test]
+fn prioritize() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/x");
+    dir.mkdirp("docs/y");
+    dir.touch("docs/y/z");
+
+    let docs = dir.join("docs");
+    let wd = WalkDir::new(dir.path())
+        .deterministic(true)
+        .prioritize(|e| if e.path().starts_with(&docs) { 1 } else { 0 });
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("docs"),
+        dir.join("docs").join("y"),
+        dir.join("docs").join("y").join("z"),
+        dir.join("a").join("x"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn bfs_memory_limit() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/x/1");
+    dir.mkdirp("a/y");
+    dir.mkdirp("b/x");
+
+    let wd = WalkDir::new(dir.path()).deterministic(true).prioritize(|_| 0);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let names: Vec<_> = r.paths().iter().map(|p| p.strip_prefix(dir.path()).unwrap().to_path_buf()).collect();
+    assert_eq!(PathBuf::from("a/x/1"), names[6]);
+
+    // Nothing fits into the queue, so subtrees are read depth first
+    let wd = WalkDir::new(dir.path()).deterministic(true).bfs_memory_limit(0).prioritize(|_| 0);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let names: Vec<_> = r.paths().iter().map(|p| p.strip_prefix(dir.path()).unwrap().to_path_buf()).collect();
+    let expected: Vec<PathBuf> = ["", "a", "b", "a/x", "a/y", "a/x/1", "b/x"].iter().map(PathBuf::from).collect();
+    assert_eq!(expected, names);
+}
+
+#[test]
+fn sample() {
+    let dir = Dir::tmp();
+    for i in 0..20 {
+        dir.mkdirp(format!("d{}", i));
+        dir.touch(format!("d{}/f", i));
+    }
+
+    let walk = |ratio, seed| {
+        let wd = WalkDir::new(dir.path()).deterministic(true).sample(ratio, seed);
+        let r = dir.run_recursive(wd.into_classic());
+        r.assert_no_errors();
+        r.ents().to_vec()
+    };
+
+    let ents = walk(0.0, 1);
+    assert_eq!(21, ents.len());
+    assert!(ents.iter().skip(1).all(|e| e.sampled_out()));
+
+    let ents = walk(0.5, 1);
+    for ent in ents.iter().filter(|e| e.sampled_out()) {
+        assert!(!ents.iter().any(|e| e.path().parent() == Some(ent.path())));
+    }
+    let paths: Vec<_> = ents.iter().map(|e| e.path().to_path_buf()).collect();
+    let again: Vec<_> = walk(0.5, 1).iter().map(|e| e.path().to_path_buf()).collect();
+    assert_eq!(paths, again);
+
+    assert_eq!(41, walk(1.0, 1).len());
+}
+
+#[test]
+fn treat_as_files() {
+    let dir = Dir::tmp();
+    dir.mkdirp("Foo.app/Contents");
+    dir.mkdirp("src/.git/objects");
+    dir.mkdirp("lib.framework.bak/x");
+    dir.touch_all(&["Foo.app/Contents/Info.plist", "src/main.rs", "bar.app"]);
+
+    let wd = WalkDir::new(dir.path()).treat_as_files(&["*.app", "*.framework", ".git"]);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("Foo.app"),
+        dir.join("bar.app"),
+        dir.join("lib.framework.bak"),
+        dir.join("lib.framework.bak").join("x"),
+        dir.join("src"),
+        dir.join("src").join(".git"),
+        dir.join("src").join("main.rs"),
+    ];
+    assert_eq!(expected, r.sorted_paths());
+    let app = r.ents().iter().find(|e| e.path() == dir.join("Foo.app")).unwrap();
+    assert!(app.file_type().is_dir());
+}
+
+#[test]
+fn announce_dirs() {
+    let dir = Dir::tmp();
+    dir.mkdirp("abc");
+    dir.mkdirp("def");
+    dir.touch_all(&["abc/qrs", "abc/tuv"]);
+
+    for &contents_first in &[false, true] {
+        let wd = WalkDir::new(dir.path())
+            .deterministic(true)
+            .contents_first(contents_first)
+            .announce_dirs(true);
+        let r = dir.run_recursive(wd.into_classic());
+        r.assert_no_errors();
+
+        let got: Vec<_> = r.ents().iter().map(|e| (e.path().to_path_buf(), e.is_announcement())).collect();
+        let expected = vec![
+            (dir.path().to_path_buf(), true),
+            (dir.join("abc"), true),
+            (dir.join("abc").join("qrs"), false),
+            (dir.join("abc").join("tuv"), false),
+            (dir.join("abc"), false),
+            (dir.join("def"), true),
+            (dir.join("def"), false),
+            (dir.path().to_path_buf(), false),
+        ];
+        assert_eq!(expected, got);
+    }
+}
+
+#[test]
+fn post_order() {
+    let dir = Dir::tmp();
+    dir.mkdirp("abc");
+    dir.mkdirp("def");
+    dir.touch_all(&["abc/qrs", "abc/tuv"]);
+
+    // Announcements are ignored
+    let wd = WalkDir::new(dir.path()).deterministic(true).announce_dirs(true).post_order(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.join("abc").join("qrs"),
+        dir.join("abc").join("tuv"),
+        dir.join("abc"),
+        dir.join("def"),
+        dir.path().to_path_buf(),
+    ];
+    assert_eq!(expected, r.paths());
+    assert!(r.ents().iter().all(|e| !e.is_announcement()));
+}
+
+#[test]
+fn post_order_random_trees() {
+    use crate::testlib::{RandomTree, Rng, TreeSpec};
+
+    for seed in 0..32 {
+        let tree = RandomTree::generate(seed, &TreeSpec::default()).unwrap();
+        for variant in 0..8 {
+            let mut rng = Rng::new(seed * 8 + variant);
+            let mut it = WalkDir::new(tree.root())
+                .post_order(true)
+                .follow_links(variant & 1 != 0)
+                .max_open(if variant & 2 != 0 { 1 } else { 10 })
+                .summarize_entry_errors(variant & 4 != 0)
+                .into_classic();
+
+            let mut yielded: Vec<(PathBuf, bool)> = vec![];
+            while let Some(result) = it.next() {
+                if let Ok(ent) = result {
+                    yielded.push((ent.path().to_path_buf(), ent.file_type().is_dir()));
+                }
+                if rng.chance(10) {
+                    it.skip_current_dir();
+                }
+            }
+
+            for (i, (path, is_dir)) in yielded.iter().enumerate() {
+                let later = yielded[i + 1..].iter().find(|(p, _)| p != path && p.starts_with(path));
+                assert!(
+                    !is_dir || later.is_none(),
+                    "seed {}, variant {}: {} yielded before {}",
+                    seed,
+                    variant,
+                    path.display(),
+                    later.unwrap().0.display()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn map_root() {
+    let dir = Dir::tmp();
+    dir.mkdirp("src/a/b");
+    dir.touch_all(&["src/a/f"]);
+
+    for prefix in &["dst", "dst/"] {
+        let wd = WalkDir::new(dir.join("src")).into_classic().map_root(dir.join(prefix));
+        let r = dir.run_recursive(wd);
+        r.assert_no_errors();
+
+        let dst = dir.join("dst");
+        let expected = vec![
+            dir.join(prefix),
+            dst.join("a"),
+            dst.join("a").join("b"),
+            dst.join("a").join("f"),
+        ];
+        assert_eq!(expected, r.sorted_paths());
+    }
+}
+
+#[test]
+fn normalize_paths() {
+    use std::borrow::Cow;
+    use std::path::Path;
+
+    fn upper_names(path: &Path) -> Cow<'_, Path> {
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name.starts_with('x') => Cow::Owned(path.with_file_name(name.to_uppercase())),
+            _ => Cow::Borrowed(path),
+        }
+    }
+
+    let dir = Dir::tmp();
+    dir.mkdirp("xa/b");
+    dir.touch_all(&["xa/b/xc", "d"]);
+
+    let wd = WalkDir::new(dir.path()).normalize_paths(upper_names);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("XA"),
+        dir.join("d"),
+        dir.join("xa").join("b"),
+        dir.join("xa").join("b").join("XC"),
+    ];
+    assert_eq!(expected, r.sorted_paths());
+}
+
+#[test]
+fn detect_unicode_collisions() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["caf\u{e9}", "cafe\u{301}", "cafe", "\u{1ec7}", "e\u{302}\u{323}", "plain"]);
+
+    let wd = WalkDir::new(dir.path()).min_depth(1).detect_unicode_collisions(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let mut got: Vec<_> = r
+        .ents()
+        .iter()
+        .map(|e| (e.file_name().to_str().unwrap().to_string(), e.has_unicode_collision()))
+        .collect();
+    got.sort();
+    let expected = vec![
+        ("cafe".to_string(), false),
+        ("cafe\u{301}".to_string(), true),
+        ("caf\u{e9}".to_string(), true),
+        ("e\u{302}\u{323}".to_string(), true),
+        ("plain".to_string(), false),
+        ("\u{1ec7}".to_string(), true),
+    ];
+    assert_eq!(expected, got);
+}
+
+#[test]
+fn max_path_len() {
+    let dir = Dir::tmp();
+    dir.mkdirp("aaaaaaaaaa/bbbbbbbbbb");
+    dir.touch_all(&["short", "aaaaaaaaaa/x"]);
+
+    let max = dir.path().as_os_str().len() + 12;
+    let wd = WalkDir::new(dir.path()).max_path_len(max);
+    let r = dir.run_recursive(wd.into_classic());
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("aaaaaaaaaa"),
+        dir.join("short"),
+    ];
+    assert_eq!(expected, r.sorted_paths());
+
+    let mut errs: Vec<_> = r.errs().iter().map(|e| (e.path().unwrap().to_path_buf(), e.depth())).collect();
+    errs.sort();
+    assert_eq!(vec![(dir.join("aaaaaaaaaa/bbbbbbbbbb"), 2), (dir.join("aaaaaaaaaa/x"), 2)], errs);
+    for err in r.errs() {
+        assert_eq!(ErrorKind::PathTooLong, err.kind());
+        assert_eq!(max, err.path_len().unwrap().1);
+        assert!(err.io_error().is_none());
+    }
+}
+
+#[test]
+fn file_name_of_root_without_name() {
+    let dir = Dir::tmp();
+    dir.mkdirp("sub");
+    dir.touch("sub/a");
+
+    let root = dir.join("sub").join("..");
+    let wd = WalkDir::new(&root);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let names: Vec<_> = r.ents().iter().map(|e| e.file_name().to_os_string()).collect();
+    assert_eq!(vec![root.into_os_string(), "sub".into(), "a".into()], names);
+}
+
+#[test]
+fn handoff_to_thread() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.mkdirp("c");
+    dir.touch_all(&["a/x", "a/b/y", "c/z", "w"]);
+
+    let mut it = WalkDir::new(dir.path()).deterministic(true).into_classic();
+    let mut paths: Vec<_> = (&mut it).take(3).map(|e| e.unwrap().into_path()).collect();
+
+    let it = it.into_sendable();
+    let rest = std::thread::spawn(move || it.map(|e| e.unwrap().into_path()).collect::<Vec<_>>())
+        .join()
+        .unwrap();
+    paths.extend(rest);
+
+    let expected: Vec<_> = WalkDir::new(dir.path())
+        .deterministic(true)
+        .into_classic()
+        .map(|e| e.unwrap().into_path())
+        .collect();
+    assert_eq!(expected, paths);
+}
+
+#[test]
+fn panic_in_sorter_poisons() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch_all(&["a/x", "a/y", "z"]);
+
+    let mut it = WalkDir::new(dir.path())
+        .sort_by(|(a, _), (b, _), _| {
+            if a.file_name() == "x" || b.file_name() == "x" {
+                panic!("sorter panicked");
+            }
+            a.file_name().cmp(b.file_name())
+        })
+        .into_classic();
+
+    let res = catch_unwind(AssertUnwindSafe(|| (&mut it).for_each(|e| drop(e.unwrap()))));
+    assert!(res.is_err());
+    assert!(it.next().is_none());
+    assert!(it.next().is_none());
+}
+
+#[test]
+fn panic_in_filter_poisons() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch_all(&["a/x", "z"]);
+
+    let mut it = WalkDir::new(dir.path())
+        .into_classic()
+        .filter_entry(|e| if e.depth() == 2 { panic!("filter panicked") } else { true });
+
+    let res = catch_unwind(AssertUnwindSafe(|| (&mut it).count()));
+    assert!(res.is_err());
+    assert!(it.next().is_none());
+}
+
+#[test]
+fn multiple_roots() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch_all(&["a/x", "f"]);
+
+    let roots = [dir.join("f"), dir.join("missing"), dir.join("a")];
+    let wd = WalkDir::new(dir.join("a").join("x")).add_roots(&roots).deterministic(true);
+    let mut paths = vec![];
+    let mut errs = 0;
+    for r in wd.into_classic() {
+        match r {
+            Ok(e) => paths.push((e.path().to_path_buf(), e.is_root())),
+            Err(_) => errs += 1,
+        }
+    }
+
+    assert_eq!(1, errs);
+    let expected = vec![
+        (dir.join("a").join("x"), true),
+        (dir.join("f"), true),
+        (dir.join("a"), true),
+        (dir.join("a").join("b"), false),
+        (dir.join("a").join("x"), false),
+    ];
+    assert_eq!(expected, paths);
+}
+
+#[test]
+fn dedupe_overlapping_roots() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+    dir.touch_all(&["a/x", "a/b/y", "z"]);
+
+    let walk = |root: &str, more: &[&str]| {
+        let roots: Vec<_> = more.iter().map(|p| dir.join(p)).collect();
+        let wd = WalkDir::new(dir.join(root)).add_roots(&roots).deterministic(true);
+        let mut paths = vec![];
+        for r in wd.dedupe_overlapping_roots(true).into_classic() {
+            let e = r.unwrap();
+            paths.push((e.path().to_path_buf(), e.is_root()));
+        }
+        paths
+    };
+
+    // A root nested in a previous root is skipped as a whole
+    let expected = vec![
+        (dir.join("a"), true),
+        (dir.join("a").join("b"), false),
+        (dir.join("a").join("b").join("c"), false),
+        (dir.join("a").join("b").join("y"), false),
+        (dir.join("a").join("x"), false),
+        (dir.join("z"), true),
+    ];
+    assert_eq!(expected, walk("a", &["a/b", "z"]));
+
+    // A previous root nested in the current one is skipped as a subtree
+    let expected = vec![
+        (dir.join("a").join("b"), true),
+        (dir.join("a").join("b").join("c"), false),
+        (dir.join("a").join("b").join("y"), false),
+        (dir.join("a"), true),
+        (dir.join("a").join("x"), false),
+    ];
+    assert_eq!(expected, walk("a/b", &["a"]));
+}
+
+#[cfg(all(unix, feature = "notify"))]
+#[test]
+fn watch_relative_root() {
+    use std::time::{Duration, Instant};
+
+    use crate::WatchEvent;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+
+    // Events arrive for the absolute path and are mapped to the root as given
+    let cwd = std::env::current_dir().unwrap();
+    let mut root: PathBuf = cwd.components().skip(1).map(|_| "..").collect();
+    root.push(dir.join("a").strip_prefix("/").unwrap());
+    let mut w = WalkDir::new(&root).watch().unwrap();
+    assert_eq!(root, w.root());
+    let next_event = |w: &mut crate::WatchDir<_, _>| {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            match w.next_timeout(Duration::from_millis(100)) {
+                Some(WatchEvent::Rescan(path)) => return Some((true, path)),
+                Some(WatchEvent::Removed(path)) => return Some((false, path)),
+                Some(WatchEvent::WatchError(err)) => panic!("{}", err),
+                _ => {},
+            }
+        }
+        None
+    };
+    assert_eq!(Some((true, root.clone())), next_event(&mut w));
+
+    dir.touch("a/x");
+    assert_eq!(Some((true, root.join("x"))), next_event(&mut w));
+    fs::remove_file(dir.join("a").join("x")).unwrap();
+    assert_eq!(Some((false, root.join("x"))), next_event(&mut w));
+}
+
+#[test]
+fn root_error_policy() {
+    use crate::{RootErrorPolicy, WalkSummary};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch_all(&["a/x", "f"]);
+
+    let roots = [dir.join("missing"), dir.join("a")];
+    let wd = WalkDir::new(dir.join("f")).add_roots(&roots).root_error_policy(RootErrorPolicy::Fatal);
+    let mut it = wd.report_finished(true).into_classic();
+    assert_eq!(dir.join("f"), it.next().unwrap().unwrap().path());
+    let err = it.next().unwrap().unwrap_err();
+    assert_eq!(0, err.depth());
+    assert_eq!(Some(dir.join("missing").as_path()), err.path());
+    assert!(it.next().is_none());
+
+    let mut it = WalkDir::new(dir.join("f"))
+        .add_roots(&roots)
+        .root_error_policy(RootErrorPolicy::Fatal)
+        .report_finished(true)
+        .into_iter();
+    let mut summary = WalkSummary::default();
+    for pos in &mut it {
+        if let Position::Finished { summary: s } = pos {
+            summary = s;
+        }
+    }
+    assert_eq!((2, 1, 1, 1), (summary.roots, summary.failed_roots, summary.entries, summary.errors));
+
+    let wd = WalkDir::new(dir.join("missing")).add_roots(&roots).root_error_policy(RootErrorPolicy::Fatal);
+    let r: Vec<_> = wd.prioritize(|_| 0).collect();
+    assert_eq!(1, r.len());
+    assert!(r[0].is_err());
+
+    // Errors about entries of a root don't end the walk
+    let wd = WalkDir::new(dir.path()).max_path_len(dir.path().as_os_str().len() + 2);
+    let wd = wd.add_roots(&[dir.join("a")]).root_error_policy(RootErrorPolicy::Fatal);
+    let r = dir.run_recursive(wd.into_classic());
+    assert!(!r.errs().is_empty());
+    assert!(r.ents().iter().any(|e| e.path() == dir.join("a") && e.depth() == 0));
+}
+
+#[test]
+fn origin() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.mkdirp("b/sub");
+    dir.touch_all(&["a/x", "b/sub/y"]);
+    dir.symlink_dir("b/sub", "a/link");
+
+    let wd = WalkDir::new(dir.join("a")).add_roots(&[dir.join("b")]).follow_links(true).deterministic(true);
+    let mut origins = vec![];
+    for r in wd.into_classic() {
+        let e = r.unwrap();
+        let o = e.origin();
+        origins.push((e.path().to_path_buf(), o.root(), o.link().cloned()));
+    }
+
+    let expected = vec![
+        (dir.join("a"), 0, None),
+        (dir.join("a/link"), 0, None),
+        (dir.join("a/link/y"), 0, Some(dir.join("a/link"))),
+        (dir.join("a/x"), 0, None),
+        (dir.join("b"), 1, None),
+        (dir.join("b/sub"), 1, None),
+        (dir.join("b/sub/y"), 1, None),
+    ];
+    assert_eq!(expected, origins);
+}
+
+#[test]
+fn observer_counts_read_dirs() {
+    use std::sync::{Arc, Mutex};
+    use crate::{DirEntryContentProcessor, FsCallCounts, StandardContext};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    dir.touch_all(&["foo/a", "b"]);
+
+    let counts = Arc::new(Mutex::new(FsCallCounts::default()));
+    let ctx = StandardContext::with_observer(counts.clone());
+    let wd = WalkDir::with_context(dir.path(), ctx, DirEntryContentProcessor {});
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let counts = counts.lock().unwrap();
+    assert_eq!(3, counts.read_dir);
+    assert_eq!(0, counts.errors);
+}
+
+#[test]
+fn random_trees_match_oracle() {
+    use crate::testlib::{check_all_options, RandomTree, TreeSpec};
+
+    for seed in 0..32 {
+        let tree = RandomTree::generate(seed, &TreeSpec::default()).unwrap();
+        if let Err(msg) = check_all_options(tree.root()) {
+            panic!("seed {}: {}", seed, msg);
+        }
+    }
+}
+
+#[cfg(feature = "testkit")]
+#[test]
+fn synth_tree_counts() {
+    use crate::synth::{make_tree, SynthSpec};
+
+    let spec = SynthSpec { width: 5, dirs: 2, depth: 3, symlink_percent: 30, ..SynthSpec::default() };
+    let tree = make_tree(&spec).unwrap();
+    let counts = tree.counts();
+    assert_eq!(2 + 4 + 8, counts.dirs);
+    assert_eq!((1 + 2 + 4) * 3 + 8 * 5, counts.files + counts.symlinks);
+
+    let wd = WalkDir::new(tree.root());
+    let n = wd.into_classic().inspect(|r| assert!(r.is_ok(), "{:?}", r)).count();
+    assert_eq!(counts.entries() + 1, n);
+}
+
+#[cfg(feature = "testkit")]
+#[test]
+fn flaky_read_dir() {
+    use std::io;
+    use crate::{DirEntryContentProcessor, FlakyContext, FlakyFs, FlakyOp};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    dir.touch_all(&["foo/a", "b"]);
+
+    let ctx = FlakyContext::default()
+        .only_ops(&[FlakyOp::ReadDir])
+        .fail_path(dir.join("foo"), io::ErrorKind::PermissionDenied);
+    let wd = crate::WalkDirBuilder::<FlakyFs<crate::DefaultDirEntry>>::with_context(
+        dir.path(),
+        ctx,
+        DirEntryContentProcessor {},
+    );
+    let r = dir.run_recursive(wd.into_classic());
+
+    assert_eq!(1, r.errs().len());
+    assert_eq!(io::ErrorKind::PermissionDenied, r.errs()[0].io_error().unwrap().kind());
+    assert_eq!(io::ErrorKind::PermissionDenied, r.errs()[0].backend_error().unwrap().kind());
+    let expected = vec![dir.path().to_path_buf(), dir.join("b"), dir.join("foo")];
+    assert_eq!(expected, r.sorted_paths());
+}
+
+#[test]
+fn read_only_fs() {
+    use std::io;
+    use crate::{DirEntryContentProcessor, ReadOnlyContext, ReadOnlyFs};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.touch_all(&["foo/a", "b"]);
+
+    let wd = crate::WalkDirBuilder::<ReadOnlyFs>::with_context(
+        dir.path(),
+        ReadOnlyContext::default(),
+        DirEntryContentProcessor {},
+    );
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+    assert_eq!(4, r.ents().len());
+    let file = r.ents().iter().find(|e| e.path() == dir.join("b")).unwrap();
+    assert_eq!(io::ErrorKind::PermissionDenied, file.open().unwrap_err().kind());
+
+    // Contents are needed to skip binary files
+    let mut it = crate::WalkDirBuilder::<ReadOnlyFs>::with_context(
+        dir.path(),
+        ReadOnlyContext::default(),
+        DirEntryContentProcessor {},
+    )
+    .skip_binary_files(true)
+    .into_iter();
+    let errs = it.by_ref().filter(|pos| matches!(pos, Position::Error(_))).count();
+    assert_eq!(2, errs);
+    assert_eq!(2, it.context().refused());
+
+    let audited = std::panic::catch_unwind(|| {
+        crate::WalkDirBuilder::<ReadOnlyFs>::with_context(
+            dir.path(),
+            ReadOnlyContext::default().audit(true),
+            DirEntryContentProcessor {},
+        )
+        .skip_binary_files(true)
+        .into_classic()
+        .count()
+    });
+    assert!(audited.is_err());
+}
+
+#[cfg(feature = "testkit")]
+#[test]
+fn report_skipped_dirs() {
+    use std::io;
+    use crate::{DirEntryContentProcessor, FlakyContext, FlakyFs, FlakyOp};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    dir.touch_all(&["foo/a", "b"]);
+
+    let ctx = FlakyContext::default()
+        .only_ops(&[FlakyOp::ReadDir])
+        .fail_path(dir.join("foo"), io::ErrorKind::PermissionDenied);
+    let wd = crate::WalkDirBuilder::<FlakyFs<crate::DefaultDirEntry>>::with_context(
+        dir.path(),
+        ctx,
+        DirEntryContentProcessor {},
+    )
+    .report_skipped_dirs(true);
+
+    let mut skipped = vec![];
+    for pos in wd {
+        match pos {
+            Position::DirSkipped { entry, error } => {
+                assert_eq!(io::ErrorKind::PermissionDenied, error.io_error().unwrap().kind());
+                skipped.push(entry.into_path());
+            }
+            Position::Error(err) => panic!("unexpected error: {}", err),
+            _ => {}
+        }
+    }
+    assert_eq!(vec![dir.join("foo")], skipped);
+}
+
+#[cfg(feature = "testkit")]
+#[test]
+fn same_file_system_soft() {
+    use std::io;
+    use crate::{DirEntryContentProcessor, FlakyContext, FlakyFs, FlakyOp};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.touch_all(&["foo/a", "b"]);
+
+    let make = |soft: bool| {
+        let ctx = FlakyContext::default()
+            .only_ops(&[FlakyOp::DeviceNum])
+            .fail_path(dir.path(), io::ErrorKind::Unsupported);
+        crate::WalkDirBuilder::<FlakyFs<crate::DefaultDirEntry>>::with_context(
+            dir.path(),
+            ctx,
+            DirEntryContentProcessor {},
+        )
+        .same_file_system(true)
+        .same_file_system_soft(soft)
+    };
+
+    let r = dir.run_recursive(make(false).into_classic());
+    assert_eq!(1, r.errs().len());
+    assert!(r.paths().is_empty());
+
+    let mut it = make(true).into_iter();
+    let mut paths = vec![];
+    for pos in &mut it {
+        match pos {
+            Position::Entry(dent) => paths.push(dent.path().to_path_buf()),
+            Position::Error(err) => panic!("unexpected error: {}", err),
+            _ => {}
+        }
+    }
+    paths.sort();
+    assert!(it.same_file_system_ignored());
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("b"),
+        dir.join("foo"),
+        dir.join("foo").join("a"),
+    ];
+    assert_eq!(expected, paths);
+}
+
+#[cfg(feature = "testkit")]
+#[test]
+fn summarize_entry_errors() {
+    use std::io;
+    use crate::{DirEntryContentProcessor, ErrorKind, FlakyContext, FlakyFs, FlakyOp};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    dir.touch_all(&["foo/a", "foo/b", "foo/c", "d"]);
+
+    let ctx = FlakyContext::default()
+        .only_ops(&[FlakyOp::NextEntry])
+        .fail_path(dir.join("foo").join("a"), io::ErrorKind::PermissionDenied)
+        .fail_path(dir.join("foo").join("c"), io::ErrorKind::PermissionDenied);
+    let wd = crate::WalkDirBuilder::<FlakyFs<crate::DefaultDirEntry>>::with_context(
+        dir.path(),
+        ctx,
+        DirEntryContentProcessor {},
+    )
+    .summarize_entry_errors(true);
+    let r = dir.run_recursive(wd.into_classic());
+
+    assert_eq!(1, r.errs().len());
+    let err = &r.errs()[0];
+    assert_eq!(ErrorKind::PartialDir, err.kind());
+    assert_eq!(Some(2), err.entry_errors());
+    assert_eq!(Some(dir.join("foo").as_path()), err.path());
+    assert_eq!(1, err.depth());
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("d"),
+        dir.join("foo"),
+        dir.join("foo").join("b"),
+        dir.join("foo").join("bar"),
+    ];
+    assert_eq!(expected, r.sorted_paths());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn same_file_system() {
+    use std::path::Path;
+
+    // This test is a little weird since it's not clear whether it's a good
+    // idea to setup a distinct mounted volume in these tests. Instead, we
+    // probe for an existing one.
+    if !Path::new("/sys").is_dir() {
+        return;
+    }
+
+    let dir = Dir::tmp();
+    dir.touch("a");
+    dir.symlink_dir("/sys", "sys-link");
+
+    // First, do a sanity check that things work without following symlinks.
+    let wd = WalkDir::new(dir.path());
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("sys-link")];
+    assert_eq!(expected, r.sorted_paths());
+
+    // ... now follow symlinks and ensure we don't descend into /sys (the
+    // followed link is a dir on another device, so it's left out).
+    let wd = WalkDir::new(dir.path()).same_file_system(true).follow_links(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![dir.path().to_path_buf(), dir.join("a")];
+    assert_eq!(expected, r.sorted_paths());
+}
+
+#[test]
+fn follow_links_same_device() {
+    use std::path::Path;
+
+    // Probe for an existing mounted volume, see `same_file_system`.
+    if !Path::new("/sys").is_dir() {
+        return;
+    }
+
+    let dir = Dir::tmp();
+    dir.mkdirp("d");
+    dir.touch("d/f");
+    dir.symlink_dir("d", "local-link");
+    dir.symlink_dir("/sys", "sys-link");
+
+    let wd = WalkDir::new(dir.path()).follow_links_same_device(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("d"),
+        dir.join("d").join("f"),
+        dir.join("local-link"),
+        dir.join("local-link").join("f"),
+        dir.join("sys-link"),
+    ];
+    assert_eq!(expected, r.sorted_paths());
+
+    let ents = r.sorted_ents();
+    let local = ents.iter().find(|e| e.path() == dir.join("local-link")).unwrap();
+    assert!(local.path_is_symlink());
+    assert!(local.file_type().is_dir());
+    let sys = ents.iter().find(|e| e.path() == dir.join("sys-link")).unwrap();
+    assert!(sys.path_is_symlink());
+    assert!(sys.file_type().is_symlink());
+}
+
+#[test]
+fn same_file_system_devices() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    dir.touch_all(&["foo/a", "b"]);
+
+    let mut it = WalkDir::new(dir.path()).same_file_system(true).into_iter();
+    assert_eq!(None, it.root_device());
+    let mut devices = vec![];
+    for pos in it.by_ref() {
+        if let Position::Entry(ent) = pos {
+            devices.push(ent.device());
+        }
+    }
+    let root_device = it.root_device();
+    assert!(root_device.is_some());
+    assert_eq!(vec![root_device; 5], devices);
+
+    // Without the option, devices are never queried
+    let wd = WalkDir::new(dir.path());
+    let r = dir.run_recursive(wd.into_classic());
+    assert!(r.ents().iter().all(|ent| ent.device().is_none()));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn device_boundaries() {
+    use std::path::Path;
+
+    // Probe for an existing mounted volume, like same_file_system does.
+    if !Path::new("/sys").is_dir() {
+        return;
+    }
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.symlink_dir("/sys", "sys-link");
+
+    let mut it = WalkDir::new(dir.path())
+        .follow_links(true)
+        .device_boundaries(true)
+        .deterministic(true)
+        .into_iter();
+    let mut boundaries = 0;
+    let mut paths = vec![];
+    while let Some(pos) = it.next() {
+        match pos {
+            Position::DeviceBoundary { from, to } => {
+                assert_ne!(from, to);
+                boundaries += 1;
+                it.skip_current_dir();
+            }
+            Position::Entry(ent) => paths.push(ent.path().to_path_buf()),
+            _ => {}
+        }
+    }
+
+    assert_eq!(1, boundaries);
+    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("sys-link")];
+    assert_eq!(expected, paths);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn skip_mount_points() {
+    use std::path::Path;
+
+    if !Path::new("/sys").is_dir() {
+        return;
+    }
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.symlink_dir("/sys", "sys-link");
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .skip_mount_points(true)
+        .deterministic(true);
+    let ents: Vec<_> = wd.into_classic().map(|r| r.unwrap()).collect();
+    let paths: Vec<_> = ents.iter().map(|ent| ent.path().to_path_buf()).collect();
+    let mounts: Vec<_> = ents.iter().map(|ent| ent.is_mount_point()).collect();
+    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("sys-link")];
+    assert_eq!(expected, paths);
+    assert_eq!(vec![false, false, true], mounts);
+}
+
+#[cfg(unix)]
+#[test]
+fn sparse_file_allocated_size() {
+    let dir = Dir::tmp();
+    let file = std::fs::File::create(dir.join("sparse")).unwrap();
+    file.set_len(16 << 20).unwrap();
+
+    let wd = WalkDir::new(dir.join("sparse"));
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let ent = &r.ents()[0];
+    assert_eq!(16 << 20, ent.metadata().len());
+    assert!(ent.allocated_size().unwrap() < ent.metadata().len());
+}
+
+#[cfg(all(unix, feature = "xattr"))]
+#[test]
+fn filter_xattr() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch("a/b/tagged");
+    dir.touch("a/untagged");
+    if xattr::set(dir.join("a/b/tagged"), "user.backup", b"yes").is_err() {
+        // The temp dir doesn't support user attributes
+        return;
+    }
+
+    let wd = WalkDir::new(dir.path()).filter_xattr("user.backup", true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    assert_eq!(vec![dir.path().to_path_buf(), dir.join("a/b/tagged")], r.sorted_paths());
+    let ent = r.ents().iter().find(|ent| ent.depth() > 0).unwrap();
+    assert_eq!(Some(b"yes".to_vec()), ent.xattr("user.backup").unwrap());
+    assert_eq!(vec![std::ffi::OsString::from("user.backup")], ent.xattrs().unwrap());
+}
+
+#[cfg(all(feature = "linux-flags", target_os = "linux"))]
+#[test]
+fn skip_nodump() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch("a/b/c");
+    dir.touch("a/d");
+    let marked = std::process::Command::new("chattr")
+        .arg("+d")
+        .arg(dir.join("a/b"))
+        .status()
+        .is_ok_and(|status| status.success());
+    if !marked {
+        // No chattr or the temp dir doesn't support inode flags
+        return;
+    }
+
+    let wd = WalkDir::new(dir.path()).skip_nodump(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("a/d")];
+    assert_eq!(expected, r.sorted_paths());
+    assert!(r.ents().iter().all(|ent| !ent.file_flags().unwrap().is_nodump()));
+}
+
+#[test]
+fn open_reads_contents() {
+    use std::io::Read;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    fs::write(dir.join("a/b"), "hello").unwrap();
+    dir.symlink_file("a/b", "a/c");
+
+    let wd = WalkDir::new(dir.path());
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let mut files = 0;
+    for ent in r.ents() {
+        if ent.file_type().is_dir() {
+            continue;
+        }
+        let mut contents = String::new();
+        ent.open().unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!("hello", contents);
+        files += 1;
+    }
+    assert_eq!(2, files);
+}
+
+#[test]
+fn skip_binary_files() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    fs::write(dir.join("a/text"), "hello\n").unwrap();
+    fs::write(dir.join("a/bin"), b"\x7fELF\x00\x01").unwrap();
+    let mut late = vec![b'x'; 10000];
+    late[9000] = 0;
+    fs::write(dir.join("a/late"), late).unwrap();
+    dir.touch("a/empty");
+    dir.symlink_file("a/bin", "a/bin-link");
+
+    let wd = WalkDir::new(dir.path()).skip_binary_files(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("a/bin-link"),
+        dir.join("a/empty"),
+        dir.join("a/late"),
+        dir.join("a/text"),
+    ];
+    assert_eq!(expected, r.sorted_paths());
 
-    // This test is a little weird since it's not clear whether it's a good
-    // idea to setup a distinct mounted volume in these tests. Instead, we
-    // probe for an existing one.
+    let wd = WalkDir::new(dir.path()).skip_binary_files(true).follow_links(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+    assert!(!r.sorted_paths().contains(&dir.join("a/bin-link")));
+}
+
+#[test]
+#[cfg(unix)]
+fn special_files() {
+    let dir = Dir::tmp();
+    dir.touch("file");
+    let _listener = std::os::unix::net::UnixListener::bind(dir.join("sock")).unwrap();
+
+    let wd = WalkDir::new(dir.path());
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+    assert_eq!(3, r.ents().len());
+
+    let wd = WalkDir::new(dir.path()).special_files(SpecialFilePolicy::Skip);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+    assert_eq!(vec![dir.path().to_path_buf(), dir.join("file")], r.sorted_paths());
+
+    let wd = WalkDir::new(dir.path()).special_files(SpecialFilePolicy::Error);
+    let r = dir.run_recursive(wd.into_classic());
+    assert_eq!(vec![dir.path().to_path_buf(), dir.join("file")], r.sorted_paths());
+    assert_eq!(1, r.errs().len());
+    assert_eq!(ErrorKind::SpecialFile, r.errs()[0].kind());
+    assert_eq!(Some(dir.join("sock").as_path()), r.errs()[0].path());
+}
+
+#[test]
+#[cfg(unix)]
+fn file_type_special_predicates() {
+    use crate::FsFileType;
+
+    let dir = Dir::tmp();
+    dir.touch("file");
+    let _listener = std::os::unix::net::UnixListener::bind(dir.join("sock")).unwrap();
+
+    let wd = WalkDir::new(dir.path()).min_depth(1).deterministic(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+    let ents = r.ents();
+    assert_eq!(2, ents.len());
+
+    let file = ents[0].file_type();
+    assert!(file.is_file());
+    assert!(!file.is_fifo() && !file.is_socket() && !file.is_block_device() && !file.is_char_device());
+
+    let sock = ents[1].file_type();
+    assert!(!sock.is_file() && !sock.is_dir() && !sock.is_symlink());
+    assert!(sock.is_socket());
+    assert!(!sock.is_fifo() && !sock.is_block_device() && !sock.is_char_device());
+}
+
+#[test]
+fn middleware() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::{EntryMiddleware, FlatDirEntry, FsDirEntry};
+
+    struct Suppress;
+    impl<E: FsDirEntry<Path = std::path::Path>> EntryMiddleware<E> for Suppress {
+        fn process(&self, flat: FlatDirEntry<E>, _depth: usize, _ctx: &mut E::Context) -> Option<FlatDirEntry<E>> {
+            if flat.raw.path().ends_with("hidden") { None } else { Some(flat) }
+        }
+    }
+
+    struct Leaf(AtomicUsize);
+    impl<E: FsDirEntry<Path = std::path::Path>> EntryMiddleware<E> for Leaf {
+        fn process(&self, mut flat: FlatDirEntry<E>, _depth: usize, _ctx: &mut E::Context) -> Option<FlatDirEntry<E>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            if flat.raw.path().ends_with("leaf") {
+                flat.is_dir = false;
+            }
+            Some(flat)
+        }
+    }
+
+    let dir = Dir::tmp();
+    dir.mkdirp("hidden/sub");
+    dir.mkdirp("leaf/sub");
+    dir.touch_all(&["a", "hidden/b", "leaf/c"]);
+
+    let wd = WalkDir::new(dir.path()).with_middleware(vec![Box::new(Suppress), Box::new(Leaf(AtomicUsize::new(0)))]);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("leaf")];
+    assert_eq!(expected, r.sorted_paths());
+}
+
+#[test]
+fn override_at_depth() {
+    let dir = Dir::tmp();
+    dir.mkdirp("t/x");
+    dir.mkdirp("a/b");
+    dir.touch("t/x/f");
+    dir.symlink_dir(dir.join("t"), "a/l1");
+    dir.symlink_dir(dir.join("t"), "a/b/l2");
+
+    let wd = WalkDir::new(dir.join("a")).override_at_depth(2, |opts| opts.follow_links = true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.join("a"),
+        dir.join("a/b"),
+        dir.join("a/b/l2"),
+        dir.join("a/b/l2/x"),
+        dir.join("a/b/l2/x/f"),
+        dir.join("a/l1"),
+    ];
+    assert_eq!(expected, r.sorted_paths());
+}
+
+#[test]
+fn override_at_depth_loop() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.symlink_dir(dir.path(), "a/b/up");
+
+    // Links are followed only at depth 2, but the loop is found against the root
+    let wd = WalkDir::new(dir.path()).override_at_depth(2, |opts| opts.follow_links = true);
+    let r = dir.run_recursive(wd.into_classic());
+
+    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("a/b")];
+    assert_eq!(expected, r.sorted_paths());
+    assert_eq!(1, r.errs().len());
+
+    let err = &r.errs()[0];
+    assert_eq!(Some(&*dir.join("a/b/up")), err.path());
+    assert_eq!(Some(dir.path()), err.loop_ancestor());
+    assert_eq!(Some(0), err.loop_ancestor_depth());
+    assert_eq!(3, err.depth());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn override_at_depth_devices() {
+    use std::path::Path;
+
+    // Probe for an existing mounted volume, see `same_file_system`.
     if !Path::new("/sys").is_dir() {
         return;
     }
 
     let dir = Dir::tmp();
-    dir.touch("a");
-    dir.symlink_dir("/sys", "sys-link");
+    dir.mkdirp("a");
+    dir.symlink_dir("/sys", "a/sys-link");
 
-    // First, do a sanity check that things work without following symlinks.
-    let wd = WalkDir::new(dir.path());
-    let r = dir.run_recursive(wd);
+    // The device of the root is needed though only depth 2 compares with it
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .override_at_depth(2, |opts| opts.same_file_system = true);
+    let r = dir.run_recursive(wd.into_classic());
     r.assert_no_errors();
 
-    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("sys-link")];
+    let expected = vec![dir.path().to_path_buf(), dir.join("a")];
     assert_eq!(expected, r.sorted_paths());
 
-    // ... now follow symlinks and ensure we don't descend into /sys.
-    let wd = WalkDir::new(dir.path()).same_file_system(true).follow_links(true);
-    let r = dir.run_recursive(wd);
+    // Devices are tracked though only depth 2 reports boundaries
+    let mut it = WalkDir::new(dir.path())
+        .follow_links(true)
+        .override_at_depth(2, |opts| opts.device_boundaries = true)
+        .into_iter();
+    let mut boundaries = 0;
+    while let Some(pos) = it.next() {
+        if let Position::DeviceBoundary { .. } = pos {
+            boundaries += 1;
+            it.skip_current_dir();
+        }
+    }
+    assert_eq!(1, boundaries);
+}
+
+#[test]
+fn options_at_runtime() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+
+    let mut it = WalkDir::new(dir.path())
+        .contents_first(true)
+        .override_at_depth(1, |opts| opts.follow_links = true)
+        .into_iter();
+    assert!(it.options().contents_first);
+    assert!(!it.options().follow_links);
+    assert!(!it.options_at(0).follow_links);
+    assert!(it.options_at(2).follow_links);
+
+    it.next();
+    let debug = format!("{:?}", it);
+    assert!(debug.starts_with("WalkDirIterator {"));
+    assert!(debug.contains("contents_first: true"));
+}
+
+#[test]
+fn parent_of() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    dir.touch_all(&["foo/a", "foo/bar/b"]);
+
+    let mut it = WalkDir::new(dir.path()).contents_first(true).into_iter();
+    let mut pairs = vec![];
+    while let Some(pos) = it.next() {
+        if let Position::Entry(dent) = pos {
+            let parent = it.parent_of(&dent).map(|p| p.path().to_path_buf());
+            pairs.push((dent.path().to_path_buf(), parent));
+        }
+    }
+    pairs.sort();
+
+    let expected = vec![
+        (dir.path().to_path_buf(), None),
+        (dir.join("foo"), Some(dir.path().to_path_buf())),
+        (dir.join("foo").join("a"), Some(dir.join("foo"))),
+        (dir.join("foo").join("bar"), Some(dir.join("foo"))),
+        (dir.join("foo").join("bar").join("b"), Some(dir.join("foo").join("bar"))),
+    ];
+    assert_eq!(expected, pairs);
+}
+
+#[test]
+fn annotations() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    dir.touch_all(&["foo/a", "foo/bar/b", "c"]);
+
+    // Count entries of every dir in the value attached to it
+    let mut it = WalkDir::new(dir.path()).into_iter();
+    let mut counts = vec![];
+    while let Some(pos) = it.next() {
+        match pos {
+            Position::BeforeContent((parent, _)) => it.push_annotation((parent.path().to_path_buf(), 0usize)),
+            Position::Entry(_) => {
+                if let Some((_, count)) = it.annotation_mut::<(PathBuf, usize)>() {
+                    *count += 1;
+                }
+            }
+            Position::AfterContent { .. } => {
+                assert_eq!(None, it.take_annotation::<String>());
+                counts.push(it.take_annotation::<(PathBuf, usize)>().unwrap());
+                assert_eq!(None, it.take_annotation::<(PathBuf, usize)>());
+            }
+            _ => {}
+        }
+    }
+    counts.sort();
+
+    let expected = vec![
+        (dir.path().to_path_buf(), 2),
+        (dir.join("foo"), 2),
+        (dir.join("foo").join("bar"), 1),
+    ];
+    assert_eq!(expected, counts);
+}
+
+#[test]
+fn readdir_batch() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.touch_all(&["foo/a", "b"]);
+
+    let it = WalkDir::new(dir.path()).into_iter();
+    assert_eq!(None, it.context().readdir_batch());
+
+    let mut it = WalkDir::new(dir.path()).readdir_batch(256).into_iter();
+    assert_eq!(Some(256), it.context().readdir_batch());
+    assert_eq!(4, (&mut it).filter(|pos| matches!(pos, Position::Entry(_))).count());
+
+    let it = WalkDir::new(dir.path()).readdir_batch(0).into_iter();
+    assert_eq!(Some(1), it.context().readdir_batch());
+}
+
+#[test]
+fn metadata_cache() {
+    use std::sync::{Arc, Mutex};
+    use crate::{CachePolicy, DirEntryContentProcessor, FsCallCounts, FsDirEntry, StandardContext};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    dir.touch_all(&["foo/a", "b", "c"]);
+
+    let run = |policy: CachePolicy| {
+        let counts = Arc::new(Mutex::new(FsCallCounts::default()));
+        let ctx = StandardContext::with_observer(counts.clone());
+        let wd = WalkDir::with_context(dir.path(), ctx, DirEntryContentProcessor {})
+            .metadata_cache(policy)
+            .sort_by(|a, b, ctx| {
+                let len = |e: &crate::DefaultDirEntry, ctx: &mut _| e.metadata(false, ctx).unwrap().len();
+                len(a.0, ctx).cmp(&len(b.0, ctx))
+            });
+        let r = dir.run_recursive(wd.into_classic());
+        r.assert_no_errors();
+        assert_eq!(5, r.ents().len());
+        let counts = *counts.lock().unwrap();
+        counts
+    };
+
+    let counts = run(CachePolicy::None);
+    assert!(counts.metadata > 5);
+    assert_eq!(0, counts.cache_hits + counts.cache_misses);
+
+    for policy in [CachePolicy::PerEntry, CachePolicy::Lru(16)] {
+        let counts = run(policy);
+        assert_eq!(5, counts.metadata, "{:?}", policy);
+        assert_eq!(5, counts.cache_misses, "{:?}", policy);
+        assert!(counts.cache_hits > 0, "{:?}", policy);
+    }
+
+    // The sorter compares entries alternately, so a single kept query is evicted
+    let counts = run(CachePolicy::Lru(1));
+    assert_eq!(counts.metadata, counts.cache_misses);
+    assert!(counts.cache_misses > 5);
+}
+
+#[test]
+fn match_case() {
+    let dir = Dir::tmp();
+    dir.mkdirp("Foo.app/x");
+
+    let wd = WalkDir::new(dir.path()).treat_as_files(&["*.APP"]);
+    let r = dir.run_recursive(wd.into_classic());
     r.assert_no_errors();
+    assert_eq!(3, r.sorted_paths().len());
 
-    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("sys-link")];
+    let wd = WalkDir::new(dir.path())
+        .treat_as_files(&["*.APP"])
+        .match_case(CaseMatch::Insensitive);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+    assert_eq!(vec![dir.path().to_path_buf(), dir.join("Foo.app")], r.sorted_paths());
+
+    let auto = cfg!(any(windows, target_os = "macos", target_os = "ios"));
+    assert_eq!(auto, CaseMatch::Auto.ignores_case::<crate::DefaultDirEntry>());
+}
+
+#[test]
+fn sync_walk() {
+    let dir = Dir::tmp();
+    dir.mkdirp("src/a/x");
+    dir.mkdirp("src/c");
+    dir.mkdirp("dst/a");
+    dir.mkdirp("dst/e/q");
+    dir.touch_all(&["src/b", "dst/b", "dst/a/w"]);
+
+    let (src, dst) = (dir.join("src"), dir.join("dst"));
+    let mut got = vec![];
+    let mut it = crate::sync_walk(&src, &dst);
+    while let Some(pos) = it.next() {
+        let pos = pos.unwrap();
+        let (kind, path) = match &pos {
+            SyncPosition::InBoth(s, _) => ("both", s.path().strip_prefix(&src).unwrap()),
+            SyncPosition::OnlySrc(s) => ("src", s.path().strip_prefix(&src).unwrap()),
+            SyncPosition::OnlyDst(d) => ("dst", d.path().strip_prefix(&dst).unwrap()),
+        };
+        got.push((kind, path.to_path_buf()));
+        if kind == "dst" {
+            it.skip_current_dir();
+        }
+    }
+
+    let expected = vec![
+        ("both", PathBuf::new()),
+        ("both", PathBuf::from("a")),
+        ("dst", PathBuf::from("a/w")),
+        ("src", PathBuf::from("a/x")),
+        ("both", PathBuf::from("b")),
+        ("src", PathBuf::from("c")),
+        ("dst", PathBuf::from("e")),
+    ];
+    assert_eq!(expected, got);
+
+    let r: Vec<_> = crate::sync_walk(&src, dir.join("missing")).collect();
+    assert!(r[0].is_err());
+    assert_eq!(5, r.iter().filter(|pos| matches!(pos, Ok(SyncPosition::OnlySrc(_)))).count());
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn par_walk() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/x/y");
+    dir.mkdirp("b");
+    dir.mkdirp("c/z");
+    dir.mkdirp(".hidden/w");
+    dir.touch_all(&["a/x/y/1", "a/2", "b/3", "c/z/4", "d", ".hidden/w/5"]);
+
+    for threads in [1, 3] {
+        let parallelism = crate::Parallelism::Fixed(threads);
+        let opts = crate::ParWalkOptions { parallelism, buffer: 1, skip_hidden: true, ..Default::default() };
+        let got: Vec<_> = crate::par_walk(dir.path(), opts).map(|e| e.unwrap()).collect();
+
+        let wd = WalkDir::new(dir.path()).deterministic(true);
+        let expected: Vec<_> = wd
+            .into_classic()
+            .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(12, got.len());
+        assert_eq!(
+            expected.iter().map(|e| (e.path(), e.depth())).collect::<Vec<_>>(),
+            got.iter().map(|e| (e.path(), e.depth())).collect::<Vec<_>>(),
+        );
+
+        let jobs = std::sync::atomic::AtomicUsize::new(0);
+        let spawner = |job: Box<dyn FnOnce() + Send>| {
+            jobs.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            std::thread::spawn(job);
+        };
+        let opts = crate::ParWalkOptions { parallelism, skip_hidden: true, ..Default::default() };
+        let on: Vec<_> = crate::par_walk_on(dir.path(), opts, &spawner).map(|e| e.unwrap().into_path()).collect();
+        assert_eq!(got.iter().map(|e| e.path().to_path_buf()).collect::<Vec<_>>(), on);
+        assert_eq!(3, jobs.load(std::sync::atomic::Ordering::Relaxed));
+    }
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn par_walk_backpressure() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let dir = Dir::tmp();
+    for i in 0..50 {
+        dir.mkdirp(format!("a/d{:02}/x", i));
+    }
+
+    for wait in [crate::ParWait::Block, crate::ParWait::YieldNow] {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let counter = reads.clone();
+        let opts = crate::ParWalkOptions {
+            parallelism: crate::Parallelism::Fixed(1),
+            buffer: 1,
+            wait,
+            process_read_dir: Some(Arc::new(move |_, _, _| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })),
+            ..Default::default()
+        };
+        let mut it = crate::par_walk(dir.path(), opts);
+        assert!(it.by_ref().take(3).all(|e| e.is_ok()));
+        // The consumer is behind, so the thread stops reading dirs
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(reads.load(Ordering::SeqCst) < 10);
+
+        assert_eq!(102 - 3, it.count());
+        assert_eq!(102, reads.load(Ordering::SeqCst));
+    }
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn par_walk_max_concurrency_per_device() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let dir = Dir::tmp();
+    for i in 0..6 {
+        dir.mkdirp(format!("d{}", i));
+        dir.touch(format!("d{}/f", i));
+    }
+
+    let run = |limit: Option<usize>| {
+        let (active, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (a, p) = (active.clone(), peak.clone());
+        let opts = crate::ParWalkOptions {
+            parallelism: crate::Parallelism::Fixed(4),
+            buffer: 1,
+            max_concurrency_per_device: limit,
+            // Every subtree is a single dir, so reading it spans the whole subtree
+            process_read_dir: Some(Arc::new(move |depth, _, _| {
+                if depth == 1 {
+                    p.fetch_max(a.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                    std::thread::sleep(std::time::Duration::from_millis(20));
+                    a.fetch_sub(1, Ordering::SeqCst);
+                }
+            })),
+            ..Default::default()
+        };
+        let got: Vec<_> = crate::par_walk(dir.path(), opts).map(|e| e.unwrap().into_path()).collect();
+        (got, peak.load(Ordering::SeqCst))
+    };
+
+    let (expected, _) = run(None);
+    assert_eq!(13, expected.len());
+    // All subtrees are on the device of the temp dir
+    let (got, peak) = run(Some(1));
+    assert_eq!(expected, got);
+    assert_eq!(1, peak);
+    let (got, peak) = run(Some(2));
+    assert_eq!(expected, got);
+    assert!(peak <= 2);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn par_walk_parallelism_auto() {
+    let dir = Dir::tmp();
+    for i in 0..40 {
+        dir.mkdirp(format!("d{:02}/x", i));
+    }
+
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut it = crate::par_walk(dir.path(), crate::ParWalkOptions::default());
+    assert_eq!(2, it.threads());
+    let got: Vec<_> = it.by_ref().map(|e| e.unwrap().into_path()).collect();
+    // Whatever the storage, the walk settles on a count once 32 dirs were read
+    assert!(it.threads() == cpus || it.threads() == cpus.clamp(2, 4));
+
+    let opts = crate::ParWalkOptions { parallelism: crate::Parallelism::Fixed(1), ..Default::default() };
+    let expected: Vec<_> = crate::par_walk(dir.path(), opts).map(|e| e.unwrap().into_path()).collect();
+    assert_eq!(81, got.len());
+    assert_eq!(expected, got);
+}
+
+#[test]
+fn fs_path_join() {
+    use crate::FsPath;
+    use std::ffi::OsStr;
+    use std::path::Path;
+
+    let path = FsPath::join(Path::new("a/b"), OsStr::new("c.tar.gz"));
+    assert_eq!(Path::new("a/b/c.tar.gz"), path);
+    assert_eq!(Some(OsStr::new("c.tar")), FsPath::file_stem(path.as_path()));
+    assert_eq!(Some(Path::new("a/b")), FsPath::parent(path.as_path()));
+
+    assert_eq!("s3://bucket/key", FsPath::join("s3://bucket", "key"));
+    assert_eq!("s3://bucket/key", FsPath::join("s3://bucket/", "key"));
+    assert_eq!("key", FsPath::join("", "key"));
+}
+
+#[test]
+fn url_path() {
+    use crate::{FsPath, UrlPath, UrlPathBuf};
+
+    let path = UrlPath::new("s3://bucket/dir/a%20b.tar.gz");
+    assert_eq!(Some("a%20b.tar.gz"), path.file_name());
+    assert_eq!(Some("a%20b.tar"), path.file_stem());
+    assert_eq!("a b.tar.gz", path.file_name_lossy().unwrap());
+    let dir = path.parent().unwrap();
+    assert_eq!("s3://bucket/dir", dir.as_str());
+    assert_eq!(Some(UrlPath::new("s3://bucket")), dir.parent());
+    assert_eq!(None, UrlPath::new("s3://bucket/").parent());
+    assert_eq!(Some(UrlPath::new("/")), UrlPath::new("/a").parent());
+
+    let mut buf = UrlPathBuf::from("s3://bucket");
+    buf.push("x/y");
+    assert_eq!("s3://bucket/x%2Fy", buf.as_str());
+    assert_eq!(Some("x/y"), buf.file_name_lossy().as_deref());
+    assert!(buf.pop());
+    assert!(!buf.pop());
+    assert_eq!("s3://bucket/d/e", dir.parent().unwrap().join("d").join("e").as_str());
+
+    assert_eq!("a/b%zz\u{20ac}", UrlPath::decode("a%2Fb%zz%E2%82%AC"));
+}
+
+#[test]
+fn into_std_and_open_options() {
+    use std::io::Write;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch_all(&["a/f"]);
+
+    let r = dir.run_recursive(WalkDir::new(dir.path()).into_classic());
+    r.assert_no_errors();
+    for ent in r.ents() {
+        if ent.file_type().is_file() {
+            let mut file = ent.open_options().append(true).open().unwrap();
+            file.write_all(b"x").unwrap();
+        }
+        let path = ent.path().to_path_buf();
+        assert_eq!(Some(path), ent.clone().into_std().map(|std_ent| std_ent.path()));
+    }
+    assert_eq!("x", fs::read_to_string(dir.join("a/f")).unwrap());
+
+    fs::remove_dir_all(dir.join("a")).unwrap();
+    let gone = r.ents().iter().filter(|ent| ent.depth() > 0);
+    assert!(gone.cloned().all(|ent| ent.into_std().is_none()));
+}
+
+#[test]
+fn globally_sorted() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/x");
+    dir.mkdirp("a b/y");
+    dir.mkdirp("a0");
+    dir.touch_all(&["a.txt", "a b.txt", "a/a.txt", "a/a-"]);
+
+    let wd = WalkDir::new(dir.path()).globally_sorted(true);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let mut expected = r.paths();
+    expected.sort_by(|a, b| a.as_os_str().as_encoded_bytes().cmp(b.as_os_str().as_encoded_bytes()));
+    assert_eq!(expected, r.paths());
+    assert_eq!(10, r.paths().len());
+    // `a` comes first, its content after `a.txt`
+    let names: Vec<_> = r.paths().iter().map(|p| p.strip_prefix(dir.path()).unwrap().to_path_buf()).collect();
+    assert_eq!(PathBuf::from("a"), names[1]);
+    assert_eq!(PathBuf::from("a/a-"), names[6]);
+}
+
+#[test]
+fn merge() {
+    let dir = Dir::tmp();
+    dir.mkdirp("one/b");
+    dir.mkdirp("two/a");
+    dir.touch_all(&["one/b/x", "one/c", "two/a/y", "two/d"]);
+
+    let walks = ["one", "two"].iter().map(|root| WalkDir::new(dir.join(root)).deterministic(true).into_classic());
+    let paths: Vec<_> = crate::merge(walks).map(|e| e.unwrap().into_path()).collect();
+    let mut expected = paths.clone();
+    expected.sort();
+    assert_eq!(expected, paths);
+    assert_eq!(8, paths.len());
+    assert_eq!(dir.join("one"), paths[0]);
+
+    // Equal items keep the order of the iterators, errors are yielded when read
+    let first = vec![Ok((1, 'a')), Ok((3, 'a'))];
+    let second = vec![Ok((1, 'b')), Err(()), Ok((2, 'b'))];
+    let merged: Vec<_> = crate::merge_by([first.into_iter(), second.into_iter()], |a, b| a.0.cmp(&b.0)).collect();
+    assert_eq!(vec![Ok((1, 'a')), Ok((1, 'b')), Err(()), Ok((2, 'b')), Ok((3, 'a'))], merged);
+}
+
+#[test]
+fn skip_dirs_unmodified_since() {
+    use std::time::{Duration, SystemTime};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("old/sub");
+    dir.mkdirp("new");
+    dir.touch_all(&["old/a", "old/sub/b", "new/c"]);
+    let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    fs::File::open(dir.join("old")).unwrap().set_modified(hour_ago - Duration::from_secs(60)).unwrap();
+
+    let wd = WalkDir::new(dir.path()).skip_dirs_unmodified_since(hour_ago);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![dir.path().to_path_buf(), dir.join("new"), dir.join("new/c"), dir.join("old")];
+    assert_eq!(expected, r.sorted_paths());
+}
+
+#[test]
+#[cfg(unix)]
+fn skip_fingerprints() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("old/sub");
+    dir.mkdirp("new");
+    dir.touch_all(&["old/a", "old/sub/b", "new/c"]);
+
+    let mut it = WalkDir::new(dir.path()).record_fingerprints(true).build();
+    while it.next().is_some() {}
+    let visited = it.visited_fingerprints().clone();
+    assert_eq!(4, visited.len());
+
+    let md = fs::metadata(dir.join("old")).unwrap();
+    let old = DirId { device: md.dev(), index: md.ino() };
+    assert!(visited.contains(&old));
+
+    let wd = WalkDir::new(dir.path()).skip_fingerprints(vec![old]);
+    let r = dir.run_recursive(wd.into_classic());
+    r.assert_no_errors();
+
+    let expected = vec![dir.path().to_path_buf(), dir.join("new"), dir.join("new/c"), dir.join("old")];
     assert_eq!(expected, r.sorted_paths());
 }
 
@@ -1071,3 +3690,38 @@ fn regression_skip_current_dir() {
     wd.skip_current_dir();
     wd.next();
 }
+
+#[cfg(feature = "cache")]
+#[test]
+fn read_dir_cache_load_corrupted() {
+    use crate::ReadDirCache;
+
+    let dir = Dir::tmp();
+    let path = dir.join("cache");
+    let header = |count: u64| {
+        let mut bytes = b"WDRC\x02".to_vec();
+        bytes.extend_from_slice(&count.to_le_bytes());
+        bytes
+    };
+
+    // A huge length of a key with nothing after it
+    let mut bytes = header(1);
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+    fs::write(&path, &bytes).unwrap();
+    let err = ReadDirCache::load(&path).unwrap_err();
+    assert_eq!(std::io::ErrorKind::UnexpectedEof, err.kind());
+
+    // A huge count of children with nothing after it
+    let mut bytes = header(1);
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&[0; 12]);
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+    fs::write(&path, &bytes).unwrap();
+    let err = ReadDirCache::load(&path).unwrap_err();
+    assert_eq!(std::io::ErrorKind::UnexpectedEof, err.kind());
+
+    // A huge count of dirs
+    fs::write(&path, header(u64::MAX)).unwrap();
+    let err = ReadDirCache::load(&path).unwrap_err();
+    assert_eq!(std::io::ErrorKind::UnexpectedEof, err.kind());
+}
@@ -5,9 +5,11 @@ use std::io;
 use std::path::{Path, PathBuf};
 use std::result;
 
-use crate::storage;
-use crate::storage::{StoragePath, StoragePathBuf};
-use crate::{DirEntry, Error};
+use crate::fs::FsDirEntry;
+use crate::{DefaultDirEntry, DirEntry, DirEntryContentProcessor, Error, WalkDirBuilder};
+
+/// Classic walker (the `WalkDir` alias of the crate is gone, tests keep the name).
+pub type WalkDir = WalkDirBuilder<DefaultDirEntry, DirEntryContentProcessor>;
 
 /// Create an error from a format!-like syntax.
 #[macro_export]
@@ -21,13 +23,12 @@ macro_rules! err {
 pub type Result<T> = result::Result<T, Box<dyn error::Error + Send + Sync>>;
 
 /// The result of running a recursive directory iterator on a single directory.
-#[derive(Debug)]
-pub struct RecursiveResults<E: storage::StorageExt> {
+pub struct RecursiveResults<E: FsDirEntry> {
     ents: Vec<DirEntry<E>>,
     errs: Vec<Error<E>>,
 }
 
-impl<E: storage::StorageExt> RecursiveResults<E> {
+impl<E: FsDirEntry<Path = Path, PathBuf = PathBuf>> RecursiveResults<E> {
     /// Return all of the errors encountered during traversal.
     pub fn errs(&self) -> &[Error<E>] {
         &self.errs
@@ -96,7 +97,7 @@ impl Dir {
 
     /// Run the given iterator and return the result as a distinct collection
     /// of directory entries and errors.
-    pub fn run_recursive<I, E: storage::StorageExt>(&self, it: I) -> RecursiveResults<E>
+    pub fn run_recursive<I, E: FsDirEntry>(&self, it: I) -> RecursiveResults<E>
     where
         I: Iterator<Item = result::Result<DirEntry<E>, Error<E>>>,
     {
@@ -0,0 +1,383 @@
+/*!
+Canonical decomposition (NFD) of file names, used to find names which are equal
+under Unicode normalization (see `WalkDir::detect_unicode_collisions`).
+
+Only a subset of Unicode is covered: precomposed letters of the Latin, Greek and
+Cyrillic blocks (including Vietnamese and polytonic Greek), Hangul syllables and
+the combining marks of U+0300..U+036F. This covers the names usually met when
+syncing between macOS (which stores names decomposed) and other systems (which
+usually store them composed). Other characters are kept as they are.
+
+The tables are generated from the Unicode 14.0.0 character database.
+*/
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Decompose `name` canonically. Returns `Cow::Borrowed` if it is already decomposed.
+pub(crate) fn nfd(name: &str) -> Cow<'_, str> {
+    if name.is_ascii() {
+        return Cow::Borrowed(name);
+    }
+
+    let mut chars: Vec<char> = Vec::with_capacity(name.len());
+    for c in name.chars() {
+        decompose(c, &mut chars);
+    }
+    reorder(&mut chars);
+
+    if chars.iter().copied().eq(name.chars()) {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(chars.into_iter().collect::<String>())
+    }
+}
+
+fn decompose(c: char, out: &mut Vec<char>) {
+    const S_BASE: u32 = 0xAC00;
+    const L_BASE: u32 = 0x1100;
+    const V_BASE: u32 = 0x1161;
+    const T_BASE: u32 = 0x11A7;
+    const T_COUNT: u32 = 28;
+    const N_COUNT: u32 = 21 * T_COUNT;
+    const S_COUNT: u32 = 19 * N_COUNT;
+
+    let s = (c as u32).wrapping_sub(S_BASE);
+    if s < S_COUNT {
+        // Hangul syllables are decomposed algorithmically
+        let push = |out: &mut Vec<char>, v| out.extend(core::char::from_u32(v));
+        push(out, L_BASE + s / N_COUNT);
+        push(out, V_BASE + (s % N_COUNT) / T_COUNT);
+        let t = s % T_COUNT;
+        if t != 0 {
+            push(out, T_BASE + t);
+        }
+        return;
+    }
+
+    match DECOMPOSITIONS.binary_search_by_key(&c, |&(c, _)| c) {
+        Ok(i) => out.extend(DECOMPOSITIONS[i].1.chars()),
+        Err(_) => out.push(c),
+    }
+}
+
+/// Sort each run of combining marks by their combining classes (stable).
+fn reorder(chars: &mut [char]) {
+    let mut i = 0;
+    while i < chars.len() {
+        if combining_class(chars[i]) == 0 {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && combining_class(chars[i]) != 0 {
+            i += 1;
+        }
+        chars[start..i].sort_by_key(|&c| combining_class(c));
+    }
+}
+
+fn combining_class(c: char) -> u8 {
+    match COMBINING_CLASSES.binary_search_by_key(&c, |&(c, _)| c) {
+        Ok(i) => COMBINING_CLASSES[i].1,
+        Err(_) => 0,
+    }
+}
+
+/// Full canonical decompositions, sorted by the precomposed char
+#[rustfmt::skip]
+static DECOMPOSITIONS: &[(char, &str)] = &[
+    ('\u{C0}', "\u{41}\u{300}"), ('\u{C1}', "\u{41}\u{301}"), ('\u{C2}', "\u{41}\u{302}"),
+    ('\u{C3}', "\u{41}\u{303}"), ('\u{C4}', "\u{41}\u{308}"), ('\u{C5}', "\u{41}\u{30A}"),
+    ('\u{C7}', "\u{43}\u{327}"), ('\u{C8}', "\u{45}\u{300}"), ('\u{C9}', "\u{45}\u{301}"),
+    ('\u{CA}', "\u{45}\u{302}"), ('\u{CB}', "\u{45}\u{308}"), ('\u{CC}', "\u{49}\u{300}"),
+    ('\u{CD}', "\u{49}\u{301}"), ('\u{CE}', "\u{49}\u{302}"), ('\u{CF}', "\u{49}\u{308}"),
+    ('\u{D1}', "\u{4E}\u{303}"), ('\u{D2}', "\u{4F}\u{300}"), ('\u{D3}', "\u{4F}\u{301}"),
+    ('\u{D4}', "\u{4F}\u{302}"), ('\u{D5}', "\u{4F}\u{303}"), ('\u{D6}', "\u{4F}\u{308}"),
+    ('\u{D9}', "\u{55}\u{300}"), ('\u{DA}', "\u{55}\u{301}"), ('\u{DB}', "\u{55}\u{302}"),
+    ('\u{DC}', "\u{55}\u{308}"), ('\u{DD}', "\u{59}\u{301}"), ('\u{E0}', "\u{61}\u{300}"),
+    ('\u{E1}', "\u{61}\u{301}"), ('\u{E2}', "\u{61}\u{302}"), ('\u{E3}', "\u{61}\u{303}"),
+    ('\u{E4}', "\u{61}\u{308}"), ('\u{E5}', "\u{61}\u{30A}"), ('\u{E7}', "\u{63}\u{327}"),
+    ('\u{E8}', "\u{65}\u{300}"), ('\u{E9}', "\u{65}\u{301}"), ('\u{EA}', "\u{65}\u{302}"),
+    ('\u{EB}', "\u{65}\u{308}"), ('\u{EC}', "\u{69}\u{300}"), ('\u{ED}', "\u{69}\u{301}"),
+    ('\u{EE}', "\u{69}\u{302}"), ('\u{EF}', "\u{69}\u{308}"), ('\u{F1}', "\u{6E}\u{303}"),
+    ('\u{F2}', "\u{6F}\u{300}"), ('\u{F3}', "\u{6F}\u{301}"), ('\u{F4}', "\u{6F}\u{302}"),
+    ('\u{F5}', "\u{6F}\u{303}"), ('\u{F6}', "\u{6F}\u{308}"), ('\u{F9}', "\u{75}\u{300}"),
+    ('\u{FA}', "\u{75}\u{301}"), ('\u{FB}', "\u{75}\u{302}"), ('\u{FC}', "\u{75}\u{308}"),
+    ('\u{FD}', "\u{79}\u{301}"), ('\u{FF}', "\u{79}\u{308}"), ('\u{100}', "\u{41}\u{304}"),
+    ('\u{101}', "\u{61}\u{304}"), ('\u{102}', "\u{41}\u{306}"), ('\u{103}', "\u{61}\u{306}"),
+    ('\u{104}', "\u{41}\u{328}"), ('\u{105}', "\u{61}\u{328}"), ('\u{106}', "\u{43}\u{301}"),
+    ('\u{107}', "\u{63}\u{301}"), ('\u{108}', "\u{43}\u{302}"), ('\u{109}', "\u{63}\u{302}"),
+    ('\u{10A}', "\u{43}\u{307}"), ('\u{10B}', "\u{63}\u{307}"), ('\u{10C}', "\u{43}\u{30C}"),
+    ('\u{10D}', "\u{63}\u{30C}"), ('\u{10E}', "\u{44}\u{30C}"), ('\u{10F}', "\u{64}\u{30C}"),
+    ('\u{112}', "\u{45}\u{304}"), ('\u{113}', "\u{65}\u{304}"), ('\u{114}', "\u{45}\u{306}"),
+    ('\u{115}', "\u{65}\u{306}"), ('\u{116}', "\u{45}\u{307}"), ('\u{117}', "\u{65}\u{307}"),
+    ('\u{118}', "\u{45}\u{328}"), ('\u{119}', "\u{65}\u{328}"), ('\u{11A}', "\u{45}\u{30C}"),
+    ('\u{11B}', "\u{65}\u{30C}"), ('\u{11C}', "\u{47}\u{302}"), ('\u{11D}', "\u{67}\u{302}"),
+    ('\u{11E}', "\u{47}\u{306}"), ('\u{11F}', "\u{67}\u{306}"), ('\u{120}', "\u{47}\u{307}"),
+    ('\u{121}', "\u{67}\u{307}"), ('\u{122}', "\u{47}\u{327}"), ('\u{123}', "\u{67}\u{327}"),
+    ('\u{124}', "\u{48}\u{302}"), ('\u{125}', "\u{68}\u{302}"), ('\u{128}', "\u{49}\u{303}"),
+    ('\u{129}', "\u{69}\u{303}"), ('\u{12A}', "\u{49}\u{304}"), ('\u{12B}', "\u{69}\u{304}"),
+    ('\u{12C}', "\u{49}\u{306}"), ('\u{12D}', "\u{69}\u{306}"), ('\u{12E}', "\u{49}\u{328}"),
+    ('\u{12F}', "\u{69}\u{328}"), ('\u{130}', "\u{49}\u{307}"), ('\u{134}', "\u{4A}\u{302}"),
+    ('\u{135}', "\u{6A}\u{302}"), ('\u{136}', "\u{4B}\u{327}"), ('\u{137}', "\u{6B}\u{327}"),
+    ('\u{139}', "\u{4C}\u{301}"), ('\u{13A}', "\u{6C}\u{301}"), ('\u{13B}', "\u{4C}\u{327}"),
+    ('\u{13C}', "\u{6C}\u{327}"), ('\u{13D}', "\u{4C}\u{30C}"), ('\u{13E}', "\u{6C}\u{30C}"),
+    ('\u{143}', "\u{4E}\u{301}"), ('\u{144}', "\u{6E}\u{301}"), ('\u{145}', "\u{4E}\u{327}"),
+    ('\u{146}', "\u{6E}\u{327}"), ('\u{147}', "\u{4E}\u{30C}"), ('\u{148}', "\u{6E}\u{30C}"),
+    ('\u{14C}', "\u{4F}\u{304}"), ('\u{14D}', "\u{6F}\u{304}"), ('\u{14E}', "\u{4F}\u{306}"),
+    ('\u{14F}', "\u{6F}\u{306}"), ('\u{150}', "\u{4F}\u{30B}"), ('\u{151}', "\u{6F}\u{30B}"),
+    ('\u{154}', "\u{52}\u{301}"), ('\u{155}', "\u{72}\u{301}"), ('\u{156}', "\u{52}\u{327}"),
+    ('\u{157}', "\u{72}\u{327}"), ('\u{158}', "\u{52}\u{30C}"), ('\u{159}', "\u{72}\u{30C}"),
+    ('\u{15A}', "\u{53}\u{301}"), ('\u{15B}', "\u{73}\u{301}"), ('\u{15C}', "\u{53}\u{302}"),
+    ('\u{15D}', "\u{73}\u{302}"), ('\u{15E}', "\u{53}\u{327}"), ('\u{15F}', "\u{73}\u{327}"),
+    ('\u{160}', "\u{53}\u{30C}"), ('\u{161}', "\u{73}\u{30C}"), ('\u{162}', "\u{54}\u{327}"),
+    ('\u{163}', "\u{74}\u{327}"), ('\u{164}', "\u{54}\u{30C}"), ('\u{165}', "\u{74}\u{30C}"),
+    ('\u{168}', "\u{55}\u{303}"), ('\u{169}', "\u{75}\u{303}"), ('\u{16A}', "\u{55}\u{304}"),
+    ('\u{16B}', "\u{75}\u{304}"), ('\u{16C}', "\u{55}\u{306}"), ('\u{16D}', "\u{75}\u{306}"),
+    ('\u{16E}', "\u{55}\u{30A}"), ('\u{16F}', "\u{75}\u{30A}"), ('\u{170}', "\u{55}\u{30B}"),
+    ('\u{171}', "\u{75}\u{30B}"), ('\u{172}', "\u{55}\u{328}"), ('\u{173}', "\u{75}\u{328}"),
+    ('\u{174}', "\u{57}\u{302}"), ('\u{175}', "\u{77}\u{302}"), ('\u{176}', "\u{59}\u{302}"),
+    ('\u{177}', "\u{79}\u{302}"), ('\u{178}', "\u{59}\u{308}"), ('\u{179}', "\u{5A}\u{301}"),
+    ('\u{17A}', "\u{7A}\u{301}"), ('\u{17B}', "\u{5A}\u{307}"), ('\u{17C}', "\u{7A}\u{307}"),
+    ('\u{17D}', "\u{5A}\u{30C}"), ('\u{17E}', "\u{7A}\u{30C}"), ('\u{1A0}', "\u{4F}\u{31B}"),
+    ('\u{1A1}', "\u{6F}\u{31B}"), ('\u{1AF}', "\u{55}\u{31B}"), ('\u{1B0}', "\u{75}\u{31B}"),
+    ('\u{1CD}', "\u{41}\u{30C}"), ('\u{1CE}', "\u{61}\u{30C}"), ('\u{1CF}', "\u{49}\u{30C}"),
+    ('\u{1D0}', "\u{69}\u{30C}"), ('\u{1D1}', "\u{4F}\u{30C}"), ('\u{1D2}', "\u{6F}\u{30C}"),
+    ('\u{1D3}', "\u{55}\u{30C}"), ('\u{1D4}', "\u{75}\u{30C}"), ('\u{1D5}', "\u{55}\u{308}\u{304}"),
+    ('\u{1D6}', "\u{75}\u{308}\u{304}"), ('\u{1D7}', "\u{55}\u{308}\u{301}"), ('\u{1D8}', "\u{75}\u{308}\u{301}"),
+    ('\u{1D9}', "\u{55}\u{308}\u{30C}"), ('\u{1DA}', "\u{75}\u{308}\u{30C}"), ('\u{1DB}', "\u{55}\u{308}\u{300}"),
+    ('\u{1DC}', "\u{75}\u{308}\u{300}"), ('\u{1DE}', "\u{41}\u{308}\u{304}"), ('\u{1DF}', "\u{61}\u{308}\u{304}"),
+    ('\u{1E0}', "\u{41}\u{307}\u{304}"), ('\u{1E1}', "\u{61}\u{307}\u{304}"), ('\u{1E2}', "\u{C6}\u{304}"),
+    ('\u{1E3}', "\u{E6}\u{304}"), ('\u{1E6}', "\u{47}\u{30C}"), ('\u{1E7}', "\u{67}\u{30C}"),
+    ('\u{1E8}', "\u{4B}\u{30C}"), ('\u{1E9}', "\u{6B}\u{30C}"), ('\u{1EA}', "\u{4F}\u{328}"),
+    ('\u{1EB}', "\u{6F}\u{328}"), ('\u{1EC}', "\u{4F}\u{328}\u{304}"), ('\u{1ED}', "\u{6F}\u{328}\u{304}"),
+    ('\u{1EE}', "\u{1B7}\u{30C}"), ('\u{1EF}', "\u{292}\u{30C}"), ('\u{1F0}', "\u{6A}\u{30C}"),
+    ('\u{1F4}', "\u{47}\u{301}"), ('\u{1F5}', "\u{67}\u{301}"), ('\u{1F8}', "\u{4E}\u{300}"),
+    ('\u{1F9}', "\u{6E}\u{300}"), ('\u{1FA}', "\u{41}\u{30A}\u{301}"), ('\u{1FB}', "\u{61}\u{30A}\u{301}"),
+    ('\u{1FC}', "\u{C6}\u{301}"), ('\u{1FD}', "\u{E6}\u{301}"), ('\u{1FE}', "\u{D8}\u{301}"),
+    ('\u{1FF}', "\u{F8}\u{301}"), ('\u{200}', "\u{41}\u{30F}"), ('\u{201}', "\u{61}\u{30F}"),
+    ('\u{202}', "\u{41}\u{311}"), ('\u{203}', "\u{61}\u{311}"), ('\u{204}', "\u{45}\u{30F}"),
+    ('\u{205}', "\u{65}\u{30F}"), ('\u{206}', "\u{45}\u{311}"), ('\u{207}', "\u{65}\u{311}"),
+    ('\u{208}', "\u{49}\u{30F}"), ('\u{209}', "\u{69}\u{30F}"), ('\u{20A}', "\u{49}\u{311}"),
+    ('\u{20B}', "\u{69}\u{311}"), ('\u{20C}', "\u{4F}\u{30F}"), ('\u{20D}', "\u{6F}\u{30F}"),
+    ('\u{20E}', "\u{4F}\u{311}"), ('\u{20F}', "\u{6F}\u{311}"), ('\u{210}', "\u{52}\u{30F}"),
+    ('\u{211}', "\u{72}\u{30F}"), ('\u{212}', "\u{52}\u{311}"), ('\u{213}', "\u{72}\u{311}"),
+    ('\u{214}', "\u{55}\u{30F}"), ('\u{215}', "\u{75}\u{30F}"), ('\u{216}', "\u{55}\u{311}"),
+    ('\u{217}', "\u{75}\u{311}"), ('\u{218}', "\u{53}\u{326}"), ('\u{219}', "\u{73}\u{326}"),
+    ('\u{21A}', "\u{54}\u{326}"), ('\u{21B}', "\u{74}\u{326}"), ('\u{21E}', "\u{48}\u{30C}"),
+    ('\u{21F}', "\u{68}\u{30C}"), ('\u{226}', "\u{41}\u{307}"), ('\u{227}', "\u{61}\u{307}"),
+    ('\u{228}', "\u{45}\u{327}"), ('\u{229}', "\u{65}\u{327}"), ('\u{22A}', "\u{4F}\u{308}\u{304}"),
+    ('\u{22B}', "\u{6F}\u{308}\u{304}"), ('\u{22C}', "\u{4F}\u{303}\u{304}"), ('\u{22D}', "\u{6F}\u{303}\u{304}"),
+    ('\u{22E}', "\u{4F}\u{307}"), ('\u{22F}', "\u{6F}\u{307}"), ('\u{230}', "\u{4F}\u{307}\u{304}"),
+    ('\u{231}', "\u{6F}\u{307}\u{304}"), ('\u{232}', "\u{59}\u{304}"), ('\u{233}', "\u{79}\u{304}"),
+    ('\u{374}', "\u{2B9}"), ('\u{37E}', "\u{3B}"), ('\u{385}', "\u{A8}\u{301}"),
+    ('\u{386}', "\u{391}\u{301}"), ('\u{387}', "\u{B7}"), ('\u{388}', "\u{395}\u{301}"),
+    ('\u{389}', "\u{397}\u{301}"), ('\u{38A}', "\u{399}\u{301}"), ('\u{38C}', "\u{39F}\u{301}"),
+    ('\u{38E}', "\u{3A5}\u{301}"), ('\u{38F}', "\u{3A9}\u{301}"), ('\u{390}', "\u{3B9}\u{308}\u{301}"),
+    ('\u{3AA}', "\u{399}\u{308}"), ('\u{3AB}', "\u{3A5}\u{308}"), ('\u{3AC}', "\u{3B1}\u{301}"),
+    ('\u{3AD}', "\u{3B5}\u{301}"), ('\u{3AE}', "\u{3B7}\u{301}"), ('\u{3AF}', "\u{3B9}\u{301}"),
+    ('\u{3B0}', "\u{3C5}\u{308}\u{301}"), ('\u{3CA}', "\u{3B9}\u{308}"), ('\u{3CB}', "\u{3C5}\u{308}"),
+    ('\u{3CC}', "\u{3BF}\u{301}"), ('\u{3CD}', "\u{3C5}\u{301}"), ('\u{3CE}', "\u{3C9}\u{301}"),
+    ('\u{3D3}', "\u{3D2}\u{301}"), ('\u{3D4}', "\u{3D2}\u{308}"), ('\u{400}', "\u{415}\u{300}"),
+    ('\u{401}', "\u{415}\u{308}"), ('\u{403}', "\u{413}\u{301}"), ('\u{407}', "\u{406}\u{308}"),
+    ('\u{40C}', "\u{41A}\u{301}"), ('\u{40D}', "\u{418}\u{300}"), ('\u{40E}', "\u{423}\u{306}"),
+    ('\u{419}', "\u{418}\u{306}"), ('\u{439}', "\u{438}\u{306}"), ('\u{450}', "\u{435}\u{300}"),
+    ('\u{451}', "\u{435}\u{308}"), ('\u{453}', "\u{433}\u{301}"), ('\u{457}', "\u{456}\u{308}"),
+    ('\u{45C}', "\u{43A}\u{301}"), ('\u{45D}', "\u{438}\u{300}"), ('\u{45E}', "\u{443}\u{306}"),
+    ('\u{476}', "\u{474}\u{30F}"), ('\u{477}', "\u{475}\u{30F}"), ('\u{4C1}', "\u{416}\u{306}"),
+    ('\u{4C2}', "\u{436}\u{306}"), ('\u{4D0}', "\u{410}\u{306}"), ('\u{4D1}', "\u{430}\u{306}"),
+    ('\u{4D2}', "\u{410}\u{308}"), ('\u{4D3}', "\u{430}\u{308}"), ('\u{4D6}', "\u{415}\u{306}"),
+    ('\u{4D7}', "\u{435}\u{306}"), ('\u{4DA}', "\u{4D8}\u{308}"), ('\u{4DB}', "\u{4D9}\u{308}"),
+    ('\u{4DC}', "\u{416}\u{308}"), ('\u{4DD}', "\u{436}\u{308}"), ('\u{4DE}', "\u{417}\u{308}"),
+    ('\u{4DF}', "\u{437}\u{308}"), ('\u{4E2}', "\u{418}\u{304}"), ('\u{4E3}', "\u{438}\u{304}"),
+    ('\u{4E4}', "\u{418}\u{308}"), ('\u{4E5}', "\u{438}\u{308}"), ('\u{4E6}', "\u{41E}\u{308}"),
+    ('\u{4E7}', "\u{43E}\u{308}"), ('\u{4EA}', "\u{4E8}\u{308}"), ('\u{4EB}', "\u{4E9}\u{308}"),
+    ('\u{4EC}', "\u{42D}\u{308}"), ('\u{4ED}', "\u{44D}\u{308}"), ('\u{4EE}', "\u{423}\u{304}"),
+    ('\u{4EF}', "\u{443}\u{304}"), ('\u{4F0}', "\u{423}\u{308}"), ('\u{4F1}', "\u{443}\u{308}"),
+    ('\u{4F2}', "\u{423}\u{30B}"), ('\u{4F3}', "\u{443}\u{30B}"), ('\u{4F4}', "\u{427}\u{308}"),
+    ('\u{4F5}', "\u{447}\u{308}"), ('\u{4F8}', "\u{42B}\u{308}"), ('\u{4F9}', "\u{44B}\u{308}"),
+    ('\u{1E00}', "\u{41}\u{325}"), ('\u{1E01}', "\u{61}\u{325}"), ('\u{1E02}', "\u{42}\u{307}"),
+    ('\u{1E03}', "\u{62}\u{307}"), ('\u{1E04}', "\u{42}\u{323}"), ('\u{1E05}', "\u{62}\u{323}"),
+    ('\u{1E06}', "\u{42}\u{331}"), ('\u{1E07}', "\u{62}\u{331}"), ('\u{1E08}', "\u{43}\u{327}\u{301}"),
+    ('\u{1E09}', "\u{63}\u{327}\u{301}"), ('\u{1E0A}', "\u{44}\u{307}"), ('\u{1E0B}', "\u{64}\u{307}"),
+    ('\u{1E0C}', "\u{44}\u{323}"), ('\u{1E0D}', "\u{64}\u{323}"), ('\u{1E0E}', "\u{44}\u{331}"),
+    ('\u{1E0F}', "\u{64}\u{331}"), ('\u{1E10}', "\u{44}\u{327}"), ('\u{1E11}', "\u{64}\u{327}"),
+    ('\u{1E12}', "\u{44}\u{32D}"), ('\u{1E13}', "\u{64}\u{32D}"), ('\u{1E14}', "\u{45}\u{304}\u{300}"),
+    ('\u{1E15}', "\u{65}\u{304}\u{300}"), ('\u{1E16}', "\u{45}\u{304}\u{301}"), ('\u{1E17}', "\u{65}\u{304}\u{301}"),
+    ('\u{1E18}', "\u{45}\u{32D}"), ('\u{1E19}', "\u{65}\u{32D}"), ('\u{1E1A}', "\u{45}\u{330}"),
+    ('\u{1E1B}', "\u{65}\u{330}"), ('\u{1E1C}', "\u{45}\u{327}\u{306}"), ('\u{1E1D}', "\u{65}\u{327}\u{306}"),
+    ('\u{1E1E}', "\u{46}\u{307}"), ('\u{1E1F}', "\u{66}\u{307}"), ('\u{1E20}', "\u{47}\u{304}"),
+    ('\u{1E21}', "\u{67}\u{304}"), ('\u{1E22}', "\u{48}\u{307}"), ('\u{1E23}', "\u{68}\u{307}"),
+    ('\u{1E24}', "\u{48}\u{323}"), ('\u{1E25}', "\u{68}\u{323}"), ('\u{1E26}', "\u{48}\u{308}"),
+    ('\u{1E27}', "\u{68}\u{308}"), ('\u{1E28}', "\u{48}\u{327}"), ('\u{1E29}', "\u{68}\u{327}"),
+    ('\u{1E2A}', "\u{48}\u{32E}"), ('\u{1E2B}', "\u{68}\u{32E}"), ('\u{1E2C}', "\u{49}\u{330}"),
+    ('\u{1E2D}', "\u{69}\u{330}"), ('\u{1E2E}', "\u{49}\u{308}\u{301}"), ('\u{1E2F}', "\u{69}\u{308}\u{301}"),
+    ('\u{1E30}', "\u{4B}\u{301}"), ('\u{1E31}', "\u{6B}\u{301}"), ('\u{1E32}', "\u{4B}\u{323}"),
+    ('\u{1E33}', "\u{6B}\u{323}"), ('\u{1E34}', "\u{4B}\u{331}"), ('\u{1E35}', "\u{6B}\u{331}"),
+    ('\u{1E36}', "\u{4C}\u{323}"), ('\u{1E37}', "\u{6C}\u{323}"), ('\u{1E38}', "\u{4C}\u{323}\u{304}"),
+    ('\u{1E39}', "\u{6C}\u{323}\u{304}"), ('\u{1E3A}', "\u{4C}\u{331}"), ('\u{1E3B}', "\u{6C}\u{331}"),
+    ('\u{1E3C}', "\u{4C}\u{32D}"), ('\u{1E3D}', "\u{6C}\u{32D}"), ('\u{1E3E}', "\u{4D}\u{301}"),
+    ('\u{1E3F}', "\u{6D}\u{301}"), ('\u{1E40}', "\u{4D}\u{307}"), ('\u{1E41}', "\u{6D}\u{307}"),
+    ('\u{1E42}', "\u{4D}\u{323}"), ('\u{1E43}', "\u{6D}\u{323}"), ('\u{1E44}', "\u{4E}\u{307}"),
+    ('\u{1E45}', "\u{6E}\u{307}"), ('\u{1E46}', "\u{4E}\u{323}"), ('\u{1E47}', "\u{6E}\u{323}"),
+    ('\u{1E48}', "\u{4E}\u{331}"), ('\u{1E49}', "\u{6E}\u{331}"), ('\u{1E4A}', "\u{4E}\u{32D}"),
+    ('\u{1E4B}', "\u{6E}\u{32D}"), ('\u{1E4C}', "\u{4F}\u{303}\u{301}"), ('\u{1E4D}', "\u{6F}\u{303}\u{301}"),
+    ('\u{1E4E}', "\u{4F}\u{303}\u{308}"), ('\u{1E4F}', "\u{6F}\u{303}\u{308}"), ('\u{1E50}', "\u{4F}\u{304}\u{300}"),
+    ('\u{1E51}', "\u{6F}\u{304}\u{300}"), ('\u{1E52}', "\u{4F}\u{304}\u{301}"), ('\u{1E53}', "\u{6F}\u{304}\u{301}"),
+    ('\u{1E54}', "\u{50}\u{301}"), ('\u{1E55}', "\u{70}\u{301}"), ('\u{1E56}', "\u{50}\u{307}"),
+    ('\u{1E57}', "\u{70}\u{307}"), ('\u{1E58}', "\u{52}\u{307}"), ('\u{1E59}', "\u{72}\u{307}"),
+    ('\u{1E5A}', "\u{52}\u{323}"), ('\u{1E5B}', "\u{72}\u{323}"), ('\u{1E5C}', "\u{52}\u{323}\u{304}"),
+    ('\u{1E5D}', "\u{72}\u{323}\u{304}"), ('\u{1E5E}', "\u{52}\u{331}"), ('\u{1E5F}', "\u{72}\u{331}"),
+    ('\u{1E60}', "\u{53}\u{307}"), ('\u{1E61}', "\u{73}\u{307}"), ('\u{1E62}', "\u{53}\u{323}"),
+    ('\u{1E63}', "\u{73}\u{323}"), ('\u{1E64}', "\u{53}\u{301}\u{307}"), ('\u{1E65}', "\u{73}\u{301}\u{307}"),
+    ('\u{1E66}', "\u{53}\u{30C}\u{307}"), ('\u{1E67}', "\u{73}\u{30C}\u{307}"), ('\u{1E68}', "\u{53}\u{323}\u{307}"),
+    ('\u{1E69}', "\u{73}\u{323}\u{307}"), ('\u{1E6A}', "\u{54}\u{307}"), ('\u{1E6B}', "\u{74}\u{307}"),
+    ('\u{1E6C}', "\u{54}\u{323}"), ('\u{1E6D}', "\u{74}\u{323}"), ('\u{1E6E}', "\u{54}\u{331}"),
+    ('\u{1E6F}', "\u{74}\u{331}"), ('\u{1E70}', "\u{54}\u{32D}"), ('\u{1E71}', "\u{74}\u{32D}"),
+    ('\u{1E72}', "\u{55}\u{324}"), ('\u{1E73}', "\u{75}\u{324}"), ('\u{1E74}', "\u{55}\u{330}"),
+    ('\u{1E75}', "\u{75}\u{330}"), ('\u{1E76}', "\u{55}\u{32D}"), ('\u{1E77}', "\u{75}\u{32D}"),
+    ('\u{1E78}', "\u{55}\u{303}\u{301}"), ('\u{1E79}', "\u{75}\u{303}\u{301}"), ('\u{1E7A}', "\u{55}\u{304}\u{308}"),
+    ('\u{1E7B}', "\u{75}\u{304}\u{308}"), ('\u{1E7C}', "\u{56}\u{303}"), ('\u{1E7D}', "\u{76}\u{303}"),
+    ('\u{1E7E}', "\u{56}\u{323}"), ('\u{1E7F}', "\u{76}\u{323}"), ('\u{1E80}', "\u{57}\u{300}"),
+    ('\u{1E81}', "\u{77}\u{300}"), ('\u{1E82}', "\u{57}\u{301}"), ('\u{1E83}', "\u{77}\u{301}"),
+    ('\u{1E84}', "\u{57}\u{308}"), ('\u{1E85}', "\u{77}\u{308}"), ('\u{1E86}', "\u{57}\u{307}"),
+    ('\u{1E87}', "\u{77}\u{307}"), ('\u{1E88}', "\u{57}\u{323}"), ('\u{1E89}', "\u{77}\u{323}"),
+    ('\u{1E8A}', "\u{58}\u{307}"), ('\u{1E8B}', "\u{78}\u{307}"), ('\u{1E8C}', "\u{58}\u{308}"),
+    ('\u{1E8D}', "\u{78}\u{308}"), ('\u{1E8E}', "\u{59}\u{307}"), ('\u{1E8F}', "\u{79}\u{307}"),
+    ('\u{1E90}', "\u{5A}\u{302}"), ('\u{1E91}', "\u{7A}\u{302}"), ('\u{1E92}', "\u{5A}\u{323}"),
+    ('\u{1E93}', "\u{7A}\u{323}"), ('\u{1E94}', "\u{5A}\u{331}"), ('\u{1E95}', "\u{7A}\u{331}"),
+    ('\u{1E96}', "\u{68}\u{331}"), ('\u{1E97}', "\u{74}\u{308}"), ('\u{1E98}', "\u{77}\u{30A}"),
+    ('\u{1E99}', "\u{79}\u{30A}"), ('\u{1E9B}', "\u{17F}\u{307}"), ('\u{1EA0}', "\u{41}\u{323}"),
+    ('\u{1EA1}', "\u{61}\u{323}"), ('\u{1EA2}', "\u{41}\u{309}"), ('\u{1EA3}', "\u{61}\u{309}"),
+    ('\u{1EA4}', "\u{41}\u{302}\u{301}"), ('\u{1EA5}', "\u{61}\u{302}\u{301}"), ('\u{1EA6}', "\u{41}\u{302}\u{300}"),
+    ('\u{1EA7}', "\u{61}\u{302}\u{300}"), ('\u{1EA8}', "\u{41}\u{302}\u{309}"), ('\u{1EA9}', "\u{61}\u{302}\u{309}"),
+    ('\u{1EAA}', "\u{41}\u{302}\u{303}"), ('\u{1EAB}', "\u{61}\u{302}\u{303}"), ('\u{1EAC}', "\u{41}\u{323}\u{302}"),
+    ('\u{1EAD}', "\u{61}\u{323}\u{302}"), ('\u{1EAE}', "\u{41}\u{306}\u{301}"), ('\u{1EAF}', "\u{61}\u{306}\u{301}"),
+    ('\u{1EB0}', "\u{41}\u{306}\u{300}"), ('\u{1EB1}', "\u{61}\u{306}\u{300}"), ('\u{1EB2}', "\u{41}\u{306}\u{309}"),
+    ('\u{1EB3}', "\u{61}\u{306}\u{309}"), ('\u{1EB4}', "\u{41}\u{306}\u{303}"), ('\u{1EB5}', "\u{61}\u{306}\u{303}"),
+    ('\u{1EB6}', "\u{41}\u{323}\u{306}"), ('\u{1EB7}', "\u{61}\u{323}\u{306}"), ('\u{1EB8}', "\u{45}\u{323}"),
+    ('\u{1EB9}', "\u{65}\u{323}"), ('\u{1EBA}', "\u{45}\u{309}"), ('\u{1EBB}', "\u{65}\u{309}"),
+    ('\u{1EBC}', "\u{45}\u{303}"), ('\u{1EBD}', "\u{65}\u{303}"), ('\u{1EBE}', "\u{45}\u{302}\u{301}"),
+    ('\u{1EBF}', "\u{65}\u{302}\u{301}"), ('\u{1EC0}', "\u{45}\u{302}\u{300}"), ('\u{1EC1}', "\u{65}\u{302}\u{300}"),
+    ('\u{1EC2}', "\u{45}\u{302}\u{309}"), ('\u{1EC3}', "\u{65}\u{302}\u{309}"), ('\u{1EC4}', "\u{45}\u{302}\u{303}"),
+    ('\u{1EC5}', "\u{65}\u{302}\u{303}"), ('\u{1EC6}', "\u{45}\u{323}\u{302}"), ('\u{1EC7}', "\u{65}\u{323}\u{302}"),
+    ('\u{1EC8}', "\u{49}\u{309}"), ('\u{1EC9}', "\u{69}\u{309}"), ('\u{1ECA}', "\u{49}\u{323}"),
+    ('\u{1ECB}', "\u{69}\u{323}"), ('\u{1ECC}', "\u{4F}\u{323}"), ('\u{1ECD}', "\u{6F}\u{323}"),
+    ('\u{1ECE}', "\u{4F}\u{309}"), ('\u{1ECF}', "\u{6F}\u{309}"), ('\u{1ED0}', "\u{4F}\u{302}\u{301}"),
+    ('\u{1ED1}', "\u{6F}\u{302}\u{301}"), ('\u{1ED2}', "\u{4F}\u{302}\u{300}"), ('\u{1ED3}', "\u{6F}\u{302}\u{300}"),
+    ('\u{1ED4}', "\u{4F}\u{302}\u{309}"), ('\u{1ED5}', "\u{6F}\u{302}\u{309}"), ('\u{1ED6}', "\u{4F}\u{302}\u{303}"),
+    ('\u{1ED7}', "\u{6F}\u{302}\u{303}"), ('\u{1ED8}', "\u{4F}\u{323}\u{302}"), ('\u{1ED9}', "\u{6F}\u{323}\u{302}"),
+    ('\u{1EDA}', "\u{4F}\u{31B}\u{301}"), ('\u{1EDB}', "\u{6F}\u{31B}\u{301}"), ('\u{1EDC}', "\u{4F}\u{31B}\u{300}"),
+    ('\u{1EDD}', "\u{6F}\u{31B}\u{300}"), ('\u{1EDE}', "\u{4F}\u{31B}\u{309}"), ('\u{1EDF}', "\u{6F}\u{31B}\u{309}"),
+    ('\u{1EE0}', "\u{4F}\u{31B}\u{303}"), ('\u{1EE1}', "\u{6F}\u{31B}\u{303}"), ('\u{1EE2}', "\u{4F}\u{31B}\u{323}"),
+    ('\u{1EE3}', "\u{6F}\u{31B}\u{323}"), ('\u{1EE4}', "\u{55}\u{323}"), ('\u{1EE5}', "\u{75}\u{323}"),
+    ('\u{1EE6}', "\u{55}\u{309}"), ('\u{1EE7}', "\u{75}\u{309}"), ('\u{1EE8}', "\u{55}\u{31B}\u{301}"),
+    ('\u{1EE9}', "\u{75}\u{31B}\u{301}"), ('\u{1EEA}', "\u{55}\u{31B}\u{300}"), ('\u{1EEB}', "\u{75}\u{31B}\u{300}"),
+    ('\u{1EEC}', "\u{55}\u{31B}\u{309}"), ('\u{1EED}', "\u{75}\u{31B}\u{309}"), ('\u{1EEE}', "\u{55}\u{31B}\u{303}"),
+    ('\u{1EEF}', "\u{75}\u{31B}\u{303}"), ('\u{1EF0}', "\u{55}\u{31B}\u{323}"), ('\u{1EF1}', "\u{75}\u{31B}\u{323}"),
+    ('\u{1EF2}', "\u{59}\u{300}"), ('\u{1EF3}', "\u{79}\u{300}"), ('\u{1EF4}', "\u{59}\u{323}"),
+    ('\u{1EF5}', "\u{79}\u{323}"), ('\u{1EF6}', "\u{59}\u{309}"), ('\u{1EF7}', "\u{79}\u{309}"),
+    ('\u{1EF8}', "\u{59}\u{303}"), ('\u{1EF9}', "\u{79}\u{303}"), ('\u{1F00}', "\u{3B1}\u{313}"),
+    ('\u{1F01}', "\u{3B1}\u{314}"), ('\u{1F02}', "\u{3B1}\u{313}\u{300}"), ('\u{1F03}', "\u{3B1}\u{314}\u{300}"),
+    ('\u{1F04}', "\u{3B1}\u{313}\u{301}"), ('\u{1F05}', "\u{3B1}\u{314}\u{301}"), ('\u{1F06}', "\u{3B1}\u{313}\u{342}"),
+    ('\u{1F07}', "\u{3B1}\u{314}\u{342}"), ('\u{1F08}', "\u{391}\u{313}"), ('\u{1F09}', "\u{391}\u{314}"),
+    ('\u{1F0A}', "\u{391}\u{313}\u{300}"), ('\u{1F0B}', "\u{391}\u{314}\u{300}"), ('\u{1F0C}', "\u{391}\u{313}\u{301}"),
+    ('\u{1F0D}', "\u{391}\u{314}\u{301}"), ('\u{1F0E}', "\u{391}\u{313}\u{342}"), ('\u{1F0F}', "\u{391}\u{314}\u{342}"),
+    ('\u{1F10}', "\u{3B5}\u{313}"), ('\u{1F11}', "\u{3B5}\u{314}"), ('\u{1F12}', "\u{3B5}\u{313}\u{300}"),
+    ('\u{1F13}', "\u{3B5}\u{314}\u{300}"), ('\u{1F14}', "\u{3B5}\u{313}\u{301}"), ('\u{1F15}', "\u{3B5}\u{314}\u{301}"),
+    ('\u{1F18}', "\u{395}\u{313}"), ('\u{1F19}', "\u{395}\u{314}"), ('\u{1F1A}', "\u{395}\u{313}\u{300}"),
+    ('\u{1F1B}', "\u{395}\u{314}\u{300}"), ('\u{1F1C}', "\u{395}\u{313}\u{301}"), ('\u{1F1D}', "\u{395}\u{314}\u{301}"),
+    ('\u{1F20}', "\u{3B7}\u{313}"), ('\u{1F21}', "\u{3B7}\u{314}"), ('\u{1F22}', "\u{3B7}\u{313}\u{300}"),
+    ('\u{1F23}', "\u{3B7}\u{314}\u{300}"), ('\u{1F24}', "\u{3B7}\u{313}\u{301}"), ('\u{1F25}', "\u{3B7}\u{314}\u{301}"),
+    ('\u{1F26}', "\u{3B7}\u{313}\u{342}"), ('\u{1F27}', "\u{3B7}\u{314}\u{342}"), ('\u{1F28}', "\u{397}\u{313}"),
+    ('\u{1F29}', "\u{397}\u{314}"), ('\u{1F2A}', "\u{397}\u{313}\u{300}"), ('\u{1F2B}', "\u{397}\u{314}\u{300}"),
+    ('\u{1F2C}', "\u{397}\u{313}\u{301}"), ('\u{1F2D}', "\u{397}\u{314}\u{301}"), ('\u{1F2E}', "\u{397}\u{313}\u{342}"),
+    ('\u{1F2F}', "\u{397}\u{314}\u{342}"), ('\u{1F30}', "\u{3B9}\u{313}"), ('\u{1F31}', "\u{3B9}\u{314}"),
+    ('\u{1F32}', "\u{3B9}\u{313}\u{300}"), ('\u{1F33}', "\u{3B9}\u{314}\u{300}"), ('\u{1F34}', "\u{3B9}\u{313}\u{301}"),
+    ('\u{1F35}', "\u{3B9}\u{314}\u{301}"), ('\u{1F36}', "\u{3B9}\u{313}\u{342}"), ('\u{1F37}', "\u{3B9}\u{314}\u{342}"),
+    ('\u{1F38}', "\u{399}\u{313}"), ('\u{1F39}', "\u{399}\u{314}"), ('\u{1F3A}', "\u{399}\u{313}\u{300}"),
+    ('\u{1F3B}', "\u{399}\u{314}\u{300}"), ('\u{1F3C}', "\u{399}\u{313}\u{301}"), ('\u{1F3D}', "\u{399}\u{314}\u{301}"),
+    ('\u{1F3E}', "\u{399}\u{313}\u{342}"), ('\u{1F3F}', "\u{399}\u{314}\u{342}"), ('\u{1F40}', "\u{3BF}\u{313}"),
+    ('\u{1F41}', "\u{3BF}\u{314}"), ('\u{1F42}', "\u{3BF}\u{313}\u{300}"), ('\u{1F43}', "\u{3BF}\u{314}\u{300}"),
+    ('\u{1F44}', "\u{3BF}\u{313}\u{301}"), ('\u{1F45}', "\u{3BF}\u{314}\u{301}"), ('\u{1F48}', "\u{39F}\u{313}"),
+    ('\u{1F49}', "\u{39F}\u{314}"), ('\u{1F4A}', "\u{39F}\u{313}\u{300}"), ('\u{1F4B}', "\u{39F}\u{314}\u{300}"),
+    ('\u{1F4C}', "\u{39F}\u{313}\u{301}"), ('\u{1F4D}', "\u{39F}\u{314}\u{301}"), ('\u{1F50}', "\u{3C5}\u{313}"),
+    ('\u{1F51}', "\u{3C5}\u{314}"), ('\u{1F52}', "\u{3C5}\u{313}\u{300}"), ('\u{1F53}', "\u{3C5}\u{314}\u{300}"),
+    ('\u{1F54}', "\u{3C5}\u{313}\u{301}"), ('\u{1F55}', "\u{3C5}\u{314}\u{301}"), ('\u{1F56}', "\u{3C5}\u{313}\u{342}"),
+    ('\u{1F57}', "\u{3C5}\u{314}\u{342}"), ('\u{1F59}', "\u{3A5}\u{314}"), ('\u{1F5B}', "\u{3A5}\u{314}\u{300}"),
+    ('\u{1F5D}', "\u{3A5}\u{314}\u{301}"), ('\u{1F5F}', "\u{3A5}\u{314}\u{342}"), ('\u{1F60}', "\u{3C9}\u{313}"),
+    ('\u{1F61}', "\u{3C9}\u{314}"), ('\u{1F62}', "\u{3C9}\u{313}\u{300}"), ('\u{1F63}', "\u{3C9}\u{314}\u{300}"),
+    ('\u{1F64}', "\u{3C9}\u{313}\u{301}"), ('\u{1F65}', "\u{3C9}\u{314}\u{301}"), ('\u{1F66}', "\u{3C9}\u{313}\u{342}"),
+    ('\u{1F67}', "\u{3C9}\u{314}\u{342}"), ('\u{1F68}', "\u{3A9}\u{313}"), ('\u{1F69}', "\u{3A9}\u{314}"),
+    ('\u{1F6A}', "\u{3A9}\u{313}\u{300}"), ('\u{1F6B}', "\u{3A9}\u{314}\u{300}"), ('\u{1F6C}', "\u{3A9}\u{313}\u{301}"),
+    ('\u{1F6D}', "\u{3A9}\u{314}\u{301}"), ('\u{1F6E}', "\u{3A9}\u{313}\u{342}"), ('\u{1F6F}', "\u{3A9}\u{314}\u{342}"),
+    ('\u{1F70}', "\u{3B1}\u{300}"), ('\u{1F71}', "\u{3B1}\u{301}"), ('\u{1F72}', "\u{3B5}\u{300}"),
+    ('\u{1F73}', "\u{3B5}\u{301}"), ('\u{1F74}', "\u{3B7}\u{300}"), ('\u{1F75}', "\u{3B7}\u{301}"),
+    ('\u{1F76}', "\u{3B9}\u{300}"), ('\u{1F77}', "\u{3B9}\u{301}"), ('\u{1F78}', "\u{3BF}\u{300}"),
+    ('\u{1F79}', "\u{3BF}\u{301}"), ('\u{1F7A}', "\u{3C5}\u{300}"), ('\u{1F7B}', "\u{3C5}\u{301}"),
+    ('\u{1F7C}', "\u{3C9}\u{300}"), ('\u{1F7D}', "\u{3C9}\u{301}"), ('\u{1F80}', "\u{3B1}\u{313}\u{345}"),
+    ('\u{1F81}', "\u{3B1}\u{314}\u{345}"), ('\u{1F82}', "\u{3B1}\u{313}\u{300}\u{345}"), ('\u{1F83}', "\u{3B1}\u{314}\u{300}\u{345}"),
+    ('\u{1F84}', "\u{3B1}\u{313}\u{301}\u{345}"), ('\u{1F85}', "\u{3B1}\u{314}\u{301}\u{345}"), ('\u{1F86}', "\u{3B1}\u{313}\u{342}\u{345}"),
+    ('\u{1F87}', "\u{3B1}\u{314}\u{342}\u{345}"), ('\u{1F88}', "\u{391}\u{313}\u{345}"), ('\u{1F89}', "\u{391}\u{314}\u{345}"),
+    ('\u{1F8A}', "\u{391}\u{313}\u{300}\u{345}"), ('\u{1F8B}', "\u{391}\u{314}\u{300}\u{345}"), ('\u{1F8C}', "\u{391}\u{313}\u{301}\u{345}"),
+    ('\u{1F8D}', "\u{391}\u{314}\u{301}\u{345}"), ('\u{1F8E}', "\u{391}\u{313}\u{342}\u{345}"), ('\u{1F8F}', "\u{391}\u{314}\u{342}\u{345}"),
+    ('\u{1F90}', "\u{3B7}\u{313}\u{345}"), ('\u{1F91}', "\u{3B7}\u{314}\u{345}"), ('\u{1F92}', "\u{3B7}\u{313}\u{300}\u{345}"),
+    ('\u{1F93}', "\u{3B7}\u{314}\u{300}\u{345}"), ('\u{1F94}', "\u{3B7}\u{313}\u{301}\u{345}"), ('\u{1F95}', "\u{3B7}\u{314}\u{301}\u{345}"),
+    ('\u{1F96}', "\u{3B7}\u{313}\u{342}\u{345}"), ('\u{1F97}', "\u{3B7}\u{314}\u{342}\u{345}"), ('\u{1F98}', "\u{397}\u{313}\u{345}"),
+    ('\u{1F99}', "\u{397}\u{314}\u{345}"), ('\u{1F9A}', "\u{397}\u{313}\u{300}\u{345}"), ('\u{1F9B}', "\u{397}\u{314}\u{300}\u{345}"),
+    ('\u{1F9C}', "\u{397}\u{313}\u{301}\u{345}"), ('\u{1F9D}', "\u{397}\u{314}\u{301}\u{345}"), ('\u{1F9E}', "\u{397}\u{313}\u{342}\u{345}"),
+    ('\u{1F9F}', "\u{397}\u{314}\u{342}\u{345}"), ('\u{1FA0}', "\u{3C9}\u{313}\u{345}"), ('\u{1FA1}', "\u{3C9}\u{314}\u{345}"),
+    ('\u{1FA2}', "\u{3C9}\u{313}\u{300}\u{345}"), ('\u{1FA3}', "\u{3C9}\u{314}\u{300}\u{345}"), ('\u{1FA4}', "\u{3C9}\u{313}\u{301}\u{345}"),
+    ('\u{1FA5}', "\u{3C9}\u{314}\u{301}\u{345}"), ('\u{1FA6}', "\u{3C9}\u{313}\u{342}\u{345}"), ('\u{1FA7}', "\u{3C9}\u{314}\u{342}\u{345}"),
+    ('\u{1FA8}', "\u{3A9}\u{313}\u{345}"), ('\u{1FA9}', "\u{3A9}\u{314}\u{345}"), ('\u{1FAA}', "\u{3A9}\u{313}\u{300}\u{345}"),
+    ('\u{1FAB}', "\u{3A9}\u{314}\u{300}\u{345}"), ('\u{1FAC}', "\u{3A9}\u{313}\u{301}\u{345}"), ('\u{1FAD}', "\u{3A9}\u{314}\u{301}\u{345}"),
+    ('\u{1FAE}', "\u{3A9}\u{313}\u{342}\u{345}"), ('\u{1FAF}', "\u{3A9}\u{314}\u{342}\u{345}"), ('\u{1FB0}', "\u{3B1}\u{306}"),
+    ('\u{1FB1}', "\u{3B1}\u{304}"), ('\u{1FB2}', "\u{3B1}\u{300}\u{345}"), ('\u{1FB3}', "\u{3B1}\u{345}"),
+    ('\u{1FB4}', "\u{3B1}\u{301}\u{345}"), ('\u{1FB6}', "\u{3B1}\u{342}"), ('\u{1FB7}', "\u{3B1}\u{342}\u{345}"),
+    ('\u{1FB8}', "\u{391}\u{306}"), ('\u{1FB9}', "\u{391}\u{304}"), ('\u{1FBA}', "\u{391}\u{300}"),
+    ('\u{1FBB}', "\u{391}\u{301}"), ('\u{1FBC}', "\u{391}\u{345}"), ('\u{1FBE}', "\u{3B9}"),
+    ('\u{1FC1}', "\u{A8}\u{342}"), ('\u{1FC2}', "\u{3B7}\u{300}\u{345}"), ('\u{1FC3}', "\u{3B7}\u{345}"),
+    ('\u{1FC4}', "\u{3B7}\u{301}\u{345}"), ('\u{1FC6}', "\u{3B7}\u{342}"), ('\u{1FC7}', "\u{3B7}\u{342}\u{345}"),
+    ('\u{1FC8}', "\u{395}\u{300}"), ('\u{1FC9}', "\u{395}\u{301}"), ('\u{1FCA}', "\u{397}\u{300}"),
+    ('\u{1FCB}', "\u{397}\u{301}"), ('\u{1FCC}', "\u{397}\u{345}"), ('\u{1FCD}', "\u{1FBF}\u{300}"),
+    ('\u{1FCE}', "\u{1FBF}\u{301}"), ('\u{1FCF}', "\u{1FBF}\u{342}"), ('\u{1FD0}', "\u{3B9}\u{306}"),
+    ('\u{1FD1}', "\u{3B9}\u{304}"), ('\u{1FD2}', "\u{3B9}\u{308}\u{300}"), ('\u{1FD3}', "\u{3B9}\u{308}\u{301}"),
+    ('\u{1FD6}', "\u{3B9}\u{342}"), ('\u{1FD7}', "\u{3B9}\u{308}\u{342}"), ('\u{1FD8}', "\u{399}\u{306}"),
+    ('\u{1FD9}', "\u{399}\u{304}"), ('\u{1FDA}', "\u{399}\u{300}"), ('\u{1FDB}', "\u{399}\u{301}"),
+    ('\u{1FDD}', "\u{1FFE}\u{300}"), ('\u{1FDE}', "\u{1FFE}\u{301}"), ('\u{1FDF}', "\u{1FFE}\u{342}"),
+    ('\u{1FE0}', "\u{3C5}\u{306}"), ('\u{1FE1}', "\u{3C5}\u{304}"), ('\u{1FE2}', "\u{3C5}\u{308}\u{300}"),
+    ('\u{1FE3}', "\u{3C5}\u{308}\u{301}"), ('\u{1FE4}', "\u{3C1}\u{313}"), ('\u{1FE5}', "\u{3C1}\u{314}"),
+    ('\u{1FE6}', "\u{3C5}\u{342}"), ('\u{1FE7}', "\u{3C5}\u{308}\u{342}"), ('\u{1FE8}', "\u{3A5}\u{306}"),
+    ('\u{1FE9}', "\u{3A5}\u{304}"), ('\u{1FEA}', "\u{3A5}\u{300}"), ('\u{1FEB}', "\u{3A5}\u{301}"),
+    ('\u{1FEC}', "\u{3A1}\u{314}"), ('\u{1FED}', "\u{A8}\u{300}"), ('\u{1FEE}', "\u{A8}\u{301}"),
+    ('\u{1FEF}', "\u{60}"), ('\u{1FF2}', "\u{3C9}\u{300}\u{345}"), ('\u{1FF3}', "\u{3C9}\u{345}"),
+    ('\u{1FF4}', "\u{3C9}\u{301}\u{345}"), ('\u{1FF6}', "\u{3C9}\u{342}"), ('\u{1FF7}', "\u{3C9}\u{342}\u{345}"),
+    ('\u{1FF8}', "\u{39F}\u{300}"), ('\u{1FF9}', "\u{39F}\u{301}"), ('\u{1FFA}', "\u{3A9}\u{300}"),
+    ('\u{1FFB}', "\u{3A9}\u{301}"), ('\u{1FFC}', "\u{3A9}\u{345}"), ('\u{1FFD}', "\u{B4}"),
+];
+
+/// Non-zero canonical combining classes, sorted by char
+#[rustfmt::skip]
+static COMBINING_CLASSES: &[(char, u8)] = &[
+    ('\u{300}', 230), ('\u{301}', 230), ('\u{302}', 230), ('\u{303}', 230), ('\u{304}', 230), ('\u{305}', 230),
+    ('\u{306}', 230), ('\u{307}', 230), ('\u{308}', 230), ('\u{309}', 230), ('\u{30A}', 230), ('\u{30B}', 230),
+    ('\u{30C}', 230), ('\u{30D}', 230), ('\u{30E}', 230), ('\u{30F}', 230), ('\u{310}', 230), ('\u{311}', 230),
+    ('\u{312}', 230), ('\u{313}', 230), ('\u{314}', 230), ('\u{315}', 232), ('\u{316}', 220), ('\u{317}', 220),
+    ('\u{318}', 220), ('\u{319}', 220), ('\u{31A}', 232), ('\u{31B}', 216), ('\u{31C}', 220), ('\u{31D}', 220),
+    ('\u{31E}', 220), ('\u{31F}', 220), ('\u{320}', 220), ('\u{321}', 202), ('\u{322}', 202), ('\u{323}', 220),
+    ('\u{324}', 220), ('\u{325}', 220), ('\u{326}', 220), ('\u{327}', 202), ('\u{328}', 202), ('\u{329}', 220),
+    ('\u{32A}', 220), ('\u{32B}', 220), ('\u{32C}', 220), ('\u{32D}', 220), ('\u{32E}', 220), ('\u{32F}', 220),
+    ('\u{330}', 220), ('\u{331}', 220), ('\u{332}', 220), ('\u{333}', 220), ('\u{334}', 1), ('\u{335}', 1),
+    ('\u{336}', 1), ('\u{337}', 1), ('\u{338}', 1), ('\u{339}', 220), ('\u{33A}', 220), ('\u{33B}', 220),
+    ('\u{33C}', 220), ('\u{33D}', 230), ('\u{33E}', 230), ('\u{33F}', 230), ('\u{340}', 230), ('\u{341}', 230),
+    ('\u{342}', 230), ('\u{343}', 230), ('\u{344}', 230), ('\u{345}', 240), ('\u{346}', 230), ('\u{347}', 220),
+    ('\u{348}', 220), ('\u{349}', 220), ('\u{34A}', 230), ('\u{34B}', 230), ('\u{34C}', 230), ('\u{34D}', 220),
+    ('\u{34E}', 220), ('\u{350}', 230), ('\u{351}', 230), ('\u{352}', 230), ('\u{353}', 220), ('\u{354}', 220),
+    ('\u{355}', 220), ('\u{356}', 220), ('\u{357}', 230), ('\u{358}', 232), ('\u{359}', 220), ('\u{35A}', 220),
+    ('\u{35B}', 230), ('\u{35C}', 233), ('\u{35D}', 234), ('\u{35E}', 234), ('\u{35F}', 233), ('\u{360}', 234),
+    ('\u{361}', 234), ('\u{362}', 233), ('\u{363}', 230), ('\u{364}', 230), ('\u{365}', 230), ('\u{366}', 230),
+    ('\u{367}', 230), ('\u{368}', 230), ('\u{369}', 230), ('\u{36A}', 230), ('\u{36B}', 230), ('\u{36C}', 230),
+    ('\u{36D}', 230), ('\u{36E}', 230), ('\u{36F}', 230),
+];
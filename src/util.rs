@@ -0,0 +1,1472 @@
+/*!
+High-level helpers answering common questions about a tree in one call.
+
+* [`largest`] finds the largest files in a tree.
+* [`extremes`] finds the largest and smallest, or newest and oldest, files in a tree.
+* [`list_dir`] lists a single dir, without recursion.
+* [`count`] counts files, dirs and other entries of a tree.
+* [`find_empty_dirs`] finds dirs with nothing in them, e.g. to clean them up.
+* [`grep`] finds lines containing a pattern in files of a tree.
+* [`ancestors`] walks upwards from a path, e.g. to find the nearest manifest.
+* [`sync_walk`] walks two trees in lockstep, e.g. to sync one to the other.
+* [`par_walk`] walks a tree on several threads, keeping the order of the walk
+  (with the `parallel` feature).
+
+Errors met below the root never stop these helpers, so an unreadable
+subdirectory doesn't spoil the answer for the rest of the tree.
+
+[`largest`]: fn.largest.html
+[`extremes`]: fn.extremes.html
+[`list_dir`]: fn.list_dir.html
+[`count`]: fn.count.html
+[`find_empty_dirs`]: fn.find_empty_dirs.html
+[`grep`]: fn.grep.html
+[`ancestors`]: fn.ancestors.html
+[`sync_walk`]: fn.sync_walk.html
+[`par_walk`]: fn.par_walk.html
+*/
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+#[cfg(feature = "parallel")]
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+#[cfg(feature = "parallel")]
+use std::sync::atomic::{AtomicU64, AtomicUsize};
+#[cfg(feature = "parallel")]
+use std::sync::Condvar;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+#[cfg(feature = "parallel")]
+use std::time::{Duration, Instant};
+
+use crate::cp::{ContentProcessor, DirEntry, DirEntryContentProcessor, SlimContentProcessor, SlimEntry};
+use crate::error::{Error, ErrorInner};
+use crate::fs::{self, DefaultDirEntry, FsFileType, FsRootDirEntry};
+use crate::walk::{ClassicIter, ClassicWalkDirIter, WalkDirBuilder, WalkDirIterator};
+use crate::wd::{ContentFilter, Depth, IntoSome, Position};
+#[cfg(feature = "parallel")]
+use crate::wd::FnProcessReadDir;
+
+/////////////////////////////////////////////////////////////////////////
+
+// Entry ordered by size, of the same size the one with smaller path is greater
+struct BySize(DirEntry<DefaultDirEntry>);
+
+impl PartialEq for BySize {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for BySize {}
+
+impl PartialOrd for BySize {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BySize {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (lhs, rhs) = (&self.0, &other.0);
+        lhs.metadata().len().cmp(&rhs.metadata().len()).then_with(|| rhs.path().cmp(lhs.path()))
+    }
+}
+
+/// Find the `n` largest regular files under `root`.
+///
+/// Files are returned from the largest to the smallest (files of the same size
+/// are ordered by path). Symbolic links are not followed. Only `n` entries are
+/// kept in memory at any time.
+///
+/// # Errors
+///
+/// Returns an error only if `root` itself cannot be walked. Other errors are
+/// skipped (see the [module docs](index.html)).
+pub fn largest<P: AsRef<Path>>(
+    root: P,
+    n: usize,
+) -> Result<Vec<DirEntry>, Error<DefaultDirEntry>> {
+    if n == 0 {
+        return Ok(vec![]);
+    }
+
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+
+    for result in WalkDirBuilder::<DefaultDirEntry>::new(root).into_classic() {
+        let dent = match result {
+            Ok(dent) => dent,
+            Err(err) if err.depth() == 0 => return Err(err),
+            Err(_) => continue,
+        };
+        if !dent.file_type().is_file() {
+            continue;
+        }
+        heap.push(Reverse(BySize(dent)));
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+
+    // Sorted ascending by `Reverse`, i.e. from the largest
+    Ok(heap.into_sorted_vec().into_iter().map(|Reverse(BySize(dent))| dent).collect())
+}
+
+/// What [`extremes`] orders files by
+///
+/// [`extremes`]: fn.extremes.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtremesBy {
+    /// Size in bytes
+    Size,
+    /// Last modification time
+    Mtime,
+}
+
+/// Files found by [`extremes`]
+///
+/// [`extremes`]: fn.extremes.html
+#[derive(Debug, Clone, Default)]
+pub struct Extremes {
+    /// Files with the greatest key (the largest or the newest), from the greatest
+    pub top: Vec<SlimEntry>,
+    /// Files with the least key (the smallest or the oldest), from the least
+    pub bottom: Vec<SlimEntry>,
+}
+
+// Entry with its key, ordered by key, of the same key the one with smaller path is less
+struct Keyed(u128, SlimEntry);
+
+impl PartialEq for Keyed {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Keyed {}
+
+impl PartialOrd for Keyed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Keyed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0).then_with(|| self.1.path().cmp(other.1.path()))
+    }
+}
+
+/// Find the `n` regular files under `root` with the greatest and the `n` with
+/// the least size or modification time, e.g. the newest and the oldest ones.
+///
+/// Files of the same key are ordered by path. Symbolic links are not followed.
+/// Files without a modification time (if the backend doesn't report it) are
+/// skipped when ordering by [`ExtremesBy::Mtime`]. With fewer than `2 * n`
+/// files, some of them are both in [`Extremes::top`] and [`Extremes::bottom`].
+///
+/// Entries are kept as [`SlimEntry`], holding only the path, size and time of
+/// a file, and only `2 * n` of them are kept in memory at any time.
+///
+/// # Errors
+///
+/// Returns an error only if `root` itself cannot be walked. Other errors are
+/// skipped (see the [module docs](index.html)).
+///
+/// [`ExtremesBy::Mtime`]: enum.ExtremesBy.html#variant.Mtime
+/// [`Extremes::top`]: struct.Extremes.html#structfield.top
+/// [`Extremes::bottom`]: struct.Extremes.html#structfield.bottom
+/// [`SlimEntry`]: struct.SlimEntry.html
+pub fn extremes<P: AsRef<Path>>(
+    root: P,
+    by: ExtremesBy,
+    n: usize,
+) -> Result<Extremes, Error<DefaultDirEntry>> {
+    if n == 0 {
+        return Ok(Extremes::default());
+    }
+
+    // Both heaps pop the worst of their files: `top` keeps the inverted key
+    let mut top = BinaryHeap::with_capacity(n + 1);
+    let mut bottom = BinaryHeap::with_capacity(n + 1);
+
+    for result in WalkDirBuilder::<DefaultDirEntry, SlimContentProcessor>::new(root).into_classic() {
+        let ent = match result {
+            Ok(ent) => ent,
+            Err(err) if err.depth() == 0 => return Err(err),
+            Err(_) => continue,
+        };
+        if !ent.file_type().is_file() {
+            continue;
+        }
+        let key = match by {
+            ExtremesBy::Size => ent.len() as u128,
+            ExtremesBy::Mtime => match ent.modified() {
+                Some(modified) => modified.as_nanos(),
+                None => continue,
+            },
+        };
+        top.push(Keyed(!key, ent.clone()));
+        if top.len() > n {
+            top.pop();
+        }
+        bottom.push(Keyed(key, ent));
+        if bottom.len() > n {
+            bottom.pop();
+        }
+    }
+
+    Ok(Extremes {
+        top: top.into_sorted_vec().into_iter().map(|Keyed(_, ent)| ent).collect(),
+        bottom: bottom.into_sorted_vec().into_iter().map(|Keyed(_, ent)| ent).collect(),
+    })
+}
+
+/////////////////////////////////////////////////////////////////////////
+
+/// Options of [`list_dir`]
+///
+/// [`list_dir`]: fn.list_dir.html
+#[derive(Debug, Clone)]
+pub struct ListOptions {
+    /// See `WalkDir::follow_links`
+    pub follow_links: bool,
+    /// Sort entries by file name (see `WalkDir::deterministic`)
+    pub sorted: bool,
+    /// Keep only files or only dirs (see `WalkDir::content_filter`)
+    pub filter: ContentFilter,
+    /// Skip files and dirs whose names start with `.`
+    pub skip_hidden: bool,
+    /// Return the first error about an entry instead of skipping it
+    pub strict: bool,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            follow_links: false,
+            sorted: true,
+            filter: ContentFilter::None,
+            skip_hidden: false,
+            strict: false,
+        }
+    }
+}
+
+/// List entries of the dir `path` (without the dir itself).
+///
+/// This never recurses: it is a walk limited to the first level, so entries
+/// are the same `DirEntry`s with depth `1` and options mean the same as for a
+/// walk. Entries are sorted by name unless [`ListOptions::sorted`] is cleared.
+///
+/// # Errors
+///
+/// Returns an error if `path` cannot be read or isn't a dir. Errors about
+/// entries are skipped, unless [`ListOptions::strict`] is set.
+///
+/// [`ListOptions::sorted`]: struct.ListOptions.html#structfield.sorted
+/// [`ListOptions::strict`]: struct.ListOptions.html#structfield.strict
+pub fn list_dir<P: AsRef<Path>>(
+    path: P,
+    opts: ListOptions,
+) -> Result<Vec<DirEntry>, Error<DefaultDirEntry>> {
+    let wd = WalkDirBuilder::<DefaultDirEntry>::new(path)
+        .follow_links(opts.follow_links)
+        .deterministic(opts.sorted)
+        .max_depth(1);
+
+    let mut entries = vec![];
+    for result in wd.into_classic() {
+        let dent = match result {
+            Ok(dent) if dent.depth() == 0 => {
+                if !dent.file_type().is_dir() {
+                    let err = io::Error::other("not a directory");
+                    let err = ErrorInner::from_path(dent.path().to_path_buf(), err);
+                    return Err(Error::from_inner(err, 0));
+                }
+                continue;
+            }
+            Ok(dent) => dent,
+            Err(err) if err.depth() == 0 || opts.strict => return Err(err),
+            Err(_) => continue,
+        };
+        let keep = match opts.filter {
+            ContentFilter::None => true,
+            ContentFilter::DirsOnly => dent.file_type().is_dir(),
+            ContentFilter::FilesOnly => !dent.file_type().is_dir(),
+            ContentFilter::SkipAll => false,
+        };
+        if keep && !(opts.skip_hidden && is_hidden(&dent)) {
+            entries.push(dent);
+        }
+    }
+    Ok(entries)
+}
+
+/////////////////////////////////////////////////////////////////////////
+
+/// Options of [`count`]
+///
+/// [`count`]: fn.count.html
+#[derive(Debug, Clone)]
+pub struct CountOptions {
+    /// See `WalkDir::follow_links`
+    pub follow_links: bool,
+    /// See `WalkDir::max_depth`
+    pub max_depth: Depth,
+    /// See `WalkDir::same_file_system`
+    pub same_file_system: bool,
+}
+
+impl Default for CountOptions {
+    fn default() -> Self {
+        Self { follow_links: false, max_depth: Depth::MAX, same_file_system: false }
+    }
+}
+
+/// Counts of entries met by [`count`]
+///
+/// [`count`]: fn.count.html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Counts {
+    /// Count of dirs (including the root)
+    pub dirs: u64,
+    /// Count of regular files
+    pub files: u64,
+    /// Count of symlinks (only without `follow_links`)
+    pub symlinks: u64,
+    /// Count of other entries (sockets, devices, etc.)
+    pub other: u64,
+    /// Count of walk errors
+    pub errors: u64,
+}
+
+// Item of CountContentProcessor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CountKind {
+    Dir,
+    File,
+    Symlink,
+    Other,
+}
+
+impl CountKind {
+    fn new<T: FsFileType>(is_dir: bool, file_type: Option<T>) -> Self {
+        match file_type {
+            _ if is_dir => Self::Dir,
+            Some(ty) if ty.is_dir() => Self::Dir,
+            Some(ty) if ty.is_file() => Self::File,
+            Some(ty) if ty.is_symlink() => Self::Symlink,
+            _ => Self::Other,
+        }
+    }
+}
+
+// Keeps only the kind of entries: no metadata, paths or names are taken
+#[derive(Debug, Default)]
+struct CountContentProcessor {}
+
+impl<E: fs::FsDirEntry> ContentProcessor<E> for CountContentProcessor {
+    type Item = CountKind;
+    type Collection = Vec<CountKind>;
+
+    fn process_root_direntry(
+        &self,
+        fsdent: &mut E::RootDirEntry,
+        follow_link: bool,
+        is_dir: bool,
+        _depth: Depth,
+        ctx: &mut E::Context,
+    ) -> Option<Self::Item> {
+        CountKind::new(is_dir, fsdent.file_type(follow_link, ctx).ok()).into_some()
+    }
+
+    fn process_direntry(
+        &self,
+        fsdent: &mut E,
+        follow_link: bool,
+        is_dir: bool,
+        _depth: Depth,
+        ctx: &mut E::Context,
+    ) -> Option<Self::Item> {
+        CountKind::new(is_dir, fsdent.file_type(follow_link, ctx).ok()).into_some()
+    }
+
+    fn is_dir(item: &Self::Item) -> bool {
+        *item == CountKind::Dir
+    }
+
+    fn collect(&self, iter: impl Iterator<Item = Self::Item>) -> Self::Collection {
+        iter.collect()
+    }
+
+    fn empty_collection() -> Self::Collection {
+        vec![]
+    }
+}
+
+/// Count entries under `root` (including `root` itself).
+///
+/// This is much faster than counting entries of a usual walk, since entries are
+/// never converted to [`DirEntry`]: no metadata is queried (unless links are
+/// followed) and no paths or file names are copied.
+///
+/// Errors (including an error about `root` itself) are counted in
+/// [`Counts::errors`].
+///
+/// [`DirEntry`]: struct.DirEntry.html
+/// [`Counts::errors`]: struct.Counts.html#structfield.errors
+pub fn count<P: AsRef<Path>>(root: P, opts: CountOptions) -> Counts {
+    let wd = WalkDirBuilder::<DefaultDirEntry, CountContentProcessor>::new(root)
+        .follow_links(opts.follow_links)
+        .max_depth(opts.max_depth)
+        .same_file_system(opts.same_file_system);
+
+    let mut counts = Counts::default();
+    for result in wd.into_classic() {
+        match result {
+            Ok(CountKind::Dir) => counts.dirs += 1,
+            Ok(CountKind::File) => counts.files += 1,
+            Ok(CountKind::Symlink) => counts.symlinks += 1,
+            Ok(CountKind::Other) => counts.other += 1,
+            Err(_) => counts.errors += 1,
+        }
+    }
+    counts
+}
+
+/////////////////////////////////////////////////////////////////////////
+
+/// Find the dirs under `root` (including `root` itself) which contain nothing.
+///
+/// Dirs are returned in the order they are left, i.e. every dir after the
+/// dirs inside it, so they may be removed in this order. A dir containing
+/// only empty dirs isn't empty itself. Symbolic links are not followed.
+///
+/// A dir is reported if its [`DirSummary`] at `Position::AfterContent` is
+/// empty. To consider entries matching a filter as absent (e.g. `.DS_Store`
+/// files), walk with the filter and check the summaries the same way.
+///
+/// # Errors
+///
+/// Returns an error only if `root` itself cannot be walked. Other errors are
+/// skipped (see the [module docs](index.html)), but a dir with errors about
+/// its entries is never reported.
+///
+/// [`DirSummary`]: struct.DirSummary.html
+pub fn find_empty_dirs<P: AsRef<Path>>(root: P) -> Result<Vec<DirEntry>, Error<DefaultDirEntry>> {
+    // Dirs whose content is being walked, the innermost last
+    let mut open = Vec::new();
+    let mut empty = Vec::new();
+
+    for pos in WalkDirBuilder::<DefaultDirEntry>::new(root).into_iter() {
+        match pos {
+            Position::BeforeContent((dir, _)) => open.push(dir),
+            Position::AfterContent { summary } => {
+                let dir = open.pop().expect("content left without being entered");
+                if summary.is_empty() {
+                    empty.push(dir);
+                }
+            }
+            Position::Error(err) if err.depth() == 0 => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(empty)
+}
+
+/////////////////////////////////////////////////////////////////////////
+
+/// Options of [`grep`]
+///
+/// [`grep`]: fn.grep.html
+#[derive(Debug, Clone)]
+pub struct GrepOptions {
+    /// See `WalkDir::follow_links`
+    pub follow_links: bool,
+    /// See `WalkDir::max_depth`
+    pub max_depth: Depth,
+    /// See `WalkDir::same_file_system`
+    pub same_file_system: bool,
+    /// Skip files and dirs whose names start with `.` (the root is never skipped)
+    pub skip_hidden: bool,
+    /// Count of threads searching files (`0` means the count of available CPUs)
+    pub threads: usize,
+}
+
+impl Default for GrepOptions {
+    fn default() -> Self {
+        Self {
+            follow_links: false,
+            max_depth: Depth::MAX,
+            same_file_system: false,
+            skip_hidden: false,
+            threads: 0,
+        }
+    }
+}
+
+/// A line found by [`grep`]
+///
+/// [`grep`]: fn.grep.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GrepMatch {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Number of the line (the first line is `1`)
+    pub line_no: u64,
+    /// The line without its terminator (invalid UTF-8 is replaced with `U+FFFD`)
+    pub line: String,
+}
+
+// Sent by the walking and searching threads to `Grep`
+#[derive(Debug)]
+enum GrepMessage {
+    Match(GrepMatch),
+    Error,
+}
+
+/// Iterator over lines found by [`grep`]
+///
+/// Dropping it stops the search: the walk ends and each thread leaves the
+/// file at hand before its next line.
+///
+/// [`grep`]: fn.grep.html
+#[derive(Debug)]
+pub struct Grep {
+    rx: mpsc::Receiver<GrepMessage>,
+    errors: u64,
+    // Set on drop, checked by the walking and searching threads
+    stop: Arc<AtomicBool>,
+}
+
+impl Grep {
+    /// Count of errors met so far (walk errors and files which couldn't be read)
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+}
+
+impl Iterator for Grep {
+    type Item = GrepMatch;
+
+    fn next(&mut self) -> Option<GrepMatch> {
+        loop {
+            match self.rx.recv().ok()? {
+                GrepMessage::Match(m) => return m.into_some(),
+                GrepMessage::Error => self.errors += 1,
+            }
+        }
+    }
+}
+
+impl Drop for Grep {
+    fn drop(&mut self) {
+        self.stop.store(true, AtomicOrdering::Relaxed);
+    }
+}
+
+fn is_hidden(dent: &DirEntry) -> bool {
+    dent.file_name().to_str().is_some_and(|name| name.starts_with('.'))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+// Send matching lines of the file in order, false if `Grep` was dropped
+fn grep_file(dent: &DirEntry, pattern: &[u8], tx: &mpsc::SyncSender<GrepMessage>, stop: &AtomicBool) -> bool {
+    let mut reader = match dent.open() {
+        Ok(file) => BufReader::new(file),
+        Err(_) => return tx.send(GrepMessage::Error).is_ok(),
+    };
+
+    let mut line = vec![];
+    let mut line_no = 0;
+    loop {
+        if stop.load(AtomicOrdering::Relaxed) {
+            return false;
+        }
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => return true,
+            Ok(_) => {},
+            Err(_) => return tx.send(GrepMessage::Error).is_ok(),
+        }
+        line_no += 1;
+
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+        if !contains(&line, pattern) {
+            continue;
+        }
+        let m = GrepMatch {
+            path: dent.path().to_path_buf(),
+            line_no,
+            line: String::from_utf8_lossy(&line).into_owned(),
+        };
+        if tx.send(GrepMessage::Match(m)).is_err() {
+            return false;
+        }
+    }
+}
+
+/// Find lines containing `pattern` in regular files under `root`.
+///
+/// The tree is walked on one thread while files are opened (with
+/// [`DirEntry::open`]) and searched on [`GrepOptions::threads`] others, so
+/// lines of a file come in order, but files come in no particular order.
+/// The pattern is matched literally against the bytes of each line, an empty
+/// pattern matches every line. With `follow_links`, links to files are
+/// searched too.
+///
+/// Errors (including an error about `root` itself) never stop the search, they
+/// are counted in [`Grep::errors`].
+///
+/// [`DirEntry::open`]: struct.DirEntry.html#method.open
+/// [`GrepOptions::threads`]: struct.GrepOptions.html#structfield.threads
+/// [`Grep::errors`]: struct.Grep.html#method.errors
+pub fn grep<P: AsRef<Path>>(root: P, pattern: &str, opts: GrepOptions) -> Grep {
+    let threads = match opts.threads {
+        0 => available_cpus(),
+        n => n,
+    };
+    let (tx, rx) = mpsc::sync_channel(256);
+    let (work_tx, work_rx) = mpsc::sync_channel::<DirEntry>(threads * 16);
+    let work_rx = Arc::new(Mutex::new(work_rx));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    for _ in 0..threads {
+        let (work_rx, tx, stop) = (Arc::clone(&work_rx), tx.clone(), Arc::clone(&stop));
+        let pattern = pattern.as_bytes().to_vec();
+        thread::spawn(move || loop {
+            // The lock is released before the file is searched
+            let dent = match work_rx.lock().ok().and_then(|rx| rx.recv().ok()) {
+                Some(dent) => dent,
+                None => return,
+            };
+            if !grep_file(&dent, &pattern, &tx, &stop) {
+                return;
+            }
+        });
+    }
+
+    let root = root.as_ref().to_path_buf();
+    let walk_stop = Arc::clone(&stop);
+    thread::spawn(move || {
+        let skip_hidden = opts.skip_hidden;
+        let wd = WalkDirBuilder::<DefaultDirEntry>::new(root)
+            .follow_links(opts.follow_links)
+            .max_depth(opts.max_depth)
+            .same_file_system(opts.same_file_system);
+
+        let it = wd.into_classic().filter_entry(move |dent| {
+            !skip_hidden || dent.depth() == 0 || !is_hidden(dent)
+        });
+        for result in it {
+            if walk_stop.load(AtomicOrdering::Relaxed) {
+                return;
+            }
+            let sent = match result {
+                Ok(dent) if dent.file_type().is_file() => work_tx.send(dent).is_ok(),
+                Ok(_) => true,
+                Err(_) => tx.send(GrepMessage::Error).is_ok(),
+            };
+            if !sent {
+                return;
+            }
+        }
+    });
+
+    Grep { rx, errors: 0, stop }
+}
+
+/////////////////////////////////////////////////////////////////////////
+
+/// Iterator over a path and its ancestors
+///
+/// Created by [`ancestors`].
+///
+/// [`ancestors`]: fn.ancestors.html
+#[derive(Debug)]
+pub struct Ancestors {
+    next: Option<PathBuf>,
+    root: Option<PathBuf>,
+}
+
+impl Ancestors {
+    /// Stop at `root` (it is yielded too). By default, the walk goes up to the
+    /// first component of the path. If `root` isn't an ancestor of the path,
+    /// this has no effect.
+    pub fn up_to<P: AsRef<Path>>(mut self, root: P) -> Self {
+        self.root = Some(root.as_ref().to_path_buf());
+        self
+    }
+}
+
+impl Iterator for Ancestors {
+    type Item = Result<DirEntry, Error<DefaultDirEntry>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let path = self.next.take()?;
+        if self.root.as_deref() != Some(path.as_path()) {
+            self.next = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .map(Path::to_path_buf);
+        }
+        // The entry is made exactly like the root of a walk
+        WalkDirBuilder::<DefaultDirEntry>::new(&path).max_depth(0).into_classic().next()
+    }
+}
+
+/// Walk upwards from `path`: yield entries for `path` itself and then for each
+/// of its parents, nearest first.
+///
+/// Entries are made like the root of a walk (so their depth is `0`), with the
+/// same metadata caching and errors: a missing ancestor yields an error and
+/// the walk goes on with its parent. Paths are taken as given, without
+/// canonicalization, so a relative path stops at its first component; use
+/// [`Ancestors::up_to`] to stop earlier. For example, to find the nearest
+/// manifest of a Cargo project:
+///
+/// ```no_run
+/// let manifest = walkdir::ancestors("src/walk").find_map(|result| {
+///     let manifest = result.ok()?.path().join("Cargo.toml");
+///     if manifest.is_file() { Some(manifest) } else { None }
+/// });
+/// ```
+///
+/// [`Ancestors::up_to`]: struct.Ancestors.html#method.up_to
+pub fn ancestors<P: AsRef<Path>>(path: P) -> Ancestors {
+    Ancestors { next: Some(path.as_ref().to_path_buf()), root: None }
+}
+
+/////////////////////////////////////////////////////////////////////////
+
+/// Entries at the same relative path in two trees, yielded by [`sync_walk`]
+///
+/// [`sync_walk`]: fn.sync_walk.html
+// Items are matched at once, so boxing the pair would only add an allocation
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum SyncPosition {
+    /// The path exists in both trees (source entry, destination entry)
+    InBoth(DirEntry, DirEntry),
+    /// The path exists only in the source tree
+    OnlySrc(DirEntry),
+    /// The path exists only in the destination tree
+    OnlyDst(DirEntry),
+}
+
+/// Iterator over two trees in lockstep
+///
+/// Created by [`sync_walk`].
+///
+/// [`sync_walk`]: fn.sync_walk.html
+#[derive(Debug)]
+pub struct SyncWalk {
+    src: SyncSide,
+    dst: SyncSide,
+    // Sides the last item was taken from (src, dst)
+    last: (bool, bool),
+}
+
+type SyncIter = ClassicIter<
+    DefaultDirEntry,
+    DirEntryContentProcessor,
+    WalkDirIterator<DefaultDirEntry, DirEntryContentProcessor>,
+>;
+
+struct SyncSide {
+    root: PathBuf,
+    it: SyncIter,
+    peeked: Option<DirEntry>,
+}
+
+impl fmt::Debug for SyncSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncSide")
+            .field("root", &self.root)
+            .field("peeked", &self.peeked)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SyncSide {
+    fn new(root: PathBuf) -> Self {
+        let it = WalkDirBuilder::<DefaultDirEntry>::new(&root).deterministic(true).into_classic();
+        Self { root, it, peeked: None }
+    }
+
+    // Peek the next entry, errors are returned at once
+    fn peek(&mut self) -> Result<Option<&DirEntry>, Error<DefaultDirEntry>> {
+        if self.peeked.is_none() {
+            self.peeked = self.it.next().transpose()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn relative<'a>(&self, dent: &'a DirEntry) -> &'a Path {
+        dent.path().strip_prefix(&self.root).unwrap_or(dent.path())
+    }
+}
+
+impl SyncWalk {
+    /// Don't descend into the dir yielded last (on the sides it was found on).
+    ///
+    /// If the last item was a file, the rest of its parent dir is skipped
+    /// instead, like [`ClassicIter::skip_current_dir`] does.
+    ///
+    /// [`ClassicIter::skip_current_dir`]: struct.ClassicIter.html#method.skip_current_dir
+    pub fn skip_current_dir(&mut self) {
+        if self.last.0 {
+            self.src.it.skip_current_dir();
+        }
+        if self.last.1 {
+            self.dst.it.skip_current_dir();
+        }
+    }
+}
+
+impl Iterator for SyncWalk {
+    type Item = Result<SyncPosition, Error<DefaultDirEntry>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.last = (false, false);
+        if let Err(err) = self.src.peek() {
+            return Some(Err(err));
+        }
+        if let Err(err) = self.dst.peek() {
+            return Some(Err(err));
+        }
+
+        // Both sides are sorted by name, so relative paths come in the order of
+        // their components and the smaller one is missing on the other side
+        let ordering = match (&self.src.peeked, &self.dst.peeked) {
+            (None, None) => return None,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(src), Some(dst)) => self.src.relative(src).cmp(self.dst.relative(dst)),
+        };
+        let pos = match ordering {
+            Ordering::Less => SyncPosition::OnlySrc(self.src.peeked.take()?),
+            Ordering::Greater => SyncPosition::OnlyDst(self.dst.peeked.take()?),
+            Ordering::Equal => {
+                SyncPosition::InBoth(self.src.peeked.take()?, self.dst.peeked.take()?)
+            }
+        };
+        self.last = match pos {
+            SyncPosition::InBoth(..) => (true, true),
+            SyncPosition::OnlySrc(_) => (true, false),
+            SyncPosition::OnlyDst(_) => (false, true),
+        };
+        Some(Ok(pos))
+    }
+}
+
+/// Walk the trees `src` and `dst` in lockstep, pairing entries by their paths
+/// relative to the roots.
+///
+/// Both trees are walked with [`deterministic`] order, so one pass over both is
+/// enough: the roots come first as [`SyncPosition::InBoth`], then every path
+/// found in both trees, or in only one of them, is yielded once. Entries of a
+/// dir found on one side only are all yielded as [`SyncPosition::OnlySrc`] or
+/// [`SyncPosition::OnlyDst`], unless [`SyncWalk::skip_current_dir`] is called.
+/// A path which is a file on one side and a dir on the other is yielded as
+/// `InBoth`, so compare the types of entries before descending.
+///
+/// This is the core loop of rsync-like tools:
+///
+/// ```no_run
+/// use walkdir::SyncPosition;
+///
+/// for pos in walkdir::sync_walk("src", "backup") {
+///     match pos.unwrap() {
+///         SyncPosition::OnlySrc(new) => println!("copy {}", new.path().display()),
+///         SyncPosition::OnlyDst(old) => println!("delete {}", old.path().display()),
+///         SyncPosition::InBoth(..) => {},
+///     }
+/// }
+/// ```
+///
+/// Errors of both walks are yielded as they come; a missing root yields its
+/// error and then the other tree is yielded as the only one.
+///
+/// [`deterministic`]: struct.WalkDir.html#method.deterministic
+/// [`SyncPosition::InBoth`]: enum.SyncPosition.html#variant.InBoth
+/// [`SyncPosition::OnlySrc`]: enum.SyncPosition.html#variant.OnlySrc
+/// [`SyncPosition::OnlyDst`]: enum.SyncPosition.html#variant.OnlyDst
+/// [`SyncWalk::skip_current_dir`]: struct.SyncWalk.html#method.skip_current_dir
+pub fn sync_walk<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> SyncWalk {
+    SyncWalk {
+        src: SyncSide::new(src.as_ref().to_path_buf()),
+        dst: SyncSide::new(dst.as_ref().to_path_buf()),
+        last: (false, false),
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////
+
+/// Options of [`par_walk`]
+///
+/// [`par_walk`]: fn.par_walk.html
+#[cfg(feature = "parallel")]
+#[derive(Clone)]
+pub struct ParWalkOptions {
+    /// See `WalkDir::max_depth`
+    pub max_depth: Depth,
+    /// Skip files and dirs whose names start with `.` (the root is never skipped)
+    pub skip_hidden: bool,
+    /// Count of subtrees walked at once
+    pub parallelism: Parallelism,
+    /// Capacity of the queue of each subtree: at most one `buffer` per thread
+    /// items wait for the consumer at once
+    pub buffer: usize,
+    /// What a thread does while the queue of its subtree is full
+    pub wait: ParWait,
+    /// Count of subtrees on the same device walked at once (`None` for no
+    /// limit besides `parallelism`), e.g. `Some(1)` so that a spinning disk
+    /// is read by one thread while subtrees on other devices are walked in
+    /// parallel
+    pub max_concurrency_per_device: Option<usize>,
+    /// See `WalkDir::process_read_dir`, called on the thread reading the dir
+    pub process_read_dir: Option<FnProcessReadDir<DefaultDirEntry>>,
+}
+
+#[cfg(feature = "parallel")]
+impl fmt::Debug for ParWalkOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParWalkOptions")
+            .field("max_depth", &self.max_depth)
+            .field("skip_hidden", &self.skip_hidden)
+            .field("parallelism", &self.parallelism)
+            .field("buffer", &self.buffer)
+            .field("wait", &self.wait)
+            .field("max_concurrency_per_device", &self.max_concurrency_per_device)
+            .field("process_read_dir", &self.process_read_dir.is_some())
+            .finish()
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl Default for ParWalkOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: Depth::MAX,
+            skip_hidden: false,
+            parallelism: Parallelism::Auto,
+            buffer: 256,
+            wait: ParWait::Block,
+            max_concurrency_per_device: None,
+            process_read_dir: None,
+        }
+    }
+}
+
+/// How many subtrees [`par_walk`] walks at once
+///
+/// [`par_walk`]: fn.par_walk.html
+#[cfg(feature = "parallel")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Parallelism {
+    /// Pick the count from the latency of the storage: the walk starts with 2
+    /// threads and times reading the first dirs; if a dir takes milliseconds,
+    /// as on a spinning disk, it goes on with 2 to 4 threads, since more would
+    /// only make the disk seek back and forth, and otherwise with as many
+    /// threads as there are CPUs
+    #[default]
+    Auto,
+    /// Exactly `n` threads (`0` means the count of available CPUs)
+    Fixed(usize),
+}
+
+// Dirs read before `Parallelism::Auto` settles on a count of threads
+#[cfg(feature = "parallel")]
+const PROBE_DIRS: usize = 32;
+// The mean time to read a dir from which the storage is taken for a spinning disk
+#[cfg(feature = "parallel")]
+const PROBE_SLOW_DIR: Duration = Duration::from_millis(2);
+
+// Time spent by jobs reading dirs while `Parallelism::Auto` probes the storage
+#[cfg(feature = "parallel")]
+#[derive(Debug, Default)]
+struct ParProbe {
+    nanos: AtomicU64,
+    dirs: AtomicUsize,
+}
+
+#[cfg(feature = "parallel")]
+impl ParProbe {
+    fn add(&self, elapsed: Duration, dirs: usize) {
+        self.nanos.fetch_add(elapsed.as_nanos() as u64, AtomicOrdering::Relaxed);
+        self.dirs.fetch_add(dirs, AtomicOrdering::Relaxed);
+    }
+
+    // The count of threads to go on with, once enough dirs were read
+    fn threads(&self, cpus: usize) -> Option<usize> {
+        let dirs = self.dirs.load(AtomicOrdering::Relaxed);
+        if dirs < PROBE_DIRS {
+            return None;
+        }
+        let mean = Duration::from_nanos(self.nanos.load(AtomicOrdering::Relaxed) / dirs as u64);
+        if mean >= PROBE_SLOW_DIR {
+            cpus.clamp(2, 4)
+        } else {
+            cpus
+        }
+        .into_some()
+    }
+}
+
+/// What a thread of [`par_walk`] does while the queue of its subtree is full,
+/// i.e. while the consumer is behind
+///
+/// Either way, the subtree isn't read further until there is room, so a slow
+/// consumer slows the walk down instead of making it buffer the tree.
+///
+/// [`par_walk`]: fn.par_walk.html
+#[cfg(feature = "parallel")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParWait {
+    /// Block until the consumer takes an item
+    #[default]
+    Block,
+    /// Retry after `std::thread::yield_now`: the thread picks up as soon as
+    /// there is room, at the cost of spinning while the consumer is slow
+    YieldNow,
+}
+
+// Send `item` waiting as `wait` says, false if the receiver was dropped
+#[cfg(feature = "parallel")]
+fn par_send<T>(tx: &mpsc::SyncSender<T>, mut item: T, wait: ParWait) -> bool {
+    if wait == ParWait::Block {
+        return tx.send(item).is_ok();
+    }
+    loop {
+        match tx.try_send(item) {
+            Ok(()) => return true,
+            Err(mpsc::TrySendError::Full(back)) => {
+                item = back;
+                thread::yield_now();
+            },
+            Err(mpsc::TrySendError::Disconnected(_)) => return false,
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+type ParDeviceNum = <DefaultDirEntry as fs::FsDirEntry>::DeviceNum;
+
+// Device of the dir at `path` (`None` if it can't be queried)
+#[cfg(feature = "parallel")]
+fn par_device(path: &Path) -> Option<ParDeviceNum> {
+    let mut ctx = Default::default();
+    let root = <DefaultDirEntry as fs::FsDirEntry>::RootDirEntry::from_path(path, &mut ctx).ok()?;
+    root.device_num(&mut ctx).ok()
+}
+
+// Permits of `ParWalkOptions::max_concurrency_per_device`. They are granted in
+// the order of the walk: the consumer drains subtrees in this order, so the
+// subtree it waits for always holds a permit (otherwise later subtrees on the
+// same device could take them all and wait for the consumer forever).
+#[cfg(feature = "parallel")]
+#[derive(Debug)]
+struct ParDeviceLimits {
+    limit: usize,
+    // Subtrees not finished yet per device, in the order of the walk
+    queues: Mutex<Vec<(ParDeviceNum, VecDeque<u64>)>>,
+    released: Condvar,
+}
+
+#[cfg(feature = "parallel")]
+impl ParDeviceLimits {
+    fn new(limit: usize) -> Self {
+        Self { limit: limit.max(1), queues: Mutex::new(Vec::new()), released: Condvar::new() }
+    }
+
+    // Queue subtree `seq` on `device`; called in the order of the walk
+    fn enqueue(self: &Arc<Self>, device: ParDeviceNum, seq: u64) -> ParTicket {
+        let mut queues = self.queues.lock().unwrap();
+        match queues.iter_mut().find(|(d, _)| *d == device) {
+            Some((_, queue)) => queue.push_back(seq),
+            None => queues.push((device, VecDeque::from([seq]))),
+        }
+        ParTicket { limits: self.clone(), device, seq }
+    }
+}
+
+// Place of a subtree in the queue of its device, left when dropped (when the
+// job ends, panics or is dropped by the executor without running)
+#[cfg(feature = "parallel")]
+struct ParTicket {
+    limits: Arc<ParDeviceLimits>,
+    device: ParDeviceNum,
+    seq: u64,
+}
+
+#[cfg(feature = "parallel")]
+impl ParTicket {
+    // Wait until the subtree is among the first `limit` unfinished ones of its device
+    fn wait(&self) {
+        let limits = &self.limits;
+        let mut queues = limits.queues.lock().unwrap();
+        loop {
+            let queue = &queues.iter().find(|(d, _)| *d == self.device).expect("BUG: subtree not queued").1;
+            if queue.iter().take(limits.limit).any(|s| *s == self.seq) {
+                return;
+            }
+            queues = limits.released.wait(queues).unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl Drop for ParTicket {
+    fn drop(&mut self) {
+        // Don't panic again if the lock was poisoned by a panicking job
+        let mut queues = match self.limits.queues.lock() {
+            Ok(queues) => queues,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some((_, queue)) = queues.iter_mut().find(|(d, _)| *d == self.device) {
+            queue.retain(|s| *s != self.seq);
+        }
+        self.limits.released.notify_all();
+    }
+}
+
+/// An executor running the jobs of [`par_walk_on`]
+///
+/// It is implemented for closures taking a job, so any thread pool can be
+/// used, e.g. `|job| pool.execute(job)`. With the `rayon` feature, it is
+/// implemented for `rayon::ThreadPool` too.
+///
+/// [`par_walk_on`]: fn.par_walk_on.html
+#[cfg(feature = "parallel")]
+pub trait Spawn {
+    /// Run `job` on some thread other than the calling one
+    fn spawn(&self, job: Box<dyn FnOnce() + Send>);
+}
+
+#[cfg(feature = "parallel")]
+impl<F: Fn(Box<dyn FnOnce() + Send>)> Spawn for F {
+    fn spawn(&self, job: Box<dyn FnOnce() + Send>) {
+        self(job)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Spawn for rayon::ThreadPool {
+    fn spawn(&self, job: Box<dyn FnOnce() + Send>) {
+        rayon::ThreadPool::spawn(self, job)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Spawn for &rayon::ThreadPool {
+    fn spawn(&self, job: Box<dyn FnOnce() + Send>) {
+        rayon::ThreadPool::spawn(self, job)
+    }
+}
+
+/// The executor of [`par_walk`]: a new thread for every job
+///
+/// [`par_walk`]: fn.par_walk.html
+#[cfg(feature = "parallel")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpawnThreads;
+
+#[cfg(feature = "parallel")]
+impl Spawn for SpawnThreads {
+    fn spawn(&self, job: Box<dyn FnOnce() + Send>) {
+        thread::spawn(job);
+    }
+}
+
+#[cfg(feature = "parallel")]
+type ParItem = Result<DirEntry, Error<DefaultDirEntry>>;
+
+// Waiting to be yielded by `ParWalk`, in the order of the walk (there are
+// only a few at once, so boxing items would only add an allocation)
+#[cfg(feature = "parallel")]
+#[allow(clippy::large_enum_variant)]
+enum ParSlot {
+    // An entry of the root level which isn't walked by a job (or an error)
+    Item(ParItem),
+    // Items of the subtree of a dir at depth 1, walked by a job
+    Subtree(mpsc::Receiver<ParItem>),
+}
+
+/// Iterator over a tree walked by several jobs, created by [`par_walk`] and
+/// [`par_walk_on`]
+///
+/// Dropping it stops the walk (each job stops at its next item).
+///
+/// [`par_walk`]: fn.par_walk.html
+/// [`par_walk_on`]: fn.par_walk_on.html
+#[cfg(feature = "parallel")]
+pub struct ParWalk<S: Spawn = SpawnThreads> {
+    spawner: S,
+    max_depth: Depth,
+    skip_hidden: bool,
+    threads: usize,
+    buffer: usize,
+    wait: ParWait,
+    process_read_dir: Option<FnProcessReadDir<DefaultDirEntry>>,
+    /// Timings of jobs, until `Parallelism::Auto` settles on `threads`
+    probe: Option<Arc<ParProbe>>,
+    /// Permits per device (only with `max_concurrency_per_device`)
+    device_limits: Option<Arc<ParDeviceLimits>>,
+    /// Sequence number of the next spawned subtree
+    seq: u64,
+    /// The root level, walked by the consumer itself
+    listing: Box<dyn Iterator<Item = ParItem> + Send>,
+    /// Listed items and spawned subtrees, not yielded yet
+    slots: VecDeque<ParSlot>,
+    /// The subtree being drained
+    subtree: Option<mpsc::Receiver<ParItem>>,
+    /// Count of subtrees spawned but not drained yet
+    subtrees: usize,
+}
+
+#[cfg(feature = "parallel")]
+impl<S: Spawn> fmt::Debug for ParWalk<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParWalk")
+            .field("threads", &self.threads)
+            .field("slots", &self.slots.len())
+            .field("subtrees", &self.subtrees)
+            .finish()
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<S: Spawn> ParWalk<S> {
+    /// Count of subtrees walked at once; with [`Parallelism::Auto`], it
+    /// changes once the first dirs were read
+    ///
+    /// [`Parallelism::Auto`]: enum.Parallelism.html#variant.Auto
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// List the root level ahead, spawning jobs for subtrees, until `threads`
+    /// subtrees are being walked
+    fn fill(&mut self) {
+        if let Some(threads) = self.probe.as_ref().and_then(|probe| probe.threads(available_cpus())) {
+            self.threads = threads;
+            self.probe = None;
+        }
+        while self.subtrees < self.threads && self.slots.len() < self.threads + self.buffer {
+            let slot = match self.listing.next() {
+                Some(Ok(dent)) if self.max_depth > 1 && dent.depth() == 1 && dent.is_dir() => {
+                    // Its own walk yields the dir again, as a single walk would
+                    let (tx, rx) = mpsc::sync_channel(self.buffer);
+                    let (max_depth, skip_hidden, wait) = (self.max_depth - 1, self.skip_hidden, self.wait);
+                    let process_read_dir = self.process_read_dir.clone();
+                    let probe = self.probe.clone();
+                    let path = dent.into_path();
+                    // Subtrees whose device is unknown aren't limited
+                    let seq = self.seq;
+                    let ticket = self.device_limits.as_ref().and_then(|limits| {
+                        par_device(&path).map(|device| limits.enqueue(device, seq))
+                    });
+                    self.seq += 1;
+                    self.spawner.spawn(Box::new(move || {
+                        if let Some(ref ticket) = ticket {
+                            ticket.wait();
+                        }
+                        let mut iter = par_walk_iter(path, 1, max_depth, skip_hidden, process_read_dir);
+                        loop {
+                            // A dir is read by the step after the one yielding it, so
+                            // the time of all steps per dir is the time to read one
+                            let started = probe.as_ref().map(|_| Instant::now());
+                            let mut item = match iter.next() {
+                                Some(item) => item,
+                                None => return,
+                            };
+                            if let (Some(probe), Some(started)) = (&probe, started) {
+                                let dirs = matches!(item, Ok(ref dent) if dent.is_dir()) as usize;
+                                probe.add(started.elapsed(), dirs);
+                            }
+                            match item {
+                                Ok(ref mut dent) => dent.add_depth(1),
+                                Err(ref mut err) => err.add_depth(1),
+                            }
+                            if !par_send(&tx, item, wait) {
+                                return;
+                            }
+                        }
+                    }));
+                    self.subtrees += 1;
+                    ParSlot::Subtree(rx)
+                },
+                Some(item) => ParSlot::Item(item),
+                None => return,
+            };
+            self.slots.push_back(slot);
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<S: Spawn> Iterator for ParWalk<S> {
+    type Item = ParItem;
+
+    fn next(&mut self) -> Option<ParItem> {
+        loop {
+            self.fill();
+            if let Some(ref subtree) = self.subtree {
+                match subtree.recv() {
+                    Ok(item) => return item.into_some(),
+                    Err(_) => {
+                        self.subtree = None;
+                        self.subtrees -= 1;
+                        continue;
+                    },
+                }
+            }
+            match self.slots.pop_front()? {
+                ParSlot::Item(item) => return item.into_some(),
+                ParSlot::Subtree(rx) => self.subtree = Some(rx),
+            }
+        }
+    }
+}
+
+// Walk with the options of `par_walk`, as a single-threaded walk would, from
+// `root` at `depth` of the whole walk (depths of entries are relative to it)
+#[cfg(feature = "parallel")]
+fn par_walk_iter(
+    root: PathBuf,
+    depth: Depth,
+    max_depth: Depth,
+    skip_hidden: bool,
+    process_read_dir: Option<FnProcessReadDir<DefaultDirEntry>>,
+) -> impl Iterator<Item = ParItem> {
+    let mut wd = WalkDirBuilder::<DefaultDirEntry>::new(root).deterministic(true).max_depth(max_depth);
+    if let Some(f) = process_read_dir {
+        wd = wd.process_read_dir(move |dir_depth, path, children| f(depth + dir_depth, path, children));
+    }
+    wd.into_classic()
+        .filter_entry(move |dent| !skip_hidden || dent.depth() == 0 || !is_hidden(dent))
+}
+
+/// Walk the tree under `root` on several threads, yielding entries in the
+/// same order as a single-threaded walk.
+///
+/// The result is exactly what a walk with [`deterministic`] (and the given
+/// [`ParWalkOptions`]) yields, entries and errors alike, so the output stays
+/// reproducible. With [`ParWalkOptions::process_read_dir`], every dir is
+/// processed on the thread reading it, so per-dir work such as sorting or
+/// filtering runs in parallel too. The root level is listed by the iterator itself, while up
+/// to [`ParWalkOptions::parallelism`] dirs right under `root` are walked at
+/// once, each on its own thread, in the order of the walk. Every subtree is sent
+/// through its own queue of at most [`ParWalkOptions::buffer`] items, and the
+/// iterator drains these queues one after another: a thread walking ahead of
+/// the consumer waits for it (see [`ParWait`]) instead of piling up the rest
+/// of its subtree in memory.
+///
+/// ```no_run
+/// use walkdir::ParWalkOptions;
+///
+/// for entry in walkdir::par_walk("/usr", ParWalkOptions::default()) {
+///     println!("{}", entry.unwrap().path().display());
+/// }
+/// ```
+///
+/// The speedup depends on the shape of the tree: a single large subtree is
+/// still walked by a single thread. Links are not followed, since loops can't
+/// be detected across subtrees walked separately. To run the walk on threads
+/// of an existing pool, use [`par_walk_on`].
+///
+/// With [`ParWalkOptions::max_concurrency_per_device`], the device of every
+/// dir right under `root` is queried before its subtree is walked, and a
+/// subtree waits (on its thread) while the limit of its device is reached.
+///
+/// [`deterministic`]: struct.WalkDir.html#method.deterministic
+/// [`ParWalkOptions`]: struct.ParWalkOptions.html
+/// [`ParWalkOptions::parallelism`]: struct.ParWalkOptions.html#structfield.parallelism
+/// [`ParWalkOptions::buffer`]: struct.ParWalkOptions.html#structfield.buffer
+/// [`ParWalkOptions::process_read_dir`]: struct.ParWalkOptions.html#structfield.process_read_dir
+/// [`ParWalkOptions::max_concurrency_per_device`]: struct.ParWalkOptions.html#structfield.max_concurrency_per_device
+/// [`ParWait`]: enum.ParWait.html
+/// [`par_walk_on`]: fn.par_walk_on.html
+#[cfg(feature = "parallel")]
+pub fn par_walk<P: AsRef<Path>>(root: P, opts: ParWalkOptions) -> ParWalk {
+    par_walk_on(root, opts, SpawnThreads)
+}
+
+/// Walk the tree under `root` like [`par_walk`], running the subtree walks on
+/// `spawner` instead of new threads.
+///
+/// This way an application which already owns a thread pool doesn't get more
+/// threads than CPUs:
+///
+/// ```no_run
+/// # #[cfg(feature = "rayon")] {
+/// use walkdir::{Parallelism, ParWalkOptions};
+///
+/// let pool = rayon::ThreadPoolBuilder::new().build().unwrap();
+/// let parallelism = Parallelism::Fixed(pool.current_num_threads());
+/// let opts = ParWalkOptions { parallelism, ..Default::default() };
+/// for entry in walkdir::par_walk_on("/usr", opts, &pool) {
+///     println!("{}", entry.unwrap().path().display());
+/// }
+/// # }
+/// ```
+///
+/// Every job walks one subtree and blocks while its queue is full, so the
+/// executor must be able to run as many jobs at once as the walk uses threads
+/// (see [`Parallelism`]) besides the thread consuming the iterator; otherwise
+/// the walk may never finish. In particular, don't consume the iterator on a thread of the same
+/// pool unless the pool has a spare thread for it.
+///
+/// [`par_walk`]: fn.par_walk.html
+/// [`Parallelism`]: enum.Parallelism.html
+#[cfg(feature = "parallel")]
+pub fn par_walk_on<P: AsRef<Path>, S: Spawn>(root: P, opts: ParWalkOptions, spawner: S) -> ParWalk<S> {
+    let (threads, probe) = match opts.parallelism {
+        Parallelism::Auto => (2, Some(Arc::default())),
+        Parallelism::Fixed(0) => (available_cpus(), None),
+        Parallelism::Fixed(n) => (n, None),
+    };
+    let root = root.as_ref().to_path_buf();
+    ParWalk {
+        spawner,
+        max_depth: opts.max_depth,
+        skip_hidden: opts.skip_hidden,
+        threads,
+        buffer: opts.buffer.max(1),
+        wait: opts.wait,
+        listing: Box::new(par_walk_iter(
+            root,
+            0,
+            opts.max_depth.min(1),
+            opts.skip_hidden,
+            opts.process_read_dir.clone(),
+        )),
+        process_read_dir: opts.process_read_dir,
+        probe,
+        device_limits: opts.max_concurrency_per_device.map(|limit| Arc::new(ParDeviceLimits::new(limit))),
+        seq: 0,
+        slots: VecDeque::new(),
+        subtree: None,
+        subtrees: 0,
+    }
+}
+
+fn available_cpus() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
@@ -1,4 +1,6 @@
 use crate::cp::ContentProcessor;
+#[cfg(feature = "std")]
+use crate::cp::DirEntry;
 use crate::walk::iter::WalkDirIter;
 use crate::fs;
 use crate::wd::{self, Position};
@@ -54,7 +56,8 @@ where
     /// Note that if the iterator has `contents_first` enabled, then this
     /// method is no different than calling the standard `Iterator::filter`
     /// method (because directory entries are yielded after they've been
-    /// descended into).
+    /// descended into). Filters may be nested: a directory rejected by any of
+    /// them is not descended into.
     ///
     /// [`skip_current_dir`]: #method.skip_current_dir
     /// [`min_depth`]: struct.WalkDir.html#method.min_depth
@@ -63,11 +66,78 @@ where
     where
         P: FnMut(&CP::Item) -> bool,
     {
-        ClassicFilterEntry { inner: self, predicate, _cp: std::marker::PhantomData }
+        ClassicFilterEntry { inner: self, predicate, poisoned: false, _cp: core::marker::PhantomData }
+    }
+
+    /// Rewrites the path of every yielded entry, replacing the root given to
+    /// [`WalkDir::new`] with `prefix` (e.g. `src/a/b` becomes `dst/a/b` for
+    /// the root `src` and the prefix `dst`). This is handy for copy and mirror
+    /// pipelines.
+    ///
+    /// The path buffer of each entry is reused when its capacity allows, so
+    /// mapping to a prefix not longer than the root never allocates. Only
+    /// paths of entries are rewritten; paths in errors still refer to the
+    /// walked tree, as does everything about the entry except [`path`] (e.g.
+    /// its metadata). [`canonical_path`] is computed for the new path.
+    ///
+    /// ```no_run
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder, WalkDirIter, ClassicWalkDirIter};
+    ///
+    /// for entry in WalkDirBuilder::<DefaultDirEntry>::new("src").into_classic().map_root("backup/src") {
+    ///     let entry = entry.unwrap();
+    ///     if entry.file_type().is_dir() {
+    ///         std::fs::create_dir_all(entry.path()).unwrap();
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`WalkDir::new`]: struct.WalkDir.html#method.new
+    /// [`path`]: struct.DirEntry.html#method.path
+    /// [`canonical_path`]: struct.DirEntry.html#method.canonical_path
+    #[cfg(feature = "std")]
+    fn map_root<T>(self, prefix: T) -> MapRoot<E, CP, Self>
+    where
+        T: Into<std::path::PathBuf>,
+        E: fs::FsDirEntry<Path = std::path::Path, PathBuf = std::path::PathBuf>,
+        CP: ContentProcessor<E, Item = DirEntry<E>>,
+    {
+        MapRoot { inner: self, prefix: prefix.into(), _cp: core::marker::PhantomData }
     }
 
     /// Skip all remaining content of current dir
     fn skip_current_dir(&mut self);
+
+    /// Skip the remaining entries of the current dir (see `WalkDirIterator::skip_siblings`)
+    fn skip_siblings(&mut self);
+
+    /// Returns `true` if the walk is over (see `WalkDirIterator::is_finished`)
+    fn is_finished(&self) -> bool;
+
+    /// Returns the iterator itself, checking at compile time that it can be
+    /// moved to another thread.
+    ///
+    /// Iterators over the standard backends are `Send` (and so are yielded
+    /// entries and errors), so a partially consumed iterator may be handed
+    /// off to another thread which continues the walk where it stopped. A
+    /// custom backend, content processor or closure which isn't `Send` makes
+    /// the call fail to compile instead of the later `thread::spawn`.
+    ///
+    /// ```no_run
+    /// use std::thread;
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder, WalkDirIter, ClassicWalkDirIter};
+    ///
+    /// let mut it = WalkDirBuilder::<DefaultDirEntry>::new("foo").into_classic();
+    /// let first = it.next();
+    ///
+    /// let it = it.into_sendable();
+    /// let rest = thread::spawn(move || it.count()).join().unwrap();
+    /// ```
+    fn into_sendable(self) -> Self
+    where
+        Self: Send,
+    {
+        self
+    }
 }
 
 /////////////////////////////////////////////////////////////////////////
@@ -81,7 +151,7 @@ where
     I: Iterator<Item = WalkDirIteratorItem<E, CP>> + WalkDirIter<E, CP>,
 {
     inner: I,
-    _cp: std::marker::PhantomData<CP>,
+    _cp: core::marker::PhantomData<CP>,
 }
 
 impl<E, CP, I> ClassicIter<E, CP, I>
@@ -91,7 +161,7 @@ where
     I: Iterator<Item = WalkDirIteratorItem<E, CP>> + WalkDirIter<E, CP>,
 {
     pub(crate) fn new(inner: I) -> Self {
-        Self { inner, _cp: std::marker::PhantomData }
+        Self { inner, _cp: core::marker::PhantomData }
     }
 }
 
@@ -114,6 +184,7 @@ where
             match self.inner.next() {
                 Some(Position::Entry(dent)) => return Some(Ok(dent)),
                 Some(Position::Error(err)) => return Some(Err(err)),
+                Some(Position::DirSkipped { error, .. }) => return Some(Err(error)),
                 Some(_) => continue,
                 None => return None,
             }
@@ -130,6 +201,14 @@ where
     fn skip_current_dir(&mut self) {
         self.inner.skip_current_dir();
     }
+
+    fn skip_siblings(&mut self) {
+        self.inner.skip_siblings();
+    }
+
+    fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
 }
 
 /////////////////////////////////////////////////////////////////////////
@@ -166,7 +245,9 @@ where
 {
     inner: I,
     predicate: P,
-    _cp: std::marker::PhantomData<CP>,
+    /// The predicate panicked, the walk is over
+    poisoned: bool,
+    _cp: core::marker::PhantomData<CP>,
 }
 
 impl<E, CP, I, P> Iterator for ClassicFilterEntry<E, CP, I, P>
@@ -185,6 +266,9 @@ where
     /// If the iterator fails to retrieve the next value, this method returns
     /// an error value. The error will be wrapped in an `Option::Some`.
     fn next(&mut self) -> Option<Self::Item> {
+        if self.poisoned {
+            return None;
+        }
         loop {
             let item = match self.inner.next() {
                 Some(item) => item,
@@ -193,7 +277,11 @@ where
 
             match item {
                 Ok(dent) => {
-                    if !(self.predicate)(&dent) {
+                    // Stays set if the predicate panics
+                    self.poisoned = true;
+                    let keep = (self.predicate)(&dent);
+                    self.poisoned = false;
+                    if !keep {
                         if CP::is_dir(&dent) {
                             self.inner.skip_current_dir();
                         }
@@ -255,13 +343,17 @@ where
     /// Note that if the iterator has `contents_first` enabled, then this
     /// method is no different than calling the standard `Iterator::filter`
     /// method (because directory entries are yielded after they've been
-    /// descended into).
+    /// descended into). Filters may be nested: a directory rejected by any of
+    /// them is not descended into.
     ///
     /// [`skip_current_dir`]: #method.skip_current_dir
     /// [`min_depth`]: struct.WalkDir.html#method.min_depth
     /// [`max_depth`]: struct.WalkDir.html#method.max_depth
-    pub fn filter_entry(self, predicate: P) -> ClassicFilterEntry<E, CP, Self, P> {
-        ClassicFilterEntry::<E, CP, _, _> { inner: self, predicate, _cp: std::marker::PhantomData }
+    pub fn filter_entry<Q>(self, predicate: Q) -> ClassicFilterEntry<E, CP, Self, Q>
+    where
+        Q: FnMut(&CP::Item) -> bool,
+    {
+        ClassicFilterEntry::<E, CP, _, _> { inner: self, predicate, poisoned: false, _cp: core::marker::PhantomData }
     }
 
     /// Skips the current directory.
@@ -322,4 +414,71 @@ where
     fn skip_current_dir(&mut self) {
         self.inner.skip_current_dir();
     }
+
+    fn skip_siblings(&mut self) {
+        self.inner.skip_siblings();
+    }
+
+    fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////
+
+/// A recursive directory iterator that rewrites the root of yielded paths.
+///
+/// Values of this type are created by calling [`.map_root()`] on a classic
+/// iterator.
+///
+/// [`.map_root()`]: trait.ClassicWalkDirIter.html#method.map_root
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct MapRoot<E, CP, I>
+where
+    E: fs::FsDirEntry<Path = std::path::Path, PathBuf = std::path::PathBuf>,
+    CP: ContentProcessor<E, Item = DirEntry<E>>,
+    I: Iterator<Item = wd::Result<CP::Item, E>> + ClassicWalkDirIter<E, CP>,
+{
+    inner: I,
+    prefix: std::path::PathBuf,
+    _cp: core::marker::PhantomData<CP>,
+}
+
+#[cfg(feature = "std")]
+impl<E, CP, I> Iterator for MapRoot<E, CP, I>
+where
+    E: fs::FsDirEntry<Path = std::path::Path, PathBuf = std::path::PathBuf>,
+    CP: ContentProcessor<E, Item = DirEntry<E>>,
+    I: Iterator<Item = wd::Result<CP::Item, E>> + ClassicWalkDirIter<E, CP>,
+{
+    type Item = wd::Result<CP::Item, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut item = self.inner.next()?;
+        if let Ok(ref mut dent) = item {
+            dent.map_root(&self.prefix);
+        }
+        Some(item)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E, CP, I> ClassicWalkDirIter<E, CP> for MapRoot<E, CP, I>
+where
+    E: fs::FsDirEntry<Path = std::path::Path, PathBuf = std::path::PathBuf>,
+    CP: ContentProcessor<E, Item = DirEntry<E>>,
+    I: Iterator<Item = wd::Result<CP::Item, E>> + ClassicWalkDirIter<E, CP>,
+{
+    fn skip_current_dir(&mut self) {
+        self.inner.skip_current_dir();
+    }
+
+    fn skip_siblings(&mut self) {
+        self.inner.skip_siblings();
+    }
+
+    fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
 }
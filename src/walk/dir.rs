@@ -1,8 +1,15 @@
-use std::cmp::Ordering;
-use std::vec;
-
-use crate::wd::{self, ContentFilter, ContentOrder, Depth, FnCmp, IntoOk, Position};
-use crate::fs;
+use core::any::Any;
+use core::cmp::Ordering;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::wd::{self, ContentFilter, ContentOrder, Depth, DirSummary, FnCmp, FnNormalize, FnProcessReadDir, IntoOk, Pass, Position};
+use crate::fs::{self, FsPath};
+use crate::unorm;
 use crate::walk::rawdent::{RawDirEntry, ReadDir};
 use crate::cp::ContentProcessor;
 use crate::walk::opts::WalkDirOptionsImmut;
@@ -11,6 +18,10 @@ use crate::error::{ErrorInner, Error};
 /////////////////////////////////////////////////////////////////////////
 ////
 
+/// An entry after following symlinks and checks of the walker, before it reaches
+/// filters and the content processor (see [`EntryMiddleware`])
+///
+/// [`EntryMiddleware`]: trait.EntryMiddleware.html
 #[derive(Debug)]
 pub struct FlatDirEntry<E: fs::FsDirEntry> {
     /// Raw DirEntry
@@ -21,8 +32,32 @@ pub struct FlatDirEntry<E: fs::FsDirEntry> {
     /// - Some(index) => is loop to ancestor[index]
     /// - None => is not loop link
     pub loop_link: Option<Depth>,
+    /// This entry is a dir which will not be walked since it wasn't sampled.
+    pub sampled_out: bool,
+    /// Name of this entry is equal to a sibling's one under Unicode normalization.
+    pub unicode_collision: bool,
+    /// This entry is a dir on another device than its parent.
+    pub mount_point: bool,
+    /// This entry doesn't pass the xattr filter, so it will not be yielded.
+    pub xattr_hidden: bool,
+    /// Pass of the content where this entry is yielded (according to opts.content_order).
+    pub pass: Pass,
+}
+
+/// A step of the entry pipeline (see `WalkDir::with_middleware`)
+pub trait EntryMiddleware<E: fs::FsDirEntry>: Send + Sync {
+    /// Inspect or rewrite an entry met at `depth`, or suppress it by returning `None`
+    fn process(
+        &self,
+        flat: FlatDirEntry<E>,
+        depth: Depth,
+        ctx: &mut E::Context,
+    ) -> Option<FlatDirEntry<E>>;
 }
 
+/// A list of middleware, applied in order
+pub type Middleware<E> = Vec<Arc<dyn EntryMiddleware<E>>>;
+
 /////////////////////////////////////////////////////////////////////////
 //// DirEntryRecord
 
@@ -30,10 +65,14 @@ pub struct FlatDirEntry<E: fs::FsDirEntry> {
 pub(crate) struct DirEntryRecord<E: fs::FsDirEntry> {
     /// Value from ReadDir
     flat: wd::ResultInner<FlatDirEntry<E>, E>,
-    /// This entry must be yielded first according to opts.content_order
-    first_pass: bool,
+    /// Pass of the content where this entry is yielded according to opts.content_order
+    pass: Pass,
     /// This entry will not be yielded according to opts.content_filter
     hidden: bool,
+    /// This dir is yielded here, but its content is walked by a later record (see `globally_sorted`)
+    leaf: bool,
+    /// This record only walks the content of a dir yielded by an earlier record
+    descend_only: bool,
 }
 
 impl<E: fs::FsDirEntry> DirEntryRecord<E> {
@@ -55,26 +94,30 @@ impl<E: fs::FsDirEntry> DirEntryRecord<E> {
         };
 
         let this = match r_flat_dent {
-            Ok(flat) => {
-                let first_pass = match opts_immut.content_order {
-                    ContentOrder::None => false,
-                    ContentOrder::DirsFirst => flat.is_dir,
-                    ContentOrder::FilesFirst => !flat.is_dir,
-                };
+            Ok(flat) => Self::from_flat(flat, opts_immut),
+            Err(err) => Self {
+                flat: Err(err),
+                pass: Pass::SECOND,
+                hidden: false,
+                leaf: false,
+                descend_only: false,
+            },
+        };
 
-                let hidden = match opts_immut.content_filter {
-                    ContentFilter::None => false,
-                    ContentFilter::DirsOnly => !flat.is_dir,
-                    ContentFilter::FilesOnly => flat.is_dir,
-                    ContentFilter::SkipAll => true,
-                };
+        Some(this)
+    }
 
-                Self { flat: Ok(flat), first_pass, hidden }
-            }
-            Err(err) => Self { flat: Err(err), first_pass: false, hidden: false },
+    fn from_flat(flat: FlatDirEntry<E>, opts_immut: &WalkDirOptionsImmut) -> Self {
+        let pass = flat.pass;
+
+        let hidden = flat.xattr_hidden || match opts_immut.content_filter {
+            ContentFilter::None => false,
+            ContentFilter::DirsOnly => !flat.is_dir,
+            ContentFilter::FilesOnly => flat.is_dir,
+            ContentFilter::SkipAll => true,
         };
 
-        Some(this)
+        Self { flat: Ok(flat), pass, hidden, leaf: false, descend_only: false }
     }
 
     fn can_be_yielded(&self) -> bool {
@@ -90,6 +133,27 @@ impl<E: fs::FsDirEntry> DirEntryRecord<E> {
     }
 }
 
+/// Order of siblings by name bytes (records walking dir content compare as the
+/// name followed by `/`), errors first
+fn cmp_globally<E: fs::FsDirEntry>(a: &DirEntryRecord<E>, b: &DirEntryRecord<E>) -> Ordering {
+    match (&a.flat, &b.flat) {
+        (Ok(a_flat), Ok(b_flat)) => {
+            let (a_path, b_path) = (a_flat.raw.path(), b_flat.raw.path());
+            match (a_path.file_name_bytes(), b_path.file_name_bytes()) {
+                (Some(a_name), Some(b_name)) => {
+                    let a_key = a_name.iter().chain(a.descend_only.then_some(&b'/'));
+                    let b_key = b_name.iter().chain(b.descend_only.then_some(&b'/'));
+                    a_key.cmp(b_key)
+                },
+                _ => a_path.cmp(b_path).then(a.descend_only.cmp(&b.descend_only)),
+            }
+        },
+        (Err(_), Err(_)) => Ordering::Equal,
+        (Ok(_), Err(_)) => Ordering::Greater,
+        (Err(_), Ok(_)) => Ordering::Less,
+    }
+}
+
 /////////////////////////////////////////////////////////////////////////
 //// DirState
 
@@ -105,7 +169,7 @@ where
     content: Vec<DirEntryRecord<E>>,
     /// Count of consumed entries = position of unconsumed in content
     current_pos: Option<usize>,
-    _cp: std::marker::PhantomData<CP>,
+    _cp: core::marker::PhantomData<CP>,
 }
 
 impl<E, CP> DirContent<E, CP>
@@ -121,21 +185,23 @@ where
             rd: ReadDir::<E>::new_once(raw)?,
             content: vec![],
             current_pos: None,
-            _cp: std::marker::PhantomData,
+            _cp: core::marker::PhantomData,
         }
         .into_ok()
     }
 
-    /// New DirContent from FsReadDir
+    /// New DirContent from FsReadDir (records are stored into the given empty vector)
     pub fn new(
         parent: &RawDirEntry<E>, 
+        records: Vec<DirEntryRecord<E>>,
         ctx: &mut E::Context
     ) -> wd::ResultInner<Self, E> {
+        debug_assert!(records.is_empty());
         Self {
             rd: parent.read_dir(ctx)?,
-            content: vec![],
+            content: records,
             current_pos: None,
-            _cp: std::marker::PhantomData,
+            _cp: core::marker::PhantomData,
         }
         .into_ok()
     }
@@ -190,13 +256,13 @@ where
             &mut E::Context,
         ) -> Option<wd::ResultInner<FlatDirEntry<E>, E>>),
         ctx: &mut E::Context,
-    ) -> Option<(bool, bool)> {
+    ) -> Option<(Pass, bool)> {
         loop {
             // Check for already loaded entry
             let next_pos = if let Some(pos) = self.current_pos { pos + 1 } else { 0 };
             if let Some(rec) = self.content.get(next_pos) {
                 self.current_pos = Some(next_pos);
-                return Some((rec.first_pass, rec.can_be_yielded()));
+                return Some((rec.pass, rec.can_be_yielded()));
             }
 
             if let Some(r_rawdent) = self.rd.next(ctx) {
@@ -210,7 +276,7 @@ where
                 let last = self.content.last();
                 assert!(last.is_some());
                 let rec = last.unwrap();
-                return Some((rec.first_pass, rec.can_be_yielded()));
+                return Some((rec.pass, rec.can_be_yielded()));
             }
 
             break;
@@ -219,22 +285,41 @@ where
         None
     }
 
+    /// Drop all loaded records and close the handle: no more records remain.
+    pub fn discard(&mut self) {
+        self.rd = ReadDir::<E>::Closed;
+        self.content.clear();
+        self.current_pos = None;
+    }
+
+    /// Take the records vector (emptied, but keeping its capacity) for reuse.
+    pub fn into_records(self) -> Vec<DirEntryRecord<E>> {
+        let mut content = self.content;
+        content.clear();
+        content
+    }
+
     /// Rewind current position: now we stand before beginning.
     pub fn rewind(&mut self) {
         self.current_pos = None;
     }
 
+    /// The nearest pass after `pass` having loaded records.
+    fn next_pass(&self, pass: Pass) -> Option<Pass> {
+        self.content.iter().map(|rec| rec.pass).filter(|&p| p > pass).min()
+    }
+
     /// Gets record at current position
     /// Doesn't change position.
     pub fn get_current_rec(
         &mut self,
         depth: Depth,
-    ) -> std::result::Result<FlatDirEntryRef<'_, E, CP>, ErrorInnerRef<'_, E>> {
+    ) -> core::result::Result<FlatDirEntryRef<'_, E, CP>, ErrorInnerRef<'_, E>> {
         let pos = self.current_pos.unwrap();
         let rec = self.content.get_mut(pos).unwrap();
 
         match rec.flat {
-            Ok(ref mut flat) => Ok(FlatDirEntryRef::<E, CP>::new(flat, depth, rec.hidden)),
+            Ok(ref mut flat) => Ok(FlatDirEntryRef::<E, CP>::new(flat, depth, rec.hidden, rec.leaf, rec.descend_only)),
             Err(ref mut err) => Err(ErrorInnerRef::<E>::new(err, depth)),
         }
     }
@@ -243,11 +328,23 @@ where
     /// Changes current position.
     fn sort_content_and_rewind(
         &mut self, 
-        cmp: &mut FnCmp<E>, 
+        mut cmp: Option<&mut FnCmp<E>>, 
+        by_name: bool,
         ctx: &mut E::Context,
     ) {
         self.content.sort_by(|a, b| match (&a.flat, &b.flat) {
-            (&Ok(ref a), &Ok(ref b)) => RawDirEntry::call_cmp(&a.raw, &b.raw, cmp, ctx),
+            (&Ok(ref a), &Ok(ref b)) => {
+                let ord = match cmp {
+                    Some(ref mut cmp) => RawDirEntry::call_cmp(&a.raw, &b.raw, cmp, ctx),
+                    None => Ordering::Equal,
+                };
+                if by_name {
+                    // Siblings share the parent, so comparing paths compares names
+                    ord.then_with(|| a.raw.path().cmp(b.raw.path()))
+                } else {
+                    ord
+                }
+            },
             (&Err(_), &Err(_)) => Ordering::Equal,
             (&Ok(_), &Err(_)) => Ordering::Greater,
             (&Err(_), &Ok(_)) => Ordering::Less,
@@ -255,12 +352,72 @@ where
         self.current_pos = None;
     }
 
+    /// Sorts all loaded content by name bytes, so that the walk yields paths in
+    /// lexicographic order. A dir having siblings which sort between it and its
+    /// content (e.g. `a` and `a.txt`) is split into a record yielding it at its
+    /// name and a record walking its content at its name followed by `/`.
+    /// Changes current position.
+    fn sort_globally_and_rewind(
+        &mut self,
+        split_dirs: bool,
+        process_rawdent: &mut impl FnMut(
+            RawDirEntry<E>,
+            &mut E::Context,
+        ) -> Option<wd::ResultInner<FlatDirEntry<E>, E>>,
+        ctx: &mut E::Context,
+    ) {
+        self.content.sort_by(cmp_globally);
+
+        if split_dirs {
+            let mut descents = vec![];
+            for index in 0..self.content.len() {
+                let (rec, next) = match self.content.get(index..index + 2) {
+                    Some([rec, next]) => (rec, next),
+                    _ => break,
+                };
+                let (flat, next_flat) = match (&rec.flat, &next.flat) {
+                    (Ok(flat), Ok(next_flat)) if flat.is_dir => (flat, next_flat),
+                    _ => continue,
+                };
+                let (name, next_name) = match (flat.raw.path().file_name_bytes(), next_flat.raw.path().file_name_bytes()) {
+                    (Some(name), Some(next_name)) => (name, next_name),
+                    _ => continue,
+                };
+                if !next_name.starts_with(name) || next_name[name.len()] >= b'/' {
+                    continue;
+                }
+
+                let raw = match RawDirEntry::<E>::from_path(flat.raw.path(), ctx) {
+                    Ok(raw) => raw.with_origin(flat.raw.origin().clone()).with_device(flat.raw.root_device()),
+                    Err(_) => continue,
+                };
+                if let Some(Ok(descent)) = process_rawdent(raw, ctx) {
+                    let pass = descent.pass;
+                    descents.push(DirEntryRecord {
+                        flat: Ok(descent),
+                        pass,
+                        hidden: true,
+                        leaf: false,
+                        descend_only: true,
+                    });
+                    self.content[index].leaf = true;
+                }
+            }
+            if !descents.is_empty() {
+                self.content.append(&mut descents);
+                self.content.sort_by(cmp_globally);
+            }
+        }
+
+        self.current_pos = None;
+    }
+
     /// Sorts all loaded content.
     /// Changes current position.
     pub fn load_all_and_sort(
         &mut self,
         opts_immut: &WalkDirOptionsImmut,
-        cmp: &mut FnCmp<E>,
+        cmp: Option<&mut FnCmp<E>>,
         process_rawdent: &mut impl (FnMut(
             RawDirEntry<E>,
             &mut E::Context,
@@ -268,13 +425,77 @@ where
         ctx: &mut E::Context,
     ) {
         self.load_all(opts_immut, process_rawdent, ctx);
-        self.sort_content_and_rewind(cmp, ctx);
+        if opts_immut.globally_sorted {
+            // Dirs yielded after their content are already in place
+            let split_dirs =
+                !(opts_immut.contents_first || opts_immut.post_order || opts_immut.announce_dirs);
+            self.sort_globally_and_rewind(split_dirs, process_rawdent, ctx);
+        } else {
+            self.sort_content_and_rewind(cmp, opts_immut.deterministic, ctx);
+        }
+    }
+
+    /// Passes all entries to `f` (see `WalkDir::process_read_dir`) and rebuilds
+    /// the content from the entries it kept, errors first.
+    /// Changes current position.
+    pub fn process_read_dir(
+        &mut self,
+        f: &FnProcessReadDir<E>,
+        depth: Depth,
+        path: &E::Path,
+        opts_immut: &WalkDirOptionsImmut,
+    ) {
+        let mut flats = Vec::with_capacity(self.content.len());
+        let mut content = Vec::with_capacity(self.content.len());
+        for rec in self.content.drain(..) {
+            match rec.flat {
+                Ok(flat) => flats.push(flat),
+                Err(_) => content.push(rec),
+            }
+        }
+
+        f(depth, path, &mut flats);
+
+        content.extend(flats.into_iter().map(|flat| DirEntryRecord::from_flat(flat, opts_immut)));
+        self.content = content;
+        self.current_pos = None;
+    }
+
+    /// Mark all loaded entries whose names are equal under Unicode normalization.
+    pub fn mark_unicode_collisions(&mut self) {
+        let mut by_key: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (index, rec) in self.content.iter().enumerate().filter(|(_, rec)| !rec.descend_only) {
+            if let Ok(ref flat) = rec.flat {
+                let name = match flat.raw.path().file_name_lossy() {
+                    Some(name) => name,
+                    None => continue,
+                };
+                // Don't mix up names which aren't Unicode
+                if !name.contains(char::REPLACEMENT_CHARACTER) {
+                    by_key.entry(unorm::nfd(&name).into_owned()).or_default().push(index);
+                }
+            }
+        }
+
+        for indices in by_key.values().filter(|indices| indices.len() > 1) {
+            for &index in indices {
+                if let Ok(ref mut flat) = self.content[index].flat {
+                    flat.unicode_collision = true;
+                }
+            }
+        }
     }
 
     // pub fn iter_content<'s, F, T: 's>(&'s self, f: F) -> impl Iterator<Item = &'s T> where F: FnMut(&DirEntryRecord<E>) -> Option<&T> {
     //     self.content.iter().filter_map( f )
     // }
 
+    /// Count of entries read so far
+    #[cfg(feature = "tracing")]
+    pub fn len(&self) -> usize {
+        self.content.len()
+    }
+
     pub fn iter_content_flats<'s, F, T: 's>(
         &'s mut self, 
         f: F
@@ -284,6 +505,7 @@ where
     {
         self.content
             .iter_mut()
+            .filter(|rec| !rec.descend_only)
             .filter_map(|rec: &mut DirEntryRecord<E>| rec.flat.as_mut().ok())
             .filter_map(f)
     }
@@ -301,7 +523,11 @@ where
     depth: Depth,
     /// This entry will not be yielded according to opts.content_filter
     hidden: bool,
-    _cp: std::marker::PhantomData<CP>,
+    /// Content of this dir is walked by a later record
+    leaf: bool,
+    /// This record only walks the content of a dir yielded earlier
+    descend_only: bool,
+    _cp: core::marker::PhantomData<CP>,
 }
 
 impl<'r, E, CP> FlatDirEntryRef<'r, E, CP>
@@ -309,16 +535,33 @@ where
     E: fs::FsDirEntry,
     CP: ContentProcessor<E>,
 {
-    fn new(flat: &'r mut FlatDirEntry<E>, depth: Depth, hidden: bool) -> Self {
-        Self { flat, depth, hidden, _cp: std::marker::PhantomData }
+    fn new(
+        flat: &'r mut FlatDirEntry<E>,
+        depth: Depth,
+        hidden: bool,
+        leaf: bool,
+        descend_only: bool,
+    ) -> Self {
+        Self { flat, depth, hidden, leaf, descend_only, _cp: core::marker::PhantomData }
     }
 
     pub fn make_content_item (
         &mut self,
         content_processor: &mut CP,
+        normalize: Option<FnNormalize<E>>,
         ctx: &mut E::Context,
     ) -> Option<CP::Item> {
-        self.flat.raw.make_content_item( content_processor, self.flat.is_dir, self.depth, ctx )
+        let mut item = self.flat.raw.make_content_item( content_processor, self.flat.is_dir, self.depth, normalize, ctx )?;
+        if self.flat.sampled_out {
+            CP::mark_sampled_out(&mut item);
+        }
+        if self.flat.unicode_collision {
+            CP::mark_unicode_collision(&mut item);
+        }
+        if self.flat.mount_point {
+            CP::mark_mount_point(&mut item);
+        }
+        Some(item)
     }
 
     pub fn as_flat(&self) -> &FlatDirEntry<E> {
@@ -337,10 +580,22 @@ where
         self.hidden
     }
 
+    pub fn leaf(&self) -> bool {
+        self.leaf
+    }
+
+    pub fn descend_only(&self) -> bool {
+        self.descend_only
+    }
+
     pub fn loop_link(&self) -> Option<Depth> {
         self.flat.loop_link
     }
 
+    pub fn set_sampled_out(&mut self) {
+        self.flat.sampled_out = true;
+    }
+
     pub fn path(&self) -> &E::Path {
         self.flat.raw.path()
     }
@@ -364,21 +619,51 @@ impl<'r, E: fs::FsDirEntry> ErrorInnerRef<'r, E> {
     }
 }
 
+/// A tracing span living as long as the dir is walked
+#[cfg(feature = "tracing")]
+#[derive(Debug)]
+struct DirTrace {
+    span: tracing::Span,
+    started: std::time::Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl DirTrace {
+    fn enter<E: fs::FsDirEntry>(parent: &RawDirEntry<E>, depth: Depth) -> Self {
+        use crate::fs::FsPathBuf;
+
+        let path = parent.pathbuf();
+        let span = tracing::debug_span!(
+            "walkdir::dir",
+            path = %path.display(),
+            depth,
+            entries = tracing::field::Empty,
+        );
+        span.in_scope(|| tracing::trace!("enter dir"));
+        Self { span, started: std::time::Instant::now() }
+    }
+
+    fn leave(&self, entries: usize) {
+        self.span.record("entries", entries);
+        let elapsed_us = self.started.elapsed().as_micros() as u64;
+        self.span.in_scope(|| tracing::debug!(entries, elapsed_us, "leave dir"));
+    }
+}
+
 /////////////////////////////////////////////////////////////////////////
 //// DirState
 
 #[derive(Debug, PartialEq, Eq)]
 enum DirPass {
     Entire,
-    First,
-    Second,
+    Only(Pass),
 }
 
 fn get_initial_pass(opts_immut: &WalkDirOptionsImmut) -> DirPass {
     if opts_immut.content_order == ContentOrder::None {
         DirPass::Entire
     } else {
-        DirPass::First
+        DirPass::Only(Pass::FIRST)
     }
 }
 
@@ -396,9 +681,18 @@ where
     pass: DirPass,
     /// Current position
     position: Position<(), (), ()>,
+    /// Count of entry errors not yielded (with `summarize_entry_errors`)
+    entry_errors: usize,
+    /// What was yielded from this dir so far
+    summary: DirSummary,
+    /// Value attached by the consumer (see `WalkDirIterator::push_annotation`)
+    annotation: Option<Box<dyn Any + Send>>,
+    /// Tracing span of this dir (not for root)
+    #[cfg(feature = "tracing")]
+    trace: Option<DirTrace>,
 
     /// Stub
-    _cp: std::marker::PhantomData<CP>,
+    _cp: core::marker::PhantomData<CP>,
 }
 
 impl<E, CP> DirState<E, CP>
@@ -410,14 +704,33 @@ where
         &mut self,
         opts_immut: &WalkDirOptionsImmut,
         sorter: &mut Option<FnCmp<E>>,
+        read_dir: Option<(&FnProcessReadDir<E>, &E::Path)>,
         process_rawdent: &mut impl (FnMut(
             RawDirEntry<E>,
             &mut E::Context,
         ) -> Option<wd::ResultInner<FlatDirEntry<E>, E>>),
         ctx: &mut E::Context,
     ) {
-        if let Some(cmp) = sorter {
-            self.content.load_all_and_sort(opts_immut, cmp, process_rawdent, ctx);
+        let sorted = opts_immut.sorting
+            && (sorter.is_some() || opts_immut.deterministic || opts_immut.globally_sorted);
+        // The global order is kept whatever `f` does, so it's applied afterwards
+        let (read_dir_before, read_dir_after) = match read_dir {
+            Some(read_dir) if sorted && !opts_immut.globally_sorted => (None, Some(read_dir)),
+            read_dir => (read_dir, None),
+        };
+        if let Some((f, path)) = read_dir_before {
+            self.content.load_all(opts_immut, process_rawdent, ctx);
+            self.content.process_read_dir(f, self.depth - 1, path, opts_immut);
+        }
+        if sorted {
+            self.content.load_all_and_sort(opts_immut, sorter.as_mut(), process_rawdent, ctx);
+        }
+        if let Some((f, path)) = read_dir_after {
+            self.content.process_read_dir(f, self.depth - 1, path, opts_immut);
+        }
+        if opts_immut.detect_unicode_collisions {
+            self.content.load_all(opts_immut, process_rawdent, ctx);
+            self.content.mark_unicode_collisions();
         }
     }
 
@@ -438,18 +751,26 @@ where
             content: DirContent::<E, CP>::new_once(raw)?,
             pass: get_initial_pass(opts_immut),
             position: Position::BeforeContent(()),
-            _cp: std::marker::PhantomData,
+            entry_errors: 0,
+            summary: DirSummary::default(),
+            annotation: None,
+            #[cfg(feature = "tracing")]
+            trace: None,
+            _cp: core::marker::PhantomData,
         };
-        this.init(opts_immut, sorter, process_rawdent, ctx);
+        this.init(opts_immut, sorter, None, process_rawdent, ctx);
         this.into_ok()
     }
 
     /// New DirState from FsReadDir
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         parent: &RawDirEntry<E>,
         depth: Depth,
         opts_immut: &WalkDirOptionsImmut,
         sorter: &mut Option<FnCmp<E>>,
+        process_read_dir: Option<&FnProcessReadDir<E>>,
+        records: Vec<DirEntryRecord<E>>,
         process_rawdent: &mut impl (FnMut(
             RawDirEntry<E>,
             &mut E::Context,
@@ -458,12 +779,18 @@ where
     ) -> wd::ResultInner<Self, E> {
         let mut this = Self {
             depth,
-            content: DirContent::<E, CP>::new(parent, ctx)?,
+            content: DirContent::<E, CP>::new(parent, records, ctx)?,
             pass: get_initial_pass(opts_immut),
             position: Position::BeforeContent(()),
-            _cp: std::marker::PhantomData,
+            entry_errors: 0,
+            summary: DirSummary::default(),
+            annotation: None,
+            #[cfg(feature = "tracing")]
+            trace: Some(DirTrace::enter(parent, depth)),
+            _cp: core::marker::PhantomData,
         };
-        this.init(opts_immut, sorter, process_rawdent, ctx);
+        let read_dir = process_read_dir.map(|f| (f, parent.path()));
+        this.init(opts_immut, sorter, read_dir, process_rawdent, ctx);
         this.into_ok()
     }
 
@@ -493,13 +820,12 @@ where
         ctx: &mut E::Context,
     ) -> bool {
         loop {
-            if let Some((first_pass, can_be_yielded)) =
+            if let Some((pass, can_be_yielded)) =
                 self.content.get_next_rec(opts_immut, process_rawdent, ctx)
             {
                 let valid_pass = match self.pass {
                     DirPass::Entire => true,
-                    DirPass::First => first_pass,
-                    DirPass::Second => !first_pass,
+                    DirPass::Only(cur) => pass == cur,
                 };
 
                 if valid_pass && can_be_yielded {
@@ -509,17 +835,18 @@ where
                 continue;
             };
 
+            // After the first pass the entire dir is loaded, so all passes are known
             match self.pass {
-                DirPass::Entire | DirPass::Second => {
-                    self.position = Position::AfterContent;
-                    return false;
-                }
-                DirPass::First => {
-                    self.pass = DirPass::Second;
+                DirPass::Only(cur) => if let Some(next) = self.content.next_pass(cur) {
+                    self.pass = DirPass::Only(next);
                     self.content.rewind();
                     continue;
-                }
+                },
+                DirPass::Entire => {}
             };
+
+            self.position = Position::AfterContent { summary: DirSummary::default() };
+            return false;
         }
     }
 
@@ -534,7 +861,7 @@ where
         ) -> Option<wd::ResultInner<FlatDirEntry<E>, E>>),
         ctx: &mut E::Context,
     ) {
-        if self.position == Position::AfterContent {
+        if matches!(self.position, Position::AfterContent { .. }) {
             return;
         };
 
@@ -542,7 +869,7 @@ where
             // Remember: at this state current rec must exist
             self.position = Position::Entry(());
         } else {
-            self.position = Position::AfterContent;
+            self.position = Position::AfterContent { summary: DirSummary::default() };
         };
     }
 
@@ -560,24 +887,26 @@ where
                     Err(err) => Position::Error(err),
                 }
             }
-            Position::AfterContent => Position::AfterContent,
+            Position::AfterContent { .. } => Position::AfterContent { summary: self.summary },
             _ => unreachable!(),
         }
     }
 
     /// Gets copy of entire dir, loading all remaining content if necessary (not considering content order).
+    /// Items are made by `content_processor`, which isn't necessarily the one of the walk.
     /// Doesn't change position.
-    pub fn clone_all_content(
+    pub fn clone_all_content<P: ContentProcessor<E>>(
         &mut self,
         filter: ContentFilter,
         opts_immut: &WalkDirOptionsImmut,
-        content_processor: &CP,
+        content_processor: &P,
+        normalize: Option<FnNormalize<E>>,
         process_rawdent: &mut impl (FnMut(
             RawDirEntry<E>,
             &mut E::Context,
         ) -> Option<wd::ResultInner<FlatDirEntry<E>, E>>),
         ctx: &mut E::Context,
-    ) -> CP::Collection {
+    ) -> P::Collection {
         self.content.load_all(opts_immut, process_rawdent, ctx);
 
         let depth = self.depth();
@@ -587,24 +916,24 @@ where
                 let iter = self
                     .content
                     .iter_content_flats(|flat| Some(flat))
-                    .filter_map(|flat| flat.raw.make_content_item( content_processor, flat.is_dir, depth, ctx ));
+                    .filter_map(|flat| flat.raw.make_content_item( content_processor, flat.is_dir, depth, normalize, ctx ));
                 content_processor.collect(iter)
             }
             ContentFilter::DirsOnly => {
                 let iter = self
                     .content
                     .iter_content_flats(|flat| if flat.is_dir { Some(flat) } else { None })
-                    .filter_map(|flat| flat.raw.make_content_item( content_processor, flat.is_dir, depth, ctx ));
+                    .filter_map(|flat| flat.raw.make_content_item( content_processor, flat.is_dir, depth, normalize, ctx ));
                 content_processor.collect(iter)
             }
             ContentFilter::FilesOnly => {
                 let iter = self
                     .content
                     .iter_content_flats(|flat| if !flat.is_dir { Some(flat) } else { None })
-                    .filter_map(|flat| flat.raw.make_content_item( content_processor, flat.is_dir, depth, ctx ));
+                    .filter_map(|flat| flat.raw.make_content_item( content_processor, flat.is_dir, depth, normalize, ctx ));
                 content_processor.collect(iter)
             }
-            ContentFilter::SkipAll => CP::empty_collection(),
+            ContentFilter::SkipAll => P::empty_collection(),
         }
     }
 
@@ -612,7 +941,60 @@ where
         self.depth
     }
 
+    /// Count an entry error instead of yielding it.
+    pub fn count_entry_error(&mut self) {
+        self.entry_errors += 1;
+    }
+
+    /// Take the count of entry errors, resetting it.
+    pub fn take_entry_errors(&mut self) -> usize {
+        core::mem::take(&mut self.entry_errors)
+    }
+
+    /// Count a yielded entry of this dir.
+    pub fn count_yielded_entry(&mut self, is_dir: bool) {
+        self.summary.entries += 1;
+        if is_dir {
+            self.summary.dirs += 1;
+        }
+    }
+
+    /// Count a yielded error about an entry of this dir.
+    pub fn count_yielded_error(&mut self) {
+        self.summary.errors += 1;
+    }
+
+    /// Attach a value to this dir, replacing the attached one.
+    pub fn set_annotation(&mut self, value: Box<dyn Any + Send>) {
+        self.annotation = Some(value);
+    }
+
+    /// Get the value attached to this dir.
+    pub fn annotation_mut(&mut self) -> Option<&mut (dyn Any + Send)> {
+        self.annotation.as_deref_mut()
+    }
+
+    /// Take the value attached to this dir.
+    pub fn take_annotation(&mut self) -> Option<Box<dyn Any + Send>> {
+        self.annotation.take()
+    }
+
+    /// Take the records vector of this dir (see `DirContent::into_records`).
+    pub fn into_records(self) -> Vec<DirEntryRecord<E>> {
+        self.content.into_records()
+    }
+
+    /// Skip all remaining content, discarding loaded records and closing the handle.
     pub fn skip_all(&mut self) {
-        self.position = Position::AfterContent;
+        self.content.discard();
+        self.position = Position::AfterContent { summary: DirSummary::default() };
+    }
+
+    /// Report leaving this dir (with count of read entries) to tracing
+    #[cfg(feature = "tracing")]
+    pub fn trace_leave(&self) {
+        if let Some(ref trace) = self.trace {
+            trace.leave(self.content.len());
+        }
     }
 }
@@ -54,7 +54,8 @@ where
     /// Note that if the iterator has `contents_first` enabled, then this
     /// method is no different than calling the standard `Iterator::filter`
     /// method (because directory entries are yielded after they've been
-    /// descended into).
+    /// descended into). Filters may be nested: a directory rejected by any of
+    /// them is not descended into.
     ///
     /// [`skip_current_dir`]: #method.skip_current_dir
     /// [`min_depth`]: struct.WalkDir.html#method.min_depth
@@ -63,16 +64,48 @@ where
     where
         P: FnMut(&CP::Item) -> bool,
     {
-        FilterEntry { inner: self, predicate, _cp: std::marker::PhantomData }
+        FilterEntry { inner: self, predicate, poisoned: false, _cp: core::marker::PhantomData }
     }
 
     /// WalkDirIter
     fn skip_current_dir(&mut self);
 
+    /// Skip the remaining entries of the current dir (see `WalkDirIterator::skip_siblings`)
+    fn skip_siblings(&mut self);
+
+    /// Returns `true` if the walk is over (see `WalkDirIterator::is_finished`)
+    fn is_finished(&self) -> bool;
+
     /// WalkDirIter
     fn into_classic(self) -> ClassicIter<E, CP, Self> {
         ClassicIter::<E, CP, Self>::new(self)
     }
+
+    /// Returns the iterator itself, checking at compile time that it can be
+    /// moved to another thread.
+    ///
+    /// Iterators over the standard backends are `Send` (and so are yielded
+    /// entries and errors), so a partially consumed iterator may be handed
+    /// off to another thread which continues the walk where it stopped. A
+    /// custom backend, content processor or closure which isn't `Send` makes
+    /// the call fail to compile instead of the later `thread::spawn`.
+    ///
+    /// ```no_run
+    /// use std::thread;
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder, WalkDirIter, ClassicWalkDirIter};
+    ///
+    /// let mut it = WalkDirBuilder::<DefaultDirEntry>::new("foo").into_iter();
+    /// let first = it.next();
+    ///
+    /// let it = it.into_sendable();
+    /// let rest = thread::spawn(move || it.count()).join().unwrap();
+    /// ```
+    fn into_sendable(self) -> Self
+    where
+        Self: Send,
+    {
+        self
+    }
 }
 
 impl<E, CP> WalkDirIter<E, CP> for WalkDirIterator<E, CP>
@@ -83,6 +116,14 @@ where
     fn skip_current_dir(&mut self) {
         WalkDirIterator::<E, CP>::skip_current_dir(self);
     }
+
+    fn skip_siblings(&mut self) {
+        WalkDirIterator::<E, CP>::skip_siblings(self);
+    }
+
+    fn is_finished(&self) -> bool {
+        WalkDirIterator::<E, CP>::is_finished(self)
+    }
 }
 
 /////////////////////////////////////////////////////////////////////////
@@ -119,7 +160,9 @@ where
 {
     inner: I,
     predicate: P,
-    _cp: std::marker::PhantomData<CP>,
+    /// The predicate panicked, the walk is over
+    poisoned: bool,
+    _cp: core::marker::PhantomData<CP>,
 }
 
 impl<E, CP, I, P> Iterator for FilterEntry<E, CP, I, P>
@@ -138,6 +181,9 @@ where
     /// If the iterator fails to retrieve the next value, this method returns
     /// an error value. The error will be wrapped in an `Option::Some`.
     fn next(&mut self) -> Option<Self::Item> {
+        if self.poisoned {
+            return None;
+        }
         loop {
             let item = match self.inner.next() {
                 Some(item) => item,
@@ -145,7 +191,11 @@ where
             };
 
             if let Position::Entry(ref dent) = item {
-                if !(self.predicate)(dent) {
+                // Stays set if the predicate panics
+                self.poisoned = true;
+                let keep = (self.predicate)(dent);
+                self.poisoned = false;
+                if !keep {
                     if CP::is_dir(dent) {
                         self.inner.skip_current_dir();
                     }
@@ -206,13 +256,17 @@ where
     /// Note that if the iterator has `contents_first` enabled, then this
     /// method is no different than calling the standard `Iterator::filter`
     /// method (because directory entries are yielded after they've been
-    /// descended into).
+    /// descended into). Filters may be nested: a directory rejected by any of
+    /// them is not descended into.
     ///
     /// [`skip_current_dir`]: #method.skip_current_dir
     /// [`min_depth`]: struct.WalkDir.html#method.min_depth
     /// [`max_depth`]: struct.WalkDir.html#method.max_depth
-    pub fn filter_entry(self, predicate: P) -> FilterEntry<E, CP, Self, P> {
-        FilterEntry { inner: self, predicate, _cp: std::marker::PhantomData }
+    pub fn filter_entry<Q>(self, predicate: Q) -> FilterEntry<E, CP, Self, Q>
+    where
+        Q: FnMut(&CP::Item) -> bool,
+    {
+        FilterEntry { inner: self, predicate, poisoned: false, _cp: core::marker::PhantomData }
     }
 
     /// Skips the current directory.
@@ -273,4 +327,12 @@ where
     fn skip_current_dir(&mut self) {
         self.inner.skip_current_dir();
     }
+
+    fn skip_siblings(&mut self) {
+        self.inner.skip_siblings();
+    }
+
+    fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
 }
@@ -0,0 +1,135 @@
+use core::cmp::Ordering;
+use core::fmt;
+use alloc::vec::Vec;
+
+use crate::cp::DirEntry;
+use crate::fs;
+use crate::wd;
+
+/////////////////////////////////////////////////////////////////////////
+
+/// One of the merged iterators with its next item
+struct Source<I: Iterator> {
+    iter: I,
+    /// The next item (always `Ok`), taken from `iter` but not yielded yet
+    head: Option<I::Item>,
+    /// `iter` is exhausted
+    done: bool,
+}
+
+/// An iterator merging several sorted iterators into one sorted stream.
+///
+/// Values of this type are created by [`merge`] and [`merge_by`].
+///
+/// [`merge`]: fn.merge.html
+/// [`merge_by`]: fn.merge_by.html
+pub struct Merge<I: Iterator, F> {
+    sources: Vec<Source<I>>,
+    cmp: F,
+}
+
+impl<I: Iterator, F> fmt::Debug for Merge<I, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Merge")
+            .field("sources", &self.sources.len())
+            .field("exhausted", &self.sources.iter().filter(|s| s.done && s.head.is_none()).count())
+            .finish()
+    }
+}
+
+impl<I, T, X, F> Iterator for Merge<I, F>
+where
+    I: Iterator<Item = Result<T, X>>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    type Item = Result<T, X>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Every source must have its next item to choose the least one;
+        // errors are yielded at once, as they have nothing to be ordered by
+        for source in self.sources.iter_mut().filter(|s| s.head.is_none() && !s.done) {
+            match source.iter.next() {
+                Some(Ok(item)) => source.head = Some(Ok(item)),
+                Some(Err(err)) => return Some(Err(err)),
+                None => source.done = true,
+            }
+        }
+
+        let cmp = &mut self.cmp;
+        let mut least: Option<(usize, &T)> = None;
+        for (index, source) in self.sources.iter().enumerate() {
+            let item = match source.head {
+                Some(Ok(ref item)) => item,
+                _ => continue,
+            };
+            // Of equal items, the one of the earlier source comes first
+            least = match least {
+                Some((_, least_item)) if cmp(item, least_item) != Ordering::Less => least,
+                _ => Some((index, item)),
+            };
+        }
+        let (index, _) = least?;
+        self.sources[index].head.take()
+    }
+}
+
+/// Merge walks sorted by path into one stream sorted by path.
+///
+/// Each of `iters` must yield entries in the order of their paths, which is
+/// the order of a walk with [`deterministic`] (the root first, then every dir
+/// followed by its content, names compared byte-wise). The result is in the
+/// same order, so several roots walked one by one, or shards of a tree walked
+/// in parallel, give one deterministic stream:
+///
+/// ```no_run
+/// use walkdir::{DefaultDirEntry, WalkDirBuilder};
+///
+/// let walks = ["src", "tests", "benches"].iter().map(|root| {
+///     WalkDirBuilder::<DefaultDirEntry>::new(root).deterministic(true).into_classic()
+/// });
+/// for entry in walkdir::merge(walks) {
+///     println!("{}", entry.unwrap().path().display());
+/// }
+/// ```
+///
+/// Of entries with equal paths, the one of the earlier iterator comes first.
+/// Errors have no path to be ordered by, so they are yielded as soon as any
+/// iterator yields them. Only the next entry of each iterator is kept in
+/// memory; choosing the least one takes time proportional to the count of
+/// iterators. For walks with [`globally_sorted`], which are ordered by whole
+/// paths compared byte-wise, use [`merge_by`] with the same order.
+///
+/// [`deterministic`]: struct.WalkDir.html#method.deterministic
+/// [`globally_sorted`]: struct.WalkDir.html#method.globally_sorted
+/// [`merge_by`]: fn.merge_by.html
+#[allow(clippy::type_complexity)]
+pub fn merge<E, I, II>(iters: II) -> Merge<I, fn(&DirEntry<E>, &DirEntry<E>) -> Ordering>
+where
+    E: fs::FsDirEntry,
+    I: Iterator<Item = wd::Result<DirEntry<E>, E>>,
+    II: IntoIterator<Item = I>,
+{
+    merge_by(iters, cmp_paths)
+}
+
+fn cmp_paths<E: fs::FsDirEntry>(a: &DirEntry<E>, b: &DirEntry<E>) -> Ordering {
+    a.path().cmp(b.path())
+}
+
+/// Merge iterators sorted by `cmp` into one stream sorted by `cmp`.
+///
+/// This is [`merge`] with a custom order, which must be the order of each of
+/// `iters`. Items may be of any type, e.g. the compact entries of
+/// [`SlimContentProcessor`].
+///
+/// [`merge`]: fn.merge.html
+/// [`SlimContentProcessor`]: struct.SlimContentProcessor.html
+pub fn merge_by<I, II, T, X, F>(iters: II, cmp: F) -> Merge<I, F>
+where
+    I: Iterator<Item = Result<T, X>>,
+    II: IntoIterator<Item = I>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let sources = iters.into_iter().map(|iter| Source { iter, head: None, done: false }).collect();
+    Merge { sources, cmp }
+}
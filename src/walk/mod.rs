@@ -4,9 +4,16 @@ mod dir;
 mod walk;
 mod iter;
 mod classic_iter;
+mod prio;
+mod merge;
 
 pub use rawdent::{RawDirEntry, ReadDir};
-pub use opts::{WalkDirBuilder, WalkDirOptions, WalkDirOptionsImmut};
+pub use dir::{EntryMiddleware, FlatDirEntry, Middleware};
+pub use opts::{FnOverride, WalkDirBuilder, WalkDirOptions, WalkDirOptionsImmut};
 pub use walk::{WalkDirIterator, WalkDirIteratorItem};
 pub use iter::{FilterEntry, WalkDirIter};
-pub use classic_iter::{ClassicFilterEntry, ClassicIter, ClassicWalkDirIter};
\ No newline at end of file
+pub use classic_iter::{ClassicFilterEntry, ClassicIter, ClassicWalkDirIter};
+#[cfg(feature = "std")]
+pub use classic_iter::MapRoot;
+pub use prio::PriorityIter;
+pub use merge::{merge, merge_by, Merge};
\ No newline at end of file
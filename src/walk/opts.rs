@@ -1,39 +1,124 @@
-//use std::cmp;
-use std::fmt;
-use std::result;
+//use core::cmp;
+use core::fmt;
+use core::result;
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 
 use crate::cp::{self, ContentProcessor};
 use crate::fs::{self, FsPath};
 //use crate::fs::FsPath;
-use crate::wd::{ContentFilter, ContentOrder, Depth, FnCmp};
+use crate::wd::{CachePolicy, CaseMatch, ContentFilter, ContentOrder, Depth, DirId, FnCmp, FnNormalize, FnPass, FnProcessReadDir, LinkKind, Pass, RootErrorPolicy, SpecialFilePolicy};
+use crate::walk::dir::{EntryMiddleware, FlatDirEntry, Middleware};
 use crate::walk::walk::{WalkDirIterator, WalkDirIteratorItem};
 use crate::walk::iter::{WalkDirIter};
 use crate::walk::classic_iter::ClassicIter;
+use crate::walk::prio::PriorityIter;
+#[cfg(feature = "notify")]
+use crate::watch::WatchDir;
 
 /////////////////////////////////////////////////////////////////////////
 //// WalkDirOptions
 
 /// Immutable options
+#[derive(Debug, Clone)]
 pub struct WalkDirOptionsImmut
 {
     /// Check for same filesystem
     pub same_file_system: bool,
+    /// Walk without `same_file_system` if the device of the root is unknown
+    pub same_file_system_soft: bool,
+    /// Yield Position::DeviceBoundary when descending into a dir on another device
+    pub device_boundaries: bool,
+    /// Mark dirs on another device than their parent as mount points
+    pub detect_mount_points: bool,
+    /// Don't descend into mount points
+    pub skip_mount_points: bool,
     /// Allow to follow symlinks
     pub follow_links: bool,
+    /// Kinds of links which are never followed
+    pub unfollowed_link_kinds: Vec<LinkKind>,
+    /// Follow links only to targets on the device of the root
+    pub follow_links_same_device: bool,
+    /// Follow the root if it's a symlink (even without follow_links)
+    pub follow_root_symlink: bool,
+    /// Stat the root with `metadata` instead of `symlink_metadata`
+    pub follow_root_metadata: bool,
+    /// What to do when a root fails
+    pub root_errors: RootErrorPolicy,
     /// Yield loop symlinks (without following them) -- otherwise it will be interpreted as errors
     pub yield_loop_links: bool,
+    /// Times a loop may be entered before it's treated as a loop
+    pub loop_revisits: usize,
     /// Max count of opened dirs
     pub max_open: usize,
+    /// Count of entries to fetch at once while reading a dir (a hint for the backend)
+    pub readdir_batch: Option<usize>,
+    /// Metadata queries kept by the backend for reuse
+    pub metadata_cache: CachePolicy,
+    /// Max estimated memory of dirs queued by a priority walk before it reads new ones depth first
+    pub bfs_memory_limit: Option<usize>,
     /// Minimal depth for yield
     pub min_depth: Depth,
     /// Maximal depth for yield
     pub max_depth: Depth,
     /// Yield content of dir (recursive) and then the dir -- othewise the dir will be yielded first before its content
     pub contents_first: bool,
+    /// Yield dirs both before and after their content
+    pub announce_dirs: bool,
+    /// Yield every dir strictly after all its descendants (overrides announce_dirs)
+    pub post_order: bool,
     /// Filter content yield (in Position::Entry(...))
     pub content_filter: ContentFilter,
     /// Control order of files and dirs
     pub content_order: ContentOrder,
+    /// Sort dir content by file name (byte-wise), also as tie-breaker for sorter
+    pub deterministic: bool,
+    /// Sort dir content at all (with sorter or deterministic); may be cleared for some depths
+    pub sorting: bool,
+    /// Order the whole output by path (byte-wise), not only entries of each dir
+    pub globally_sorted: bool,
+    /// Skip entries whose canonical path was already met (with their content)
+    pub dedupe_canonical: bool,
+    /// Skip roots (and subtrees) whose canonical path was met under a previous root
+    pub dedupe_overlapping_roots: bool,
+    /// Mark siblings whose names are equal under Unicode normalization
+    pub detect_unicode_collisions: bool,
+    /// Descend only into a fraction of dirs: Some((ratio, seed))
+    pub sample: Option<(f64, u64)>,
+    /// Name patterns of dirs which are yielded as leaves without descending
+    pub treat_as_files: Vec<String>,
+    /// How name patterns treat case of letters
+    pub match_case: CaseMatch,
+    /// Don't descend into dirs last modified before this time (since the Unix epoch)
+    pub dirs_unmodified_since: Option<core::time::Duration>,
+    /// Don't descend into dirs with these fingerprints
+    pub skipped_fingerprints: BTreeSet<DirId>,
+    /// Collect fingerprints of descended dirs
+    pub record_fingerprints: bool,
+    /// Report entries whose full path is longer than this as errors
+    pub max_path_len: Option<usize>,
+    /// Hide entries which have (`false`) or don't have (`true`) the given extended attribute
+    pub xattr_filter: Option<(String, bool)>,
+    /// Skip entries with the nodump flag
+    pub skip_nodump: bool,
+    /// Skip regular files with a NUL byte among the first 8 KiB
+    pub skip_binary_files: bool,
+    /// What to do with FIFOs, sockets and device nodes
+    pub special_files: SpecialFilePolicy,
+    /// Don't open or descend into cloud placeholders
+    pub avoid_hydration: bool,
+    /// Yield alternate data streams of files as their children
+    pub alternate_streams: bool,
+    /// Count errors of entries inside a dir and yield one error per dir instead of each
+    pub summarize_entry_errors: bool,
+    /// Yield Position::DirSkipped instead of Position::Error when a dir can't be read
+    pub report_skipped_dirs: bool,
+    /// Yield Position::Finished with a summary as the last item
+    pub report_finished: bool,
     /// Yield Position::BeforeContent((dir, Same(ItemsCollection))) -- otherwise Position::BeforeContent((dir, None)) will be yielded
     pub yield_before_content_with_content: bool,
 }
@@ -42,19 +127,59 @@ impl Default for WalkDirOptionsImmut {
     fn default() -> Self {
         Self {
             same_file_system: false,
+            same_file_system_soft: false,
+            device_boundaries: false,
+            detect_mount_points: false,
+            skip_mount_points: false,
             follow_links: false,
+            unfollowed_link_kinds: Vec::new(),
+            follow_links_same_device: false,
+            follow_root_symlink: true,
+            follow_root_metadata: false,
+            root_errors: RootErrorPolicy::Yield,
             yield_loop_links: false,
+            loop_revisits: 0,
             max_open: 10,
+            readdir_batch: None,
+            metadata_cache: CachePolicy::None,
+            bfs_memory_limit: None,
             min_depth: 0,
-            max_depth: ::std::usize::MAX,
+            max_depth: ::core::usize::MAX,
             contents_first: false,
+            announce_dirs: false,
+            post_order: false,
             content_filter: ContentFilter::None,
             content_order: ContentOrder::None,
+            deterministic: false,
+            sorting: true,
+            globally_sorted: false,
+            dedupe_canonical: false,
+            dedupe_overlapping_roots: false,
+            detect_unicode_collisions: false,
+            sample: None,
+            treat_as_files: Vec::new(),
+            match_case: CaseMatch::Sensitive,
+            dirs_unmodified_since: None,
+            skipped_fingerprints: BTreeSet::new(),
+            record_fingerprints: false,
+            max_path_len: None,
+            xattr_filter: None,
+            skip_nodump: false,
+            skip_binary_files: false,
+            special_files: SpecialFilePolicy::Yield,
+            avoid_hydration: false,
+            alternate_streams: false,
+            summarize_entry_errors: false,
+            report_skipped_dirs: false,
+            report_finished: false,
             yield_before_content_with_content: false,
         }
     }
 }
 
+/// An override of immutable options (see `WalkDir::override_at_depth`)
+pub type FnOverride = Arc<dyn Fn(&mut WalkDirOptionsImmut) + Send + Sync + 'static>;
+
 /// Options for WalkDir
 pub struct WalkDirOptions<E, CP>
 where
@@ -65,6 +190,16 @@ where
     pub immut: WalkDirOptionsImmut,
     /// Sorter object
     pub sorter: Option<FnCmp<E>>,
+    /// Pass chooser for ContentOrder::Custom
+    pub content_pass: Option<FnPass<E>>,
+    /// Processor of the content of every dir read
+    pub process_read_dir: Option<FnProcessReadDir<E>>,
+    /// Path normalization function
+    pub normalize: Option<FnNormalize<E>>,
+    /// Entry pipeline
+    pub middleware: Middleware<E>,
+    /// Overrides of immutable options for some depths (in the order they were given)
+    pub depth_overrides: Vec<(Depth, FnOverride)>,
     /// Content processor
     pub content_processor: CP,
     /// The fs context
@@ -81,6 +216,11 @@ where
         Self {
             immut: WalkDirOptionsImmut::default(),
             sorter: None,
+            content_pass: None,
+            process_read_dir: None,
+            normalize: None,
+            middleware: Vec::new(),
+            depth_overrides: Vec::new(),
             content_processor: CP::default(),
             ctx: E::Context::default(), 
         }
@@ -100,12 +240,63 @@ where
         Self {
             immut: WalkDirOptionsImmut::default(),
             sorter: None,
+            content_pass: None,
+            process_read_dir: None,
+            normalize: None,
+            middleware: Vec::new(),
+            depth_overrides: Vec::new(),
             content_processor,
             ctx, 
         }
     }
 }
 
+impl<E, CP> WalkDirOptions<E, CP>
+where
+    E: fs::FsDirEntry,
+    CP: ContentProcessor<E>,
+{
+    /// Copy of these options for one more walk: callbacks are shared, the fs
+    /// context is the given one
+    pub(crate) fn share(&self, ctx: E::Context) -> Self
+    where
+        CP: Clone,
+    {
+        Self {
+            immut: self.immut.clone(),
+            sorter: self.sorter.clone(),
+            content_pass: self.content_pass.clone(),
+            process_read_dir: self.process_read_dir.clone(),
+            normalize: self.normalize,
+            middleware: self.middleware.clone(),
+            depth_overrides: self.depth_overrides.clone(),
+            content_processor: self.content_processor.clone(),
+            ctx,
+        }
+    }
+
+    /// Immutable options with overrides applied, for each depth where they change
+    /// (ascending; depths above the first one use `immut` as is)
+    pub(crate) fn depth_levels(&self) -> Vec<(Depth, WalkDirOptionsImmut)> {
+        let mut overrides: Vec<_> = self.depth_overrides.iter().collect();
+        overrides.sort_by_key(|(depth, _)| *depth);
+
+        let mut levels: Vec<(Depth, WalkDirOptionsImmut)> = Vec::new();
+        for (depth, f) in overrides {
+            let mut opts = match levels.last() {
+                Some((_, last)) => last.clone(),
+                None => self.immut.clone(),
+            };
+            f(&mut opts);
+            match levels.last_mut() {
+                Some(last) if last.0 == *depth => last.1 = opts,
+                _ => levels.push((*depth, opts)),
+            }
+        }
+        levels
+    }
+}
+
 impl<E, CP> fmt::Debug for WalkDirOptions<E, CP>
 where
     E: fs::FsDirEntry,
@@ -118,21 +309,64 @@ where
         } else {
             "None"
         };
+        let normalize_str = if self.normalize.is_some() { "Some(...)" } else { "None" };
         f.debug_struct("WalkDirOptions")
             .field("same_file_system", &self.immut.same_file_system)
+            .field("same_file_system_soft", &self.immut.same_file_system_soft)
+            .field("device_boundaries", &self.immut.device_boundaries)
+            .field("detect_mount_points", &self.immut.detect_mount_points)
+            .field("skip_mount_points", &self.immut.skip_mount_points)
             .field("follow_links", &self.immut.follow_links)
+            .field("unfollowed_link_kinds", &self.immut.unfollowed_link_kinds)
+            .field("follow_links_same_device", &self.immut.follow_links_same_device)
+            .field("follow_root_symlink", &self.immut.follow_root_symlink)
+            .field("follow_root_metadata", &self.immut.follow_root_metadata)
+            .field("root_errors", &self.immut.root_errors)
             .field("yield_loop_links", &self.immut.yield_loop_links)
+            .field("loop_revisits", &self.immut.loop_revisits)
             .field("max_open", &self.immut.max_open)
+            .field("readdir_batch", &self.immut.readdir_batch)
+            .field("metadata_cache", &self.immut.metadata_cache)
+            .field("bfs_memory_limit", &self.immut.bfs_memory_limit)
             .field("min_depth", &self.immut.min_depth)
             .field("max_depth", &self.immut.max_depth)
             .field("contents_first", &self.immut.contents_first)
+            .field("announce_dirs", &self.immut.announce_dirs)
+            .field("post_order", &self.immut.post_order)
             .field("content_filter", &self.immut.content_filter)
             .field("content_order", &self.immut.content_order)
+            .field("deterministic", &self.immut.deterministic)
+            .field("sorting", &self.immut.sorting)
+            .field("globally_sorted", &self.immut.globally_sorted)
+            .field("dedupe_canonical", &self.immut.dedupe_canonical)
+            .field("dedupe_overlapping_roots", &self.immut.dedupe_overlapping_roots)
+            .field("detect_unicode_collisions", &self.immut.detect_unicode_collisions)
+            .field("sample", &self.immut.sample)
+            .field("treat_as_files", &self.immut.treat_as_files)
+            .field("match_case", &self.immut.match_case)
+            .field("dirs_unmodified_since", &self.immut.dirs_unmodified_since)
+            .field("skipped_fingerprints", &self.immut.skipped_fingerprints)
+            .field("record_fingerprints", &self.immut.record_fingerprints)
+            .field("max_path_len", &self.immut.max_path_len)
+            .field("xattr_filter", &self.immut.xattr_filter)
+            .field("skip_nodump", &self.immut.skip_nodump)
+            .field("skip_binary_files", &self.immut.skip_binary_files)
+            .field("special_files", &self.immut.special_files)
+            .field("avoid_hydration", &self.immut.avoid_hydration)
+            .field("alternate_streams", &self.immut.alternate_streams)
+            .field("summarize_entry_errors", &self.immut.summarize_entry_errors)
+            .field("report_skipped_dirs", &self.immut.report_skipped_dirs)
+            .field("report_finished", &self.immut.report_finished)
             .field(
                 "yield_before_content_with_content",
                 &self.immut.yield_before_content_with_content,
             )
             .field("sorter", &sorter_str)
+            .field("content_pass", &self.content_pass.is_some())
+            .field("process_read_dir", &self.process_read_dir.is_some())
+            .field("normalize", &normalize_str)
+            .field("middleware", &self.middleware.len())
+            .field("depth_overrides", &self.depth_overrides.iter().map(|(depth, _)| depth).collect::<Vec<_>>())
             .field("content_processor", &self.content_processor)
             .field("ctx", &self.ctx)
             .finish()
@@ -166,6 +400,11 @@ where
 /// of a `for` loop. You may need to call [`into_iter`] explicitly if you want
 /// to use iterator adapters such as [`filter_entry`].
 ///
+/// A reference to the builder implements [`IntoIterator`] too, so the same
+/// walker may be iterated many times (e.g. in tests and benchmarks) with
+/// `for entry in &walker`. Every such iterator shares the callbacks of the
+/// builder and gets a new default fs context.
+///
 /// Idiomatic use of this type should use method chaining to set desired
 /// options. For example, this only shows entries with a depth of `1`, `2` or
 /// `3` (relative to `foo`):
@@ -216,6 +455,7 @@ where
 ///
 /// Note that when following symbolic/soft links, loops are detected and an
 /// error is reported.
+#[cfg(feature = "std")]
 #[derive(Debug)]
 pub struct WalkDirBuilder<E = fs::DefaultDirEntry, CP = cp::DirEntryContentProcessor>
 where
@@ -224,6 +464,21 @@ where
 {
     opts: WalkDirOptions<E, CP>,
     root: E::PathBuf,
+    more_roots: Vec<E::PathBuf>,
+}
+
+/// A builder to create an iterator for recursively walking a directory (there is no
+/// default backend without `std`).
+#[cfg(not(feature = "std"))]
+#[derive(Debug)]
+pub struct WalkDirBuilder<E, CP = cp::DirEntryContentProcessor>
+where
+    E: fs::FsDirEntry,
+    CP: cp::ContentProcessor<E>,
+{
+    opts: WalkDirOptions<E, CP>,
+    root: E::PathBuf,
+    more_roots: Vec<E::PathBuf>,
 }
 
 impl<E, CP> WalkDirBuilder<E, CP>
@@ -246,6 +501,7 @@ where
         Self {
             opts: WalkDirOptions::<E, CP>::default(),
             root: root.as_ref().to_path_buf(),
+            more_roots: Vec::new(),
         }
     }
 
@@ -258,12 +514,58 @@ where
         Self {
             opts: WalkDirOptions::with_context( ctx, content_processor ),
             root: root.as_ref().to_path_buf(),
+            more_roots: Vec::new(),
         }
     }
 
+    /// Walk `roots` too, one after another after the roots given before.
+    ///
+    /// Every root is handled like the one given to [`new`]: a file (or any
+    /// other non-directory) is yielded alone with depth `0`, and a directory
+    /// is yielded with depth `0` and walked. So a list of paths of unknown
+    /// type (e.g. from a command line) can be walked without checking them in
+    /// advance, and [`DirEntry::is_root`] tells the given paths from the found
+    /// ones. A root which can't be accessed yields an error, and the walk
+    /// goes on with the next root.
+    ///
+    /// ```no_run
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder};
+    ///
+    /// for entry in WalkDirBuilder::<DefaultDirEntry>::new("Cargo.toml").add_roots(&["src", "README.md"]).into_classic() {
+    ///     let entry = entry.unwrap();
+    ///     println!("{} {}", entry.is_root(), entry.path().display());
+    /// }
+    /// ```
+    ///
+    /// Options apply to every root separately (e.g. `same_file_system`
+    /// compares devices with the device of the current root), except
+    /// `dedupe_canonical` which skips entries met under any previous root.
+    /// Use [`dedupe_overlapping_roots`] to skip only roots nested in each
+    /// other. [`watch`] doesn't support multiple roots.
+    ///
+    /// [`new`]: struct.WalkDir.html#method.new
+    /// [`dedupe_overlapping_roots`]: struct.WalkDir.html#method.dedupe_overlapping_roots
+    /// [`watch`]: struct.WalkDir.html#method.watch
+    /// [`DirEntry::is_root`]: struct.DirEntry.html#method.is_root
+    pub fn add_roots<I, P>(mut self, roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<E::Path>,
+    {
+        self.more_roots.extend(roots.into_iter().map(|root| root.as_ref().to_path_buf()));
+        self
+    }
+
+    fn into_roots(root: E::PathBuf, more_roots: Vec<E::PathBuf>) -> Vec<E::PathBuf> {
+        let mut roots = Vec::with_capacity(more_roots.len() + 1);
+        roots.push(root);
+        roots.extend(more_roots);
+        roots
+    }
+
     /// Builds an iterator
     pub fn build(self) -> WalkDirIterator<E, CP> {
-        WalkDirIterator::<E, CP>::new(self.opts, self.root)
+        WalkDirIterator::<E, CP>::with_roots(self.opts, Self::into_roots(self.root, self.more_roots))
     }
 
     /// Into classic iterator
@@ -271,6 +573,65 @@ where
         self.into_iter().into_classic()
     }
 
+    /// Into an iterator descending into directories in order of priority.
+    ///
+    /// Instead of walking the tree depth first, the iterator keeps a queue of
+    /// all met but not yet read directories and always reads the one with the
+    /// highest `priority` next (of directories with the same priority, the one
+    /// met first is read first). This way e.g. an indexer can scan user
+    /// documents before system directories, wherever they are in the tree.
+    ///
+    /// `priority` is called once for every directory which will be descended
+    /// into. Every directory is read entirely at once, and its entries are
+    /// yielded right after that.
+    ///
+    /// ```no_run
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder};
+    ///
+    /// let docs_first = WalkDirBuilder::<DefaultDirEntry>::new("/home")
+    ///     .prioritize(|e| if e.file_name() == "Documents" { 1 } else { 0 });
+    /// for entry in docs_first {
+    ///     println!("{}", entry.unwrap().path().display());
+    /// }
+    /// ```
+    ///
+    /// The options `follow_links`, `yield_loop_links`, `min_depth`,
+    /// `max_depth`, `same_file_system`, `sort_by`, `deterministic` and
+    /// `with_middleware` are respected; other options have no effect. Note that all queued
+    /// directories are kept in memory (but not opened) until they are read;
+    /// use [`bfs_memory_limit`] to bound it.
+    ///
+    /// [`bfs_memory_limit`]: struct.WalkDir.html#method.bfs_memory_limit
+    pub fn prioritize<F>(self, priority: F) -> PriorityIter<E, CP, F>
+    where
+        F: FnMut(&CP::Item) -> u32,
+    {
+        PriorityIter::new(self.opts, Self::into_roots(self.root, self.more_roots), priority)
+    }
+
+    /// Walk the tree and then keep watching it for changes.
+    ///
+    /// The returned [`WatchDir`] yields all items of the initial walk first.
+    /// After that, every subtree reported as changed by the file system
+    /// notifications is walked again with the same options, so a file indexer
+    /// can keep its view of the tree up to date without rescanning everything.
+    ///
+    /// This method is only available with the `notify` feature. Only a single
+    /// root is supported: an error is returned if [`add_roots`] was used.
+    ///
+    /// [`WatchDir`]: struct.WatchDir.html
+    /// [`add_roots`]: struct.WalkDir.html#method.add_roots
+    #[cfg(feature = "notify")]
+    pub fn watch(self) -> notify::Result<WatchDir<E, CP>>
+    where
+        E: fs::FsDirEntry<Path = std::path::Path, PathBuf = std::path::PathBuf>,
+    {
+        if !self.more_roots.is_empty() {
+            return Err(notify::Error::generic("watching multiple roots is not supported"));
+        }
+        WatchDir::new(self.opts, self.root)
+    }
+
     /// Do not cross file system boundaries.
     ///
     /// When this option is enabled, directory traversal will not descend into
@@ -278,12 +639,103 @@ where
     ///
     /// Currently, this option is only supported on Unix and Windows. If this
     /// option is used on an unsupported platform, then directory traversal
-    /// will immediately return an error and will not yield any entries.
+    /// will immediately return an error and will not yield any entries
+    /// (unless [`same_file_system_soft`] is enabled).
+    ///
+    /// [`same_file_system_soft`]: struct.WalkDir.html#method.same_file_system_soft
     pub fn same_file_system(mut self, yes: bool) -> Self {
         self.opts.immut.same_file_system = yes;
         self
     }
 
+    /// Enable [`same_file_system`] only where devices can be queried. By
+    /// default, this is disabled.
+    ///
+    /// When `yes` is `true` and the device of a root can't be queried (e.g.
+    /// the backend doesn't support it), the root is walked as if
+    /// `same_file_system` wasn't set instead of failing, and a warning is
+    /// logged (with the `tracing` feature). Likewise, a dir whose device can't
+    /// be queried is descended into instead of yielding an error. Use
+    /// [`same_file_system_ignored`] to check whether the restriction was
+    /// dropped. This lets portable code request the option opportunistically.
+    ///
+    /// This option implies [`same_file_system`].
+    ///
+    /// [`same_file_system`]: struct.WalkDir.html#method.same_file_system
+    /// [`same_file_system_ignored`]: struct.WalkDirIterator.html#method.same_file_system_ignored
+    pub fn same_file_system_soft(mut self, yes: bool) -> Self {
+        self.opts.immut.same_file_system_soft = yes;
+        if yes {
+            self.opts.immut.same_file_system = true;
+        }
+        self
+    }
+
+    /// Report descending onto another device. By default, this is disabled.
+    ///
+    /// When `yes` is `true`, the device of every directory is queried before
+    /// it is descended into. If it differs from the device of its parent, a
+    /// `Position::DeviceBoundary { from, to }` marker is yielded right before
+    /// the content of the directory. This allows consumers to adjust their
+    /// behavior mid-walk, e.g. to disable hashing on network mounts.
+    ///
+    /// Markers are only yielded by the iterator returned by [`into_iter`];
+    /// [`into_classic`] skips them. If the device of a directory can't be
+    /// queried, no marker is yielded when entering it or its subdirectories.
+    /// Nothing is ever reported with [`same_file_system`]
+    /// enabled, since other devices are not descended into then.
+    ///
+    /// [`into_iter`]: struct.WalkDir.html#method.into_iter
+    /// [`into_classic`]: struct.WalkDir.html#method.into_classic
+    /// [`same_file_system`]: struct.WalkDir.html#method.same_file_system
+    pub fn device_boundaries(mut self, yes: bool) -> Self {
+        self.opts.immut.device_boundaries = yes;
+        self
+    }
+
+    /// Detect mount points. By default, this is disabled.
+    ///
+    /// When `yes` is `true`, the device of every directory is compared with
+    /// the device of its parent, and directories on another device are
+    /// reported by [`DirEntry::is_mount_point`]. On Windows only reparse
+    /// points are checked, so ordinary directories cost no extra system
+    /// calls. With [`follow_links`] enabled, a followed link to a directory on
+    /// another device is a mount point as well. The root is never reported as
+    /// a mount point, since its parent isn't walked.
+    ///
+    /// Currently, this option is only supported on Unix and Windows. On other
+    /// platforms no mount points are ever detected.
+    ///
+    /// [`DirEntry::is_mount_point`]: struct.DirEntry.html#method.is_mount_point
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    pub fn detect_mount_points(mut self, yes: bool) -> Self {
+        self.opts.immut.detect_mount_points = yes;
+        self
+    }
+
+    /// Yield mount points as leaves, without descending into them. By
+    /// default, this is disabled.
+    ///
+    /// Mount points are detected as with [`detect_mount_points`] (which this
+    /// option implies). Unlike [`same_file_system`], which compares every
+    /// directory with the device of the root and drops other ones entirely,
+    /// this option compares a directory with its parent, and mount points are
+    /// still yielded (with [`DirEntry::is_mount_point`] returning `true`),
+    /// only their content is skipped. Like [`treat_as_files`], skipped mount
+    /// points are subject to [`content_filter`] and [`content_order`] as
+    /// files.
+    ///
+    /// [`detect_mount_points`]: struct.WalkDir.html#method.detect_mount_points
+    /// [`same_file_system`]: struct.WalkDir.html#method.same_file_system
+    /// [`DirEntry::is_mount_point`]: struct.DirEntry.html#method.is_mount_point
+    /// [`treat_as_files`]: struct.WalkDir.html#method.treat_as_files
+    /// [`content_filter`]: struct.WalkDir.html#method.content_filter
+    /// [`content_order`]: struct.WalkDir.html#method.content_order
+    pub fn skip_mount_points(mut self, yes: bool) -> Self {
+        self.opts.immut.skip_mount_points = yes;
+        self
+    }
+
     /// Follow symbolic links. By default, this is disabled.
     ///
     /// When `yes` is `true`, symbolic links are followed as if they were
@@ -300,6 +752,118 @@ where
         self
     }
 
+    /// Follow links of the given kind when [`follow_links`] is enabled. By
+    /// default, links of all kinds are followed.
+    ///
+    /// When `yes` is `false`, links of this kind are yielded as links and are
+    /// not descended into, while other links are still followed. E.g. on
+    /// Windows, junctions often point back into the same tree (like
+    /// `Application Data` in user profiles), so walking them as symbolic links
+    /// yields the same files many times:
+    ///
+    /// ```no_run
+    /// use walkdir::{DefaultDirEntry, LinkKind, WalkDirBuilder};
+    ///
+    /// let wd = WalkDirBuilder::<DefaultDirEntry>::new("C:\\Users")
+    ///     .follow_links(true)
+    ///     .follow_link_kind(LinkKind::Junction, false);
+    /// ```
+    ///
+    /// The kind of a link is only queried if some kind is excluded. On Unix
+    /// every link is a [`LinkKind::Symlink`]. App execution aliases are never
+    /// followed, since they are not reported as symbolic links at all. The
+    /// root is governed by [`follow_root_symlink`] instead.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    /// [`follow_root_symlink`]: struct.WalkDir.html#method.follow_root_symlink
+    /// [`LinkKind::Symlink`]: enum.LinkKind.html#variant.Symlink
+    pub fn follow_link_kind(mut self, kind: LinkKind, yes: bool) -> Self {
+        let kinds = &mut self.opts.immut.unfollowed_link_kinds;
+        kinds.retain(|k| *k != kind);
+        if !yes {
+            kinds.push(kind);
+        }
+        self
+    }
+
+    /// Follow symbolic links only if their target is on the device of the
+    /// root. By default, this is disabled.
+    ///
+    /// When `yes` is `true`, a link whose target is on another device is
+    /// yielded as a link, like with [`follow_links`] disabled, and other links
+    /// are followed. So a link to e.g. a network mount is neither descended
+    /// into nor dropped. [`same_file_system`] on its own checks dirs only
+    /// after links to them are followed, and leaves out a followed link to a
+    /// dir on another device altogether, while links to files there are still
+    /// followed. Dirs which aren't links are descended into as usual (use
+    /// `same_file_system` as well to stay on one device).
+    ///
+    /// The device of the root is queried as with `same_file_system` (see
+    /// [`same_file_system_soft`] if it may be unknown). This option implies
+    /// [`follow_links`].
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    /// [`same_file_system`]: struct.WalkDir.html#method.same_file_system
+    /// [`same_file_system_soft`]: struct.WalkDir.html#method.same_file_system_soft
+    pub fn follow_links_same_device(mut self, yes: bool) -> Self {
+        self.opts.immut.follow_links_same_device = yes;
+        if yes {
+            self.opts.immut.follow_links = true;
+        }
+        self
+    }
+
+    /// Follow the root if it is a symbolic link to a directory. By default,
+    /// this is enabled.
+    ///
+    /// When `yes` is `true` (as is the default), the contents of the target of
+    /// a symlinked root are walked even if [`follow_links`] is disabled, while
+    /// the root entry itself still reports a symbolic link.
+    ///
+    /// When `yes` is `false`, a symlinked root is yielded as a link without
+    /// descending into it, like any other link. This is useful for e.g. backup
+    /// tools which archive the root link itself as a link. This option has no
+    /// effect when [`follow_links`] is enabled.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    pub fn follow_root_symlink(mut self, yes: bool) -> Self {
+        self.opts.immut.follow_root_symlink = yes;
+        self
+    }
+
+    /// Stat the root with `metadata` (following a symbolic link) instead of
+    /// `symlink_metadata`. By default, this is disabled.
+    ///
+    /// When `yes` is `true`, a symlinked root is treated as if [`follow_links`]
+    /// were enabled for it alone: the root entry reports the type and metadata
+    /// of the target (and [`path_is_symlink`] still reports the link), and a
+    /// root link to a missing target always fails. [`follow_root_symlink`] has
+    /// no effect then.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    /// [`follow_root_symlink`]: struct.WalkDir.html#method.follow_root_symlink
+    /// [`path_is_symlink`]: struct.DirEntry.html#method.path_is_symlink
+    pub fn follow_root_metadata(mut self, yes: bool) -> Self {
+        self.opts.immut.follow_root_metadata = yes;
+        self
+    }
+
+    /// Set what to do when a root fails: it can't be statted (e.g. it doesn't
+    /// exist) or its content can't be read. By default, a single error is
+    /// yielded for the root and the walk goes on with the next root
+    /// (`RootErrorPolicy::Yield`), so a missing root doesn't spoil the others.
+    ///
+    /// With `RootErrorPolicy::Fatal`, the walk ends with the error of the
+    /// failed root: the roots left are skipped (and with [`prioritize`] so are
+    /// the entries of the roots before it not yielded yet). Errors about
+    /// entries of a root don't end the walk.
+    ///
+    /// [`prioritize`]: struct.WalkDir.html#method.prioritize
+    pub fn root_error_policy(mut self, policy: RootErrorPolicy) -> Self {
+        self.opts.immut.root_errors = policy;
+        self
+    }
+
     /// Yield links leading to loop. By default, this is disabled.
     ///
     /// When `yes` is `true`, symbolic links are followed as if they were
@@ -316,6 +880,23 @@ where
         self
     }
 
+    /// Allow to enter a loop up to `n` times before it's treated as a loop
+    /// (see [`yield_loop_links`]). By default, this is `0`.
+    ///
+    /// With `n = 1`, a link pointing to an ancestor dir is followed once, so
+    /// the content of the ancestor is yielded again below the link, and the
+    /// same link met there is a loop. Revisits are counted per dir along the
+    /// path from the root, so other branches of the walk don't use them up.
+    ///
+    /// This has no effect unless [`follow_links`] is enabled.
+    ///
+    /// [`yield_loop_links`]: struct.WalkDir.html#method.yield_loop_links
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    pub fn allow_loop_revisits(mut self, n: usize) -> Self {
+        self.opts.immut.loop_revisits = n;
+        self
+    }
+
     /// Set the minimum depth of entries yielded by the iterator.
     ///
     /// The smallest depth is `0` and always corresponds to the path given
@@ -379,6 +960,77 @@ where
         self
     }
 
+    /// Set how many entries the backend fetches from the system at once while
+    /// reading a directory. By default, the backend chooses.
+    ///
+    /// This is a hint passed to the backend once, before the walk starts (see
+    /// `FsDirEntry::set_readdir_batch`), e.g. as the size of a `getdents`
+    /// buffer, the large fetch flag of `FindFirstFileEx` or the page size of a
+    /// `PROPFIND` request of a network backend. Larger batches make fewer
+    /// calls at the cost of memory per open directory, which may pay off on
+    /// slow or remote storage. It has no impact on the results yielded by the
+    /// iterator. If `n` is `0`, then it is set to `1` automatically.
+    ///
+    /// The standard backends keep the hint in their context (see
+    /// `StandardContext::readdir_batch`), since `std::fs::read_dir` uses a
+    /// buffer of a fixed size.
+    pub fn readdir_batch(mut self, n: usize) -> Self {
+        self.opts.immut.readdir_batch = Some(n.max(1));
+        self
+    }
+
+    /// Set which metadata queries the backend keeps for reuse. By default,
+    /// nothing is kept (`CachePolicy::None`) and every check needing metadata
+    /// queries it again.
+    ///
+    /// An entry may be queried several times: by options such as
+    /// [`skip_dirs_unmodified_since`], by a sorter or a filter comparing
+    /// sizes or times, and once more to make the yielded [`DirEntry`]. With
+    /// `CachePolicy::PerEntry`, each entry keeps the metadata of its first
+    /// query until it is dropped, which is enough for the checks of one
+    /// entry to share a query. With `CachePolicy::Lru(n)`, the metadata of
+    /// the `n` last queried paths is kept, which also covers entries opened
+    /// again by path (such as the roots). Lookups are made by path, so `n`
+    /// only bounds the memory taken.
+    ///
+    /// Either way a file changed while being walked may be reported as it
+    /// was when first queried. Kept queries are dropped when a walk starts, so
+    /// nothing is reused between walks (e.g. between the rewalks of
+    /// [`watch`]). This is a hint passed to the backend once, before the walk
+    /// starts (see `FsDirEntry::set_metadata_cache`). The standard backends
+    /// keep the cache in their context and report every lookup to its
+    /// observer (see `FsObserver::on_metadata_cache`); other backends ignore
+    /// the hint.
+    ///
+    /// [`skip_dirs_unmodified_since`]: struct.WalkDir.html#method.skip_dirs_unmodified_since
+    /// [`DirEntry`]: struct.DirEntry.html
+    /// [`watch`]: struct.WalkDir.html#method.watch
+    pub fn metadata_cache(mut self, policy: CachePolicy) -> Self {
+        self.opts.immut.metadata_cache = policy;
+        self
+    }
+
+    /// Limit the memory taken by the queue of a [`prioritize`] walk to about
+    /// `bytes` bytes.
+    ///
+    /// A walk made by [`prioritize`] (breadth-first if all priorities are
+    /// equal) keeps every met but not yet read directory in memory, which can
+    /// exhaust it on extremely wide trees. With this limit, once the estimated
+    /// size of the queue (the entries and their paths) would exceed `bytes`,
+    /// new directories are not queued but read depth first, subtree by
+    /// subtree, in the order they were met and ignoring their priority. When
+    /// all of them are read, the walk takes the queue up again. The order
+    /// stays close to breadth-first, while the memory grows only with the
+    /// count of directories along one path down the tree (and their siblings).
+    ///
+    /// By default, there is no limit. It has no effect on other iterators.
+    ///
+    /// [`prioritize`]: struct.WalkDir.html#method.prioritize
+    pub fn bfs_memory_limit(mut self, bytes: usize) -> Self {
+        self.opts.immut.bfs_memory_limit = Some(bytes);
+        self
+    }
+
     /// Set a function for sorting directory entries.
     ///
     /// If a compare function is set, the resulting iterator will return all
@@ -386,7 +1038,7 @@ where
     /// entries from the same directory.
     ///
     /// ```rust,no_run
-    /// use std::cmp;
+    /// use core::cmp;
     /// use std::ffi::OsString;
     /// use walkdir::WalkDir;
     ///
@@ -394,9 +1046,566 @@ where
     /// ```
     pub fn sort_by<F>(mut self, cmp: F) -> Self
     where
-        F: FnMut((&E, &E::FileType), (&E, &E::FileType), &mut E::Context) -> std::cmp::Ordering + Send + Sync + 'static,
+        F: Fn((&E, &E::FileType), (&E, &E::FileType), &mut E::Context) -> core::cmp::Ordering + Send + Sync + 'static,
     {
-        self.opts.sorter = Some(Box::new(cmp));
+        self.opts.sorter = Some(Arc::new(cmp));
+        self
+    }
+
+    /// Normalize paths of yielded entries with the given function. By
+    /// default, paths are yielded as they are built by the walker.
+    ///
+    /// `normalize` is called for the path of every yielded entry (including
+    /// entries in the content of `Position::BeforeContent`). If it returns
+    /// `Cow::Owned`, the returned path replaces the original one; returning
+    /// `Cow::Borrowed` keeps the original path without copying it. This is
+    /// useful when results feed storage expecting canonical forms, e.g. with
+    /// normalized separators or Unicode normalization.
+    ///
+    /// Only the path reported by [`DirEntry::path`] is changed: the walker
+    /// still uses the original paths to read directories. The normalized path
+    /// should keep the components of the original one, since
+    /// [`DirEntry::ancestor_path`] relies on them.
+    ///
+    /// ```no_run
+    /// use std::borrow::Cow;
+    /// use std::path::Path;
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder};
+    ///
+    /// fn forward_slashes(path: &Path) -> Cow<'_, Path> {
+    ///     match path.to_str() {
+    ///         Some(s) if s.contains('\\') => Cow::Owned(s.replace('\\', "/").into()),
+    ///         _ => Cow::Borrowed(path),
+    ///     }
+    /// }
+    ///
+    /// for entry in WalkDirBuilder::<DefaultDirEntry>::new("foo").normalize_paths(forward_slashes).into_classic() {
+    ///     println!("{}", entry.unwrap().path().display());
+    /// }
+    /// ```
+    ///
+    /// [`DirEntry::path`]: struct.DirEntry.html#method.path
+    /// [`DirEntry::ancestor_path`]: struct.DirEntry.html#method.ancestor_path
+    pub fn normalize_paths(mut self, normalize: FnNormalize<E>) -> Self {
+        self.opts.normalize = Some(normalize);
+        self
+    }
+
+    /// Pass every entry through the given middleware, in order. By default,
+    /// there is none.
+    ///
+    /// Each [`EntryMiddleware`] gets a [`FlatDirEntry`] after symbolic links
+    /// were followed and the walker's own checks were made, but before the
+    /// entry reaches content filters, sorting and the content processor. It
+    /// may inspect the entry, rewrite it (e.g. clear `is_dir` to yield a
+    /// directory without descending into it) or suppress it by returning
+    /// `None`, in which case neither the entry nor its contents are yielded
+    /// and the remaining middleware doesn't see it. The root goes through
+    /// the middleware too; errors don't.
+    ///
+    /// This is a composable extension point for policies which don't need a
+    /// whole backend. Middleware with state must use interior mutability.
+    ///
+    /// [`EntryMiddleware`]: trait.EntryMiddleware.html
+    /// [`FlatDirEntry`]: struct.FlatDirEntry.html
+    pub fn with_middleware(mut self, middleware: Vec<Box<dyn EntryMiddleware<E>>>) -> Self {
+        self.opts.middleware = middleware.into_iter().map(Arc::from).collect();
+        self
+    }
+
+    /// Change options for entries at `depth` and deeper. By default, all
+    /// depths use the same options.
+    ///
+    /// `f` gets a copy of the options in effect above `depth` (with the
+    /// overrides for smaller depths applied) and changes it. Overrides are
+    /// applied when the walk starts, so they see every option set on the
+    /// builder, whatever the order of calls. For example, to sort only the
+    /// top of the tree and to follow links only below the first level:
+    ///
+    /// ```no_run
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder};
+    ///
+    /// let wd = WalkDirBuilder::<DefaultDirEntry>::new("foo")
+    ///     .deterministic(true)
+    ///     .override_at_depth(3, |opts| opts.sorting = false)
+    ///     .override_at_depth(2, |opts| opts.follow_links = true);
+    /// ```
+    ///
+    /// The options of a depth are used when the entries at this depth are
+    /// read: following of links, sorting (`sorting`, `deterministic` and
+    /// whether [`sort_by`] is applied), content filtering and ordering, and
+    /// the checks of each entry (e.g. [`treat_as_files`] or
+    /// [`special_files`]). Depth limits, the yield mode (e.g.
+    /// [`contents_first`]) and `max_open` are taken from the base options
+    /// only. Overrides are respected by [`into_iter`] and [`into_classic`].
+    ///
+    /// [`sort_by`]: struct.WalkDir.html#method.sort_by
+    /// [`treat_as_files`]: struct.WalkDir.html#method.treat_as_files
+    /// [`special_files`]: struct.WalkDir.html#method.special_files
+    /// [`contents_first`]: struct.WalkDir.html#method.contents_first
+    /// [`into_iter`]: struct.WalkDir.html#method.into_iter
+    /// [`into_classic`]: struct.WalkDir.html#method.into_classic
+    pub fn override_at_depth<F>(mut self, depth: Depth, f: F) -> Self
+    where
+        F: Fn(&mut WalkDirOptionsImmut) + Send + Sync + 'static,
+    {
+        self.opts.depth_overrides.push((depth, Arc::new(f)));
+        self
+    }
+
+    /// Yield directory entries in a stable, reproducible order. By default,
+    /// this is disabled.
+    ///
+    /// When `yes` is `true`, the entries of every directory are ordered by
+    /// their file names compared byte-wise, even if no [`sort_by`] function
+    /// was given. If [`sort_by`] is set, it still decides the order and file
+    /// names only break its ties.
+    ///
+    /// The resulting order depends only on the names in the tree, not on the
+    /// platform or on the order the underlying storage returns entries in.
+    /// This makes the output suitable for reproducible build tooling and
+    /// content hashing. Note that enabling this option requires reading each
+    /// directory fully before yielding its first entry.
+    ///
+    /// [`sort_by`]: struct.WalkDir.html#method.sort_by
+    pub fn deterministic(mut self, yes: bool) -> Self {
+        self.opts.immut.deterministic = yes;
+        self
+    }
+
+    /// Yield entries in lexicographic order of their full paths. By default,
+    /// this is disabled.
+    ///
+    /// [`deterministic`] orders the entries of each directory, but a directory
+    /// is still followed by its content, so `a/x` comes before `a.txt`, while
+    /// comparing whole paths byte-wise puts `a.txt` first (`.` sorts before
+    /// `/`). When `yes` is `true`, the output is in the latter order, the one
+    /// of sorted manifests (e.g. `find | LC_ALL=C sort`), so it can be diffed
+    /// against them directly.
+    ///
+    /// Every directory is read fully and its entries are buffered and sorted,
+    /// as with [`deterministic`]. When a directory has siblings which sort
+    /// between it and its content, it is yielded at its own place and its
+    /// content is walked after those siblings; this reads the directory's
+    /// metadata once more. [`sort_by`] and [`deterministic`] have no effect
+    /// when this is enabled. In [`contents_first`] mode, each directory still
+    /// comes after its content, which is otherwise ordered by path.
+    /// [`content_order`] is applied before the path order, so leave it unset
+    /// to get the order described above.
+    ///
+    /// [`deterministic`]: struct.WalkDir.html#method.deterministic
+    /// [`sort_by`]: struct.WalkDir.html#method.sort_by
+    /// [`contents_first`]: struct.WalkDir.html#method.contents_first
+    /// [`content_order`]: struct.WalkDir.html#method.content_order
+    pub fn globally_sorted(mut self, yes: bool) -> Self {
+        self.opts.immut.globally_sorted = yes;
+        self
+    }
+
+    /// Skip entries which resolve to an already met file or directory. By
+    /// default, this is disabled.
+    ///
+    /// When `yes` is `true`, the canonical path of every entry is computed
+    /// (with the backend's `canonicalize_path`) and an entry is skipped, along
+    /// with its content, if an entry with the same canonical path was met
+    /// before. This is mostly useful together with [`follow_links`], when the
+    /// same directory may be reached through several links. The entry met first
+    /// wins.
+    ///
+    /// Links involved in a loop are never skipped this way, so loop errors are
+    /// still reported. Entries whose canonical path cannot be computed (e.g.
+    /// broken links) are never skipped either.
+    ///
+    /// Note that enabling this option costs an extra system call per entry and
+    /// keeps the canonical paths of all met entries in memory.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    pub fn dedupe_canonical(mut self, yes: bool) -> Self {
+        self.opts.immut.dedupe_canonical = yes;
+        self
+    }
+
+    /// Skip roots which overlap with previous roots. By default, this is
+    /// disabled.
+    ///
+    /// When `yes` is `true`, the canonical path of every directory met is
+    /// remembered, and a directory whose canonical path was met under a
+    /// previous root (see [`add_roots`]) is skipped along with its content.
+    /// So a root nested in a previous root is skipped as a whole, and a
+    /// previous root nested in the current one is skipped as a subtree; each
+    /// directory is walked once. Directories met again under the same root
+    /// (e.g. through links) are not affected.
+    ///
+    /// Unlike [`dedupe_canonical`], only directories are compared. Note that
+    /// enabling this option costs an extra system call per directory and
+    /// keeps the canonical paths of all met directories in memory.
+    ///
+    /// [`add_roots`]: struct.WalkDir.html#method.add_roots
+    /// [`dedupe_canonical`]: struct.WalkDir.html#method.dedupe_canonical
+    pub fn dedupe_overlapping_roots(mut self, yes: bool) -> Self {
+        self.opts.immut.dedupe_overlapping_roots = yes;
+        self
+    }
+
+    /// Mark entries whose names differ from a sibling's name only in Unicode
+    /// normalization. By default, this is disabled.
+    ///
+    /// When `yes` is `true`, names of all entries of a directory are compared
+    /// after canonical decomposition (NFD), and entries whose names are equal
+    /// this way (e.g. `café` written with a precomposed `é` and with `e`
+    /// followed by a combining accent) are yielded with
+    /// [`DirEntry::has_unicode_collision`] returning `true`. Such names are
+    /// typically created when syncing between macOS and other systems, and
+    /// they denote the same file on file systems which normalize names.
+    ///
+    /// Decomposition covers precomposed letters of the Latin, Greek and
+    /// Cyrillic scripts and Hangul syllables; other characters are compared
+    /// as they are. Note that enabling this option requires reading each
+    /// directory fully before yielding its first entry.
+    ///
+    /// [`DirEntry::has_unicode_collision`]: struct.DirEntry.html#method.has_unicode_collision
+    pub fn detect_unicode_collisions(mut self, yes: bool) -> Self {
+        self.opts.immut.detect_unicode_collisions = yes;
+        self
+    }
+
+    /// Descend only into a pseudo-random fraction of directories. By default,
+    /// all directories are descended into.
+    ///
+    /// Every directory below the root is descended into with probability
+    /// `ratio` (which is clamped to `0.0..=1.0`). Directories which were not
+    /// chosen are still yielded, but their content is skipped, and
+    /// [`DirEntry::sampled_out`] returns `true` for them. This allows quickly
+    /// estimating statistics of huge trees.
+    ///
+    /// The choice depends only on `seed` and on the order in which directories
+    /// are met, so walks of the same tree with the same seed are the same.
+    /// Enable [`deterministic`] to make them the same across platforms too.
+    ///
+    /// [`DirEntry::sampled_out`]: struct.DirEntry.html#method.sampled_out
+    /// [`deterministic`]: struct.WalkDir.html#method.deterministic
+    pub fn sample(mut self, ratio: f64, seed: u64) -> Self {
+        let ratio = if ratio > 1.0 { 1.0 } else if ratio >= 0.0 { ratio } else { 0.0 };
+        self.opts.immut.sample = Some((ratio, seed));
+        self
+    }
+
+    /// Yield directories whose names match any of `patterns` as leaves,
+    /// without descending into them. By default, this list is empty.
+    ///
+    /// Patterns are matched against the file name only. `*` matches any
+    /// (possibly empty) sequence of characters and `?` matches any single
+    /// character, e.g. `&["*.app", "*.framework", ".git"]` skips the content of
+    /// macOS bundles and git metadata. Matched directories are treated like
+    /// files in every respect: they are subject to [`content_filter`] and
+    /// [`content_order`] as files, but still report a directory file type. The
+    /// root is always descended into.
+    ///
+    /// Calling this again replaces the previously given patterns. Names which
+    /// are not valid Unicode are matched lossily. Case of letters matters
+    /// unless set otherwise with [`match_case`].
+    ///
+    /// [`content_filter`]: struct.WalkDir.html#method.content_filter
+    /// [`content_order`]: struct.WalkDir.html#method.content_order
+    /// [`match_case`]: struct.WalkDir.html#method.match_case
+    pub fn treat_as_files(mut self, patterns: &[&str]) -> Self {
+        self.opts.immut.treat_as_files = patterns.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// Set how name patterns (e.g. of [`treat_as_files`]) treat case of
+    /// letters. By default, case matters (`CaseMatch::Sensitive`).
+    ///
+    /// With `CaseMatch::Insensitive`, `*.APP` matches `Foo.app`. With
+    /// `CaseMatch::Auto`, case is ignored only if the backend reports that
+    /// names on its storage are case-insensitive (see
+    /// `FsDirEntry::is_case_insensitive`): the standard backends do so on
+    /// Windows and macOS, so filters behave naturally on NTFS and APFS as
+    /// well as on ext4.
+    ///
+    /// [`treat_as_files`]: struct.WalkDir.html#method.treat_as_files
+    pub fn match_case(mut self, case: CaseMatch) -> Self {
+        self.opts.immut.match_case = case;
+        self
+    }
+
+    /// Don't descend into directories which weren't modified since `since`.
+    /// By default, every directory is descended into.
+    ///
+    /// Before a directory is descended into, its modification time is
+    /// queried. If it is older than `since`, the directory is yielded as a
+    /// leaf, like with [`treat_as_files`], and its whole subtree is pruned.
+    /// This gives cheap incremental scans: the caller keeps what it knew
+    /// about pruned subtrees from an earlier scan made at `since`. Directories
+    /// whose modification time can't be queried are descended into, and the
+    /// root is always descended into.
+    ///
+    /// This is only correct on file systems where the modification time of
+    /// a directory changes whenever one of its entries is added, removed or
+    /// renamed, and only for such changes: a modified file or a change deeper
+    /// in the subtree doesn't update the directory's modification time.
+    /// Changes made within the timestamp resolution of the file system may
+    /// be missed, so `since` should be taken with a margin.
+    ///
+    /// [`treat_as_files`]: struct.WalkDir.html#method.treat_as_files
+    #[cfg(feature = "std")]
+    pub fn skip_dirs_unmodified_since(mut self, since: std::time::SystemTime) -> Self {
+        let since = since.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        self.opts.immut.dirs_unmodified_since = Some(since);
+        self
+    }
+
+    /// Don't descend into directories with the given fingerprints. By
+    /// default, no directory is skipped this way.
+    ///
+    /// A fingerprint ([`DirId`]) identifies a directory by its device and
+    /// inode number (volume serial number and file index on Windows), so it
+    /// stays the same between runs. A directory whose fingerprint is in `ids`
+    /// is yielded as a leaf, like with [`treat_as_files`], and its whole
+    /// subtree is pruned. This lets repeated crawls skip branches known to be
+    /// unchanged, e.g. from an external change feed, using fingerprints
+    /// collected by an earlier walk with [`record_fingerprints`].
+    ///
+    /// The root is always descended into. Fingerprints are only available on
+    /// Unix and with the `windows-ext` feature on Windows; elsewhere nothing
+    /// is skipped. Inode numbers may be reused after a directory is removed,
+    /// so the set should come from a recent walk of the same tree.
+    ///
+    /// [`DirId`]: struct.DirId.html
+    /// [`treat_as_files`]: struct.WalkDir.html#method.treat_as_files
+    /// [`record_fingerprints`]: struct.WalkDir.html#method.record_fingerprints
+    pub fn skip_fingerprints<I: IntoIterator<Item = DirId>>(mut self, ids: I) -> Self {
+        self.opts.immut.skipped_fingerprints = ids.into_iter().collect();
+        self
+    }
+
+    /// Collect fingerprints of directories which are descended into. By
+    /// default, this is disabled.
+    ///
+    /// When `yes` is `true`, the fingerprint of every directory whose content
+    /// is read (including the root) is recorded. The set is available from
+    /// [`WalkDirIterator::visited_fingerprints`] and may be saved and passed
+    /// to [`skip_fingerprints`] of a later walk.
+    ///
+    /// [`WalkDirIterator::visited_fingerprints`]: struct.WalkDirIterator.html#method.visited_fingerprints
+    /// [`skip_fingerprints`]: struct.WalkDir.html#method.skip_fingerprints
+    pub fn record_fingerprints(mut self, yes: bool) -> Self {
+        self.opts.immut.record_fingerprints = yes;
+        self
+    }
+
+    /// Report entries whose full path is longer than `n` as errors. By
+    /// default, path length is not limited.
+    ///
+    /// The length is measured in bytes of the path as it would be yielded
+    /// (i.e. including the root path as given). Instead of such an entry, an
+    /// [`Error`] is yielded whose [`kind`] is [`ErrorKind::PathTooLong`], and a
+    /// directory with a too long path is not descended into. This allows to
+    /// catch paths which downstream consumers can't handle, e.g. `260` for
+    /// legacy Windows tools or `4096` for some archivers, at the walk itself.
+    ///
+    /// [`Error`]: struct.Error.html
+    /// [`kind`]: struct.Error.html#method.kind
+    /// [`ErrorKind::PathTooLong`]: enum.ErrorKind.html#variant.PathTooLong
+    pub fn max_path_len(mut self, n: usize) -> Self {
+        self.opts.immut.max_path_len = Some(n);
+        self
+    }
+
+    /// Never trigger hydration of cloud placeholders (e.g. OneDrive files
+    /// which are not available offline). By default, this is disabled.
+    ///
+    /// When `yes` is `true`, placeholders are recognized from the metadata
+    /// read along with their directory and are yielded as leaves: placeholder
+    /// directories are not descended into (which would fetch their content),
+    /// and placeholders are neither followed as links nor opened for other
+    /// queries (e.g. [`same_file_system`] or [`detect_mount_points`]).
+    /// Placeholders are reported by [`DirEntry::is_placeholder`] regardless of
+    /// this option. The root is always walked.
+    ///
+    /// Currently, placeholders only exist on Windows; elsewhere this option
+    /// has no effect.
+    ///
+    /// [`same_file_system`]: struct.WalkDir.html#method.same_file_system
+    /// [`detect_mount_points`]: struct.WalkDir.html#method.detect_mount_points
+    /// [`DirEntry::is_placeholder`]: struct.DirEntry.html#method.is_placeholder
+    pub fn avoid_hydration(mut self, yes: bool) -> Self {
+        self.opts.immut.avoid_hydration = yes;
+        self
+    }
+
+    /// Yield alternate data streams of files as their children. By default,
+    /// this is disabled.
+    ///
+    /// When `yes` is `true`, the named `$DATA` streams of every yielded file
+    /// are enumerated with `FindFirstStreamW` and yielded right after the
+    /// file (even in [`contents_first`] mode), one level deeper, with paths
+    /// like `file.txt:stream`. The main stream isn't yielded separately.
+    /// Streams are subject to [`max_depth`], but only streams of yielded files
+    /// are enumerated. Streams of symbolic links, directories and the root
+    /// are not enumerated.
+    ///
+    /// This requires the `windows-ext` feature and is only supported on
+    /// Windows. It is ignored by [`prioritize`].
+    ///
+    /// [`contents_first`]: struct.WalkDir.html#method.contents_first
+    /// [`max_depth`]: struct.WalkDir.html#method.max_depth
+    /// [`prioritize`]: struct.WalkDir.html#method.prioritize
+    #[cfg(all(feature = "windows-ext", windows))]
+    pub fn alternate_streams(mut self, yes: bool) -> Self {
+        self.opts.immut.alternate_streams = yes;
+        self
+    }
+
+    /// Yield only entries on which the extended attribute `name` is set
+    /// (with `present` being `true`) or is not set (with `present` being
+    /// `false`). By default, entries are not filtered by extended attributes.
+    ///
+    /// Like [`content_filter`], this only hides entries: directories which
+    /// don't pass the filter are still descended into. The attribute of a
+    /// followed link is checked on its target. The root is always yielded.
+    /// If querying the attribute fails, an error is yielded instead of the
+    /// entry; file systems without extended attributes support are treated
+    /// as having none.
+    ///
+    /// This requires the `xattr` feature and is only supported on Unix.
+    /// Values and names of all attributes of yielded entries are available
+    /// with [`DirEntry::xattr`] and [`DirEntry::xattrs`].
+    ///
+    /// [`content_filter`]: struct.WalkDir.html#method.content_filter
+    /// [`DirEntry::xattr`]: struct.DirEntry.html#method.xattr
+    /// [`DirEntry::xattrs`]: struct.DirEntry.html#method.xattrs
+    #[cfg(feature = "xattr")]
+    pub fn filter_xattr(mut self, name: &str, present: bool) -> Self {
+        self.opts.immut.xattr_filter = Some((name.to_string(), present));
+        self
+    }
+
+    /// Skip entries marked with the `nodump` flag (`chattr +d`), like `dump`
+    /// does. By default, this is disabled.
+    ///
+    /// When `yes` is `true`, the flags of every regular file and directory are
+    /// queried, and marked entries are neither yielded nor descended into.
+    /// Entries whose flags can't be read (e.g. because of permissions) are not
+    /// skipped, and the root is always walked. Flags of single entries are
+    /// available with [`DirEntry::file_flags`].
+    ///
+    /// This requires the `linux-flags` feature and is only supported on Linux.
+    ///
+    /// [`DirEntry::file_flags`]: struct.DirEntry.html#method.file_flags
+    #[cfg(all(feature = "linux-flags", target_os = "linux"))]
+    pub fn skip_nodump(mut self, yes: bool) -> Self {
+        self.opts.immut.skip_nodump = yes;
+        self
+    }
+
+    /// Skip binary files. By default, this is disabled.
+    ///
+    /// When `yes` is `true`, every regular file (or target of a followed
+    /// symbolic link) is opened with the backend's reader and its first 8 KiB
+    /// are read. Files containing a NUL byte there are considered binary and
+    /// are not yielded, so text processing pipelines don't need a second pass
+    /// to weed them out. Files which can't be opened or read are yielded as
+    /// errors. Symbolic links which are not followed and the root are never
+    /// sniffed, nor are cloud placeholders with [`avoid_hydration`].
+    ///
+    /// Backends which can't read contents never report binary files.
+    ///
+    /// [`avoid_hydration`]: struct.WalkDir.html#method.avoid_hydration
+    pub fn skip_binary_files(mut self, yes: bool) -> Self {
+        self.opts.immut.skip_binary_files = yes;
+        self
+    }
+
+    /// Set what to do with special files: named pipes (FIFOs), sockets,
+    /// block and character devices. By default, they are yielded
+    /// (`SpecialFilePolicy::Yield`).
+    ///
+    /// With `SpecialFilePolicy::Skip` they are not yielded at all, and with
+    /// `SpecialFilePolicy::Error` an [`Error`] whose [`kind`] is
+    /// [`ErrorKind::SpecialFile`] is yielded instead of each of them. This
+    /// lets archivers and hashers avoid opening a FIFO, which would block.
+    ///
+    /// The type is checked after following symbolic links (if enabled), so a
+    /// followed link to a FIFO is a special file too. The root is never
+    /// checked. Special files are recognized on Unix only (and by backends
+    /// implementing the corresponding `FsFileType` methods).
+    ///
+    /// [`Error`]: struct.Error.html
+    /// [`kind`]: struct.Error.html#method.kind
+    /// [`ErrorKind::SpecialFile`]: enum.ErrorKind.html#variant.SpecialFile
+    pub fn special_files(mut self, policy: SpecialFilePolicy) -> Self {
+        self.opts.immut.special_files = policy;
+        self
+    }
+
+    /// Report failed entries of a directory with a single summary error. By
+    /// default, this is disabled and an error is yielded for each entry.
+    ///
+    /// When `yes` is `true`, errors of individual entries of a directory
+    /// which was opened successfully (e.g. a permission error on `stat` or a
+    /// failed step of `read_dir`) are not yielded. They are counted instead,
+    /// and after the content of the directory one [`Error`] is yielded whose
+    /// [`kind`] is [`ErrorKind::PartialDir`], whose [`path`] is the directory
+    /// and whose [`entry_errors`] is the count. The remaining entries are
+    /// yielded as usual. This suits consumers which only care about the
+    /// aggregate health of the tree.
+    ///
+    /// Errors about the directory itself (e.g. it can't be opened or it is a
+    /// loop) and errors about the root are still yielded as usual.
+    ///
+    /// [`Error`]: struct.Error.html
+    /// [`kind`]: struct.Error.html#method.kind
+    /// [`path`]: struct.Error.html#method.path
+    /// [`entry_errors`]: struct.Error.html#method.entry_errors
+    /// [`ErrorKind::PartialDir`]: enum.ErrorKind.html#variant.PartialDir
+    pub fn summarize_entry_errors(mut self, yes: bool) -> Self {
+        self.opts.immut.summarize_entry_errors = yes;
+        self
+    }
+
+    /// Report directories whose contents can't be read together with the
+    /// error. By default, this is disabled and only the error is yielded.
+    ///
+    /// When `yes` is `true` and a directory which should be descended into
+    /// can't be opened (e.g. it was removed during the walk or permission is
+    /// denied), a `Position::DirSkipped { entry, error }` item is yielded
+    /// instead of a bare `Position::Error`, so consumers know which
+    /// (possibly already yielded or announced) directory produced no
+    /// contents. The directory itself is yielded as usual.
+    ///
+    /// Only the iterator returned by [`into_iter`] yields these items;
+    /// [`into_classic`] yields the `error` as before. Errors about the root
+    /// are never reported this way.
+    ///
+    /// [`into_iter`]: struct.WalkDir.html#method.into_iter
+    /// [`into_classic`]: struct.WalkDir.html#method.into_classic
+    pub fn report_skipped_dirs(mut self, yes: bool) -> Self {
+        self.opts.immut.report_skipped_dirs = yes;
+        self
+    }
+
+    /// Yield a terminal `Position::Finished { summary }` item when the walk
+    /// is over. By default, this is disabled.
+    ///
+    /// The [`WalkSummary`] counts the roots (and how many of them failed to
+    /// open), the yielded entries, dirs and errors, and holds the deepest
+    /// depth reached and the duration of the walk, so consumers can produce
+    /// end-of-run reports and tell a walk which ended from one which never
+    /// started. The item is
+    /// yielded once, after which the iterator returns `None`; a poisoned
+    /// iterator doesn't yield it. [`is_finished`] reports the same without
+    /// this option.
+    ///
+    /// Only the iterator returned by [`into_iter`] yields this item;
+    /// [`into_classic`] skips it.
+    ///
+    /// [`WalkSummary`]: struct.WalkSummary.html
+    /// [`is_finished`]: struct.WalkDirIterator.html#method.is_finished
+    /// [`into_iter`]: struct.WalkDir.html#method.into_iter
+    /// [`into_classic`]: struct.WalkDir.html#method.into_classic
+    pub fn report_finished(mut self, yes: bool) -> Self {
+        self.opts.immut.report_finished = yes;
         self
     }
 
@@ -462,6 +1671,74 @@ where
         self
     }
 
+    /// Walk in depth-first post-order: every directory is yielded strictly
+    /// after all of its descendants. By default, this is disabled.
+    ///
+    /// This is [`contents_first`] with a guarantee: when `yes` is `true`,
+    /// [`announce_dirs`] is ignored, so no directory is ever yielded before
+    /// one of its descendants. The order holds whatever happens inside a
+    /// directory: errors of its entries (or their summary, see
+    /// [`summarize_entry_errors`]), device boundaries and alternate data
+    /// streams are all yielded before the directory itself, and after
+    /// [`skip_current_dir`] the directory is still yielded after the entries
+    /// that were yielded before the skip. Directories whose contents can't be
+    /// read are yielded after the error about them. Only entries which are
+    /// yielded at all count, e.g. a directory filtered out with
+    /// [`filter_entry`] isn't yielded while its descendants may be.
+    ///
+    /// [`prioritize`] doesn't support this order.
+    ///
+    /// [`contents_first`]: struct.WalkDir.html#method.contents_first
+    /// [`announce_dirs`]: struct.WalkDir.html#method.announce_dirs
+    /// [`summarize_entry_errors`]: struct.WalkDir.html#method.summarize_entry_errors
+    /// [`skip_current_dir`]: struct.WalkDirIterator.html#method.skip_current_dir
+    /// [`filter_entry`]: trait.ClassicWalkDirIter.html#method.filter_entry
+    /// [`prioritize`]: struct.WalkDir.html#method.prioritize
+    pub fn post_order(mut self, yes: bool) -> Self {
+        self.opts.immut.post_order = yes;
+        self
+    }
+
+    /// Yield every directory twice: before and after its contents. By
+    /// default, this is disabled.
+    ///
+    /// When `yes` is `true`, a directory is yielded first before its contents
+    /// are read (with [`DirEntry::is_announcement`] returning `true`), so that
+    /// e.g. a matching output directory can be created, and then once more
+    /// after all its contents (with `is_announcement` returning `false`), so
+    /// that it can be finalized. Directories whose contents are skipped (e.g.
+    /// because of [`max_depth`]) are yielded twice as well. This option takes
+    /// precedence over [`contents_first`], but is ignored in [`post_order`].
+    ///
+    /// With the tree from [`contents_first`]:
+    ///
+    /// ```no_run
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder};
+    ///
+    /// for entry in WalkDirBuilder::<DefaultDirEntry>::new("foo").announce_dirs(true).into_classic() {
+    ///     let entry = entry.unwrap();
+    ///     println!("{} {}", entry.is_announcement(), entry.path().display());
+    /// }
+    ///
+    /// // true foo
+    /// // true foo/abc
+    /// // false foo/abc/qrs
+    /// // false foo/abc/tuv
+    /// // false foo/abc
+    /// // true foo/def
+    /// // false foo/def
+    /// // false foo
+    /// ```
+    ///
+    /// [`DirEntry::is_announcement`]: struct.DirEntry.html#method.is_announcement
+    /// [`max_depth`]: struct.WalkDir.html#method.max_depth
+    /// [`contents_first`]: struct.WalkDir.html#method.contents_first
+    /// [`post_order`]: struct.WalkDir.html#method.post_order
+    pub fn announce_dirs(mut self, yes: bool) -> Self {
+        self.opts.immut.announce_dirs = yes;
+        self
+    }
+
     /// A variants for filtering content
     pub fn content_filter(mut self, filter: ContentFilter) -> Self {
         self.opts.immut.content_filter = filter;
@@ -474,6 +1751,90 @@ where
         self
     }
 
+    /// Yield the content of every dir in passes: the pass of every entry is
+    /// the one `f` returns for it, and passes go in order of their labels
+    /// (`Pass::FIRST`, `Pass::SECOND`, `Pass::THIRD`, ...). This sets
+    /// [`content_order`] to `ContentOrder::Custom`.
+    ///
+    /// `f` is called once for every entry, after it has passed the walker's
+    /// checks and the middleware. Entries keep their order within a pass;
+    /// errors are yielded in `Pass::SECOND`. Note that the first pass makes
+    /// the walker load the entire dir.
+    ///
+    /// ```rust,no_run
+    /// use walkdir::{DefaultDirEntry, Pass, WalkDirBuilder};
+    ///
+    /// // Small files first, then large ones and dirs
+    /// let wd = WalkDirBuilder::<DefaultDirEntry>::new("foo").content_order_by(|flat| {
+    ///     match std::fs::symlink_metadata(flat.raw.path()) {
+    ///         Ok(md) if md.is_file() && md.len() < 4096 => Pass::FIRST,
+    ///         _ => Pass::SECOND,
+    ///     }
+    /// });
+    ///
+    /// // Dirs, then files, then symlinks
+    /// let wd = WalkDirBuilder::<DefaultDirEntry>::new("foo").content_order_by(|flat| {
+    ///     if flat.raw.is_symlink() {
+    ///         Pass::THIRD
+    ///     } else if flat.is_dir {
+    ///         Pass::FIRST
+    ///     } else {
+    ///         Pass::SECOND
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// [`content_order`]: struct.WalkDir.html#method.content_order
+    pub fn content_order_by<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&FlatDirEntry<E>) -> Pass + Send + Sync + 'static,
+    {
+        self.opts.immut.content_order = ContentOrder::Custom;
+        self.opts.content_pass = Some(Arc::new(f));
+        self
+    }
+
+    /// Set a function processing the content of every dir as a whole.
+    ///
+    /// `f` is called once for every dir read, with its depth, its path and
+    /// all of its entries which passed the walker's checks and the
+    /// middleware, before any of them is yielded. It may reorder the entries,
+    /// remove those which should neither be yielded nor descended into, or
+    /// change their flags (e.g. `pass` with [`content_order_by`] set, or
+    /// `is_dir` to keep the walker out of a dir). Errors are not passed to
+    /// `f`; they are yielded before the entries.
+    ///
+    /// ```rust,no_run
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder};
+    ///
+    /// // Skip build outputs of Rust projects, wherever they are
+    /// let wd = WalkDirBuilder::<DefaultDirEntry>::new("foo").process_read_dir(|_depth, _path, children| {
+    ///     if children.iter().any(|flat| flat.raw.file_name() == "Cargo.toml") {
+    ///         children.retain(|flat| flat.raw.file_name() != "target");
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// This makes the walker load every dir entirely. `f` gets entries already
+    /// sorted by [`sort_by`] and [`deterministic`], so it has the last word on
+    /// their order, except with [`globally_sorted`], which orders the entries
+    /// kept by `f` afterwards. Since the whole content is at hand, `f` is the
+    /// place for work that is better done per dir, e.g. in the threads of
+    /// [`par_walk`].
+    ///
+    /// [`content_order_by`]: struct.WalkDir.html#method.content_order_by
+    /// [`sort_by`]: struct.WalkDir.html#method.sort_by
+    /// [`deterministic`]: struct.WalkDir.html#method.deterministic
+    /// [`globally_sorted`]: struct.WalkDir.html#method.globally_sorted
+    /// [`par_walk`]: fn.par_walk.html
+    pub fn process_read_dir<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Depth, &E::Path, &mut Vec<FlatDirEntry<E>>) + Send + Sync + 'static,
+    {
+        self.opts.process_read_dir = Some(Arc::new(f));
+        self
+    }
+
     /// Set content processor
     pub fn content_processor(mut self, content_processor: CP) -> Self {
         self.opts.content_processor = content_processor;
@@ -505,3 +1866,20 @@ where
         self.build()
     }
 }
+
+impl<E, CP> IntoIterator for &WalkDirBuilder<E, CP>
+where
+    E: fs::FsDirEntry,
+    E::Context: Default,
+    CP: cp::ContentProcessor<E> + Clone,
+{
+    type Item = WalkDirIteratorItem<E, CP>;
+    type IntoIter = WalkDirIterator<E, CP>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut roots = Vec::with_capacity(self.more_roots.len() + 1);
+        roots.push(self.root.clone());
+        roots.extend(self.more_roots.iter().cloned());
+        WalkDirIterator::<E, CP>::with_roots(self.opts.share(E::Context::default()), roots)
+    }
+}
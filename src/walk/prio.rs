@@ -0,0 +1,433 @@
+use core::cmp::Ordering;
+use core::fmt;
+use core::mem;
+use alloc::collections::{BinaryHeap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::cp::ContentProcessor;
+use crate::error::{Error, ErrorInner, ErrorKind};
+use crate::fs::{self, FsPath};
+use crate::walk::dir::FlatDirEntry;
+use crate::walk::opts::WalkDirOptions;
+use crate::walk::rawdent::RawDirEntry;
+use crate::walk::walk::WalkDirIterator;
+use crate::wd::{self, CachePolicy, Depth};
+
+/////////////////////////////////////////////////////////////////////////
+
+/// A queued dir or one of its ancestors, used to check for loops when
+/// [`follow_links`] is enabled.
+///
+/// Canonical paths are used instead of fingerprints, so queued dirs don't keep
+/// any handles open.
+///
+/// [`follow_links`]: struct.WalkDir.html#method.follow_links
+#[derive(Debug)]
+struct AncestorNode<E: fs::FsDirEntry> {
+    /// The path of this ancestor.
+    path: E::PathBuf,
+    /// The canonical path of this ancestor.
+    canonical: E::PathBuf,
+    /// The depth of this ancestor.
+    depth: Depth,
+    /// Times the same dir was entered through a loop above this ancestor.
+    revisits: usize,
+    /// The parent of this ancestor (`None` for the root).
+    parent: Option<Arc<AncestorNode<E>>>,
+}
+
+/// A dir waiting to be read
+#[derive(Debug)]
+struct QueuedDir<E: fs::FsDirEntry> {
+    /// Priority given by user
+    priority: u32,
+    /// Sequence number: of dirs with the same priority, the earlier queued is read first
+    seq: u64,
+    /// The dir itself
+    raw: RawDirEntry<E>,
+    /// Depth of the dir
+    depth: Depth,
+    /// The dir and its ancestors (only when `follow_links` is enabled)
+    ancestors: Option<Arc<AncestorNode<E>>>,
+    /// The device of the dir's root (only with `same_file_system`)
+    root_device: Option<E::DeviceNum>,
+}
+
+impl<E: fs::FsDirEntry> PartialEq for QueuedDir<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<E: fs::FsDirEntry> Eq for QueuedDir<E> {}
+
+impl<E: fs::FsDirEntry> PartialOrd for QueuedDir<E> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<E: fs::FsDirEntry> Ord for QueuedDir<E> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////
+
+/// An iterator descending into directories in order of their priority.
+///
+/// Values of this type are created by calling [`.prioritize()`] on a
+/// `WalkDir`. See it for details.
+///
+/// [`.prioritize()`]: struct.WalkDir.html#method.prioritize
+pub struct PriorityIter<E, CP, F>
+where
+    E: fs::FsDirEntry,
+    CP: ContentProcessor<E>,
+    F: FnMut(&CP::Item) -> u32,
+{
+    /// Options specified in the builder.
+    opts: WalkDirOptions<E, CP>,
+    /// The start paths.
+    ///
+    /// These are only present at the beginning. After the first iteration,
+    /// this is always empty.
+    start: Vec<E::PathBuf>,
+    /// Priority function
+    priority: F,
+    /// Dirs waiting to be read, the highest priority first
+    queue: BinaryHeap<QueuedDir<E>>,
+    /// Dirs met while the queue was over `bfs_memory_limit`, read depth first
+    /// (the last one first) before the queue
+    stack: Vec<QueuedDir<E>>,
+    /// Estimated memory of `queue` and `stack`
+    queued_bytes: usize,
+    /// The dir being read was taken from `stack`, so its subdirs go there too
+    depth_first: bool,
+    /// Entries and errors of the last read dir, not yielded yet
+    pending: VecDeque<wd::Result<CP::Item, E>>,
+    /// Count of queued dirs so far
+    seq: u64,
+    /// The device of the root of the dir being read (only with `same_file_system`).
+    root_device: Option<E::DeviceNum>,
+    /// A user callback panicked inside `next`, the walk is over
+    poisoned: bool,
+}
+
+impl<E, CP, F> PriorityIter<E, CP, F>
+where
+    E: fs::FsDirEntry,
+    CP: ContentProcessor<E>,
+    F: FnMut(&CP::Item) -> u32,
+{
+    pub(crate) fn new(mut opts: WalkDirOptions<E, CP>, roots: Vec<E::PathBuf>, priority: F) -> Self {
+        if let Some(batch) = opts.immut.readdir_batch {
+            E::set_readdir_batch(&mut opts.ctx, batch);
+        }
+        if opts.immut.metadata_cache != CachePolicy::None {
+            E::set_metadata_cache(&mut opts.ctx, opts.immut.metadata_cache);
+        }
+        Self {
+            opts,
+            start: roots,
+            priority,
+            queue: BinaryHeap::new(),
+            stack: Vec::new(),
+            queued_bytes: 0,
+            depth_first: false,
+            pending: VecDeque::new(),
+            seq: 0,
+            root_device: None,
+            poisoned: false,
+        }
+    }
+
+    /// Get the fs context (e.g. to store collected data after walking)
+    pub fn context(&self) -> &E::Context {
+        &self.opts.ctx
+    }
+
+    fn init(&mut self, root_path: &E::Path) -> wd::ResultInner<(), E> {
+        let root = RawDirEntry::<E>::from_path(root_path, &mut self.opts.ctx)?;
+
+        self.root_device =
+            WalkDirIterator::<E, CP>::root_device_of(&self.opts.immut, &[], &root, &mut self.opts.ctx)?;
+
+        let device = self.root_device.filter(|_| self.opts.immut.same_file_system);
+        self.process(root.with_device(device), 0, None);
+
+        Ok(())
+    }
+
+    /// Read the whole dir, yield its content and queue its subdirs
+    fn read_dir(&mut self, dir: QueuedDir<E>) {
+        let QueuedDir { raw, depth, ancestors, root_device, .. } = dir;
+        self.root_device = root_device;
+
+        let mut rd = match raw.read_dir(&mut self.opts.ctx) {
+            Ok(rd) => rd,
+            Err(err) => {
+                self.pending.push_back(Err(Error::from_inner(err, depth)));
+                return;
+            }
+        };
+
+        let start = self.pending.len();
+        let stack_start = self.stack.len();
+        let mut content = Vec::new();
+        while let Some(r_rawdent) = rd.next(&mut self.opts.ctx) {
+            match r_rawdent {
+                Ok(rawdent) => content.push(rawdent),
+                Err(err) => self.pending.push_back(Err(Error::from_inner(err, depth + 1))),
+            }
+        }
+
+        if self.opts.sorter.is_some() || self.opts.immut.deterministic {
+            let by_name = self.opts.immut.deterministic;
+            let sorter = &mut self.opts.sorter;
+            let ctx = &mut self.opts.ctx;
+            content.sort_by(|a, b| {
+                let ord = match sorter {
+                    Some(ref mut cmp) => RawDirEntry::call_cmp(a, b, cmp, ctx),
+                    None => Ordering::Equal,
+                };
+                if by_name {
+                    ord.then_with(|| a.path().cmp(b.path()))
+                } else {
+                    ord
+                }
+            });
+        }
+
+        for rawdent in content {
+            self.process(rawdent, depth + 1, ancestors.as_ref());
+        }
+        // Read stacked subdirs in the order they were met
+        self.stack[stack_start..].reverse();
+
+        if self.opts.immut.summarize_entry_errors {
+            self.summarize_entry_errors(start, raw.path(), depth);
+        }
+    }
+
+    /// Replace errors of entries pending since `start` with a single summary
+    /// (loop errors are kept, like in the depth-first walker)
+    fn summarize_entry_errors(&mut self, start: usize, path: &E::Path, depth: Depth) {
+        let mut errors = 0;
+        let tail: Vec<_> = self.pending.drain(start..).collect();
+        for item in tail {
+            match item {
+                Err(ref err) if err.kind() != ErrorKind::Loop => errors += 1,
+                item => self.pending.push_back(item),
+            }
+        }
+        if errors > 0 {
+            let err = ErrorInner::<E>::from_partial_dir(path, errors);
+            self.pending.push_back(Err(Error::from_inner(err, depth)));
+        }
+    }
+
+    /// Make an item of entry (or an error) pending and queue the entry if it's a dir
+    fn process(
+        &mut self,
+        rawdent: RawDirEntry<E>,
+        depth: Depth,
+        ancestors: Option<&Arc<AncestorNode<E>>>,
+    ) {
+        let flat = match WalkDirIterator::<E, CP>::process_rawdent_without_ancestors(
+            rawdent,
+            depth,
+            &self.opts.immut,
+            &self.root_device,
+            &self.opts.middleware,
+            &mut self.opts.ctx,
+        ) {
+            Some(Ok(flat)) => flat,
+            Some(Err(err)) => {
+                self.pending.push_back(Err(Error::from_inner(err, depth)));
+                return;
+            }
+            None => return,
+        };
+        let FlatDirEntry { raw: mut rawdent, is_dir, .. } = flat;
+
+        let mut descend = is_dir && depth < self.opts.immut.max_depth;
+        let mut node = None;
+        if is_dir && self.opts.immut.follow_links {
+            match Self::check_loop(&rawdent, depth, self.opts.immut.loop_revisits, ancestors) {
+                Ok(Ok(new_node)) => node = Some(Arc::new(new_node)),
+                Ok(Err(ancestor)) => {
+                    // Like the depth-first walker, report a loop only if it would be descended
+                    if !self.opts.immut.yield_loop_links {
+                        if descend {
+                            let err = ErrorInner::<E>::from_loop(
+                                &ancestor.path,
+                                ancestor.depth,
+                                rawdent.path(),
+                                depth,
+                            );
+                            self.pending.push_back(Err(Error::from_inner(err, depth)));
+                        }
+                        return;
+                    }
+                    descend = false;
+                }
+                Err(err) => {
+                    self.pending.push_back(Err(Error::from_inner(err, depth)));
+                    return;
+                }
+            }
+        }
+
+        let item = match rawdent.make_content_item(
+            &self.opts.content_processor,
+            is_dir,
+            depth,
+            self.opts.normalize,
+            &mut self.opts.ctx,
+        ) {
+            Some(item) => item,
+            None => return,
+        };
+
+        if descend {
+            let dir = QueuedDir {
+                priority: (self.priority)(&item),
+                seq: self.seq,
+                raw: rawdent,
+                depth,
+                ancestors: node,
+                root_device: self.root_device,
+            };
+            self.seq += 1;
+            self.queued_bytes += Self::queued_size(&dir);
+            match self.opts.immut.bfs_memory_limit {
+                Some(limit) if self.depth_first || self.queued_bytes > limit => self.stack.push(dir),
+                _ => self.queue.push(dir),
+            }
+        }
+
+        if depth >= self.opts.immut.min_depth {
+            self.pending.push_back(Ok(item));
+        }
+    }
+
+    /// Take the next dir to read: the last stacked one or the one with the highest priority
+    fn pop_dir(&mut self) -> Option<QueuedDir<E>> {
+        let dir = match self.stack.pop() {
+            Some(dir) => {
+                self.depth_first = true;
+                dir
+            }
+            None => {
+                self.depth_first = false;
+                self.queue.pop()?
+            }
+        };
+        self.queued_bytes -= Self::queued_size(&dir);
+        Some(dir)
+    }
+
+    /// Estimated memory of a queued dir (its ancestors are shared and not counted)
+    fn queued_size(dir: &QueuedDir<E>) -> usize {
+        mem::size_of::<QueuedDir<E>>() + dir.raw.path().path_len()
+    }
+
+    /// Make a node for the dir, or return its ancestor if the dir is a loop
+    /// entered more than `loop_revisits` times
+    fn check_loop<'a>(
+        rawdent: &RawDirEntry<E>,
+        depth: Depth,
+        loop_revisits: usize,
+        ancestors: Option<&'a Arc<AncestorNode<E>>>,
+    ) -> wd::ResultInner<Result<AncestorNode<E>, &'a Arc<AncestorNode<E>>>, E> {
+        let canonical = E::canonicalize_path(rawdent.path())
+            .map_err(|err| ErrorInner::<E>::from_path(rawdent.pathbuf(), err))?;
+
+        let mut revisits = 0;
+        let mut cur = ancestors;
+        while let Some(ancestor) = cur {
+            if ancestor.canonical == canonical {
+                if ancestor.revisits >= loop_revisits {
+                    return Ok(Err(ancestor));
+                }
+                revisits = ancestor.revisits + 1;
+                break;
+            }
+            cur = ancestor.parent.as_ref();
+        }
+
+        Ok(Ok(AncestorNode { path: rawdent.pathbuf(), canonical, depth, revisits, parent: ancestors.cloned() }))
+    }
+}
+
+impl<E, CP, F> Iterator for PriorityIter<E, CP, F>
+where
+    E: fs::FsDirEntry,
+    CP: ContentProcessor<E>,
+    F: FnMut(&CP::Item) -> u32,
+{
+    type Item = wd::Result<CP::Item, E>;
+
+    /// Advances the iterator and returns the next value.
+    ///
+    /// # Errors
+    ///
+    /// If the iterator fails to retrieve the next value, this method returns
+    /// an error value. The error will be wrapped in an `Option::Some`.
+    ///
+    /// # Panics
+    ///
+    /// A panic in the priority function or another user callback propagates
+    /// to the caller. If it is caught, every later call returns `None`.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.poisoned {
+            return None;
+        }
+        let stack_start = self.stack.len();
+        for start in mem::take(&mut self.start) {
+            if let Err(err) = self.init(&start) {
+                self.pending.push_back(Err(Error::from_inner(err, 0)));
+            }
+        }
+        self.stack[stack_start..].reverse();
+
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                if let Err(ref err) = item {
+                    if WalkDirIterator::<E, CP>::is_fatal(&self.opts.immut, err) {
+                        self.pending.clear();
+                        self.queue.clear();
+                        self.stack.clear();
+                        self.queued_bytes = 0;
+                    }
+                }
+                return Some(item);
+            }
+            let dir = self.pop_dir()?;
+            // Stays set if reading the dir panics
+            self.poisoned = true;
+            self.read_dir(dir);
+            self.poisoned = false;
+        }
+    }
+}
+
+impl<E, CP, F> fmt::Debug for PriorityIter<E, CP, F>
+where
+    E: fs::FsDirEntry,
+    CP: ContentProcessor<E>,
+    F: FnMut(&CP::Item) -> u32,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PriorityIter")
+            .field("opts", &self.opts)
+            .field("start", &self.start)
+            .field("queue", &self.queue.len())
+            .field("stack", &self.stack.len())
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
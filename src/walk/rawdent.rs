@@ -1,8 +1,11 @@
 use crate::error::{into_io_err, into_path_err, ErrorInner};
 use crate::fs::{self, FsRootDirEntry, FsReadDirIterator, FsFileType};
-use crate::wd::{self, FnCmp, IntoOk, IntoSome, Depth};
+use crate::wd::{self, DirId, FnCmp, FnNormalize, IntoOk, IntoSome, Depth, LinkKind, Origin};
 use crate::cp::ContentProcessor;
 
+use alloc::vec;
+use alloc::vec::Vec;
+
 #[derive(Debug)]
 enum RawDirEntryKind<E: fs::FsDirEntry> {
     Root { 
@@ -48,6 +51,10 @@ pub struct RawDirEntry<E: fs::FsDirEntry> {
     follow_link: bool,
     /// Cached file_type()
     ty: E::FileType,
+    /// Root and followed link this entry was found through
+    origin: Origin<E::PathBuf>,
+    /// Device of the root this entry was found under (only with `same_file_system`)
+    device: Option<E::DeviceNum>,
 }
 
 impl<E: fs::FsDirEntry> RawDirEntry<E> {
@@ -65,6 +72,8 @@ impl<E: fs::FsDirEntry> RawDirEntry<E> {
             kind: RawDirEntryKind::<E>::Root{ fsdent },
             follow_link: false,
             ty,
+            origin: Origin::default(),
+            device: None,
         }.into_ok()
     }
 
@@ -79,6 +88,8 @@ impl<E: fs::FsDirEntry> RawDirEntry<E> {
             kind: RawDirEntryKind::<E>::DirEntry{ fsdent },
             follow_link: false,
             ty,
+            origin: Origin::default(),
+            device: None,
         }.into_ok()
     }
 
@@ -89,9 +100,33 @@ impl<E: fs::FsDirEntry> RawDirEntry<E> {
             kind:           self.kind,
             follow_link:    true,
             ty,
+            origin:         self.origin,
+            device:         self.device,
         }.into_ok()
     }
 
+    /// Replace origin of this entry
+    pub fn with_origin(mut self, origin: Origin<E::PathBuf>) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Root and followed link this entry was found through
+    pub fn origin(&self) -> &Origin<E::PathBuf> {
+        &self.origin
+    }
+
+    /// Replace the root device of this entry
+    pub fn with_device(mut self, device: Option<E::DeviceNum>) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Device of the root this entry was found under (only with `same_file_system`)
+    pub fn root_device(&self) -> Option<E::DeviceNum> {
+        self.device
+    }
+
     /// The full path that this entry represents.
     ///
     /// The full path is created by joining the parents of this entry up to the
@@ -174,7 +209,7 @@ impl<E: fs::FsDirEntry> RawDirEntry<E> {
             RawDirEntryKind::DirEntry { fsdent, .. } => {
                 fsdent.file_type( follow_link, ctx )
             },
-        }.map_err(into_io_err)
+        }.map_err(|err| into_path_err(self.path(), err))
     }
 
     /// Return the file type for the file that this entry points to.
@@ -239,7 +274,7 @@ impl<E: fs::FsDirEntry> RawDirEntry<E> {
     ///
     /// If this entry has no file name (e.g., `/`), then the full path is
     /// returned.
-    pub fn file_name(&self) -> E::FileName {
+    pub fn file_name(&self) -> &E::FileName {
         match &self.kind {
             RawDirEntryKind::Root { fsdent, .. } => {
                 fsdent.file_name()
@@ -288,7 +323,13 @@ impl<E: fs::FsDirEntry> RawDirEntry<E> {
                 fsdent.read_dir( ctx )
             },
         }.map_err(into_io_err)?;
-        ReadDir::<E>::new(rd).into_ok()
+        // Content of a followed link is reached through it
+        let origin = if self.follow_link {
+            self.origin.through_link(self.pathbuf())
+        } else {
+            self.origin.clone()
+        };
+        ReadDir::<E>::new(rd, origin, self.device).into_ok()
     }
 
     fn as_fsdent_ty(&self) -> Option<(&E, &E::FileType)> {
@@ -304,7 +345,7 @@ impl<E: fs::FsDirEntry> RawDirEntry<E> {
         b: &Self, 
         cmp: &mut FnCmp<E>,
         ctx: &mut E::Context,
-    ) -> std::cmp::Ordering {
+    ) -> core::cmp::Ordering {
         let ap = a.as_fsdent_ty().unwrap();
         let bp = b.as_fsdent_ty().unwrap();
         cmp(ap, bp, ctx)
@@ -316,16 +357,25 @@ impl<E: fs::FsDirEntry> RawDirEntry<E> {
         content_processor: &CP,
         is_dir: bool,
         depth: Depth,
+        normalize: Option<FnNormalize<E>>,
         ctx: &mut E::Context,
     ) -> Option<CP::Item> {
-        match &mut self.kind {
+        let mut item = match &mut self.kind {
             RawDirEntryKind::Root { fsdent, .. } => {
                 content_processor.process_root_direntry( fsdent, self.follow_link, is_dir, depth, ctx )
             },
             RawDirEntryKind::DirEntry { fsdent, .. } => {
                 content_processor.process_direntry( fsdent, self.follow_link, is_dir, depth, ctx )
             },
+        }?;
+        CP::set_origin(&mut item, &self.origin);
+        if let Some(device) = self.device {
+            CP::set_device(&mut item, device);
         }
+        if let Some(normalize) = normalize {
+            CP::normalize_path(&mut item, normalize);
+        }
+        Some(item)
     }
 
     // pub fn error_inner_from_entry(&self, err: E::Error) -> ErrorInner<E> {
@@ -362,19 +412,119 @@ impl<E: fs::FsDirEntry> RawDirEntry<E> {
         }.map_err(into_io_err)
     }
 
+    /// Check if this entry is a mount point (the root never is)
+    pub fn is_mount_point(
+        &self,
+        parent_device: &E::DeviceNum,
+        ctx: &mut E::Context,
+    ) -> wd::ResultInner<bool, E> {
+        match &self.kind {
+            RawDirEntryKind::Root { .. } => Ok(false),
+            RawDirEntryKind::DirEntry { fsdent, .. } => {
+                fsdent.is_mount_point(parent_device, ctx).map_err(into_io_err)
+            },
+        }
+    }
+
+    /// Check if the extended attribute `name` is set (on the target of a
+    /// followed link). Roots are never filtered, so this isn't checked for them.
+    pub fn has_xattr(
+        &self,
+        name: &str,
+        ctx: &mut E::Context,
+    ) -> wd::ResultInner<bool, E> {
+        match &self.kind {
+            RawDirEntryKind::Root { .. } => Ok(false),
+            RawDirEntryKind::DirEntry { fsdent, .. } => {
+                fsdent.has_xattr(name, self.follow_link, ctx).map_err(into_io_err)
+            },
+        }
+    }
+
+    /// Get paths of alternate data streams (roots aren't queried)
+    pub fn alternate_streams(
+        &self,
+        ctx: &mut E::Context,
+    ) -> wd::ResultInner<Vec<E::PathBuf>, E> {
+        match &self.kind {
+            RawDirEntryKind::Root { .. } => Ok(Vec::new()),
+            RawDirEntryKind::DirEntry { fsdent, .. } => {
+                fsdent.alternate_streams(ctx).map_err(into_io_err)
+            },
+        }
+    }
+
+    /// Get kind of this entry if it is a link (the kind of a root link isn't
+    /// checked, it is governed by `follow_root_symlink`)
+    pub fn link_kind(
+        &self,
+        ctx: &mut E::Context,
+    ) -> wd::ResultInner<Option<LinkKind>, E> {
+        match &self.kind {
+            RawDirEntryKind::Root { .. } => Ok(None),
+            RawDirEntryKind::DirEntry { fsdent, .. } => {
+                fsdent.link_kind(ctx).map_err(into_io_err)
+            },
+        }
+    }
+
+    /// Check if this entry is a cloud placeholder (roots are always walked, so
+    /// they are never reported)
+    pub fn is_placeholder(
+        &self,
+        ctx: &mut E::Context,
+    ) -> wd::ResultInner<bool, E> {
+        match &self.kind {
+            RawDirEntryKind::Root { .. } => Ok(false),
+            RawDirEntryKind::DirEntry { fsdent, .. } => {
+                fsdent.is_placeholder(ctx).map_err(into_io_err)
+            },
+        }
+    }
+
+    /// Check if contents of this file look binary (roots are never sniffed)
+    pub fn is_binary(
+        &self,
+        ctx: &mut E::Context,
+    ) -> wd::ResultInner<bool, E> {
+        match &self.kind {
+            RawDirEntryKind::Root { .. } => Ok(false),
+            RawDirEntryKind::DirEntry { fsdent, .. } => {
+                fsdent.is_binary(ctx).map_err(into_io_err)
+            },
+        }
+    }
+
+    /// Get persistent fingerprint of this dir (follow symlink)
+    pub fn dir_id(&self) -> wd::ResultInner<Option<DirId>, E> {
+        E::dir_id_path(self.path()).map_err(into_io_err)
+    }
+
+    /// Check if this entry is excluded from backups (roots never are)
+    pub fn is_nodump(
+        &self,
+        ctx: &mut E::Context,
+    ) -> wd::ResultInner<bool, E> {
+        match &self.kind {
+            RawDirEntryKind::Root { .. } => Ok(false),
+            RawDirEntryKind::DirEntry { fsdent, .. } => {
+                fsdent.is_nodump(self.follow_link, ctx).map_err(into_io_err)
+            },
+        }
+    }
+
     /// Get parts
     pub fn to_parts(
         &mut self,
         force_metadata: bool,
-        force_file_name: bool,
         ctx: &mut E::Context,
-    ) -> (E::PathBuf, Option<E::Metadata>, Option<E::FileName>) {
+    ) -> (E::PathBuf, Option<E::Metadata>) {
         match &mut self.kind {
             RawDirEntryKind::Root { fsdent, .. } => {
-                fsdent.to_parts(self.follow_link, force_metadata, force_file_name, ctx)
+                fsdent.to_parts(self.follow_link, force_metadata, ctx)
             },
             RawDirEntryKind::DirEntry { fsdent, .. } => {
-                fsdent.to_parts(self.follow_link, force_metadata, force_file_name, ctx)
+                fsdent.to_parts(self.follow_link, force_metadata, ctx)
             },
         }
     }
@@ -412,7 +562,11 @@ pub enum ReadDir<E: fs::FsDirEntry> {
     /// [`Option<...>`]: https://doc.rust-lang.org/stable/std/option/enum.Option.html
     Opened { 
         /// Underlying ReadDir
-        rd: E::ReadDir 
+        rd: E::ReadDir,
+        /// Origin of read entries
+        origin: Origin<E::PathBuf>,
+        /// Root device of read entries
+        device: Option<E::DeviceNum>,
     },
 
     /// A closed handle.
@@ -436,12 +590,12 @@ impl<E: fs::FsDirEntry> ReadDir<E> {
     }
 
     /// Create new ReadDir
-    fn new(rd: E::ReadDir) -> Self {
+    fn new(rd: E::ReadDir, origin: Origin<E::PathBuf>, device: Option<E::DeviceNum>) -> Self {
         // match rd {
         //     Ok(rd) => Self::Opened { rd },
         //     Err(err) => Self::Error( Some(err) ),
         // }
-        Self::Opened { rd }
+        Self::Opened { rd, origin, device }
     }
 
     /// Collect all content and make this ReadDir closed
@@ -451,8 +605,8 @@ impl<E: fs::FsDirEntry> ReadDir<E> {
         ctx: &mut E::Context,
     ) -> Vec<T> {
         match self {
-            ReadDir::Opened { rd } => {
-                let entries = ReadDirOpenedIterator::new( rd, process_rawdent, ctx )
+            ReadDir::Opened { rd, origin, device } => {
+                let entries = ReadDirOpenedIterator::new( rd, origin, *device, process_rawdent, ctx )
                     .filter_map(|opt| opt)
                     .collect();
                 *self = ReadDir::<E>::Closed;
@@ -494,9 +648,9 @@ impl<E: fs::FsDirEntry> ReadDir<E> {
             ReadDir::Once { ref mut item } => {
                 item.take().map(Ok)
             },
-            ReadDir::Opened { ref mut rd } => {
+            ReadDir::Opened { ref mut rd, ref origin, device } => {
                 match rd.next_entry(ctx)? {
-                    Ok(fsdent)  => RawDirEntry::<E>::from_fsdent( fsdent, ctx ),
+                    Ok(fsdent)  => RawDirEntry::<E>::from_fsdent( fsdent, ctx ).map(|raw| raw.with_origin(origin.clone()).with_device(device)),
                     Err(e)      => Err(into_io_err(e)),
                 }.into_some()
             },
@@ -519,6 +673,8 @@ where
     P: (FnMut(wd::ResultInner<RawDirEntry<E>, E>, &mut E::Context) -> Option<T>),
 {
     rd: &'c mut E::ReadDir,
+    origin: &'c Origin<E::PathBuf>,
+    device: Option<E::DeviceNum>,
     process_rawdent: &'c mut P,
     ctx: &'c mut E::Context,
 }
@@ -530,11 +686,15 @@ where
 {
     fn new(
         rd: &'c mut E::ReadDir,
+        origin: &'c Origin<E::PathBuf>,
+        device: Option<E::DeviceNum>,
         process_rawdent: &'c mut P,
         ctx: &'c mut E::Context,
     ) -> Self {
         Self {
             rd,
+            origin,
+            device,
             process_rawdent,
             ctx,
         }
@@ -550,7 +710,7 @@ where
 
     fn next(&mut self) -> Option<Self::Item> {
         let rrawdent = match self.rd.next_entry(self.ctx)? {
-            Ok(fsdent)  => RawDirEntry::<E>::from_fsdent( fsdent, self.ctx ),
+            Ok(fsdent)  => RawDirEntry::<E>::from_fsdent( fsdent, self.ctx ).map(|raw| raw.with_origin(self.origin.clone()).with_device(self.device)),
             Err(e)      => Err(into_io_err(e)),
         };
         
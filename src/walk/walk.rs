@@ -1,14 +1,23 @@
-use std::cmp;
-use std::vec;
-
-use crate::cp::ContentProcessor;
-use crate::fs::{self, FsFileType};
-use crate::walk::dir::{DirState, FlatDirEntry};
+use core::any::Any;
+use core::cmp;
+use core::fmt;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use alloc::vec;
+use smallvec::SmallVec;
+
+use crate::cp::{ContentProcessor, DirEntry};
+use crate::fs::{self, FsFileType, FsMetadata, FsPath};
+use crate::walk::dir::{DirEntryRecord, DirState, EntryMiddleware, FlatDirEntry};
 use crate::walk::rawdent::{RawDirEntry};
-use crate::error::{ErrorInner, Error};
+use crate::rng::Rng;
+use crate::error::{ErrorInner, Error, ErrorKind};
 use crate::walk::opts::{WalkDirOptions, WalkDirOptionsImmut};
 use crate::wd::{
-    self, ContentFilter, Depth, FnCmp, IntoOk, IntoSome, Position,
+    self, glob_match, CachePolicy, ContentFilter, ContentOrder, Depth, DirId, FnCmp, FnPass, FnProcessReadDir, IntoOk, IntoSome,
+    Origin, Pass, Position, RootErrorPolicy, SpecialFilePolicy, WalkSummary,
 };
 
 // /// Like try, but for iterators that return [`Option<Result<_, _>>`].
@@ -37,22 +46,37 @@ use crate::wd::{
 
 macro_rules! process_dent {
     ($self:expr, $depth:expr) => {
-        process_dent!(&$self.opts.immut, &$self.root_device, &$self.ancestors, $depth)
+        process_dent!(
+            Self::opts_at(&$self.opts.immut, &$self.depth_opts, $depth),
+            &$self.root_device,
+            &$self.ancestors,
+            Self::parent_device(Self::opts_at(&$self.opts.immut, &$self.depth_opts, $depth), &$self.devices, $depth),
+            &$self.opts.middleware,
+            &mut $self.opts.content_pass,
+            $depth
+        )
     };
-    ($opts_immut:expr, $root_device:expr, $ancestors:expr, $depth:expr) => {
-        ((|opts_immut, root_device, ancestors, depth| {
-            move |raw_dent: RawDirEntry<E>, ctx: &mut E::Context| {
-                Self::process_rawdent(raw_dent, depth, opts_immut, root_device, ancestors, ctx)
-            }
-        })($opts_immut, $root_device, $ancestors, $depth))
+    ($opts_immut:expr, $root_device:expr, $ancestors:expr, $parent_device:expr, $middleware:expr, $content_pass:expr, $depth:expr) => {
+        Self::rawdent_processor($opts_immut, $root_device, $ancestors, $parent_device, $middleware, $content_pass, $depth)
     };
 }
 
+/// Report an error about to be yielded to tracing
+#[cfg(feature = "tracing")]
+fn trace_error<E: fs::FsDirEntry>(err: &Error<E>) {
+    tracing::warn!(depth = err.depth(), error = %err, "walkdir error");
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+fn trace_error<E: fs::FsDirEntry>(_err: &Error<E>) {}
+
 /// Type of item for Iterators
 pub type WalkDirIteratorItem<E, CP> = Position<
     (<CP as ContentProcessor<E>>::Item, <CP as ContentProcessor<E>>::Collection),
     <CP as ContentProcessor<E>>::Item,
     Error<E>,
+    <E as fs::FsDirEntry>::DeviceNum,
 >;
 
 /////////////////////////////////////////////////////////////////////////
@@ -66,6 +90,8 @@ struct Ancestor<E: fs::FsDirEntry> {
     path: E::PathBuf,
     /// Fingerprint
     fingerprint: E::DirFingerprint,
+    /// Times the same dir was entered through a loop above this ancestor
+    revisits: usize,
 }
 
 impl<E: fs::FsDirEntry> Ancestor<E> {
@@ -76,10 +102,19 @@ impl<E: fs::FsDirEntry> Ancestor<E> {
     ) -> wd::ResultInner<Self, E> {
         Self { 
             path: raw.pathbuf(), 
-            fingerprint: raw.fingerprint(ctx)? 
+            fingerprint: raw.fingerprint(ctx)?,
+            revisits: 0,
         }.into_ok()
     }
 
+    /// Count a revisit if this dir is entered again through a loop.
+    fn count_revisits(mut self, ancestors: &[Ancestor<E>]) -> Self {
+        if let Some(ancestor) = ancestors.iter().rev().find(|a| a.is_same(&self)) {
+            self.revisits = ancestor.revisits + 1;
+        }
+        self
+    }
+
     /// Returns true if and only if the given open file handle corresponds to
     /// the same directory as this ancestor.
     fn is_same(&self, rhs: &Self) -> bool {
@@ -110,7 +145,6 @@ enum TransitionState {
 ///
 /// [`WalkDir`]: struct.WalkDir.html
 /// [`.into_iter()`]: struct.WalkDir.html#into_iter.v
-#[derive(Debug)]
 pub struct WalkDirIterator<E, CP>
 where
     E: fs::FsDirEntry,
@@ -120,24 +154,47 @@ where
     opts: WalkDirOptions<E, CP>,
     /// The start path.
     ///
-    /// This is only `Some(...)` at the beginning. After the first iteration,
-    /// this is always `None`.
+    /// This is only `Some(...)` at the beginning (and before walking each of
+    /// `next_roots`). After the first iteration, this is always `None`.
     start: Option<E::PathBuf>,
+    /// Roots to walk after the current one.
+    next_roots: VecDeque<E::PathBuf>,
+    /// Options with overrides applied, for each depth where they change.
+    ///
+    /// This is *only* used when [`override_at_depth`] was called. In all
+    /// other cases this vector is empty.
+    ///
+    /// [`override_at_depth`]: struct.WalkDir.html#method.override_at_depth
+    depth_opts: Vec<(Depth, WalkDirOptionsImmut)>,
+    /// Index of the current root.
+    root_index: usize,
+    /// Alternate data streams of the last yielded file, to be yielded next
+    /// (with their depth).
+    ///
+    /// This is *only* used when [`alternate_streams`] is enabled.
+    ///
+    /// [`alternate_streams`]: struct.WalkDir.html#method.alternate_streams
+    streams: VecDeque<(wd::ResultInner<RawDirEntry<E>, E>, Depth)>,
+    /// Depth of the last yielded item if it's an alternate data stream (or an
+    /// error about one), which isn't part of the content of any dir.
+    stream_depth: Option<Depth>,
     /// A stack of open (up to max fd) or closed handles to directories.
     /// An open handle is a plain [`fs::ReadDir`] while a closed handle is
     /// a `Vec<fs::DirEntry>` corresponding to the as-of-yet consumed entries.
     ///
     /// [`fs::ReadDir`]: https://doc.rust-lang.org/stable/std/fs/struct.ReadDir.html
-    states: Vec<DirState<E, CP>>,
+    states: Stack<DirState<E, CP>>,
     /// before push down / after pop up
     transition_state: TransitionState,
-    /// A stack of file paths.
+    /// A stack of file paths, one for every open dir (so its index is the
+    /// depth of the dir).
     ///
-    /// This is *only* used when [`follow_links`] is enabled. In all other
-    /// cases this stack is empty.
+    /// This is *only* used when [`follow_links`] is enabled (for any depth, see
+    /// [`override_at_depth`]). In all other cases this stack is empty.
     ///
     /// [`follow_links`]: struct.WalkDir.html#method.follow_links
-    ancestors: Vec<Ancestor<E>>,
+    /// [`override_at_depth`]: struct.WalkDir.html#method.override_at_depth
+    ancestors: Stack<Ancestor<E>>,
     /// An index into `states` that points to the oldest open directory
     /// handle. If the maximum fd limit is reached and a new directory needs to
     /// be read, the handle at this index is closed before the new directory is
@@ -153,8 +210,64 @@ where
     /// `None`. Conversely, if it is enabled, this is always `Some(...)` after
     /// handling the root path.
     root_device: Option<E::DeviceNum>,
+    /// Canonical paths of met entries.
+    ///
+    /// This is *only* used when [`dedupe_canonical`] is enabled. In all other
+    /// cases this set is empty.
+    ///
+    /// [`dedupe_canonical`]: struct.WalkDir.html#method.dedupe_canonical
+    seen: BTreeSet<E::PathBuf>,
+    /// Fingerprints of descended dirs.
+    ///
+    /// This is *only* used when [`record_fingerprints`] is enabled. In all
+    /// other cases this set is empty.
+    ///
+    /// [`record_fingerprints`]: struct.WalkDir.html#method.record_fingerprints
+    visited: BTreeSet<DirId>,
+    /// Canonical paths of met dirs, with the index of the root they were met
+    /// under first.
+    ///
+    /// This is *only* used when [`dedupe_overlapping_roots`] is enabled. In
+    /// all other cases this map is empty.
+    ///
+    /// [`dedupe_overlapping_roots`]: struct.WalkDir.html#method.dedupe_overlapping_roots
+    root_dirs: BTreeMap<E::PathBuf, usize>,
+    /// The pseudo-random generator used by [`sample`].
+    ///
+    /// [`sample`]: struct.WalkDir.html#method.sample
+    sample_rng: Rng,
+    /// The last yielded item is a dir yielded after its content (in
+    /// `contents_first`, `post_order` or `announce_dirs` mode).
+    yielded_after_content: bool,
+    /// A user callback panicked inside `next`, so the state may be
+    /// inconsistent and the walk is over.
+    poisoned: bool,
+    /// The walk is over (and `Position::Finished` was yielded if requested).
+    finished: bool,
+    /// The device of a root couldn't be queried in `same_file_system_soft`
+    /// mode, so it was walked across file systems.
+    same_file_system_ignored: bool,
+    /// Counts of roots, entries and errors met so far.
+    summary: WalkSummary,
+    /// When `next` was called for the first time.
+    #[cfg(feature = "std")]
+    started_at: Option<std::time::Instant>,
+    /// Record vectors of popped dirs, reused by pushed ones.
+    pool: Vec<Vec<DirEntryRecord<E>>>,
+    /// Devices of entered dirs (`None` if unknown).
+    ///
+    /// This is *only* used when [`device_boundaries`] or mount point detection
+    /// is enabled. In all other cases this stack is empty.
+    ///
+    /// [`device_boundaries`]: struct.WalkDir.html#method.device_boundaries
+    devices: Stack<Option<E::DeviceNum>>,
 }
 
+/// Count of dirs kept inline in the state stacks (and of pooled record vectors).
+const STACK_INLINE: usize = 16;
+
+type Stack<T> = SmallVec<[T; STACK_INLINE]>;
+
 type PushDirData<E, CP> = (DirState<E, CP>, Option<Ancestor<E>>);
 
 impl<E, CP> WalkDirIterator<E, CP>
@@ -164,33 +277,200 @@ where
 {
     /// Make new
     pub fn new(opts: WalkDirOptions<E, CP>, root: E::PathBuf) -> Self {
+        Self::with_roots(opts, vec![root])
+    }
+
+    /// Make new walking the given roots one after another
+    pub fn with_roots(mut opts: WalkDirOptions<E, CP>, roots: Vec<E::PathBuf>) -> Self {
+        if let Some(batch) = opts.immut.readdir_batch {
+            E::set_readdir_batch(&mut opts.ctx, batch);
+        }
+        if opts.immut.metadata_cache != CachePolicy::None {
+            E::set_metadata_cache(&mut opts.ctx, opts.immut.metadata_cache);
+        }
+        let mut next_roots = VecDeque::from(roots);
+        let sample_rng = Rng::new(opts.immut.sample.map_or(0, |(_, seed)| seed));
+        let depth_opts = opts.depth_levels();
         Self {
             opts,
-            start: Some(root),
-            states: vec![],
+            start: next_roots.pop_front(),
+            next_roots,
+            depth_opts,
+            root_index: 0,
+            streams: VecDeque::new(),
+            stream_depth: None,
+            states: Stack::new(),
             transition_state: TransitionState::None,
-            ancestors: vec![],
+            ancestors: Stack::new(),
             oldest_opened: 0,
             depth: 0,
             root_device: None,
+            seen: BTreeSet::new(),
+            visited: BTreeSet::new(),
+            root_dirs: BTreeMap::new(),
+            sample_rng,
+            yielded_after_content: false,
+            poisoned: false,
+            finished: false,
+            same_file_system_ignored: false,
+            summary: WalkSummary::default(),
+            #[cfg(feature = "std")]
+            started_at: None,
+            pool: Vec::new(),
+            devices: Stack::new(),
+        }
+    }
+
+    /// Takes back the options (with the fs context and content processor)
+    #[cfg(feature = "notify")]
+    pub(crate) fn into_options(self) -> WalkDirOptions<E, CP> {
+        self.opts
+    }
+
+    /// Returns `true` if a user callback panicked inside [`next`] and the walk
+    /// was stopped.
+    ///
+    /// [`next`]: #method.next
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Returns `true` if the walk is over: every root was walked (or failed
+    /// to open) and [`next`] returns `None` from now on. Use [`summary`] to
+    /// tell whether anything was walked at all.
+    ///
+    /// [`next`]: #method.next
+    /// [`summary`]: #method.summary
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns `true` if the device of a root couldn't be queried, so it was
+    /// walked without the `same_file_system` restriction (see
+    /// [`same_file_system_soft`]).
+    ///
+    /// [`same_file_system_soft`]: struct.WalkDir.html#method.same_file_system_soft
+    pub fn same_file_system_ignored(&self) -> bool {
+        self.same_file_system_ignored
+    }
+
+    /// Returns the device of the root being walked if [`same_file_system`]
+    /// or [`follow_links_same_device`] is enabled (at any depth), once the
+    /// root was read.
+    /// With `same_file_system` it is the device of every yielded entry too
+    /// (see [`DirEntry::device`]).
+    ///
+    /// [`same_file_system`]: struct.WalkDir.html#method.same_file_system
+    /// [`follow_links_same_device`]: struct.WalkDir.html#method.follow_links_same_device
+    /// [`DirEntry::device`]: struct.DirEntry.html#method.device
+    pub fn root_device(&self) -> Option<E::DeviceNum> {
+        self.root_device
+    }
+
+    // Device shared by all entries of the root (only if the walk never leaves it)
+    pub(crate) fn entries_device(&self) -> Option<E::DeviceNum> {
+        self.root_device.filter(|_| self.opts.immut.same_file_system)
+    }
+
+    /// Counts of roots, entries and errors met so far (see [`report_finished`]).
+    /// The duration is set when the walk is over.
+    ///
+    /// [`report_finished`]: struct.WalkDir.html#method.report_finished
+    pub fn summary(&self) -> WalkSummary {
+        self.summary
+    }
+
+    /// Get the fs context (e.g. to store collected data after walking)
+    pub fn context(&self) -> &E::Context {
+        &self.opts.ctx
+    }
+
+    /// Options this iterator was built with (without the ones set by
+    /// [`override_at_depth`], see [`options_at`])
+    ///
+    /// [`override_at_depth`]: struct.WalkDir.html#method.override_at_depth
+    /// [`options_at`]: #method.options_at
+    pub fn options(&self) -> &WalkDirOptionsImmut {
+        &self.opts.immut
+    }
+
+    /// Options in effect for the content of dirs at `depth` (with overrides
+    /// set by [`override_at_depth`] applied)
+    ///
+    /// [`override_at_depth`]: struct.WalkDir.html#method.override_at_depth
+    pub fn options_at(&self, depth: Depth) -> &WalkDirOptionsImmut {
+        Self::opts_at(&self.opts.immut, &self.depth_opts, depth)
+    }
+
+    /// Fingerprints of dirs descended into so far (see [`record_fingerprints`])
+    ///
+    /// [`record_fingerprints`]: struct.WalkDir.html#method.record_fingerprints
+    pub fn visited_fingerprints(&self) -> &BTreeSet<DirId> {
+        &self.visited
+    }
+
+    // process_rawdent bound to the state of the walker at depth (see process_dent!).
+    fn rawdent_processor<'a>(
+        opts_immut: &'a WalkDirOptionsImmut,
+        root_device: &'a Option<E::DeviceNum>,
+        ancestors: &'a [Ancestor<E>],
+        parent_device: Option<&'a E::DeviceNum>,
+        middleware: &'a [Arc<dyn EntryMiddleware<E>>],
+        content_pass: &'a mut Option<FnPass<E>>,
+        depth: Depth,
+    ) -> impl FnMut(RawDirEntry<E>, &mut E::Context) -> Option<wd::ResultInner<FlatDirEntry<E>, E>> + 'a {
+        move |raw_dent, ctx| {
+            Self::process_rawdent(raw_dent, depth, opts_immut, root_device, ancestors, parent_device, middleware, content_pass, ctx)
         }
     }
 
-    // Follow symlinks and check same_file_system. Also determine is_dir flag.
+    // Follow symlinks, check same_file_system and mount points. Also determine is_dir flag.
     // - Some(Ok((dent, is_dir))) -- normal entry to yielding
     // - Some(Err(_)) -- some error occured
     // - None -- entry must be ignored
+    #[allow(clippy::too_many_arguments)]
     fn process_rawdent(
         rawdent: RawDirEntry<E>,
         depth: Depth,
         opts_immut: &WalkDirOptionsImmut,
         root_device_opt: &Option<E::DeviceNum>,
-        ancestors: &Vec<Ancestor<E>>,
+        ancestors: &[Ancestor<E>],
+        parent_device: Option<&E::DeviceNum>,
+        middleware: &[Arc<dyn EntryMiddleware<E>>],
+        content_pass: &mut Option<FnPass<E>>,
         ctx: &mut E::Context,
     ) -> Option<wd::ResultInner<FlatDirEntry<E>, E>> {
+        if let Some(max) = opts_immut.max_path_len {
+            let len = rawdent.path().path_len();
+            if len > max {
+                return Err(ErrorInner::<E>::from_too_long(rawdent.path(), len, max)).into_some();
+            }
+        }
+
+        let placeholder = if opts_immut.avoid_hydration && depth > 0 {
+            match rawdent.is_placeholder(ctx) {
+                Ok(v) => v,
+                Err(err) => return Err(err).into_some(),
+            }
+        } else {
+            false
+        };
+
+        let follow = rawdent.is_symlink() && !placeholder
+            && ((depth == 0 && opts_immut.follow_root_metadata)
+                || (opts_immut.follow_links
+                    && match Self::follows_link_kind(opts_immut, &rawdent, ctx) {
+                        Ok(v) => v,
+                        Err(err) => return Err(err).into_some(),
+                    }
+                    && match Self::follows_to_device(opts_immut, root_device_opt, &rawdent, ctx) {
+                        Ok(v) => v,
+                        Err(err) => return Err(err).into_some(),
+                    }));
+
         let (rawdent, loop_link) =
-            if rawdent.is_symlink() && opts_immut.follow_links {
-                let (rawdent, loop_link) = match Self::follow(rawdent, ancestors, ctx) {
+            if follow {
+                let (rawdent, loop_link) = match Self::follow(rawdent, opts_immut, ancestors, ctx) {
                     Ok(v) => v,
                     Err(err) => return Err(err).into_some(),    
                 };
@@ -199,18 +479,43 @@ where
                 (rawdent, None)
             };
 
-        let mut is_normal_dir = !rawdent.is_symlink() && rawdent.is_dir();
+        let mut is_normal_dir = !placeholder && !rawdent.is_symlink() && rawdent.is_dir();
+        let mut mount_point = false;
+
+        if is_normal_dir && depth > 0 && Self::is_treated_as_file(opts_immut, rawdent.path()) {
+            is_normal_dir = false;
+        }
+
+        if is_normal_dir && depth > 0 && Self::is_unmodified(opts_immut, &rawdent, ctx) {
+            is_normal_dir = false;
+        }
+
+        if is_normal_dir && depth > 0 && Self::has_skipped_fingerprint(opts_immut, &rawdent) {
+            is_normal_dir = false;
+        }
 
         if is_normal_dir {
             if opts_immut.same_file_system && depth > 0 {
-                let root_device = root_device_opt.as_ref().expect("BUG: called is_same_file_system without root device");
-                match Self::is_same_file_system(root_device, &rawdent, ctx) {
-                    Ok(true) => {},
-                    Ok(false) => return None,
-                    Err(err) => return Err(err).into_some(),    
+                match root_device_opt {
+                    Some(root_device) => match Self::is_same_file_system(root_device, &rawdent, ctx) {
+                        Ok(true) => {},
+                        Ok(false) => return None,
+                        Err(_) if opts_immut.same_file_system_soft => {},
+                        Err(err) => return Err(err).into_some(),
+                    },
+                    None => assert!(opts_immut.same_file_system_soft, "BUG: called is_same_file_system without root device"),
                 }
             };
-        } else if depth == 0 && rawdent.is_symlink() {
+            if let Some(parent_device) = parent_device {
+                mount_point = match rawdent.is_mount_point(parent_device, ctx) {
+                    Ok(v) => v,
+                    Err(err) => return Err(err).into_some(),
+                };
+                if mount_point && opts_immut.skip_mount_points {
+                    is_normal_dir = false;
+                }
+            }
+        } else if depth == 0 && rawdent.is_symlink() && opts_immut.follow_root_symlink {
             // As a special case, if we are processing a root entry, then we
             // always follow it even if it's a symlink and follow_links is
             // false. We are careful to not let this change the semantics of
@@ -224,28 +529,192 @@ where
             }.is_dir();
         };
 
-        FlatDirEntry { 
+        if opts_immut.skip_nodump && depth > 0 {
+            if let Ok(true) = rawdent.is_nodump(ctx) {
+                return None;
+            }
+        }
+
+        if opts_immut.special_files != SpecialFilePolicy::Yield && depth > 0 && Self::is_special(rawdent.file_type()) {
+            match opts_immut.special_files {
+                SpecialFilePolicy::Skip => return None,
+                _ => return Err(ErrorInner::<E>::from_special_file(rawdent.path())).into_some(),
+            }
+        }
+
+        if opts_immut.skip_binary_files && depth > 0 && !placeholder && rawdent.file_type().is_file() {
+            match rawdent.is_binary(ctx) {
+                Ok(true) => return None,
+                Ok(false) => {},
+                Err(err) => return Err(err).into_some(),
+            }
+        }
+
+        let xattr_hidden = match opts_immut.xattr_filter {
+            Some((ref name, present)) if depth > 0 => match rawdent.has_xattr(name, ctx) {
+                Ok(has) => has != present,
+                Err(err) => return Err(err).into_some(),
+            },
+            _ => false,
+        };
+
+        let mut flat = FlatDirEntry { 
             raw: rawdent, 
             is_dir: is_normal_dir, 
-            loop_link 
-        }.into_ok().into_some()
+            loop_link,
+            sampled_out: false,
+            unicode_collision: false,
+            mount_point,
+            xattr_hidden,
+            pass: Pass::SECOND,
+        };
+        for mw in middleware {
+            flat = mw.process(flat, depth, ctx)?;
+        }
+        flat.pass = match opts_immut.content_order {
+            ContentOrder::None => Pass::SECOND,
+            ContentOrder::DirsFirst if flat.is_dir => Pass::FIRST,
+            ContentOrder::FilesFirst if !flat.is_dir => Pass::FIRST,
+            ContentOrder::DirsFirst | ContentOrder::FilesFirst => Pass::SECOND,
+            ContentOrder::Custom => match content_pass {
+                Some(f) => f(&flat),
+                None => Pass::SECOND,
+            },
+        };
+        flat.into_ok().into_some()
+    }
+
+    // Device of a root for `same_file_system` and `follow_links_same_device` (`None`
+    // if both options are disabled at every depth, or if
+    // the device can't be queried in `same_file_system_soft` mode).
+    pub(crate) fn root_device_of(
+        opts_immut: &WalkDirOptionsImmut,
+        levels: &[(Depth, WalkDirOptionsImmut)],
+        root: &RawDirEntry<E>,
+        ctx: &mut E::Context,
+    ) -> wd::ResultInner<Option<E::DeviceNum>, E> {
+        if !Self::at_any_depth(opts_immut, levels, |opts| opts.same_file_system || opts.follow_links_same_device) {
+            return Ok(None);
+        }
+        match root.device_num(ctx) {
+            Ok(device) => Ok(Some(device)),
+            Err(_err) if Self::at_any_depth(opts_immut, levels, |opts| opts.same_file_system_soft) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    error = %Error::from_inner(_err, 0),
+                    "device of the root is unknown, walking without same_file_system"
+                );
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    // Same as process_rawdent, but loops are never detected (see PriorityIter).
+    pub(crate) fn process_rawdent_without_ancestors(
+        rawdent: RawDirEntry<E>,
+        depth: Depth,
+        opts_immut: &WalkDirOptionsImmut,
+        root_device_opt: &Option<E::DeviceNum>,
+        middleware: &[Arc<dyn EntryMiddleware<E>>],
+        ctx: &mut E::Context,
+    ) -> Option<wd::ResultInner<FlatDirEntry<E>, E>> {
+        Self::process_rawdent(rawdent, depth, opts_immut, root_device_opt, &[], None, middleware, &mut None, ctx)
+    }
+
+    // Options for entries at depth (with overrides applied).
+    fn opts_at<'o>(
+        base: &'o WalkDirOptionsImmut,
+        levels: &'o [(Depth, WalkDirOptionsImmut)],
+        depth: Depth,
+    ) -> &'o WalkDirOptionsImmut {
+        match levels.iter().rev().find(|(from, _)| *from <= depth) {
+            Some((_, opts)) => opts,
+            None => base,
+        }
+    }
+
+    // Dirs are yielded before their content too (never in post-order).
+    fn announces_dirs(opts_immut: &WalkDirOptionsImmut) -> bool {
+        opts_immut.announce_dirs && !opts_immut.post_order
+    }
+
+    // Dirs are yielded after their content.
+    fn yields_dirs_after_content(opts_immut: &WalkDirOptionsImmut) -> bool {
+        opts_immut.contents_first || opts_immut.post_order || Self::announces_dirs(opts_immut)
+    }
+
+    // Check the base options and every override.
+    fn at_any_depth(
+        base: &WalkDirOptionsImmut,
+        levels: &[(Depth, WalkDirOptionsImmut)],
+        f: impl Fn(&WalkDirOptionsImmut) -> bool,
+    ) -> bool {
+        f(base) || levels.iter().any(|(_, opts)| f(opts))
+    }
+
+    // Entered dirs are tracked as ancestors if links are followed at any depth, so
+    // a loop is found whatever depth the followed link is at.
+    fn tracks_ancestors(base: &WalkDirOptionsImmut, levels: &[(Depth, WalkDirOptionsImmut)]) -> bool {
+        Self::at_any_depth(base, levels, |opts| opts.follow_links)
+    }
+
+    // Devices of entered dirs are tracked for device boundaries and mount points
+    // (if any depth asks for them, so the stack matches the dir stack).
+    fn tracks_devices(base: &WalkDirOptionsImmut, levels: &[(Depth, WalkDirOptionsImmut)]) -> bool {
+        Self::at_any_depth(base, levels, |opts| opts.device_boundaries || Self::detects_mount_points(opts))
+    }
+
+    fn detects_mount_points(opts_immut: &WalkDirOptionsImmut) -> bool {
+        opts_immut.detect_mount_points || opts_immut.skip_mount_points
+    }
+
+    // Device of the dir whose content is at `depth` (only if mount points are detected).
+    fn parent_device<'d>(
+        opts_immut: &WalkDirOptionsImmut,
+        devices: &'d [Option<E::DeviceNum>],
+        depth: Depth,
+    ) -> Option<&'d E::DeviceNum> {
+        if !Self::detects_mount_points(opts_immut) {
+            return None;
+        }
+        depth.checked_sub(1).and_then(|i| devices.get(i)).and_then(|device| device.as_ref())
     }
 
     fn init(
         &mut self, 
         root_path: &E::Path, 
     ) -> wd::ResultInner<(), E> {
-        let root = RawDirEntry::<E>::from_path( root_path, &mut self.opts.ctx )?;
+        let root = RawDirEntry::<E>::from_path( root_path, &mut self.opts.ctx )?
+            .with_origin(Origin::new(self.root_index));
 
-        if self.opts.immut.same_file_system {
-            self.root_device = Some(root.device_num(&mut self.opts.ctx)?);
+        self.root_device = Self::root_device_of(&self.opts.immut, &self.depth_opts, &root, &mut self.opts.ctx)?;
+        if self.root_device.is_none() && Self::at_any_depth(&self.opts.immut, &self.depth_opts, |opts| opts.same_file_system) {
+            self.same_file_system_ignored = true;
         }
 
-        self.push_root(root, 0)?;
+        self.push_root(root.with_device(self.entries_device()), 0)?;
 
         Ok(())
     }
 
+    // Switch to the next root (if any), dropping the state of the current one.
+    fn next_root(&mut self) -> bool {
+        let root = match self.next_roots.pop_front() {
+            Some(root) => root,
+            None => return false,
+        };
+        self.states.clear();
+        self.ancestors.clear();
+        self.devices.clear();
+        self.transition_state = TransitionState::None;
+        self.oldest_opened = 0;
+        self.root_device = None;
+        self.root_index += 1;
+        self.start = Some(root);
+        true
+    }
+
     fn push_root(
         &mut self, 
         root: RawDirEntry<E>, 
@@ -254,7 +723,7 @@ where
         let state = DirState::<E, CP>::new_once(
             root,
             depth,
-            &self.opts.immut,
+            Self::opts_at(&self.opts.immut, &self.depth_opts, depth),
             &mut self.opts.sorter,
             &mut process_dent!(self, depth),
             &mut self.opts.ctx,
@@ -271,20 +740,27 @@ where
         if free == self.opts.immut.max_open {
             let state = self.states.get_mut(self.oldest_opened).unwrap();
             state.load_all(
-                &self.opts.immut,
+                Self::opts_at(&self.opts.immut, &self.depth_opts, state.depth()),
                 &mut process_dent!(self, state.depth()),
                 &mut self.opts.ctx,
             );
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn push_dir_1(
         flat: &FlatDirEntry<E>,
         new_depth: Depth,
         opts_immut: &WalkDirOptionsImmut,
         sorter: &mut Option<FnCmp<E>>,
+        process_read_dir: Option<&FnProcessReadDir<E>>,
         root_device: &Option<E::DeviceNum>,
-        ancestors: &Vec<Ancestor<E>>,
+        ancestors: &[Ancestor<E>],
+        tracks_ancestors: bool,
+        device: Option<&E::DeviceNum>,
+        middleware: &[Arc<dyn EntryMiddleware<E>>],
+        content_pass: &mut Option<FnPass<E>>,
+        pool: &mut Vec<Vec<DirEntryRecord<E>>>,
         ctx: &mut E::Context,
     ) -> wd::ResultInner<PushDirData<E, CP>, E> {
         // This is safe as we makes any changes strictly AFTER using dent_ptr.
@@ -298,12 +774,17 @@ where
             new_depth,
             opts_immut,
             sorter,
-            &mut process_dent!(opts_immut, root_device, ancestors, new_depth),
+            process_read_dir,
+            pool.pop().unwrap_or_default(),
+            &mut process_dent!(opts_immut, root_device, ancestors, device, middleware, content_pass, new_depth),
             ctx,
         )?;
 
-        let ancestor = if opts_immut.follow_links {
-            let ancestor = Ancestor::new(&flat.raw, ctx)?;
+        let ancestor = if tracks_ancestors {
+            let mut ancestor = Ancestor::new(&flat.raw, ctx)?;
+            if opts_immut.loop_revisits > 0 {
+                ancestor = ancestor.count_revisits(ancestors);
+            }
             Some(ancestor)
         } else {
             None
@@ -339,11 +820,34 @@ where
         self.states.push(state);
     }
 
+    // Remember the device of the entered dir (at `depth`), return a boundary if it
+    // differs from the parent's.
+    fn push_device(&mut self, device: Option<E::DeviceNum>, depth: Depth) -> Option<(E::DeviceNum, E::DeviceNum)> {
+        if !Self::tracks_devices(&self.opts.immut, &self.depth_opts) {
+            return None;
+        }
+        let parent = self.devices.last().copied().flatten();
+        self.devices.push(device);
+        let boundaries = Self::opts_at(&self.opts.immut, &self.depth_opts, depth).device_boundaries;
+        match (parent, device) {
+            (Some(from), Some(to)) if from != to && boundaries => Some((from, to)),
+            _ => None,
+        }
+    }
+
     fn pop_dir(&mut self) {
-        self.states.pop().expect("BUG: cannot pop from empty stack");
-        if self.opts.immut.follow_links {
+        let state = self.states.pop().expect("BUG: cannot pop from empty stack");
+        #[cfg(feature = "tracing")]
+        state.trace_leave();
+        if self.pool.len() < STACK_INLINE {
+            self.pool.push(state.into_records());
+        }
+        if Self::tracks_ancestors(&self.opts.immut, &self.depth_opts) {
             self.ancestors.pop().expect("BUG: list/path stacks out of sync");
         }
+        if Self::tracks_devices(&self.opts.immut, &self.depth_opts) {
+            self.devices.pop().expect("BUG: list/device stacks out of sync");
+        }
         // If everything in the stack is already closed, then there is
         // room for at least one more open descriptor and it will
         // always be at the top of the stack.
@@ -356,6 +860,25 @@ where
     /// recently yielded directory. This means any remaining entries in that
     /// directory will be skipped (including sub-directories).
     ///
+    /// More precisely, the effect depends on the last yielded item:
+    ///
+    /// * a directory yielded before its contents: its contents are skipped
+    ///   (the directory itself is still yielded after them if
+    ///   `contents_first`, `post_order` or `announce_dirs` is enabled);
+    /// * a directory yielded after its contents: nothing is skipped;
+    /// * `Position::BeforeContent`: the contents of that directory are skipped;
+    /// * `Position::AfterContent`: nothing is skipped;
+    /// * `Position::DeviceBoundary`: the contents of the entered directory are
+    ///   skipped;
+    /// * `Position::DirSkipped`: nothing is skipped;
+    /// * an error about a directory which isn't descended into (e.g. it can't
+    ///   be opened or it is a loop): nothing is skipped;
+    /// * any other entry or error: the remaining entries of the directory
+    ///   containing it are skipped.
+    ///
+    /// Entries of the skipped directory which are already read into memory
+    /// are discarded and its handle (if still open) is closed.
+    ///
     /// Note that the ergonomics of this method are questionable since it
     /// borrows the iterator mutably. Namely, you must write out the looping
     /// condition manually. For example, to skip hidden entries efficiently on
@@ -394,21 +917,87 @@ where
     ///
     /// [`filter_entry`]: #method.filter_entry
     pub fn skip_current_dir(&mut self) {
+        self.streams.clear();
+        if self.poisoned || self.yielded_after_content {
+            // Content of the yielded dir is already walked
+            return;
+        }
+        match self.transition_state {
+            // A dir is yielded before its content: don't descend into it
+            TransitionState::CloseOldestBeforePushDown | TransitionState::BeforePushDown => {
+                self.transition_state = TransitionState::AfterPopUp;
+            }
+            // A dir is yielded but won't be descended into anyway, or
+            // Position::AfterContent is yielded
+            TransitionState::AfterPopUp | TransitionState::BeforePopUp => {}
+            TransitionState::None => {
+                if let Some(cur_state) = self.states.last_mut() {
+                    cur_state.skip_all();
+                }
+            }
+        }
+    }
+
+    /// Skips the remaining entries of the current directory.
+    ///
+    /// This abandons the entries of the directory containing the least
+    /// recently yielded item which aren't yielded yet, but the walk goes on
+    /// as usual: `Position::AfterContent` of the directory is still yielded
+    /// (and so is the directory itself, if it is yielded after its contents).
+    /// This is useful when a consumer finds what it needs in a directory, e.g.
+    /// a manifest file.
+    ///
+    /// Unlike [`skip_current_dir`], this never descends: after a directory
+    /// yielded before its contents, its contents are skipped along with its
+    /// siblings. After `Position::BeforeContent` or `Position::DeviceBoundary`
+    /// all the contents of the entered directory are skipped, and after
+    /// `Position::AfterContent` nothing is skipped.
+    ///
+    /// ```no_run
+    /// use walkdir::{DefaultDirEntry, WalkDirBuilder, WalkDirIter, ClassicWalkDirIter};
+    ///
+    /// let mut it = WalkDirBuilder::<DefaultDirEntry>::new("foo").into_classic();
+    /// while let Some(entry) = it.next() {
+    ///     let entry = entry.unwrap();
+    ///     if entry.file_name() == "Cargo.toml" {
+    ///         println!("{}", entry.path().display());
+    ///         it.skip_siblings();
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`skip_current_dir`]: #method.skip_current_dir
+    pub fn skip_siblings(&mut self) {
+        self.streams.clear();
+        if self.poisoned {
+            return;
+        }
+        match self.transition_state {
+            // Position::AfterContent is yielded: the dir is already walked
+            TransitionState::BeforePopUp => return,
+            // A dir is yielded: don't descend into it (its content is skipped too)
+            TransitionState::CloseOldestBeforePushDown
+            | TransitionState::BeforePushDown
+            | TransitionState::AfterPopUp => {
+                self.transition_state = TransitionState::None;
+            }
+            TransitionState::None => {}
+        }
         if let Some(cur_state) = self.states.last_mut() {
             cur_state.skip_all();
-            self.transition_state = TransitionState::None;
         }
     }
 
     fn follow(
         raw: RawDirEntry<E>,
-        ancestors: &Vec<Ancestor<E>>,
+        opts_immut: &WalkDirOptionsImmut,
+        ancestors: &[Ancestor<E>],
         ctx: &mut E::Context,
     ) -> wd::ResultInner<(RawDirEntry<E>, Option<Depth>), E> {
         let dent = raw.follow(ctx)?;
 
         let loop_link = if dent.is_dir() && !ancestors.is_empty() {
-            Self::check_loop( &dent, ancestors, ctx )?
+            Self::check_loop( &dent, opts_immut.loop_revisits, ancestors, ctx )?
         } else {
             None
         };
@@ -418,14 +1007,16 @@ where
 
     fn check_loop(
         raw: &RawDirEntry<E>,
-        ancestors: &Vec<Ancestor<E>>,
+        loop_revisits: usize,
+        ancestors: &[Ancestor<E>],
         ctx: &mut E::Context,
     ) -> wd::ResultInner<Option<Depth>, E> {
         let raw_as_ancestor = Ancestor::<E>::new( raw, ctx )?;
 
         for (index, ancestor) in ancestors.iter().enumerate().rev() {
             if ancestor.is_same(&raw_as_ancestor) {
-                return Ok(Some(index));
+                // The nearest same ancestor knows how many times the loop was entered
+                return Ok(if ancestor.revisits < loop_revisits { None } else { Some(index) });
             }
         }
 
@@ -433,13 +1024,146 @@ where
     }
 
     fn make_loop_error(
-        ancestors: &Vec<Ancestor<E>>,
+        ancestors: &[Ancestor<E>],
         depth: Depth,
         child: &E::Path,
+        child_depth: Depth,
     ) -> ErrorInner<E> {
         let ancestor = ancestors.get(depth).unwrap();
 
-        ErrorInner::<E>::from_loop(&ancestor.path, child)
+        ErrorInner::<E>::from_loop(&ancestor.path, depth, child, child_depth)
+    }
+
+    // Queue alternate data streams of a yielded file at `depth`.
+    fn queue_streams(
+        streams: &mut VecDeque<(wd::ResultInner<RawDirEntry<E>, E>, Depth)>,
+        opts_immut: &WalkDirOptionsImmut,
+        flat: &FlatDirEntry<E>,
+        depth: Depth,
+        ctx: &mut E::Context,
+    ) {
+        if !opts_immut.alternate_streams || depth >= opts_immut.max_depth {
+            return;
+        }
+        if flat.raw.is_dir() || flat.raw.is_symlink() {
+            return;
+        }
+        match flat.raw.alternate_streams(ctx) {
+            Ok(paths) => {
+                for path in paths {
+                    let raw = RawDirEntry::<E>::from_path(path.as_ref(), ctx)
+                        .map(|raw| raw.with_origin(flat.raw.origin().clone()).with_device(flat.raw.root_device()));
+                    streams.push_back((raw, depth + 1));
+                }
+            }
+            Err(err) => streams.push_back((Err(err), depth + 1)),
+        }
+    }
+
+    // Check if a link may be followed according to its kind.
+    fn follows_link_kind(
+        opts_immut: &WalkDirOptionsImmut,
+        rawdent: &RawDirEntry<E>,
+        ctx: &mut E::Context,
+    ) -> wd::ResultInner<bool, E> {
+        if opts_immut.unfollowed_link_kinds.is_empty() {
+            return Ok(true);
+        }
+        Ok(match rawdent.link_kind(ctx)? {
+            Some(kind) => !opts_immut.unfollowed_link_kinds.contains(&kind),
+            None => true,
+        })
+    }
+
+    // Check if dir wasn't modified since skip_dirs_unmodified_since (false if unknown).
+    fn is_unmodified(opts_immut: &WalkDirOptionsImmut, rawdent: &RawDirEntry<E>, ctx: &mut E::Context) -> bool {
+        let since = match opts_immut.dirs_unmodified_since {
+            Some(since) => since,
+            None => return false,
+        };
+        match rawdent.metadata(ctx) {
+            Ok(md) => md.modified().is_some_and(|modified| modified < since),
+            Err(_) => false,
+        }
+    }
+
+    // Check if the type is a FIFO, a socket or a device node.
+    fn is_special(ty: E::FileType) -> bool {
+        ty.is_fifo() || ty.is_socket() || ty.is_block_device() || ty.is_char_device()
+    }
+
+    // Check if dir's fingerprint was passed to skip_fingerprints (false if unknown).
+    fn has_skipped_fingerprint(opts_immut: &WalkDirOptionsImmut, rawdent: &RawDirEntry<E>) -> bool {
+        if opts_immut.skipped_fingerprints.is_empty() {
+            return false;
+        }
+        match rawdent.dir_id() {
+            Ok(Some(id)) => opts_immut.skipped_fingerprints.contains(&id),
+            _ => false,
+        }
+    }
+
+    // Check if dir name matches one of treat_as_files patterns.
+    fn is_treated_as_file(opts_immut: &WalkDirOptionsImmut, path: &E::Path) -> bool {
+        if opts_immut.treat_as_files.is_empty() {
+            return false;
+        }
+        match path.file_name_lossy() {
+            Some(name) => {
+                let ignore_case = opts_immut.match_case.ignores_case::<E>();
+                opts_immut.treat_as_files.iter().any(|p| glob_match(p, &name, ignore_case))
+            }
+            None => false,
+        }
+    }
+
+    // Decide if the next dir is descended into (always true without sampling).
+    fn sample_dir(opts_immut: &WalkDirOptionsImmut, rng: &mut Rng) -> bool {
+        let ratio = match opts_immut.sample {
+            Some((ratio, _)) => ratio,
+            None => return true,
+        };
+        ((rng.next_u64() >> 11) as f64) < ratio * ((1u64 << 53) as f64)
+    }
+
+    // Register canonical path of entry, return true if it was already met.
+    // Entries without canonical path are never treated as duplicates.
+    fn is_duplicate(seen: &mut BTreeSet<E::PathBuf>, path: &E::Path) -> bool {
+        match E::canonicalize_path(path) {
+            Ok(canonical) => !seen.insert(canonical),
+            Err(_) => false,
+        }
+    }
+
+    // Check if the dir was met under a previous root (and remember it otherwise)
+    fn is_overlapping(root_dirs: &mut BTreeMap<E::PathBuf, usize>, root_index: usize, path: &E::Path) -> bool {
+        match E::canonicalize_path(path) {
+            Ok(canonical) => *root_dirs.entry(canonical).or_insert(root_index) < root_index,
+            Err(_) => false,
+        }
+    }
+
+    // An error about a root itself (not about its entries) ending the walk
+    pub(crate) fn is_fatal(opts_immut: &WalkDirOptionsImmut, err: &Error<E>) -> bool {
+        opts_immut.root_errors == RootErrorPolicy::Fatal
+            && err.depth() == 0
+            && err.kind() != ErrorKind::PartialDir
+    }
+
+    // Check if the target of a link is on the device of the root (always true
+    // without `follow_links_same_device` or if the device of the root is unknown)
+    fn follows_to_device(
+        opts_immut: &WalkDirOptionsImmut,
+        root_device_opt: &Option<E::DeviceNum>,
+        rawdent: &RawDirEntry<E>,
+        ctx: &mut E::Context,
+    ) -> wd::ResultInner<bool, E> {
+        match root_device_opt {
+            Some(root_device) if opts_immut.follow_links_same_device => {
+                Self::is_same_file_system(root_device, rawdent, ctx)
+            }
+            _ => Ok(true),
+        }
     }
 
     fn is_same_file_system(
@@ -456,21 +1180,121 @@ where
 
         let content = cur_state.clone_all_content(
             filter,
-            &self.opts.immut,
-            &mut self.opts.content_processor,
+            Self::opts_at(&self.opts.immut, &self.depth_opts, cur_state.depth()),
+            &self.opts.content_processor,
+            self.opts.normalize,
             &mut process_dent!(self, cur_state.depth()),
             &mut self.opts.ctx,
         );
 
         content
     }
+
+    /// Gets content of current dir made by another content processor.
+    ///
+    /// Like `get_current_dir_content`, but items are made by `cp` while the
+    /// walk goes on with its own content processor. So e.g. a preview of a dir
+    /// may take only names of its entries without paying for full `DirEntry`s.
+    pub fn get_current_dir_content_with<P: ContentProcessor<E>>(
+        &mut self,
+        cp: &P,
+        filter: ContentFilter,
+    ) -> P::Collection {
+        let cur_state = self.states.last_mut().unwrap();
+
+        cur_state.clone_all_content(
+            filter,
+            Self::opts_at(&self.opts.immut, &self.depth_opts, cur_state.depth()),
+            cp,
+            self.opts.normalize,
+            &mut process_dent!(self, cur_state.depth()),
+            &mut self.opts.ctx,
+        )
+    }
+
+    /// Returns the dir containing `entry` while the walk is still inside it.
+    ///
+    /// An entry yielded by this iterator has its parent (and all ancestors)
+    /// open until the walk leaves it. So in `contents_first` mode, consumers
+    /// can attach children to their parent (which is yielded after them)
+    /// without maintaining their own map keyed by path. Returns `None` for a
+    /// root and for an entry whose parent was already left.
+    ///
+    /// The parent entry is made anew on every call, so it is returned by value.
+    pub fn parent_of(&mut self, entry: &DirEntry<E>) -> Option<DirEntry<E>>
+    where
+        CP: ContentProcessor<E, Item = DirEntry<E>>,
+    {
+        let depth = entry.depth().checked_sub(1)?;
+        let parent_path = entry.path().parent()?;
+        let state = self.states.get_mut(depth)?;
+        let parent = match state.get_current_position() {
+            Position::Entry(mut rflat) => rflat.make_content_item(
+                &mut self.opts.content_processor,
+                self.opts.normalize,
+                &mut self.opts.ctx,
+            )?,
+            _ => return None,
+        };
+        if parent.path() != parent_path {
+            return None;
+        }
+        Some(parent)
+    }
+
+    /// Attaches `value` to the dir whose content is being walked, to be taken
+    /// back with [`take_annotation`] when the walk leaves it.
+    ///
+    /// Called on `Position::BeforeContent`, it keeps state per dir (e.g. a
+    /// handle of the matching output dir) without a stack parallel to the
+    /// walk: on `Position::AfterContent` of the same dir, [`take_annotation`]
+    /// hands the value back whatever was attached to the dirs inside it, and
+    /// [`annotation_mut`] reaches it from the entries in between. A later call
+    /// replaces the value. Values not taken are dropped when the walk leaves
+    /// the dir (a value attached before the content of a root is entered is
+    /// dropped with the root).
+    ///
+    /// [`take_annotation`]: #method.take_annotation
+    /// [`annotation_mut`]: #method.annotation_mut
+    pub fn push_annotation<T: Any + Send>(&mut self, value: T) {
+        if let Some(state) = self.states.last_mut() {
+            state.set_annotation(Box::new(value));
+        }
+    }
+
+    /// Gets the value attached to the dir whose content is being walked (see
+    /// [`push_annotation`]), or `None` if nothing was attached or it isn't a `T`.
+    ///
+    /// [`push_annotation`]: #method.push_annotation
+    pub fn annotation_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.states.last_mut()?.annotation_mut()?.downcast_mut()
+    }
+
+    /// Takes the value attached to the dir whose content is being walked (see
+    /// [`push_annotation`]), or returns `None` if nothing was attached or it
+    /// isn't a `T` (then it stays attached).
+    ///
+    /// [`push_annotation`]: #method.push_annotation
+    pub fn take_annotation<T: Any>(&mut self) -> Option<T> {
+        let state = self.states.last_mut()?;
+        match state.take_annotation()?.downcast::<T>() {
+            Ok(value) => Some(*value),
+            Err(value) => {
+                state.set_annotation(value);
+                None
+            }
+        }
+    }
 }
 
 macro_rules! next_and_yield_rflat {
     ($self:expr, $cur_state:expr, $cur_depth:expr, $rflat:expr) => {{
-        let odent = $rflat.make_content_item(&mut $self.opts.content_processor, &mut $self.opts.ctx);
+        let odent = $rflat.make_content_item(&mut $self.opts.content_processor, $self.opts.normalize, &mut $self.opts.ctx);
+        if odent.is_some() {
+            Self::queue_streams(&mut $self.streams, &$self.opts.immut, $rflat.as_flat(), $cur_depth, &mut $self.opts.ctx);
+        }
         $cur_state.next_position(
-            &$self.opts.immut,
+            Self::opts_at(&$self.opts.immut, &$self.depth_opts, $cur_depth),
             &mut process_dent!($self, $cur_depth),
             &mut $self.opts.ctx,
         );
@@ -484,7 +1308,10 @@ macro_rules! next_and_yield_rflat {
 
 macro_rules! yield_rflat {
     ($self:expr, $cur_state:expr, $cur_depth:expr, $rflat:expr) => {{
-        let odent = $rflat.make_content_item(&mut $self.opts.content_processor, &mut $self.opts.ctx);
+        let odent = $rflat.make_content_item(&mut $self.opts.content_processor, $self.opts.normalize, &mut $self.opts.ctx);
+        if odent.is_some() {
+            Self::queue_streams(&mut $self.streams, &$self.opts.immut, $rflat.as_flat(), $cur_depth, &mut $self.opts.ctx);
+        }
         if let Some(dent) = odent {
             return Position::Entry(dent).into_some();
         } else {
@@ -493,19 +1320,12 @@ macro_rules! yield_rflat {
     }};
 }
 
-impl<E, CP> Iterator for WalkDirIterator<E, CP>
+impl<E, CP> WalkDirIterator<E, CP>
 where
     E: fs::FsDirEntry,
     CP: ContentProcessor<E>,
 {
-    type Item = WalkDirIteratorItem<E, CP>;
-    /// Advances the iterator and returns the next value.
-    ///
-    /// # Errors
-    ///
-    /// If the iterator fails to retrieve the next value, this method returns
-    /// an error value. The error will be wrapped in an Option::Some.
-    fn next(&mut self) -> Option<Self::Item> {
+    fn next_item(&mut self) -> Option<WalkDirIteratorItem<E, CP>> {
         fn get_parent_dent<E, CP>(this: &mut WalkDirIterator<E, CP>, cur_depth: Depth) -> CP::Item
         where
             E: fs::FsDirEntry,
@@ -514,23 +1334,54 @@ where
             let prev_state = this.states.get_mut(cur_depth - 1).unwrap();
             match prev_state.get_current_position() {
                 Position::Entry(mut rflat) => {
-                    rflat.make_content_item(&mut this.opts.content_processor, &mut this.opts.ctx).unwrap()
+                    rflat.make_content_item(&mut this.opts.content_processor, this.opts.normalize, &mut this.opts.ctx).unwrap()
                 }
                 _ => unreachable!(),
             }
         }
 
-        // Initial actions
-        if let Some(start) = self.start.take() {
-            if let Err(e) = self.init(&start) {
-                return Position::Error(Error::from_inner(e, 0)).into_some();
-                // Here self.states is empty, so next call will always return None.
-            };
+        self.yielded_after_content = false;
+
+        while let Some((rraw, depth)) = self.streams.pop_front() {
+            self.stream_depth = Some(depth);
+            match rraw {
+                Ok(mut raw) => {
+                    let content_processor = &self.opts.content_processor;
+                    let odent = raw.make_content_item(content_processor, false, depth, self.opts.normalize, &mut self.opts.ctx);
+                    if let Some(dent) = odent {
+                        return Position::Entry(dent).into_some();
+                    }
+                }
+                Err(err) => {
+                    let err = Error::from_inner(err, depth);
+                    trace_error(&err);
+                    return Position::Error(err).into_some();
+                }
+            }
         }
+        self.stream_depth = None;
 
         loop {
+            // Initial actions (for every root)
+            if let Some(start) = self.start.take() {
+                self.summary.roots += 1;
+                if let Err(e) = self.init(&start) {
+                    self.summary.failed_roots += 1;
+                    let err = Error::from_inner(e, 0);
+                    trace_error(&err);
+                    return Position::Error(err).into_some();
+                    // Here self.states is empty, so next call will switch to the next root.
+                };
+            }
+
             let cur_depth = match self.states.len() {
-                0 => unreachable!(),
+                // Root failed to init
+                0 => {
+                    if self.next_root() {
+                        continue;
+                    }
+                    return None;
+                }
                 len @ _ => (len - 1),
             };
 
@@ -550,7 +1401,7 @@ where
 
                     // Shift to first entry
                     cur_state.next_position(
-                        &self.opts.immut,
+                        Self::opts_at(&self.opts.immut, &self.depth_opts, cur_depth),
                         &mut process_dent!(self, cur_depth),
                         &mut self.opts.ctx,
                     );
@@ -561,8 +1412,9 @@ where
                     }
                     let content = cur_state.clone_all_content(
                         ContentFilter::None,
-                        &self.opts.immut,
+                        Self::opts_at(&self.opts.immut, &self.depth_opts, cur_state.depth()),
                         &mut self.opts.content_processor,
+                        self.opts.normalize,
                         &mut process_dent!(self, cur_state.depth()),
                         &mut self.opts.ctx,
                     );
@@ -572,6 +1424,36 @@ where
                 Position::Entry(mut rflat) => {
                     // Process entry
 
+                    // Skip already met entry (with all its content) on first step
+                    if self.opts.immut.dedupe_canonical
+                        && self.transition_state == TransitionState::None
+                        && rflat.loop_link().is_none()
+                        && !rflat.descend_only()
+                        && Self::is_duplicate(&mut self.seen, rflat.path())
+                    {
+                        cur_state.next_position(
+                            Self::opts_at(&self.opts.immut, &self.depth_opts, cur_depth),
+                            &mut process_dent!(self, cur_depth),
+                            &mut self.opts.ctx,
+                        );
+                        continue;
+                    }
+
+                    // Skip dir (with all its content) already walked under a previous root
+                    if self.opts.immut.dedupe_overlapping_roots
+                        && self.transition_state == TransitionState::None
+                        && rflat.is_dir()
+                        && rflat.loop_link().is_none()
+                        && Self::is_overlapping(&mut self.root_dirs, self.root_index, rflat.path())
+                    {
+                        cur_state.next_position(
+                            Self::opts_at(&self.opts.immut, &self.depth_opts, cur_depth),
+                            &mut process_dent!(self, cur_depth),
+                            &mut self.opts.ctx,
+                        );
+                        continue;
+                    }
+
                     // Allow yield this entry if (require all):
                     // - It isn't hidden
                     // - Current depth is in allowed range
@@ -587,8 +1469,9 @@ where
                     if rflat.is_dir() {
                         // Process dir entry
 
-                        // If (cur_depth + 1) still in allowed range ...
-                        let allow_push = cur_depth < self.opts.immut.max_depth;
+                        // If (cur_depth + 1) still in allowed range and the content
+                        // isn't walked by a later record (see `globally_sorted`) ...
+                        let allow_push = cur_depth < self.opts.immut.max_depth && !rflat.leaf();
 
                         match self.transition_state {
                             // First step
@@ -605,25 +1488,41 @@ where
                                                 &self.ancestors,
                                                 loop_depth,
                                                 rflat.path(),
+                                                cur_depth,
                                             );
-                                            return Position::Error(Error::from_inner(
-                                                err, cur_depth,
-                                            ))
-                                            .into_some();
+                                            let err = Error::from_inner(err, cur_depth);
+                                            trace_error(&err);
+                                            return Position::Error(err).into_some();
                                         }
                                         continue;
                                     }
 
-                                    // Before open new dir, we must close opened one
-                                    self.transition_state =
-                                        TransitionState::CloseOldestBeforePushDown;
+                                    if cur_depth > 0
+                                        && !Self::sample_dir(&self.opts.immut, &mut self.sample_rng)
+                                    {
+                                        // Not sampled: skip all children and jump to last step
+                                        rflat.set_sampled_out();
+                                        self.transition_state = TransitionState::AfterPopUp;
+                                    } else {
+                                        // Before open new dir, we must close opened one
+                                        self.transition_state =
+                                            TransitionState::CloseOldestBeforePushDown;
+                                    }
                                 } else {
                                     // Skip all children and jump to last step
                                     self.transition_state = TransitionState::AfterPopUp;
                                 }
 
                                 // In content_first mode: yield Position::Entry (if allowed) and shift to next entry
-                                if !self.opts.immut.contents_first && allow_yield {
+                                if Self::announces_dirs(&self.opts.immut) && allow_yield {
+                                    let odent = rflat.make_content_item(&mut self.opts.content_processor, self.opts.normalize, &mut self.opts.ctx);
+                                    if let Some(mut dent) = odent {
+                                        CP::mark_announcement(&mut dent);
+                                        return Position::Entry(dent).into_some();
+                                    }
+                                    // If conversion to CP::Item failed, skip all children and jump to last step
+                                    self.transition_state = TransitionState::AfterPopUp;
+                                } else if !Self::yields_dirs_after_content(&self.opts.immut) && allow_yield {
                                     if !yield_rflat!(self, cur_state, cur_depth, rflat) {
                                         // If conversion to CP::Item failed, skip all children and jump to last step
                                         self.transition_state = TransitionState::AfterPopUp;
@@ -635,26 +1534,55 @@ where
                                 // Deeper dir must start with empty state
                                 self.transition_state = TransitionState::None;
 
+                                // The device is needed before the content is read to detect mount points
+                                let device = if Self::tracks_devices(&self.opts.immut, &self.depth_opts) {
+                                    rflat.as_flat().raw.device_num(&mut self.opts.ctx).ok()
+                                } else {
+                                    None
+                                };
+                                let detects_mount_points = Self::detects_mount_points(
+                                    Self::opts_at(&self.opts.immut, &self.depth_opts, cur_depth + 1),
+                                );
+
                                 match Self::push_dir_1(
                                     rflat.as_flat(),
                                     cur_depth + 1,
-                                    &self.opts.immut,
+                                    Self::opts_at(&self.opts.immut, &self.depth_opts, cur_depth + 1),
                                     &mut self.opts.sorter,
+                                    self.opts.process_read_dir.as_ref(),
                                     &self.root_device,
                                     &self.ancestors,
+                                    Self::tracks_ancestors(&self.opts.immut, &self.depth_opts),
+                                    device.as_ref().filter(|_| detects_mount_points),
+                                    &self.opts.middleware,
+                                    &mut self.opts.content_pass,
+                                    &mut self.pool,
                                     &mut self.opts.ctx,
                                 ) {
                                     Ok(data) => {
+                                        if self.opts.immut.record_fingerprints {
+                                            if let Ok(Some(id)) = rflat.as_flat().raw.dir_id() {
+                                                self.visited.insert(id);
+                                            }
+                                        }
                                         self.push_dir_2(data);
+                                        if let Some((from, to)) = self.push_device(device, cur_depth) {
+                                            return Position::DeviceBoundary { from, to }.into_some();
+                                        }
                                     }
                                     Err(err) => {
                                         // Jump to last step
                                         self.transition_state = TransitionState::AfterPopUp;
                                         // And yield an error
-                                        return Position::Error(Error::from_inner(
-                                            err, cur_depth,
-                                        ))
-                                        .into_some();
+                                        let err = Error::from_inner(err, cur_depth);
+                                        trace_error(&err);
+                                        if self.opts.immut.report_skipped_dirs {
+                                            let odent = rflat.make_content_item(&mut self.opts.content_processor, self.opts.normalize, &mut self.opts.ctx);
+                                            if let Some(entry) = odent {
+                                                return Position::DirSkipped { entry, error: err }.into_some();
+                                            }
+                                        }
+                                        return Position::Error(err).into_some();
                                     }
                                 }
                             }
@@ -664,12 +1592,14 @@ where
                                 self.transition_state = TransitionState::None;
 
                                 // In !content_first mode: yield Position::Entry (if allowed) and shift to next entry
-                                if self.opts.immut.contents_first && allow_yield {
+                                if Self::yields_dirs_after_content(&self.opts.immut) && allow_yield {
+                                    self.yielded_after_content = true;
                                     next_and_yield_rflat!(self, cur_state, cur_depth, rflat);
+                                    self.yielded_after_content = false;
                                 // If conversion to CP::Item failed, ignore it
                                 } else {
                                     cur_state.next_position(
-                                        &self.opts.immut,
+                                        Self::opts_at(&self.opts.immut, &self.depth_opts, cur_depth),
                                         &mut process_dent!(self, cur_depth),
                                         &mut self.opts.ctx,
                                     );
@@ -687,7 +1617,7 @@ where
                         // If conversion to CP::Item failed, ignore it
                         } else {
                             cur_state.next_position(
-                                &self.opts.immut,
+                                Self::opts_at(&self.opts.immut, &self.depth_opts, cur_depth),
                                 &mut process_dent!(self, cur_depth),
                                 &mut self.opts.ctx,
                             );
@@ -698,20 +1628,56 @@ where
                     // Process error
                     assert!(self.transition_state == TransitionState::None);
 
+                    // Count errors of entries and report them after the content
+                    if self.opts.immut.summarize_entry_errors && cur_depth > 0 {
+                        cur_state.count_entry_error();
+                        cur_state.next_position(
+                            Self::opts_at(&self.opts.immut, &self.depth_opts, cur_depth),
+                            &mut process_dent!(self, cur_depth),
+                            &mut self.opts.ctx,
+                        );
+                        continue;
+                    }
+
                     // Yield Position::Error and shift to next entry
                     let err = rerr.into_error();
+                    trace_error(&err);
                     cur_state.next_position(
-                        &self.opts.immut,
+                        Self::opts_at(&self.opts.immut, &self.depth_opts, cur_depth),
                         &mut process_dent!(self, cur_depth),
                         &mut self.opts.ctx,
                     );
                     return Position::Error(err).into_some();
                 }
-                Position::AfterContent => {
+                Position::DeviceBoundary { .. } | Position::DirSkipped { .. } | Position::Finished { .. } => {
+                    unreachable!()
+                }
+                Position::AfterContent { summary } => {
                     // After content of current dir
 
-                    // For root: stop the iterator (without yielding Position::AfterContent)
+                    // Yield the summary of counted entry errors first
+                    if cur_depth > 0 && self.transition_state == TransitionState::None {
+                        let errors = cur_state.take_entry_errors();
+                        if errors > 0 {
+                            let prev_state = self.states.get_mut(cur_depth - 1).unwrap();
+                            let err = match prev_state.get_current_position() {
+                                Position::Entry(rflat) => {
+                                    ErrorInner::<E>::from_partial_dir(rflat.path(), errors)
+                                }
+                                _ => unreachable!(),
+                            };
+                            let err = Error::from_inner(err, cur_depth - 1);
+                            trace_error(&err);
+                            return Position::Error(err).into_some();
+                        }
+                    }
+
+                    // For root: go to the next root or stop the iterator (without
+                    // yielding Position::AfterContent)
                     if cur_depth == 0 {
+                        if self.next_root() {
+                            continue;
+                        }
                         return None;
                     }
 
@@ -720,7 +1686,7 @@ where
                         TransitionState::None => {
                             // Just yield Position::AfterContent
                             self.transition_state = TransitionState::BeforePopUp;
-                            return Position::AfterContent.into_some();
+                            return Position::AfterContent { summary }.into_some();
                         }
                         // Second step: surface to parent
                         TransitionState::BeforePopUp => {
@@ -735,3 +1701,94 @@ where
         }
     }
 }
+
+impl<E, CP> fmt::Debug for WalkDirIterator<E, CP>
+where
+    E: fs::FsDirEntry,
+    CP: ContentProcessor<E>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WalkDirIterator")
+            .field("opts", &self.opts)
+            .field("start", &self.start)
+            .field("next_roots", &self.next_roots)
+            .field("root_index", &self.root_index)
+            .field("depth", &self.states.len().checked_sub(1))
+            .field("transition_state", &self.transition_state)
+            .field("poisoned", &self.poisoned)
+            .field("finished", &self.finished)
+            .field("summary", &self.summary)
+            .finish()
+    }
+}
+
+impl<E, CP> Iterator for WalkDirIterator<E, CP>
+where
+    E: fs::FsDirEntry,
+    CP: ContentProcessor<E>,
+{
+    type Item = WalkDirIteratorItem<E, CP>;
+    /// Advances the iterator and returns the next value.
+    ///
+    /// # Errors
+    ///
+    /// If the iterator fails to retrieve the next value, this method returns
+    /// an error value. The error will be wrapped in an Option::Some.
+    ///
+    /// # Panics
+    ///
+    /// A panic in a user callback (a sorter, a content processor, a
+    /// normalizing function, ...) propagates to the caller. If it is caught,
+    /// the iterator is poisoned and every later call returns `None`.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.poisoned || self.finished {
+            return None;
+        }
+        #[cfg(feature = "std")]
+        self.started_at.get_or_insert_with(std::time::Instant::now);
+        // Stays set if anything below panics
+        self.poisoned = true;
+        let item = self.next_item();
+        self.poisoned = false;
+
+        match item {
+            Some(Position::Entry(ref entry)) => {
+                self.summary.entries += 1;
+                if CP::is_dir(entry) {
+                    self.summary.dirs += 1;
+                }
+                // An entry at depth `d` is yielded from the `d`-th state (alternate
+                // streams carry their own depth and belong to no dir summary)
+                let depth = self.stream_depth.unwrap_or_else(|| self.states.len().saturating_sub(1));
+                self.summary.max_depth_reached = self.summary.max_depth_reached.max(depth);
+                if let (None, Some(state)) = (self.stream_depth, self.states.last_mut()) {
+                    state.count_yielded_entry(CP::is_dir(entry));
+                }
+            }
+            Some(Position::Error(ref err)) | Some(Position::DirSkipped { error: ref err, .. }) => {
+                self.summary.errors += 1;
+                if let (None, Some(state)) = (self.stream_depth, self.states.last_mut()) {
+                    state.count_yielded_error();
+                }
+                if Self::is_fatal(&self.opts.immut, err) {
+                    // The next call finds no state and no root left
+                    self.states.clear();
+                    self.streams.clear();
+                    self.next_roots.clear();
+                }
+            }
+            Some(_) => {}
+            None => {
+                self.finished = true;
+                #[cfg(feature = "std")]
+                if let Some(started_at) = self.started_at {
+                    self.summary.duration = started_at.elapsed();
+                }
+                if self.opts.immut.report_finished {
+                    return Position::Finished { summary: self.summary }.into_some();
+                }
+            }
+        }
+        item
+    }
+}
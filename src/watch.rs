@@ -0,0 +1,260 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::cp::ContentProcessor;
+use crate::fs;
+use crate::walk::{WalkDirIterator, WalkDirIteratorItem, WalkDirOptions};
+use crate::wd::IntoSome;
+
+/// An item yielded by [`WatchDir`].
+///
+/// [`WatchDir`]: struct.WatchDir.html
+pub enum WatchEvent<E, CP>
+where
+    E: fs::FsDirEntry,
+    CP: ContentProcessor<E>,
+{
+    /// A (re)walk of the subtree at the given path begins. The first walk always
+    /// starts at the root.
+    Rescan(PathBuf),
+    /// An item of the current (re)walk. Depths are relative to the subtree being
+    /// rewalked.
+    Position(WalkDirIteratorItem<E, CP>),
+    /// The given path was removed
+    Removed(PathBuf),
+    /// The watcher reported an error
+    WatchError(notify::Error),
+}
+
+/// A handle which walks the tree once and then rewalks only changed subtrees.
+///
+/// Values of this type are created by calling [`watch`] on a `WalkDir`. At first the
+/// whole tree is walked. After this walk is over, every change reported by the
+/// file system notifications is turned into a rewalk of the changed subtree (or a
+/// [`Removed`] event if the path doesn't exist anymore). Changes arriving together
+/// are coalesced: nested paths are rewalked only once.
+///
+/// Paths of events are under the root as it was given (a relative root gives
+/// relative paths), though the tree is watched by its canonical path.
+///
+/// As an iterator, this type blocks until the next change arrives. Use
+/// [`try_next`] or [`next_timeout`] to poll it without blocking.
+///
+/// [`watch`]: struct.WalkDir.html#method.watch
+/// [`Removed`]: enum.WatchEvent.html#variant.Removed
+/// [`try_next`]: #method.try_next
+/// [`next_timeout`]: #method.next_timeout
+pub struct WatchDir<E, CP>
+where
+    E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+    CP: ContentProcessor<E>,
+{
+    root: PathBuf,
+    /// Canonical root: the watched path, which notifications are relative to
+    watched_root: PathBuf,
+    /// Options of the walker (taken by the current walk while it's in progress)
+    opts: Option<WalkDirOptions<E, CP>>,
+    /// The walk in progress
+    current: Option<WalkDirIterator<E, CP>>,
+    /// Subtrees waiting to be rewalked
+    pending: Vec<PathBuf>,
+    /// Removed paths waiting to be reported
+    removed: Vec<PathBuf>,
+    /// Errors about changed paths waiting to be reported
+    errors: Vec<notify::Error>,
+    watcher: notify::RecommendedWatcher,
+    rx: mpsc::Receiver<notify::Result<notify::Event>>,
+}
+
+impl<E, CP> WatchDir<E, CP>
+where
+    E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+    CP: ContentProcessor<E>,
+{
+    pub(crate) fn new(opts: WalkDirOptions<E, CP>, root: PathBuf) -> notify::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        let watched_root = root.canonicalize().map_err(|err| notify::Error::io(err).add_path(root.clone()))?;
+        watcher.watch(&watched_root, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            pending: vec![root.clone()],
+            root,
+            watched_root,
+            opts: Some(opts),
+            current: None,
+            removed: vec![],
+            errors: vec![],
+            watcher,
+            rx,
+        })
+    }
+
+    /// The root of the watched tree
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Stop watching. Walks already in progress or pending are still completed.
+    pub fn unwatch(&mut self) -> notify::Result<()> {
+        self.watcher.unwatch(&self.watched_root)
+    }
+
+    /// Next event, if it is available without waiting for changes
+    pub fn try_next(&mut self) -> Option<WatchEvent<E, CP>> {
+        loop {
+            if let Some(ev) = self.next_ready() {
+                return Some(ev);
+            }
+            match self.rx.try_recv() {
+                Ok(ev) => {
+                    if let Some(err) = self.process_event(ev) {
+                        return WatchEvent::WatchError(err).into_some();
+                    }
+                },
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Next event, waiting for changes no longer than `timeout`
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<WatchEvent<E, CP>> {
+        loop {
+            if let Some(ev) = self.next_ready() {
+                return Some(ev);
+            }
+            match self.rx.recv_timeout(timeout) {
+                Ok(ev) => {
+                    if let Some(err) = self.process_event(ev) {
+                        return WatchEvent::WatchError(err).into_some();
+                    }
+                },
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Yields from the walk in progress or starts the next pending one.
+    fn next_ready(&mut self) -> Option<WatchEvent<E, CP>> {
+        if let Some(ref mut current) = self.current {
+            if let Some(item) = current.next() {
+                return WatchEvent::Position(item).into_some();
+            }
+            let current = self.current.take().unwrap();
+            self.opts = Some(current.into_options());
+        }
+
+        if let Some(path) = self.removed.pop() {
+            return WatchEvent::Removed(path).into_some();
+        }
+
+        if !self.errors.is_empty() {
+            return WatchEvent::WatchError(self.errors.remove(0)).into_some();
+        }
+
+        // Drain already arrived notifications so that they are coalesced
+        while let Ok(ev) = self.rx.try_recv() {
+            if let Some(err) = self.process_event(ev) {
+                return WatchEvent::WatchError(err).into_some();
+            }
+        }
+
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let path = self.pending.remove(0);
+        let opts = self.opts.take().expect("BUG: options are taken by finished walk");
+        self.current = Some(WalkDirIterator::new(opts, path.clone()));
+        WatchEvent::Rescan(path).into_some()
+    }
+
+    /// Turns notification into pending rewalks. Returns watcher error if any.
+    fn process_event(&mut self, ev: notify::Result<notify::Event>) -> Option<notify::Error> {
+        let ev = match ev {
+            Ok(ev) => ev,
+            Err(err) => return Some(err),
+        };
+
+        if let EventKind::Access(_) = ev.kind {
+            return None;
+        }
+
+        for watched_path in ev.paths {
+            // Report the path under the root as it was given
+            let path = match watched_path.strip_prefix(&self.watched_root) {
+                Ok(rel) if rel.as_os_str().is_empty() => self.root.clone(),
+                Ok(rel) => self.root.join(rel),
+                Err(_) => continue,
+            };
+            match watched_path.symlink_metadata() {
+                Ok(_) => self.add_pending(path),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    if !self.removed.contains(&path) {
+                        self.removed.push(path);
+                    }
+                },
+                Err(err) => self.errors.push(notify::Error::io(err).add_path(path)),
+            }
+        }
+
+        None
+    }
+
+    fn add_pending(&mut self, path: PathBuf) {
+        if self.pending.iter().any(|p| path.starts_with(p)) {
+            return;
+        }
+        self.pending.retain(|p| !p.starts_with(&path));
+        self.pending.push(path);
+    }
+}
+
+impl<E, CP> Iterator for WatchDir<E, CP>
+where
+    E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+    CP: ContentProcessor<E>,
+{
+    type Item = WatchEvent<E, CP>;
+
+    /// Advances the iterator, waiting for changes when all walks are done.
+    ///
+    /// Returns `None` only when the watcher is gone.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(ev) = self.next_ready() {
+                return Some(ev);
+            }
+            match self.rx.recv() {
+                Ok(ev) => {
+                    if let Some(err) = self.process_event(ev) {
+                        return WatchEvent::WatchError(err).into_some();
+                    }
+                },
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+impl<E, CP> std::fmt::Debug for WatchDir<E, CP>
+where
+    E: fs::FsDirEntry<Path = Path, PathBuf = PathBuf>,
+    CP: ContentProcessor<E>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WatchDir")
+            .field("root", &self.root)
+            .field("watched_root", &self.watched_root)
+            .field("opts", &self.opts)
+            .field("current", &self.current)
+            .field("pending", &self.pending)
+            .field("removed", &self.removed)
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
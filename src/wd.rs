@@ -1,4 +1,11 @@
+use core::fmt;
+
+use alloc::borrow::Cow;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
 use crate::fs;
+use crate::walk::FlatDirEntry;
 
 // use crate::cp::ContentProcessor;
 // pub use crate::dent::DirEntry;
@@ -19,11 +26,11 @@ impl<T> IntoSome<T> for T {
 /// An useful wrapper for Ok(...) ready to chaining
 pub trait IntoOk<T, E> {
     /// Ok(...)
-    fn into_ok(self) -> std::result::Result<T, E>;
+    fn into_ok(self) -> core::result::Result<T, E>;
 }
 
 impl<T, E> IntoOk<T, E> for T {
-    fn into_ok(self) -> std::result::Result<Self, E> {
+    fn into_ok(self) -> core::result::Result<Self, E> {
         Ok(self)
     }
 }
@@ -31,11 +38,11 @@ impl<T, E> IntoOk<T, E> for T {
 /// An useful wrapper for Err(...) ready to chaining
 pub trait IntoErr<T, E> {
     /// Err(...)
-    fn into_err(self) -> std::result::Result<T, E>;
+    fn into_err(self) -> core::result::Result<T, E>;
 }
 
 impl<T, E> IntoErr<T, E> for E {
-    fn into_err(self) -> std::result::Result<T, Self> {
+    fn into_err(self) -> core::result::Result<T, Self> {
         Err(self)
     }
 }
@@ -54,20 +61,33 @@ pub type Depth = usize;
 ///
 /// [`io::Result`]: https://doc.rust-lang.org/stable/std/io/type.Result.html
 /// [`try!`]: https://doc.rust-lang.org/stable/std/macro.try.html
-pub type Result<T, E> = ::std::result::Result<T, Error<E>>;
+pub type Result<T, E> = ::core::result::Result<T, Error<E>>;
 
 /// A result type for walkdir operations with inner errors.
 pub type ResultInner<T, E> =
-    ::std::result::Result<T, ErrorInner<E>>;
+    ::core::result::Result<T, ErrorInner<E>>;
 
-/// A DirEntry sorter function.
-pub type FnCmp<E> = Box<
-    dyn FnMut( (&E, &<E as fs::FsDirEntry>::FileType), (&E, &<E as fs::FsDirEntry>::FileType), &mut <E as fs::FsDirEntry>::Context, ) -> std::cmp::Ordering
+/// A DirEntry sorter function (shared by all iterators made from one builder).
+pub type FnCmp<E> = Arc<
+    dyn Fn( (&E, &<E as fs::FsDirEntry>::FileType), (&E, &<E as fs::FsDirEntry>::FileType), &mut <E as fs::FsDirEntry>::Context, ) -> core::cmp::Ordering
         + Send
         + Sync
         + 'static,
 >;
 
+/// A function choosing the pass of an entry (see `WalkDir::content_order_by`).
+pub type FnPass<E> = Arc<dyn Fn(&FlatDirEntry<E>) -> Pass + Send + Sync + 'static>;
+
+/// A function processing the content of a dir as a whole (see `WalkDir::process_read_dir`).
+pub type FnProcessReadDir<E> = Arc<
+    dyn Fn(Depth, &<E as fs::FsDirEntry>::Path, &mut Vec<FlatDirEntry<E>>) + Send + Sync + 'static,
+>;
+
+/// A path normalization function (see `WalkDir::normalize_paths`).
+pub type FnNormalize<E> = for<'p> fn(
+    &'p <E as fs::FsDirEntry>::Path,
+) -> Cow<'p, <E as fs::FsDirEntry>::Path>;
+
 // Convert FsReadDir.next() to some Option<T>.
 // - Some(T) -- add T to collected vec,
 // - None -- entry must be ignored
@@ -80,7 +100,8 @@ pub type FnCmp<E> = Box<
 //pub type ProcessDirEntry<E: storage::StorageExt> = self::Result<(DirEntry<E>, bool), E>
 
 /// A variants for filtering content
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ContentFilter {
     /// No filter, all content will be yielded (default)
     None,
@@ -93,7 +114,8 @@ pub enum ContentFilter {
 }
 
 /// A variants for ordering content
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ContentOrder {
     /// No arrange (default)
     None,
@@ -101,11 +123,203 @@ pub enum ContentOrder {
     FilesFirst,
     /// Yield dirs (with theirs content) first, then files
     DirsFirst,
+    /// Yield entries in passes chosen by the function given to
+    /// `WalkDir::content_order_by` (without the function, all entries go to
+    /// the second pass)
+    Custom,
+}
+
+/// A pass of the content where an entry is yielded (see `ContentOrder`).
+///
+/// Passes are yielded in order of their labels; passes without entries are
+/// skipped, so labels don't need to be contiguous.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pass(pub u8);
+
+impl Pass {
+    /// The first pass
+    pub const FIRST: Pass = Pass(0);
+    /// The second pass (errors are always yielded in it)
+    pub const SECOND: Pass = Pass(1);
+    /// The third pass
+    pub const THIRD: Pass = Pass(2);
+}
+
+/// What to do when a root fails (see `WalkDir::root_error_policy`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RootErrorPolicy {
+    /// Yield a single error for the root and go on with the next root (default)
+    #[default]
+    Yield,
+    /// Yield the error and end the walk, skipping the roots left
+    Fatal,
+}
+
+/// What to do with special files: named pipes (FIFOs), sockets and device nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SpecialFilePolicy {
+    /// Yield them as any other entry (default)
+    Yield,
+    /// Don't yield them
+    Skip,
+    /// Yield an error instead of each of them
+    Error,
+}
+
+/// What metadata queries a backend keeps for reuse (see `WalkDir::metadata_cache`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CachePolicy {
+    /// Query metadata every time (default)
+    #[default]
+    None,
+    /// Keep the metadata with each entry, so checks of one entry share a query
+    PerEntry,
+    /// Keep the metadata of the given count of last queried paths
+    Lru(usize),
+}
+
+/// Kinds of links (on Unix every link is a `Symlink`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LinkKind {
+    /// A symbolic link
+    Symlink,
+    /// A directory junction (Windows only)
+    Junction,
+    /// A volume mounted into a directory (Windows only)
+    MountPoint,
+    /// An app execution alias, e.g. in `WindowsApps` (Windows only, never followed)
+    AppExecLink,
+}
+
+/// How name patterns (see `WalkDir::match_case`) treat case of letters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CaseMatch {
+    /// Case matters
+    Sensitive,
+    /// Case is ignored
+    Insensitive,
+    /// Case is ignored if the backend reports case-insensitive names (see
+    /// `FsDirEntry::is_case_insensitive`), e.g. on Windows and macOS
+    Auto,
+}
+
+impl CaseMatch {
+    /// Returns `true` if case is ignored with the backend `E`
+    pub fn ignores_case<E: fs::FsDirEntry>(self) -> bool {
+        match self {
+            CaseMatch::Sensitive => false,
+            CaseMatch::Insensitive => true,
+            CaseMatch::Auto => E::is_case_insensitive(),
+        }
+    }
+}
+
+/// A fingerprint of a dir which stays the same between runs (unlike the handle-based one
+/// used to detect loops), so it can be saved and passed to a later walk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirId {
+    /// Device (volume serial number on Windows)
+    pub device: u64,
+    /// Inode number (file index on Windows)
+    pub index: u64,
+}
+
+/// Counts of what a walk met (see `Position::Finished`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WalkSummary {
+    /// Count of roots the walk tried to open
+    pub roots: usize,
+    /// Count of roots which failed to open
+    pub failed_roots: usize,
+    /// Count of yielded entries
+    pub entries: usize,
+    /// Count of yielded entries which are dirs
+    pub dirs: usize,
+    /// Count of yielded errors (including the ones about roots)
+    pub errors: usize,
+    /// Depth of the deepest yielded entry
+    pub max_depth_reached: Depth,
+    /// Time from the first call of `next` to the end of the walk (always zero
+    /// without `std`)
+    pub duration: core::time::Duration,
+}
+
+impl WalkSummary {
+    /// Returns `true` if at least one root was opened
+    pub fn started(&self) -> bool {
+        self.failed_roots < self.roots
+    }
+}
+
+/// Counts of what was yielded from the content of a dir (see `Position::AfterContent`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirSummary {
+    /// Count of yielded entries (entries filtered out aren't counted)
+    pub entries: usize,
+    /// Count of yielded entries which are dirs
+    pub dirs: usize,
+    /// Count of yielded errors about entries of the dir
+    pub errors: usize,
+}
+
+impl DirSummary {
+    /// Returns `true` if nothing was yielded from the content: neither an
+    /// entry (the dir is empty or all its entries were filtered out) nor an
+    /// error
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0 && self.errors == 0
+    }
+}
+
+/// Where an entry was found: the root it belongs to and the followed link it was
+/// reached through (see `DirEntry::origin`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin<P> {
+    root: usize,
+    link: Option<Arc<P>>,
+}
+
+impl<P> Origin<P> {
+    /// Origin of entries under the root with the given index
+    pub fn new(root: usize) -> Self {
+        Self { root, link: None }
+    }
+
+    /// Index of the root (in the order the roots were given, starting from `0`)
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    /// Path of the innermost followed link to a dir which the entry was reached
+    /// through (`None` if there is no such link between the root and the entry)
+    pub fn link(&self) -> Option<&P> {
+        self.link.as_deref()
+    }
+
+    /// Origin of entries inside the followed link at `link`
+    pub(crate) fn through_link(&self, link: P) -> Self {
+        Self { root: self.root, link: Some(Arc::new(link)) }
+    }
+}
+
+impl<P> Default for Origin<P> {
+    fn default() -> Self {
+        Self::new(0)
+    }
 }
 
 /// A position in dirs tree
 #[derive(Debug, PartialEq, Eq)]
-pub enum Position<BC, EN, ER> {
+pub enum Position<BC, EN, ER, DV = ()> {
     /// Before content of current dir
     BeforeContent(BC),
     /// An entry
@@ -113,7 +327,94 @@ pub enum Position<BC, EN, ER> {
     /// An error
     Error(ER),
     /// After content of current dir
-    AfterContent,
+    AfterContent {
+        /// What was yielded from the content
+        summary: DirSummary,
+    },
+    /// The walk descends into a dir on another device (only with `device_boundaries`)
+    DeviceBoundary {
+        /// Device of the parent dir
+        from: DV,
+        /// Device of the entered dir
+        to: DV,
+    },
+    /// Content of a dir couldn't be read (only with `report_skipped_dirs`)
+    DirSkipped {
+        /// The dir
+        entry: EN,
+        /// Why it couldn't be read
+        error: ER,
+    },
+    /// The walk is over, this is the last item (only with `report_finished`)
+    Finished {
+        /// What the walk met
+        summary: WalkSummary,
+    },
 }
 
 
+
+impl<EN, C, ER, DV> fmt::Display for Position<(EN, C), EN, ER, DV>
+where
+    EN: fmt::Display,
+    ER: fmt::Display,
+    DV: fmt::Debug,
+{
+    /// Displays the entry (or the dir whose content begins), the error, or a marker
+    /// for the end of content, a device change or a skipped dir.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Position::BeforeContent((dir, _)) => write!(f, "enter {}", dir),
+            Position::Entry(entry) => entry.fmt(f),
+            Position::Error(err) => write!(f, "error: {}", err),
+            Position::AfterContent { .. } => f.write_str("leave"),
+            Position::DeviceBoundary { from, to } => write!(f, "device {:?} -> {:?}", from, to),
+            Position::DirSkipped { entry, error } => write!(f, "skipped {}: {}", entry, error),
+            Position::Finished { summary } => write!(
+                f,
+                "finished: {} entries, {} errors",
+                summary.entries, summary.errors
+            ),
+        }
+    }
+}
+
+/// Match `name` against a shell-like `pattern`: `*` matches any (possibly
+/// empty) sequence of characters, `?` matches any single character.
+pub(crate) fn glob_match(pattern: &str, name: &str, ignore_case: bool) -> bool {
+    let chars = |s: &str| -> alloc::vec::Vec<char> {
+        if ignore_case {
+            s.to_lowercase().chars().collect()
+        } else {
+            s.chars().collect()
+        }
+    };
+    let pattern = chars(pattern);
+    let name = chars(name);
+
+    let (mut p, mut n) = (0, 0);
+    // Position of last `*` in pattern and of name char it currently matches up to
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match star {
+                // Let the last `*` eat one more char
+                Some((sp, sn)) => {
+                    star = Some((sp, sn + 1));
+                    p = sp + 1;
+                    n = sn + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}